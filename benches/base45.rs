@@ -0,0 +1,123 @@
+//! Base45 decoding of a batch of QR code sized inputs
+//!
+//! Compares the lookup table decoder to the previous per-character `match`, and decoding
+//! into a reused buffer to allocating a new one for each input.
+use std::convert::TryFrom;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use dcc_decode::core::base45::{base45_decode, base45_decode_into};
+
+const ALPHABET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Inputs per batch, each about as long as the base45 text of a vaccination DCC
+const BATCH: usize = 1000;
+const INPUT_BYTES: usize = 400;
+
+fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() / 2 * 3 + 2);
+    let mut push = |mut value: usize, len: usize| {
+        for _ in 0..len {
+            out.push(char::from(ALPHABET[value % 45]));
+            value /= 45;
+        }
+    };
+    let mut pairs = data.chunks_exact(2);
+    for pair in pairs.by_ref() {
+        push(usize::from(pair[0]) << 8 | usize::from(pair[1]), 3);
+    }
+    if let [last] = *pairs.remainder() {
+        push(usize::from(last), 2);
+    }
+    out
+}
+
+fn inputs() -> Vec<String> {
+    // A simple LCG is enough to avoid hitting the same branches on every input
+    let mut state: u32 = 0x1234_5678;
+    (0..BATCH)
+        .map(|i| {
+            let data: Vec<u8> = (0..INPUT_BYTES + i % 3)
+                .map(|_| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    (state >> 24) as u8
+                })
+                .collect();
+            encode(&data)
+        })
+        .collect()
+}
+
+/// The decoder before the lookup table, for comparison
+fn decode_match(input: &str) -> Option<Vec<u8>> {
+    fn cval(input: u8) -> Option<u32> {
+        match input {
+            b'0'..=b'9' => Some(u32::from(input - b'0')),
+            b'A'..=b'Z' => Some(u32::from(input - b'A') + 10),
+            b' ' => Some(36),
+            b'$' => Some(37),
+            b'%' => Some(38),
+            b'*' => Some(39),
+            b'+' => Some(40),
+            b'-' => Some(41),
+            b'.' => Some(42),
+            b'/' => Some(43),
+            b':' => Some(44),
+            _ => None,
+        }
+    }
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len() * 2 / 3 + 1);
+    let mut triples = bytes.chunks_exact(3);
+    for triple in triples.by_ref() {
+        let sum = cval(triple[0])? + 45 * cval(triple[1])? + 45 * 45 * cval(triple[2])?;
+        out.extend_from_slice(&u16::try_from(sum).ok()?.to_be_bytes());
+    }
+    match *triples.remainder() {
+        [] => {}
+        [c, d] => out.push(u8::try_from(cval(c)? + 45 * cval(d)?).ok()?),
+        _ => return None,
+    }
+    Some(out)
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let inputs = inputs();
+    let total: usize = inputs.iter().map(String::len).sum();
+    for input in &inputs {
+        assert_eq!(base45_decode(input).ok(), decode_match(input));
+    }
+
+    let mut group = c.benchmark_group("base45");
+    group.throughput(Throughput::Bytes(total as u64));
+    group.bench_function("match", |b| {
+        b.iter(|| {
+            for input in &inputs {
+                black_box(decode_match(input).unwrap());
+            }
+        })
+    });
+    group.bench_function("table", |b| {
+        b.iter(|| {
+            for input in &inputs {
+                black_box(base45_decode(input).unwrap());
+            }
+        })
+    });
+    group.bench_function("table_into", |b| {
+        b.iter_batched_ref(
+            || Vec::with_capacity(INPUT_BYTES + 2),
+            |buf| {
+                for input in &inputs {
+                    buf.clear();
+                    base45_decode_into(input, buf).unwrap();
+                    black_box(&buf);
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
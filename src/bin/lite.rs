@@ -0,0 +1,32 @@
+//! Minimal decoder that only runs the core decode path and prints JSON
+use std::{convert::TryFrom, io::Read, process};
+
+use dcc_decode::dcc::{load_sign1, CertPayload};
+
+fn run(buf: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let sign1 = load_sign1(buf)?;
+    let v = CertPayload::try_from(&sign1)?;
+    let jout = serde_json::to_string(&v.health_claim.cert)?;
+    Ok(jout)
+}
+
+fn main() {
+    // Load certificate data
+    let mut buf = String::new();
+    let res = match std::env::args().nth(1).filter(|f| f != "-") {
+        Some(file) => std::fs::read_to_string(file).map(|s| buf = s),
+        None => std::io::stdin().read_to_string(&mut buf).map(|_| ()),
+    };
+    if let Err(e) = res {
+        eprintln!("Failed to read input: {}", e);
+        process::exit(2);
+    }
+
+    match run(&buf) {
+        Ok(jout) => println!("{}", jout),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
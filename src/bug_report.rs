@@ -0,0 +1,116 @@
+//! Anonymized dumps of the intermediate stages of a decode, for bug reports
+//!
+//! The name, date of birth and UVCI in the CWT payload are replaced with placeholders of
+//! the same length (see [`redact::anonymize_cbor`]), in the payload itself and in the
+//! COSE message and `Sig_structure` around it. The encoding stays the same otherwise, so
+//! a failure in the COSE message or the payload can be reproduced with the `cose` stage
+//! and `--input-format hex`. The signature is kept, but no longer matches the payload.
+//!
+//! The stages before the COSE message (the input text and the compressed data) can't be
+//! anonymized and are left out.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    cbor,
+    cose::CoseSign1,
+    error_code::ErrorCode,
+    redact::{self, MASK},
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BugReport {
+    /// The version of dcc-decode
+    pub version: &'static str,
+    pub input_format: String,
+    /// The error that stopped the run, with personal data masked
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+    /// The paths of the values in the payload that were replaced, e.g. `-260.1.nam.fn`
+    pub anonymized: Vec<String>,
+    /// The anonymized stages (`cose`, `sig_structure`, `payload`, `signature`) as hex
+    pub stages: BTreeMap<&'static str, String>,
+    /// The anonymized payload in CBOR diagnostic notation
+    pub payload_diag: Option<String>,
+}
+
+impl BugReport {
+    pub fn new(input_format: &str, error: Option<&color_eyre::Report>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            input_format: input_format.to_string(),
+            error: error.map(|e| format!("{:#}", e)),
+            error_code: error.map(ErrorCode::of_report),
+            anonymized: Vec::new(),
+            stages: BTreeMap::new(),
+            payload_diag: None,
+        }
+    }
+
+    /// Add the stages of a (decompressed) COSE message
+    pub fn add_cose(&mut self, cose: &[u8]) -> color_eyre::Result<()> {
+        let sign1 = CoseSign1::from_slice(cose)?;
+        let payload = anonymize_payload(&sign1.payload)?;
+        let mut cose = cose.to_vec();
+        replace(&mut cose, &sign1.payload, &payload.bytes);
+        let sig_structure = crate::core::cose::sig_structure(&sign1.protected_raw, &payload.bytes);
+        self.stages.insert("cose", hex(&cose));
+        self.stages.insert("sig_structure", hex(&sig_structure));
+        self.stages.insert("signature", hex(&sign1.signature));
+        self.set_payload(payload);
+        Ok(())
+    }
+
+    /// Add a bare CWT payload
+    pub fn add_payload(&mut self, payload: &[u8]) -> color_eyre::Result<()> {
+        let payload = anonymize_payload(payload)?;
+        self.set_payload(payload);
+        Ok(())
+    }
+
+    fn set_payload(&mut self, payload: AnonymizedPayload) {
+        self.stages.insert("payload", hex(&payload.bytes));
+        self.payload_diag = Some(payload.diag);
+        // The error may quote a value, e.g. a date of birth that could not be parsed
+        for (path, text) in payload.replaced {
+            if let Some(error) = self.error.as_mut().filter(|_| !text.is_empty()) {
+                *error = error.replace(&text, MASK);
+            }
+            self.anonymized.push(path);
+        }
+    }
+}
+
+struct AnonymizedPayload {
+    bytes: Vec<u8>,
+    diag: String,
+    /// The paths and original texts of the replaced values
+    replaced: Vec<(String, String)>,
+}
+
+fn anonymize_payload(payload: &[u8]) -> color_eyre::Result<AnonymizedPayload> {
+    let item = cbor::parse(payload)?;
+    let mut bytes = payload.to_vec();
+    let replaced = redact::anonymize_cbor(&mut bytes, &item);
+    let diag = cbor::parse(&bytes)?.to_diag();
+    Ok(AnonymizedPayload {
+        bytes,
+        diag,
+        replaced,
+    })
+}
+
+/// Replace the first occurrence of `from` in `data` with `to` of the same length
+fn replace(data: &mut [u8], from: &[u8], to: &[u8]) {
+    if from.is_empty() || from.len() != to.len() {
+        return;
+    }
+    if let Some(at) = data.windows(from.len()).position(|window| window == from) {
+        data[at..at + to.len()].copy_from_slice(to);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
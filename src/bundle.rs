@@ -0,0 +1,368 @@
+//! Signed offline bundles of the trust data, for devices without network access
+//!
+//! A bundle is a COSE_Sign1 message (ES256) whose payload is a CBOR map with the
+//! creation time and the bundled files by their relative path:
+//!
+//! ```text
+//! { "v": 1, "created": <unix time>, "files": { "ehn-dcc-valuesets/test-type.json": h'...', ... } }
+//! ```
+//!
+//! The files are extracted to a directory and then loaded like any other trust list,
+//! value set, blocklist, CSCA or CRL file.
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use color_eyre::eyre::eyre;
+use serde_cbor::Value;
+use tracing::{debug, warn};
+use x509_parser::parse_x509_certificate;
+
+#[cfg(feature = "ring")]
+use crate::cose::{HEADER_ALG, HEADER_KID};
+use crate::{
+    cose::{CoseSign1, ALG_ES256},
+    crypto::{self, SignatureAlgorithm},
+};
+
+/// The version of the payload format
+const BUNDLE_VERSION: i128 = 1;
+
+/// A set of files with the time they were bundled
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub created: DateTime<Utc>,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Default for Bundle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self {
+            created: Utc::now(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Add a file under the relative path `name`, which uses `/` as separator
+    pub fn insert(&mut self, name: &str, contents: Vec<u8>) -> color_eyre::Result<()> {
+        check_name(name)?;
+        self.files.insert(name.to_string(), contents);
+        Ok(())
+    }
+
+    pub fn add_file<P: AsRef<Path>>(&mut self, name: &str, path: P) -> color_eyre::Result<()> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read(path).map_err(|e| eyre!("Failed to read '{}': {}", path.display(), e))?;
+        self.insert(name, contents)
+    }
+
+    /// Add all files in `dir` and its subdirectories, below `prefix`
+    pub fn add_dir<P: AsRef<Path>>(&mut self, prefix: &str, dir: P) -> color_eyre::Result<()> {
+        let dir = dir.as_ref();
+        let mut entries = std::fs::read_dir(dir)
+            .map_err(|e| eyre!("Failed to read '{}': {}", dir.display(), e))?
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let file_name = entry.file_name();
+            let file_name = file_name
+                .to_str()
+                .ok_or_else(|| eyre!("'{}' is not valid UTF-8", entry.path().display()))?;
+            let name = format!("{}/{}", prefix, file_name);
+            if entry.file_type()?.is_dir() {
+                self.add_dir(&name, entry.path())?;
+            } else {
+                self.add_file(&name, entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The relative paths and contents of all files
+    pub fn files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+
+    /// Sign the bundle with a PKCS#8 P-256 key
+    ///
+    /// The `kid` (e.g. of the certificate of the key) is put into the protected header.
+    #[cfg(feature = "ring")]
+    pub fn sign(&self, pkcs8: &[u8], kid: Option<&[u8]>) -> color_eyre::Result<Vec<u8>> {
+        use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8)
+            .map_err(|e| eyre!("Unsupported key: {}", e))?;
+        let mut protected = BTreeMap::new();
+        protected.insert(Value::Integer(HEADER_ALG), Value::Integer(ALG_ES256));
+        if let Some(kid) = kid {
+            protected.insert(Value::Integer(HEADER_KID), Value::Bytes(kid.to_vec()));
+        }
+        let protected = serde_cbor::to_vec(&Value::Map(protected))?;
+        let payload = serde_cbor::to_vec(&self.to_value())?;
+
+        let rng = ring::rand::SystemRandom::new();
+        let signature = key_pair
+            .sign(
+                &rng,
+                &crate::core::cose::sig_structure(&protected, &payload),
+            )
+            .map_err(|_| eyre!("Failed to sign the bundle"))?;
+        let message = Value::Tag(
+            18,
+            Box::new(Value::Array(vec![
+                Value::Bytes(protected),
+                Value::Map(BTreeMap::new()),
+                Value::Bytes(payload),
+                Value::Bytes(signature.as_ref().to_vec()),
+            ])),
+        );
+        Ok(serde_cbor::to_vec(&message)?)
+    }
+
+    /// Parse a signed bundle, verifying its signature with `anchor` (a DER encoded
+    /// certificate)
+    pub fn from_bytes(bytes: &[u8], anchor: &[u8]) -> color_eyre::Result<Self> {
+        let sign1 = CoseSign1::from_slice(bytes)?;
+        check_signature(&sign1, anchor)?;
+        Self::from_payload(&sign1.payload)
+    }
+
+    /// Parse a signed bundle without verifying its signature
+    ///
+    /// Anyone can create such a bundle, so this is only meant for local test setups.
+    pub fn from_bytes_unverified(bytes: &[u8]) -> color_eyre::Result<Self> {
+        let sign1 = CoseSign1::from_slice(bytes)?;
+        warn!("Bundle signature not verified");
+        Self::from_payload(&sign1.payload)
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P, anchor: &[u8]) -> color_eyre::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(&bytes, anchor)
+    }
+
+    /// Write all files below `dir`
+    pub fn extract<P: AsRef<Path>>(&self, dir: P) -> color_eyre::Result<()> {
+        let dir = dir.as_ref();
+        for (name, contents) in &self.files {
+            let path = Self::path_in(dir, name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, contents)
+                .map_err(|e| eyre!("Failed to write '{}': {}", path.display(), e))?;
+        }
+        debug!("Extracted {} files to '{}'", self.len(), dir.display());
+        Ok(())
+    }
+
+    /// The path of the file `name` when extracted to `dir`
+    pub fn path_in(dir: &Path, name: &str) -> PathBuf {
+        name.split('/')
+            .fold(dir.to_path_buf(), |path, part| path.join(part))
+    }
+
+    #[cfg(feature = "ring")]
+    fn to_value(&self) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|(name, contents)| (Value::Text(name.clone()), Value::Bytes(contents.clone())))
+            .collect();
+        let mut map = BTreeMap::new();
+        map.insert(Value::Text("v".into()), Value::Integer(BUNDLE_VERSION));
+        map.insert(
+            Value::Text("created".into()),
+            Value::Integer(self.created.timestamp().into()),
+        );
+        map.insert(Value::Text("files".into()), Value::Map(files));
+        Value::Map(map)
+    }
+
+    fn from_payload(payload: &[u8]) -> color_eyre::Result<Self> {
+        let mut map = match serde_cbor::from_slice(payload)? {
+            Value::Map(map) => map,
+            _ => return Err(eyre!("Bundle payload is not a map")),
+        };
+        let mut take = |key: &str| map.remove(&Value::Text(key.into()));
+        match take("v") {
+            Some(Value::Integer(BUNDLE_VERSION)) => {}
+            Some(Value::Integer(v)) => return Err(eyre!("Unsupported bundle version {}", v)),
+            _ => return Err(eyre!("Bundle payload has no version")),
+        }
+        let created = match take("created") {
+            Some(Value::Integer(t)) => Utc
+                .timestamp_opt(t as i64, 0)
+                .single()
+                .ok_or_else(|| eyre!("Invalid bundle creation time {}", t))?,
+            _ => return Err(eyre!("Bundle payload has no creation time")),
+        };
+        let entries = match take("files") {
+            Some(Value::Map(files)) => files,
+            _ => return Err(eyre!("Bundle payload has no files")),
+        };
+        let mut bundle = Self {
+            created,
+            files: BTreeMap::new(),
+        };
+        for (name, contents) in entries {
+            match (name, contents) {
+                (Value::Text(name), Value::Bytes(contents)) => bundle.insert(&name, contents)?,
+                _ => return Err(eyre!("Bundle file entries must map a text to bytes")),
+            }
+        }
+        Ok(bundle)
+    }
+}
+
+/// Only accept relative paths that stay within the extraction directory
+fn check_name(name: &str) -> color_eyre::Result<()> {
+    let valid = !name.is_empty()
+        && !name.contains('\\')
+        && Path::new(name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if valid {
+        Ok(())
+    } else {
+        Err(eyre!("Invalid file name '{}' in the bundle", name))
+    }
+}
+
+fn check_signature(sign1: &CoseSign1, anchor: &[u8]) -> color_eyre::Result<()> {
+    if sign1.alg() != Some(ALG_ES256) {
+        return Err(eyre!(
+            "Unsupported bundle signature algorithm {:?}",
+            sign1.alg()
+        ));
+    }
+    if let Some(kid) = sign1.kid() {
+        let anchor_kid = crate::cert::compute_kid(anchor);
+        if kid != anchor_kid.as_slice() {
+            return Err(eyre!(
+                "Bundle was signed with kid '{}', but the anchor has kid '{}'",
+                base64::encode(kid),
+                base64::encode(anchor_kid)
+            ));
+        }
+    }
+    let (_, cert) = parse_x509_certificate(anchor)?;
+    crypto::verify(
+        SignatureAlgorithm::EcdsaP256Sha256Fixed,
+        cert.tbs_certificate.subject_pki.subject_public_key.data,
+        &sign1.sig_structure(),
+        &sign1.signature,
+    )
+    .map_err(|_| eyre!("Bundle signature verification failed"))?;
+    debug!("Bundle signature verified");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_name, Bundle};
+
+    #[test]
+    fn names() {
+        for name in [
+            "blocklist.txt",
+            "trust-dir/0/dsc.pem",
+            "ehn-dcc-valuesets/a.json",
+        ] {
+            assert!(check_name(name).is_ok(), "{}", name);
+        }
+        let invalid = [
+            "",
+            "../blocklist.txt",
+            "trust-dir/../../etc/passwd",
+            "./blocklist.txt",
+            "/etc/passwd",
+            "trust-dir\\0\\dsc.pem",
+            "..\\blocklist.txt",
+        ];
+        for name in invalid {
+            assert!(check_name(name).is_err(), "{}", name);
+            assert!(Bundle::new().insert(name, Vec::new()).is_err(), "{}", name);
+        }
+    }
+
+    #[cfg(feature = "ring")]
+    mod signed {
+        use crate::{bundle::Bundle, cert::compute_kid, keygen::TestSigner};
+
+        fn bundle() -> Bundle {
+            let mut bundle = Bundle::new();
+            bundle
+                .insert("blocklist.txt", b"kid:1N3OpCzZjAM=\n".to_vec())
+                .unwrap();
+            bundle
+                .insert(
+                    "trust-dir/0/dsc.pem",
+                    b"-----BEGIN CERTIFICATE-----".to_vec(),
+                )
+                .unwrap();
+            bundle
+        }
+
+        #[test]
+        fn round_trip() {
+            let signer = TestSigner::generate("DE", "Bundle signer", 30).unwrap();
+            let kid = compute_kid(&signer.certificate);
+            for kid in [None, Some(kid.as_slice())] {
+                let signed = bundle().sign(&signer.pkcs8, kid).unwrap();
+                let loaded = Bundle::from_bytes(&signed, &signer.certificate).unwrap();
+                assert_eq!(loaded.created.timestamp(), bundle().created.timestamp());
+                let files: Vec<_> = loaded.files().collect();
+                assert_eq!(files, bundle().files().collect::<Vec<_>>());
+            }
+        }
+
+        #[test]
+        fn tampered_payload() {
+            let signer = TestSigner::generate("DE", "Bundle signer", 30).unwrap();
+            let mut signed = bundle().sign(&signer.pkcs8, None).unwrap();
+            let pos = (signed.windows(16))
+                .position(|w| w == b"kid:1N3OpCzZjAM=")
+                .unwrap();
+            signed[pos + 4] = b'X';
+            assert!(Bundle::from_bytes(&signed, &signer.certificate).is_err());
+            // The payload itself is still readable
+            let unverified = Bundle::from_bytes_unverified(&signed).unwrap();
+            assert!(unverified.contains("blocklist.txt"));
+        }
+
+        #[test]
+        fn wrong_anchor() {
+            let signer = TestSigner::generate("DE", "Bundle signer", 30).unwrap();
+            let other = TestSigner::generate("DE", "Other signer", 30).unwrap();
+            let kid = compute_kid(&signer.certificate);
+            for kid in [None, Some(kid.as_slice())] {
+                let signed = bundle().sign(&signer.pkcs8, kid).unwrap();
+                assert!(Bundle::from_bytes(&signed, &other.certificate).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,632 @@
+//! A minimal CBOR parser that keeps the structure of the encoding
+//!
+//! Unlike `serde_cbor::Value`, this keeps the order of map entries (and duplicates),
+//! indefinite-length items and the position of each item, which is needed to show
+//! what was actually encoded. The parser itself is in [`crate::core::cbor`].
+use std::{collections::BTreeSet, error::Error, fmt, fmt::Write};
+
+use serde_cbor::Value;
+
+use crate::core::cbor::{f16_to_f64, shortest_info, write_head};
+pub use crate::core::cbor::{parse, CborError, Item, ItemKind};
+
+impl Error for CborError {}
+
+impl Item {
+    /// Render this item in diagnostic notation (RFC 8949, section 8)
+    pub fn to_diag(&self) -> String {
+        let mut out = String::new();
+        self.write_diag(&mut out, 0);
+        out
+    }
+
+    fn is_container(&self) -> bool {
+        match &self.kind {
+            ItemKind::Array(items) => !items.is_empty(),
+            ItemKind::Map(entries) => !entries.is_empty(),
+            ItemKind::Tag(_, inner) | ItemKind::Embedded(inner) => inner.is_container(),
+            _ => false,
+        }
+    }
+
+    fn write_diag(&self, out: &mut String, indent: usize) {
+        let pad = "  ".repeat(indent + 1);
+        let end_pad = "  ".repeat(indent);
+        let marker = if self.indefinite { "_ " } else { "" };
+        match &self.kind {
+            ItemKind::Unsigned(n) => write!(out, "{}", n).unwrap(),
+            ItemKind::Negative(n) => write!(out, "{}", -1 - i128::from(*n)).unwrap(),
+            ItemKind::Bytes(bytes) => {
+                out.push_str(if self.indefinite { "(_ h'" } else { "h'" });
+                bytes.iter().for_each(|b| write!(out, "{:02x}", b).unwrap());
+                out.push_str(if self.indefinite { "')" } else { "'" });
+            }
+            ItemKind::Text(text) => {
+                if self.indefinite {
+                    out.push_str("(_ ");
+                }
+                write!(out, "{:?}", text).unwrap();
+                if self.indefinite {
+                    out.push(')');
+                }
+            }
+            ItemKind::Array(items) => {
+                let multiline = items.iter().any(Item::is_container);
+                out.push('[');
+                out.push_str(marker);
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                        if !multiline {
+                            out.push(' ');
+                        }
+                    }
+                    if multiline {
+                        out.push('\n');
+                        out.push_str(&pad);
+                    }
+                    item.write_diag(out, indent + 1);
+                }
+                if multiline {
+                    out.push('\n');
+                    out.push_str(&end_pad);
+                }
+                out.push(']');
+            }
+            ItemKind::Map(entries) => {
+                let multiline = entries.iter().any(|(_, v)| v.is_container());
+                out.push('{');
+                out.push_str(marker);
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                        if !multiline {
+                            out.push(' ');
+                        }
+                    }
+                    if multiline {
+                        out.push('\n');
+                        out.push_str(&pad);
+                    }
+                    key.write_diag(out, indent + 1);
+                    out.push_str(": ");
+                    value.write_diag(out, indent + 1);
+                }
+                if multiline {
+                    out.push('\n');
+                    out.push_str(&end_pad);
+                }
+                out.push('}');
+            }
+            ItemKind::Tag(tag, inner) => {
+                write!(out, "{}(", tag).unwrap();
+                inner.write_diag(out, indent);
+                out.push(')');
+            }
+            ItemKind::Embedded(inner) => {
+                out.push_str("<<");
+                inner.write_diag(out, indent);
+                out.push_str(">>");
+            }
+            ItemKind::Bool(b) => write!(out, "{}", b).unwrap(),
+            ItemKind::Null => out.push_str("null"),
+            ItemKind::Undefined => out.push_str("undefined"),
+            ItemKind::Simple(n) => write!(out, "simple({})", n).unwrap(),
+            ItemKind::Float(f) => {
+                if f.is_nan() {
+                    out.push_str("NaN");
+                } else if f.is_infinite() {
+                    out.push_str(if *f > 0.0 { "Infinity" } else { "-Infinity" });
+                } else {
+                    write!(out, "{:?}", f).unwrap();
+                }
+            }
+        }
+    }
+}
+
+impl From<&Value> for Item {
+    fn from(value: &Value) -> Self {
+        let kind = match value {
+            Value::Null => ItemKind::Null,
+            Value::Bool(b) => ItemKind::Bool(*b),
+            Value::Integer(n) if *n < 0 => ItemKind::Negative((-1 - *n) as u64),
+            Value::Integer(n) => ItemKind::Unsigned(*n as u64),
+            Value::Float(f) => ItemKind::Float(*f),
+            Value::Bytes(bytes) => ItemKind::Bytes(bytes.clone()),
+            Value::Text(text) => ItemKind::Text(text.clone()),
+            Value::Array(items) => ItemKind::Array(items.iter().map(Item::from).collect()),
+            Value::Map(map) => {
+                ItemKind::Map(map.iter().map(|(k, v)| (k.into(), v.into())).collect())
+            }
+            Value::Tag(tag, inner) => ItemKind::Tag(*tag, Box::new(inner.as_ref().into())),
+            _ => ItemKind::Undefined,
+        };
+        Item {
+            offset: 0,
+            indefinite: false,
+            kind,
+        }
+    }
+}
+
+/// Parse a COSE_Sign1 message
+///
+/// The protected header and payload are parsed as [`ItemKind::Embedded`] items, if they
+/// contain valid CBOR.
+pub fn parse_cose_sign1(data: &[u8]) -> Result<Item, CborError> {
+    let mut item = parse(data)?;
+    let message = match &mut item.kind {
+        ItemKind::Tag(_, inner) => &mut inner.kind,
+        kind => kind,
+    };
+    if let ItemKind::Array(items) = message {
+        for index in [0, 2].iter().copied() {
+            let embedded = match items.get(index).map(|item| &item.kind) {
+                Some(ItemKind::Bytes(bytes)) if !bytes.is_empty() => parse(bytes).ok(),
+                _ => None,
+            };
+            if let Some(embedded) = embedded {
+                items[index].kind = ItemKind::Embedded(Box::new(embedded));
+            }
+        }
+    }
+    Ok(item)
+}
+
+/// Render a COSE_Sign1 message in diagnostic notation
+///
+/// The protected header and payload are shown as embedded CBOR (`<<...>>`).
+pub fn diag_cose_sign1(data: &[u8]) -> Result<String, CborError> {
+    Ok(parse_cose_sign1(data)?.to_diag())
+}
+
+/// The half-precision encoding of `value`, if it is exact
+fn f64_to_f16(value: f64) -> Option<u16> {
+    if value.is_nan() {
+        return Some(0x7e00);
+    }
+    let single = value as f32;
+    if f64::from(single) != value {
+        return None;
+    }
+    let bits = single.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mant = bits & 0x7f_ffff;
+    let half = if value == 0.0 {
+        sign
+    } else if value.is_infinite() {
+        sign | 0x7c00
+    } else if (1..=30).contains(&exp) {
+        sign | (exp as u16) << 10 | (mant >> 13) as u16
+    } else if (-10..=0).contains(&exp) {
+        // Subnormal, with the implicit leading bit
+        sign | ((mant | 0x80_0000) >> (14 - exp)) as u16
+    } else {
+        return None;
+    };
+    Some(half).filter(|half| f16_to_f64(*half) == value)
+}
+
+/// Write the shortest floating point encoding that keeps the value
+fn write_float(out: &mut Vec<u8>, value: f64) {
+    if let Some(half) = f64_to_f16(value) {
+        out.push(0xf9);
+        out.extend_from_slice(&half.to_be_bytes());
+    } else if f64::from(value as f32) == value {
+        out.push(0xfa);
+        out.extend_from_slice(&(value as f32).to_be_bytes());
+    } else {
+        out.push(0xfb);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+impl Item {
+    /// Encode this item in the deterministic encoding (RFC 8949, section 4.2.1)
+    ///
+    /// Heads use the shortest argument, floats the shortest exact precision, all lengths
+    /// are definite and map keys are sorted by their encoded bytes. Embedded items are
+    /// encoded the same way. Fails for maps with duplicate keys.
+    pub fn to_canonical(&self) -> Result<Vec<u8>, CborError> {
+        let mut out = Vec::new();
+        self.write_canonical(&mut out)?;
+        Ok(out)
+    }
+
+    fn write_canonical(&self, out: &mut Vec<u8>) -> Result<(), CborError> {
+        match &self.kind {
+            ItemKind::Unsigned(n) => write_head(out, 0, *n),
+            ItemKind::Negative(n) => write_head(out, 1, *n),
+            ItemKind::Bytes(bytes) => {
+                write_head(out, 2, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+            }
+            ItemKind::Text(text) => {
+                write_head(out, 3, text.len() as u64);
+                out.extend_from_slice(text.as_bytes());
+            }
+            ItemKind::Array(items) => {
+                write_head(out, 4, items.len() as u64);
+                for item in items {
+                    item.write_canonical(out)?;
+                }
+            }
+            ItemKind::Map(entries) => {
+                let mut encoded = Vec::with_capacity(entries.len());
+                for (key, value) in entries {
+                    encoded.push((key.offset, key.to_canonical()?, value.to_canonical()?));
+                }
+                encoded.sort_by(|a, b| a.1.cmp(&b.1));
+                if let Some(pair) = encoded.windows(2).find(|pair| pair[0].1 == pair[1].1) {
+                    let offset = pair[0].0.max(pair[1].0);
+                    return Err(CborError {
+                        offset,
+                        msg: "duplicate map key",
+                    });
+                }
+                write_head(out, 5, encoded.len() as u64);
+                for (_, key, value) in encoded {
+                    out.extend(key);
+                    out.extend(value);
+                }
+            }
+            ItemKind::Tag(tag, inner) => {
+                write_head(out, 6, *tag);
+                inner.write_canonical(out)?;
+            }
+            ItemKind::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+            ItemKind::Null => out.push(0xf6),
+            ItemKind::Undefined => out.push(0xf7),
+            ItemKind::Simple(n) => write_head(out, 7, u64::from(*n)),
+            ItemKind::Float(f) => write_float(out, *f),
+            ItemKind::Embedded(inner) => {
+                let bytes = inner.to_canonical()?;
+                write_head(out, 2, bytes.len() as u64);
+                out.extend(bytes);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Re-encode a single CBOR item in the deterministic encoding
+///
+/// This normalizes the encoding of e.g. a CWT payload or Sig_structure that was
+/// produced by another encoder, see [`Item::to_canonical`].
+pub fn canonicalize(data: &[u8]) -> Result<Vec<u8>, CborError> {
+    parse(data)?.to_canonical()
+}
+
+/// A rule of the deterministic encoding (RFC 8949, section 4.2.1)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeterministicRule {
+    /// Indefinite-length items must not be used
+    DefiniteLength,
+    /// Map keys must be sorted by their encoded bytes
+    SortedKeys,
+    /// Map keys must be unique
+    UniqueKeys,
+    /// Integers, lengths, tags and floats must use the shortest form
+    ShortestForm,
+}
+
+impl fmt::Display for DeterministicRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DefiniteLength => write!(f, "indefinite-length item"),
+            Self::SortedKeys => write!(f, "map keys are not sorted"),
+            Self::UniqueKeys => write!(f, "duplicate map key"),
+            Self::ShortestForm => write!(f, "argument is not in the shortest form"),
+        }
+    }
+}
+
+/// A violation of the deterministic encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Offset of the offending item (or map key)
+    pub offset: usize,
+    pub rule: DeterministicRule,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.rule, self.offset)
+    }
+}
+
+/// Check that `data` is a single CBOR item in deterministic encoding
+pub fn check_deterministic(data: &[u8]) -> Result<Vec<Violation>, CborError> {
+    let item = parse(data)?;
+    let mut violations = Vec::new();
+    check_item(&item, data, &mut violations);
+    Ok(violations)
+}
+
+/// The additional information of the shortest head for an item
+fn expected_info(kind: &ItemKind) -> Option<u8> {
+    let arg = match kind {
+        ItemKind::Unsigned(n) | ItemKind::Negative(n) | ItemKind::Tag(n, _) => *n,
+        ItemKind::Bytes(bytes) => bytes.len() as u64,
+        ItemKind::Text(text) => text.len() as u64,
+        ItemKind::Array(items) => items.len() as u64,
+        ItemKind::Map(entries) => entries.len() as u64,
+        ItemKind::Float(f) => {
+            let mut out = Vec::new();
+            write_float(&mut out, *f);
+            return Some(out[0] & 0x1f);
+        }
+        _ => return None,
+    };
+    Some(shortest_info(arg))
+}
+
+fn check_item(item: &Item, data: &[u8], violations: &mut Vec<Violation>) {
+    if item.indefinite {
+        violations.push(Violation {
+            offset: item.offset,
+            rule: DeterministicRule::DefiniteLength,
+        });
+    } else if let Some(expected) = expected_info(&item.kind) {
+        if data[item.offset] & 0x1f != expected {
+            violations.push(Violation {
+                offset: item.offset,
+                rule: DeterministicRule::ShortestForm,
+            });
+        }
+    }
+    match &item.kind {
+        ItemKind::Array(items) => items.iter().for_each(|i| check_item(i, data, violations)),
+        ItemKind::Map(entries) => {
+            // The encoded key extends up to the start of its value
+            let mut seen = BTreeSet::new();
+            let mut previous: Option<&[u8]> = None;
+            for (key, value) in entries {
+                let encoded = &data[key.offset..value.offset];
+                let rule = if !seen.insert(encoded) {
+                    Some(DeterministicRule::UniqueKeys)
+                } else if previous.is_some_and(|previous| previous > encoded) {
+                    Some(DeterministicRule::SortedKeys)
+                } else {
+                    None
+                };
+                if let Some(rule) = rule {
+                    violations.push(Violation {
+                        offset: key.offset,
+                        rule,
+                    });
+                }
+                previous = Some(encoded);
+                check_item(key, data, violations);
+                check_item(value, data, violations);
+            }
+        }
+        ItemKind::Tag(_, inner) => check_item(inner, data, violations),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::dcc::CertPayload;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn item(kind: ItemKind) -> Item {
+        Item {
+            offset: 0,
+            indefinite: false,
+            kind,
+        }
+    }
+
+    fn rules(data: &[u8]) -> Vec<DeterministicRule> {
+        let violations = check_deterministic(data).unwrap();
+        violations.into_iter().map(|v| v.rule).collect()
+    }
+
+    // RFC 8949, Appendix A
+    #[test]
+    fn shortest_integers() {
+        let unsigned = [
+            (0, "00"),
+            (1, "01"),
+            (10, "0a"),
+            (23, "17"),
+            (24, "1818"),
+            (25, "1819"),
+            (100, "1864"),
+            (1000, "1903e8"),
+            (1000000, "1a000f4240"),
+            (1000000000000, "1b000000e8d4a51000"),
+            (18446744073709551615, "1bffffffffffffffff"),
+        ];
+        for (n, expected) in unsigned {
+            assert_eq!(
+                item(ItemKind::Unsigned(n)).to_canonical().unwrap(),
+                hex(expected)
+            );
+        }
+        let negative = [(-1, "20"), (-10, "29"), (-100, "3863"), (-1000, "3903e7")];
+        for (n, expected) in negative {
+            let kind = ItemKind::Negative((-1 - n) as u64);
+            assert_eq!(item(kind).to_canonical().unwrap(), hex(expected));
+        }
+    }
+
+    #[test]
+    fn shortest_floats() {
+        let floats = [
+            (0.0, "f90000"),
+            (-0.0, "f98000"),
+            (1.0, "f93c00"),
+            (1.1, "fb3ff199999999999a"),
+            (1.5, "f93e00"),
+            (65504.0, "f97bff"),
+            (100000.0, "fa47c35000"),
+            (3.4028234663852886e+38, "fa7f7fffff"),
+            (1.0e+300, "fb7e37e43c8800759c"),
+            (5.960464477539063e-8, "f90001"),
+            (0.00006103515625, "f90400"),
+            (-4.0, "f9c400"),
+            (-4.1, "fbc010666666666666"),
+            (f64::INFINITY, "f97c00"),
+            (f64::NAN, "f97e00"),
+            (f64::NEG_INFINITY, "f9fc00"),
+        ];
+        for (f, expected) in floats {
+            let encoded = item(ItemKind::Float(f)).to_canonical().unwrap();
+            assert_eq!(encoded, hex(expected), "{}", f);
+        }
+        // The longer encodings of Appendix A are shortened
+        for long in ["fa7f800000", "fb7ff0000000000000"] {
+            assert_eq!(canonicalize(&hex(long)).unwrap(), hex("f97c00"));
+        }
+        for long in ["fa7fc00000", "fb7ff8000000000000"] {
+            assert_eq!(canonicalize(&hex(long)).unwrap(), hex("f97e00"));
+        }
+    }
+
+    #[test]
+    fn sorted_map_keys() {
+        // The key order of RFC 8949, section 4.2.1, encoded in reverse
+        let keys = ["f4", "8120", "811864", "626161", "617a", "20", "1864", "0a"];
+        let mut data = vec![0xa8];
+        for (value, key) in keys.iter().enumerate() {
+            data.extend(hex(key));
+            data.push(value as u8);
+        }
+        assert!(rules(&data).contains(&DeterministicRule::SortedKeys));
+
+        let canonical = canonicalize(&data).unwrap();
+        let mut expected = vec![0xa8];
+        for (value, key) in keys.iter().enumerate().rev() {
+            expected.extend(hex(key));
+            expected.push(value as u8);
+        }
+        assert_eq!(canonical, expected);
+        assert!(rules(&canonical).is_empty());
+
+        assert_eq!(
+            canonicalize(&hex("a26161016162820203")).unwrap(),
+            hex("a26161016162820203")
+        );
+        assert!(canonicalize(&hex("a201020103")).is_err());
+    }
+
+    #[test]
+    fn indefinite_lengths() {
+        let data = hex("9f018202039f0405ffff");
+        assert_eq!(rules(&data), [DeterministicRule::DefiniteLength; 2]);
+        assert_eq!(canonicalize(&data).unwrap(), hex("8301820203820405"));
+        let text = hex("7f657374726561646d696e67ff");
+        assert_eq!(canonicalize(&text).unwrap(), hex("6973747265616d696e67"));
+    }
+
+    #[test]
+    fn non_shortest_heads() {
+        for data in [
+            "1817",
+            "190064",
+            "1a000003e8",
+            "3a00000063",
+            "780161",
+            "f93c00",
+        ] {
+            let expected: &[DeterministicRule] = match data {
+                "f93c00" => &[],
+                _ => &[DeterministicRule::ShortestForm],
+            };
+            assert_eq!(rules(&hex(data)), expected, "{}", data);
+        }
+        assert_eq!(rules(&hex("fa3fc00000")), [DeterministicRule::ShortestForm]);
+        assert_eq!(rules(&hex("c11a514b67b0")), []);
+        assert_eq!(
+            rules(&hex("d8011a514b67b0")),
+            [DeterministicRule::ShortestForm]
+        );
+        assert_eq!(rules(&hex("a201020103")), [DeterministicRule::UniqueKeys]);
+    }
+
+    /// The claims of the Austrian vaccination example of the eHN test data
+    fn ehn_payload() -> Value {
+        let text = |s: &str| Value::Text(s.to_string());
+        let map = |entries: Vec<(Value, Value)>| Value::Map(entries.into_iter().collect());
+        let vaccination = map(vec![
+            (text("tg"), text("840539006")),
+            (text("vp"), text("1119349007")),
+            (text("mp"), text("EU/1/20/1528")),
+            (text("ma"), text("ORG-100030215")),
+            (text("dn"), Value::Integer(1)),
+            (text("sd"), Value::Integer(2)),
+            (text("dt"), text("2021-02-18")),
+            (text("co"), text("AT")),
+            (text("is"), text("Ministry of Health, Austria")),
+            (
+                text("ci"),
+                text("URN:UVCI:01:AT:10807843F94AEE0EE5093FBC254BD813#B"),
+            ),
+        ]);
+        let cert = map(vec![
+            (text("ver"), text("1.2.1")),
+            (
+                text("nam"),
+                map(vec![
+                    (text("fn"), text("Musterfrau-Gößinger")),
+                    (text("gn"), text("Gabriele")),
+                    (text("fnt"), text("MUSTERFRAU<GOESSINGER")),
+                    (text("gnt"), text("GABRIELE")),
+                ]),
+            ),
+            (text("dob"), text("1998-02-26")),
+            (text("v"), Value::Array(vec![vaccination])),
+        ]);
+        let mut hcert = BTreeMap::new();
+        hcert.insert(Value::Integer(1), cert);
+        map(vec![
+            (Value::Integer(1), text("AT")),
+            (Value::Integer(4), Value::Integer(1624458597)),
+            (Value::Integer(6), Value::Integer(1624372197)),
+            (Value::Integer(-260), Value::Map(hcert)),
+        ])
+    }
+
+    #[test]
+    fn ehn_payload_round_trip() {
+        // The claims in the order of the test data, i.e. `exp` and `iat` before `iss`
+        let mut data = vec![0xa4];
+        let claims = match ehn_payload() {
+            Value::Map(claims) => claims,
+            _ => unreachable!(),
+        };
+        for key in [4, 6, 1, -260] {
+            let key = Value::Integer(key);
+            data.extend(serde_cbor::to_vec(&key).unwrap());
+            data.extend(serde_cbor::to_vec(&claims[&key]).unwrap());
+        }
+        assert!(rules(&data).contains(&DeterministicRule::SortedKeys));
+
+        let canonical = canonicalize(&data).unwrap();
+        assert_ne!(canonical, data);
+        assert!(rules(&canonical).is_empty());
+        assert_eq!(canonicalize(&canonical).unwrap(), canonical);
+        let value: Value = serde_cbor::from_slice(&canonical).unwrap();
+        assert_eq!(value, ehn_payload());
+
+        let payload = CertPayload::from_slice(&canonical).unwrap();
+        let cert = &payload.health_claim.cert;
+        assert_eq!(payload.issuer, "AT");
+        assert_eq!(cert.name.first_name_transliterated, "MUSTERFRAU<GOESSINGER");
+        assert_eq!(cert.vaccine[0].dose_number, 1);
+    }
+}
@@ -1,12 +1,49 @@
+use std::{fmt, path::Path};
+
 use color_eyre::eyre::eyre;
 use serde::Deserialize;
+use tracing::{debug, warn};
 use x509_parser::{
+    certificate::X509Certificate,
     der_parser::{self, oid},
     oid_registry::OidRegistry,
-    x509::SubjectPublicKeyInfo,
+    parse_x509_certificate,
+    pem::Pem,
+    revocation_list::CertificateRevocationList,
+    x509::{SubjectPublicKeyInfo, X509Name},
 };
 
-use crate::json::Loadable;
+use crate::{dcc::EntryKind, json::Loadable, oid_registry};
+
+/// Debug formatting for an X.509 name using the short names from the OID registry
+pub struct CertSubject<'a>(pub &'a X509Name<'a>);
+
+impl fmt::Debug for CertSubject<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let oid_registry = oid_registry();
+        let mut debug = f.debug_struct("X509Name");
+
+        for key in self.0.iter_attributes() {
+            match (oid_registry.get(&key.attr_type), key.attr_value.as_str()) {
+                (Some(name), Ok(value)) => {
+                    debug.field(name.sn(), &value);
+                }
+                (Some(name), Err(_)) => {
+                    debug.field(name.sn(), &"???");
+                }
+                (None, Ok(value)) => {
+                    let name = format!("oid_{}", key.attr_type);
+                    debug.field(&name, &value);
+                }
+                (None, Err(_)) => {
+                    let name = format!("oid_{}", key.attr_type);
+                    debug.field(&name, &"???");
+                }
+            }
+        }
+        debug.finish()
+    }
+}
 
 #[derive(Debug, Copy, Clone, Deserialize, PartialEq, Eq)]
 pub enum CertType {
@@ -27,6 +64,19 @@ pub struct Certificate {
     pub timestamp: String,
 }
 
+impl Certificate {
+    /// Compute the kid from `raw_data`
+    pub fn computed_kid(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        base64::decode(&self.raw_data).map(|der| compute_kid(&der))
+    }
+
+    /// Compute the thumbprint (hex encoded SHA-256) from `raw_data`
+    pub fn computed_thumbprint(&self) -> Result<String, base64::DecodeError> {
+        let der = base64::decode(&self.raw_data)?;
+        Ok(crate::crypto::sha256_hex(&der))
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct TrustList {
     pub certificates: Vec<Certificate>,
@@ -34,28 +84,306 @@ pub struct TrustList {
 
 impl Loadable for TrustList {}
 
+impl TrustList {
+    /// Warn about all entries where the `kid` does not match the one computed from the certificate
+    pub fn check_kids(&self) {
+        for cert in &self.certificates {
+            match cert.computed_kid() {
+                Ok(kid) => {
+                    let computed = base64::encode(&kid);
+                    if computed != cert.kid {
+                        warn!(
+                            "kid '{}' ({}) does not match the certificate (computed '{}')",
+                            cert.kid, cert.country, computed
+                        );
+                    }
+                }
+                Err(e) => warn!("Invalid certificate data for kid '{}': {}", cert.kid, e),
+            }
+        }
+    }
+
+    /// Check the `thumbprint` of all entries against their certificate
+    ///
+    /// Entries that do not match are removed, unless `warn_only` is set.
+    pub fn check_thumbprints(&mut self, warn_only: bool) {
+        self.certificates.retain(|cert| {
+            let computed = match cert.computed_thumbprint() {
+                Ok(computed) => computed,
+                Err(e) => {
+                    warn!("Invalid certificate data for kid '{}': {}", cert.kid, e);
+                    return warn_only;
+                }
+            };
+            if computed.eq_ignore_ascii_case(&cert.thumbprint) {
+                true
+            } else if warn_only {
+                warn!(
+                    "Thumbprint of DSC '{}' ({}) does not match the certificate",
+                    cert.kid, cert.country
+                );
+                true
+            } else {
+                warn!(
+                    "Dropping DSC '{}' ({}): thumbprint does not match the certificate",
+                    cert.kid, cert.country
+                );
+                false
+            }
+        });
+    }
+
+    /// Remove all DSCs that do not chain to a CSCA in `store`
+    pub fn retain_chained(&mut self, store: &CscaStore) {
+        self.certificates.retain(|cert| {
+            let res = base64::decode(&cert.raw_data)
+                .map_err(color_eyre::Report::from)
+                .and_then(|der| store.verify_der(&der));
+            match res {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Dropping DSC '{}' ({}): {}", cert.kid, cert.country, e);
+                    false
+                }
+            }
+        });
+    }
+}
+
+/// Read the DER encoded certificates from a PEM file (with one or more certificates) or a DER file
+pub fn read_certificates<P: AsRef<Path>>(path: P) -> color_eyre::Result<Vec<Vec<u8>>> {
+    let bytes = std::fs::read(path)?;
+    let mut certificates = Vec::new();
+    if bytes.starts_with(b"-----BEGIN") {
+        for pem in Pem::iter_from_buffer(&bytes) {
+            let pem = pem?;
+            if pem.label == "CERTIFICATE" {
+                certificates.push(pem.contents);
+            }
+        }
+    } else {
+        certificates.push(bytes);
+    }
+    for der in &certificates {
+        parse_x509_certificate(der)?;
+    }
+    Ok(certificates)
+}
+
+/// Read a public key (`SubjectPublicKeyInfo`) from a PEM or DER file
+pub fn read_public_key<P: AsRef<Path>>(path: P) -> color_eyre::Result<Vec<u8>> {
+    let bytes = std::fs::read(path)?;
+    if bytes.starts_with(b"-----BEGIN") {
+        // The PEM parser only keeps the first word of the label, so look at the contents instead
+        for pem in Pem::iter_from_buffer(&bytes) {
+            let pem = pem?;
+            if SubjectPublicKeyInfo::from_der(&pem.contents).is_ok() {
+                return Ok(pem.contents);
+            }
+        }
+        Err(eyre!("Expected a 'PUBLIC KEY' PEM block"))
+    } else {
+        SubjectPublicKeyInfo::from_der(&bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Read a PKCS#8 private key from a PEM or DER file
+pub fn read_private_key<P: AsRef<Path>>(path: P) -> color_eyre::Result<Vec<u8>> {
+    let bytes = std::fs::read(path)?;
+    if bytes.starts_with(b"-----BEGIN") {
+        // "PRIVATE KEY" is PKCS#8, as opposed to e.g. "EC PRIVATE KEY"
+        for pem in Pem::iter_from_buffer(&bytes) {
+            let pem = pem?;
+            if pem.label == "PRIVATE" {
+                return Ok(pem.contents);
+            }
+        }
+        Err(eyre!(
+            "Expected a PKCS#8 'PRIVATE KEY' PEM block (see `openssl pkcs8 -topk8 -nocrypt`)"
+        ))
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Compute the key identifier of a DER encoded certificate
+///
+/// This is the first 8 bytes of the SHA-256 hash of the certificate.
+pub fn compute_kid(der: &[u8]) -> Vec<u8> {
+    crate::crypto::sha256(der)[..8].to_vec()
+}
+
+/// The DER encoded `SubjectPublicKeyInfo` of a DER encoded certificate
+pub fn spki_der(der: &[u8]) -> Option<&[u8]> {
+    use asn1_der::typed::{DerDecodable, Sequence};
+
+    let cert = Sequence::decode(der).ok()?;
+    let tbs: Sequence = cert.get_as(0).ok()?;
+    // The version ([0]) is optional, then serial, signature, issuer, validity and subject
+    let first = usize::from(tbs.get(0).ok()?.tag() == 0xa0);
+    Some(tbs.get(first + 5).ok()?.raw())
+}
+
+/// A store of trusted country signing CA (CSCA) certificates
+#[derive(Debug, Clone, Default)]
+pub struct CscaStore {
+    certificates: Vec<Vec<u8>>,
+}
+
+impl CscaStore {
+    /// Load CSCA certificates from a PEM file (with one or more certificates) or a DER file
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> color_eyre::Result<Self> {
+        let certificates = read_certificates(path)?;
+        debug!("Loaded {} CSCA certificate(s)", certificates.len());
+        Ok(Self { certificates })
+    }
+
+    /// Check that the DER encoded `dsc` was issued and signed by one of the CSCAs in this store
+    pub fn verify_der(&self, dsc: &[u8]) -> color_eyre::Result<()> {
+        let (_, dsc) = parse_x509_certificate(dsc)?;
+        self.verify_dsc(&dsc)
+    }
+
+    /// Check that `dsc` was issued and signed by one of the CSCAs in this store
+    pub fn verify_dsc(&self, dsc: &X509Certificate) -> color_eyre::Result<()> {
+        let issuer = dsc.issuer();
+        let mut error = eyre!("No CSCA found for issuer '{}'", issuer);
+        for der in &self.certificates {
+            let (_, csca) = parse_x509_certificate(der)?;
+            if csca.subject().as_raw() != issuer.as_raw() {
+                continue;
+            }
+            if !csca.tbs_certificate.is_ca() {
+                error = eyre!("CSCA '{}' is not a CA (basic constraints)", issuer);
+                continue;
+            }
+            if let Some((_, key_usage)) = csca.tbs_certificate.key_usage() {
+                if !key_usage.key_cert_sign() {
+                    error = eyre!("CSCA '{}' may not sign certificates (key usage)", issuer);
+                    continue;
+                }
+            }
+            match crate::crypto::verify_certificate(dsc, &csca.tbs_certificate.subject_pki) {
+                Ok(()) => return Ok(()),
+                Err(e) => error = eyre!("Invalid signature by CSCA '{}': {}", issuer, e),
+            }
+        }
+        Err(error)
+    }
+
+    /// Check that `crl` was signed by the CSCA that issued it
+    pub fn verify_crl(&self, crl: &CertificateRevocationList) -> color_eyre::Result<()> {
+        let issuer = crl.issuer();
+        let mut error = eyre!("No CSCA found for the CRL issuer '{}'", issuer);
+        for der in &self.certificates {
+            let (_, csca) = parse_x509_certificate(der)?;
+            if csca.subject().as_raw() != issuer.as_raw() {
+                continue;
+            }
+            if let Some((_, key_usage)) = csca.tbs_certificate.key_usage() {
+                if !key_usage.crl_sign() {
+                    error = eyre!("CSCA '{}' may not sign CRLs (key usage)", issuer);
+                    continue;
+                }
+            }
+            match crate::crypto::verify_crl(crl, &csca.tbs_certificate.subject_pki) {
+                Ok(()) => return Ok(()),
+                Err(e) => error = eyre!("Invalid CRL signature by CSCA '{}': {}", issuer, e),
+            }
+        }
+        Err(error)
+    }
+}
+
+/// A named elliptic curve of an `id-ecPublicKey`
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Prime {
+    /// NIST P-256 (`secp256r1`)
     Prime256v1,
+    /// NIST P-384
+    Secp384r1,
+    /// NIST P-521
+    Secp521r1,
+    BrainpoolP256r1,
+    BrainpoolP384r1,
+}
+
+impl Prime {
+    /// The curve with the OID `oid`, if it is known
+    fn from_oid(oid: &der_parser::oid::Oid) -> Option<Self> {
+        let known = [
+            (oid!(1.2.840 .10045 .3 .1 .7), Self::Prime256v1),
+            (oid!(1.3.132 .0 .34), Self::Secp384r1),
+            (oid!(1.3.132 .0 .35), Self::Secp521r1),
+            (oid!(1.3.36 .3 .3 .2 .8 .1 .1 .7), Self::BrainpoolP256r1),
+            (oid!(1.3.36 .3 .3 .2 .8 .1 .1 .11), Self::BrainpoolP384r1),
+        ];
+        known
+            .iter()
+            .find(|(known, _)| known == oid)
+            .map(|&(_, prime)| prime)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Prime256v1 => "P-256",
+            Self::Secp384r1 => "P-384",
+            Self::Secp521r1 => "P-521",
+            Self::BrainpoolP256r1 => "brainpoolP256r1",
+            Self::BrainpoolP384r1 => "brainpoolP384r1",
+        }
+    }
+
+    /// The length of `r` and `s` in a fixed size `r || s` signature (in bytes)
+    pub fn scalar_len(self) -> usize {
+        match self {
+            Self::Prime256v1 | Self::BrainpoolP256r1 => 32,
+            Self::Secp384r1 | Self::BrainpoolP384r1 => 48,
+            Self::Secp521r1 => 66,
+        }
+    }
+
+    /// Whether signatures with a key on this curve can be verified
+    pub fn is_supported(self) -> bool {
+        matches!(self, Self::Prime256v1 | Self::Secp384r1)
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Algorithm {
     IdEcPublicKey(Prime),
+    RsaEncryption,
+}
+
+impl Algorithm {
+    /// Whether signatures with a key of this type can be verified
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::IdEcPublicKey(prime) => prime.is_supported(),
+            Self::RsaEncryption => true,
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IdEcPublicKey(prime) => write!(f, "EC {}", prime.name()),
+            Self::RsaEncryption => write!(f, "RSA"),
+        }
+    }
 }
 
+/// Get the type of the key in `sigpki`
+///
+/// Keys on a known curve are returned even if signatures with them can't be verified
+/// (see [`Algorithm::is_supported`]), so that they can at least be listed.
 pub fn get_pk_sig_algorithm(sigpki: &SubjectPublicKeyInfo) -> color_eyre::Result<Algorithm> {
-    //println!("Signature Certificate Public Key");
-    let mut registry = OidRegistry::default().with_crypto().with_x509();
-    registry.insert(
-        oid!(1.2.840 .10045 .3 .1 .7),
-        ("prime256v1", "256-bit Elliptic Curve Cryptography (ECC)"),
-    );
+    let registry = OidRegistry::default().with_crypto().with_x509();
 
     let e = registry.get(&sigpki.algorithm.algorithm);
     if let Some(entry) = e {
-        //println!("alg-sn: {}", entry.sn());
-        //println!("alg-description: {}", entry.description());
         if entry.sn() == "id-ecPublicKey" {
             let prime_ber = sigpki
                 .algorithm
@@ -63,28 +391,55 @@ pub fn get_pk_sig_algorithm(sigpki: &SubjectPublicKeyInfo) -> color_eyre::Result
                 .as_ref()
                 .ok_or_else(|| eyre!("Expected prime parameter for 'id-ecPublicKey'"))?;
             let oid = prime_ber.as_oid()?;
-
-            //println!("prime: {}", oid);
-            let prime = if let Some(prime) = registry.get(oid) {
-                //println!("prime-sn: {}", prime.sn());
-                //println!("prime-description: {}", prime.description());
-                if prime.sn() == "prime256v1" {
-                    Ok(Prime::Prime256v1)
-                } else {
-                    Err(eyre!(
-                        "Unsupported prime parameter '{}' ({}) for 'id-ecPublicKey'",
-                        prime.sn(),
-                        oid
-                    ))
-                }
-            } else {
-                Err(eyre!(
-                    "Unknown prime parameter '{}' for 'id-ecPublicKey'",
-                    oid
-                ))
-            }?;
+            let prime = Prime::from_oid(oid)
+                .ok_or_else(|| eyre!("Unknown prime parameter '{}' for 'id-ecPublicKey'", oid))?;
             return Ok(Algorithm::IdEcPublicKey(prime));
         }
+        if entry.sn() == "rsaEncryption" {
+            return Ok(Algorithm::RsaEncryption);
+        }
     }
     Err(eyre!("Unknown algorithm"))
 }
+
+/// Get the kinds of DCC entries a DSC is allowed to sign
+///
+/// DSCs may be restricted via extended key usage OIDs `1.3.6.1.4.1.1847.2021.1.x`
+/// (or `1.3.6.1.4.1.0.1847.2021.1.x`), where `x` is 1 for tests, 2 for vaccinations and
+/// 3 for recoveries. Returns `None` if the certificate has none of these, i.e.
+/// is not restricted.
+pub fn get_allowed_entry_kinds(cert: &X509Certificate) -> Option<Vec<EntryKind>> {
+    let known = [
+        (oid!(1.3.6 .1 .4 .1 .1847 .2021 .1 .1), EntryKind::Test),
+        (
+            oid!(1.3.6 .1 .4 .1 .1847 .2021 .1 .2),
+            EntryKind::Vaccination,
+        ),
+        (oid!(1.3.6 .1 .4 .1 .1847 .2021 .1 .3), EntryKind::Recovery),
+        (oid!(1.3.6 .1 .4 .1 .0 .1847 .2021 .1 .1), EntryKind::Test),
+        (
+            oid!(1.3.6 .1 .4 .1 .0 .1847 .2021 .1 .2),
+            EntryKind::Vaccination,
+        ),
+        (
+            oid!(1.3.6 .1 .4 .1 .0 .1847 .2021 .1 .3),
+            EntryKind::Recovery,
+        ),
+    ];
+
+    let (_critical, eku) = cert.tbs_certificate.extended_key_usage()?;
+    let mut kinds = Vec::new();
+    for oid in &eku.other {
+        if let Some((_, kind)) = known.iter().find(|(k, _)| k == oid) {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+    }
+
+    if kinds.is_empty() {
+        None
+    } else {
+        Some(kinds)
+    }
+}
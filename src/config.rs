@@ -0,0 +1,160 @@
+//! Defaults for the command line options from a TOML file
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+use crate::{lang::Lang, profile::Profile, tls::TlsPins, trust::TrustListFormat};
+
+/// The prefix of the environment variables with settings, e.g. `DCC_DECODE_TRUSTLIST`
+pub const ENV_PREFIX: &str = "DCC_DECODE_";
+
+/// The contents of `config.toml`
+///
+/// All settings are optional; options given on the command line take precedence.
+/// Relative paths are resolved against the directory of the file.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Path of the trust list
+    pub trustlist: Option<PathBuf>,
+    pub trustlist_format: Option<TrustListFormat>,
+    /// Only trust DSCs that chain to a CSCA from this file
+    pub csca: Option<PathBuf>,
+    /// Blocked kids and certificate identifiers
+    pub blocklist: Option<PathBuf>,
+    /// The CRLs of the CSCAs, as paths or URLs
+    pub crl: Option<Vec<String>>,
+    /// Directory of the eHN value sets
+    pub valuesets: Option<PathBuf>,
+    pub lang: Option<Lang>,
+    /// The verification profile (strict, standard, lenient)
+    pub profile: Option<Profile>,
+    /// Reject CWT payloads that are not in deterministic CBOR encoding
+    pub strict: Option<bool>,
+    /// Mask the personal data of the holder in the output
+    pub redact: Option<bool>,
+    /// Where `trustlist update` downloads the trust list from
+    pub trustlist_url: Option<String>,
+    /// Where `rules update` downloads the business rules from
+    pub rules_url: Option<String>,
+    /// The pinned certificates of the download servers, by host name
+    pub tls_pins: Option<TlsPins>,
+    /// The client certificate for downloads from gateways that require mutual TLS
+    pub client_cert: Option<PathBuf>,
+    /// The PKCS#8 private key of the client certificate
+    pub client_key: Option<PathBuf>,
+}
+
+impl Config {
+    /// The default location, e.g. `~/.config/dcc-decode/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        crate::dirs::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// The settings from the `DCC_DECODE_*` environment variables
+    ///
+    /// The variables are named like the keys of the file, e.g. `DCC_DECODE_TRUSTLIST_URL`
+    /// for `trustlist-url`. Empty variables are treated as unset.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let path = |name| env_var(name).map(PathBuf::from);
+        Ok(Config {
+            trustlist: path("TRUSTLIST"),
+            trustlist_format: parse_env("TRUSTLIST_FORMAT")?,
+            csca: path("CSCA"),
+            blocklist: path("BLOCKLIST"),
+            crl: env_var("CRL").map(|crl| vec![crl]),
+            valuesets: path("VALUESETS"),
+            lang: parse_env("LANG")?,
+            profile: parse_env("PROFILE")?,
+            strict: parse_env_bool("STRICT")?,
+            redact: parse_env_bool("REDACT")?,
+            trustlist_url: env_var("TRUSTLIST_URL"),
+            rules_url: env_var("RULES_URL"),
+            // Only in the file, as a table
+            tls_pins: None,
+            client_cert: path("CLIENT_CERT"),
+            client_key: path("CLIENT_KEY"),
+        })
+    }
+
+    /// Use the settings from `other` where this config has none
+    pub fn or(self, other: Config) -> Config {
+        Config {
+            trustlist: self.trustlist.or(other.trustlist),
+            trustlist_format: self.trustlist_format.or(other.trustlist_format),
+            csca: self.csca.or(other.csca),
+            blocklist: self.blocklist.or(other.blocklist),
+            crl: self.crl.or(other.crl),
+            valuesets: self.valuesets.or(other.valuesets),
+            lang: self.lang.or(other.lang),
+            profile: self.profile.or(other.profile),
+            strict: self.strict.or(other.strict),
+            redact: self.redact.or(other.redact),
+            trustlist_url: self.trustlist_url.or(other.trustlist_url),
+            rules_url: self.rules_url.or(other.rules_url),
+            tls_pins: self.tls_pins.or(other.tls_pins),
+            client_cert: self.client_cert.or(other.client_cert),
+            client_key: self.client_key.or(other.client_key),
+        }
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let mut config: Self = toml::from_str(&text)?;
+        if let Some(base) = path.parent() {
+            let mut paths = [
+                &mut config.trustlist,
+                &mut config.csca,
+                &mut config.blocklist,
+                &mut config.valuesets,
+                &mut config.client_cert,
+                &mut config.client_key,
+            ];
+            for path in paths.iter_mut().filter_map(|path| path.as_mut()) {
+                *path = base.join(&*path);
+            }
+            let crls = config.crl.iter_mut().flatten();
+            for crl in crls.filter(|crl| !crl.contains("://")) {
+                *crl = base.join(&*crl).display().to_string();
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// The value of the environment variable `DCC_DECODE_<name>`, if it is set and not empty
+pub fn env_var(name: &str) -> Option<String> {
+    std::env::var(format!("{}{}", ENV_PREFIX, name))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_env<T>(name: &str) -> Result<Option<T>, Box<dyn Error>>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    env_var(name)
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|e| format!("{}{}: {}", ENV_PREFIX, name, e).into())
+}
+
+fn parse_env_bool(name: &str) -> Result<Option<bool>, Box<dyn Error>> {
+    match env_var(name).as_deref() {
+        None => Ok(None),
+        Some("1") | Some("true") | Some("yes") => Ok(Some(true)),
+        Some("0") | Some("false") | Some("no") => Ok(Some(false)),
+        Some(value) => Err(format!(
+            "{}{}: expected true or false, got '{}'",
+            ENV_PREFIX, name, value
+        )
+        .into()),
+    }
+}
@@ -0,0 +1,286 @@
+use serde_cose::Sign1;
+#[cfg(feature = "verify")]
+use tracing::info;
+
+#[cfg(feature = "verify")]
+use crate::dcc::sign1_kid;
+#[cfg(feature = "verify")]
+use crate::{
+    cert::CscaStore,
+    crl::CrlStore,
+    shc::{self, Jws},
+    trust::{Blocklist, SignerCert, SignerInfo, TrustSource},
+    vds::{self, Seal},
+    verify::{self, Verification, VerifyOptions},
+};
+use crate::{
+    core::cose,
+    dcc::{valuesets::EhnData, CertPayload, DecodeError, DecodeOutcome},
+};
+
+/// Everything needed to decode and verify certificates
+///
+/// This replaces process-wide state, so that e.g. a server can use different
+/// value sets or trust sources per request.
+#[derive(Default)]
+pub struct DecodeContext {
+    /// The value sets used to resolve display names
+    pub valuesets: EhnData,
+    /// The source of trusted DSCs, if any
+    #[cfg(feature = "verify")]
+    pub trust: Option<Box<dyn TrustSource + Send + Sync>>,
+    #[cfg(feature = "verify")]
+    pub options: VerifyOptions,
+    /// The keys of SMART Health Card issuers, if any
+    #[cfg(feature = "verify")]
+    pub shc_trust: Option<Box<dyn TrustSource + Send + Sync>>,
+    /// The CSCAs that issue VDS-NC barcode signer certificates, if any
+    #[cfg(feature = "verify")]
+    pub vds_csca: Option<CscaStore>,
+    /// Blocked kids and certificate identifiers
+    #[cfg(feature = "verify")]
+    pub blocklist: Blocklist,
+    /// The DER encoded certificates to check COSE countersignatures against
+    #[cfg(feature = "verify")]
+    pub countersign_trust: Vec<Vec<u8>>,
+    /// The CRLs of the CSCAs, to check the DSCs against
+    #[cfg(feature = "verify")]
+    pub revocations: CrlStore,
+}
+
+impl DecodeContext {
+    #[cfg_attr(not(feature = "verify"), allow(clippy::needless_update))]
+    pub fn new(valuesets: EhnData) -> Self {
+        Self {
+            valuesets,
+            ..Self::default()
+        }
+    }
+
+    /// Swap in new value sets (e.g. after an update), returning the old ones
+    ///
+    /// Certificates that were already decoded keep their resolved values.
+    pub fn replace_valuesets(&mut self, valuesets: EhnData) -> EhnData {
+        std::mem::replace(&mut self.valuesets, valuesets)
+    }
+
+    /// Decode the payload of `sign1`, resolving all value set entries
+    pub fn decode_payload(&self, sign1: &Sign1) -> Result<CertPayload, DecodeError> {
+        self.decode_claims(&sign1.payload)
+    }
+
+    /// Decode CBOR encoded CWT claims, resolving all value set entries
+    pub fn decode_claims(&self, bytes: &[u8]) -> Result<CertPayload, DecodeError> {
+        let mut payload = CertPayload::from_slice(bytes)?;
+        payload.resolve(&self.valuesets);
+        Ok(payload)
+    }
+
+    /// Decode the payload of the COSE message `cose` like [`Self::decode_payload`],
+    /// collecting the non-fatal issues of the payload and the headers
+    pub fn decode_sign1(&self, cose: &cose::Sign1) -> Result<DecodeOutcome, DecodeError> {
+        let mut outcome = self.decode_outcome(&cose.payload)?;
+        outcome.check_headers(cose);
+        Ok(outcome)
+    }
+
+    /// Decode CBOR encoded CWT claims like [`Self::decode_claims`], collecting the
+    /// non-fatal issues
+    pub fn decode_outcome(&self, bytes: &[u8]) -> Result<DecodeOutcome, DecodeError> {
+        let payload = self.decode_claims(bytes)?;
+        Ok(DecodeOutcome::new(payload, bytes, &self.valuesets))
+    }
+
+    /// Verify the signature of `sign1` against the trust source
+    ///
+    /// Returns [`Verification::KeyNotFound`] if there is no trust source.
+    #[cfg(feature = "verify")]
+    pub fn verify(&self, sign1: &Sign1, payload: &CertPayload) -> color_eyre::Result<Verification> {
+        let (verification, _) = self.verify_signer(sign1, payload)?;
+        Ok(verification)
+    }
+
+    /// Verify the signature of `sign1` like [`Self::verify`], also summarizing the DSC
+    /// that was checked
+    ///
+    /// Without a kid in `sign1`, this is the only way to learn which DSC of the issuing
+    /// country verified the signature.
+    #[cfg(feature = "verify")]
+    pub fn verify_signer(
+        &self,
+        sign1: &Sign1,
+        payload: &CertPayload,
+    ) -> color_eyre::Result<(Verification, Option<SignerInfo>)> {
+        let (verification, signer) = match &self.trust {
+            Some(trust) => verify::find_signer(sign1, payload, trust.as_ref(), &self.options)?,
+            None => (Verification::KeyNotFound, None),
+        };
+        let verification = match signer.as_ref() {
+            Some(signer) => self.check_revocation(signer, verification),
+            None => verification,
+        };
+        let verification = self.check_blocklist(sign1, payload, signer.as_ref(), verification);
+        Ok((verification, signer.as_ref().map(SignerCert::info)))
+    }
+
+    /// Replace a successful verification with [`Verification::Revoked`] if the DSC is
+    /// revoked by one of the CRLs
+    #[cfg(feature = "verify")]
+    pub fn check_revocation(
+        &self,
+        signer: &SignerCert,
+        verification: Verification,
+    ) -> Verification {
+        if !verification.is_verified() {
+            return verification;
+        }
+        let revocation = signer
+            .certificate()
+            .and_then(|der| self.revocations.check(der));
+        match revocation {
+            Some(revocation) => Verification::Revoked(revocation.to_string()),
+            None => verification,
+        }
+    }
+
+    /// Replace the outcome of a verification with [`Verification::Blocked`] if the kid or
+    /// the certificate identifier of the certificate is on the blocklist
+    ///
+    /// Without a kid in `sign1`, the kid of the DSC that was checked (`signer`) is used.
+    #[cfg(feature = "verify")]
+    pub fn check_blocklist(
+        &self,
+        sign1: &Sign1,
+        payload: &CertPayload,
+        signer: Option<&SignerCert>,
+        verification: Verification,
+    ) -> Verification {
+        let kid = sign1_kid(sign1)
+            .or_else(|| signer.map(|signer| signer.kid.as_slice()))
+            .unwrap_or_default();
+        match self.blocklist.check(kid, payload) {
+            Some(reason) => {
+                info!("Blocked certificate ({})", verification);
+                Verification::Blocked(reason)
+            }
+            None => verification,
+        }
+    }
+
+    /// Verify the signature of a SMART Health Card against the issuer keys
+    ///
+    /// Returns [`Verification::KeyNotFound`] if there are no issuer keys.
+    #[cfg(feature = "verify")]
+    pub fn verify_shc(&self, jws: &Jws) -> color_eyre::Result<Verification> {
+        match &self.shc_trust {
+            Some(trust) => shc::verify(jws, trust.as_ref()),
+            None => Ok(Verification::KeyNotFound),
+        }
+    }
+
+    /// Verify the signature of a VDS-NC and check its signer against the CSCAs
+    #[cfg(feature = "verify")]
+    pub fn verify_vds(&self, seal: &Seal) -> color_eyre::Result<Verification> {
+        vds::verify(seal, self.vds_csca.as_ref())
+    }
+
+    /// Summarize the first DSC with the given kid from the trust source
+    #[cfg(feature = "verify")]
+    pub fn signer(&self, kid: &[u8]) -> Option<SignerInfo> {
+        let trust = self.trust.as_ref()?;
+        trust.lookup(kid).first().map(|signer| signer.info())
+    }
+}
+
+#[cfg(all(test, feature = "ring"))]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{Duration, Utc};
+    use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_cbor::Value;
+
+    use super::DecodeContext;
+    use crate::{
+        cose::{ALG_ES256, HEADER_ALG},
+        dcc::parse_sign1,
+        keygen::TestSigner,
+        trust::{MemoryTrust, SignerCert},
+        verify::Verification,
+    };
+
+    /// A German DCC without entries, signed by `signer` without a kid in the headers
+    fn sign_without_kid(signer: &TestSigner) -> Vec<u8> {
+        let int = |n: i64| Value::Integer(n.into());
+        let text = |s: &str| Value::Text(s.to_string());
+        let map = |entries: Vec<(Value, Value)>| Value::Map(entries.into_iter().collect());
+
+        let now = Utc::now();
+        let cert = map(vec![
+            (text("ver"), text("1.3.0")),
+            (text("dob"), text("1964-08-12")),
+            (
+                text("nam"),
+                map(vec![
+                    (text("fn"), text("Mustermann")),
+                    (text("gn"), text("Erika")),
+                    (text("fnt"), text("MUSTERMANN")),
+                    (text("gnt"), text("ERIKA")),
+                ]),
+            ),
+        ]);
+        let claims = map(vec![
+            (int(1), text("DE")),
+            (int(4), int((now + Duration::days(1)).timestamp())),
+            (int(6), int(now.timestamp())),
+            (int(-260), map(vec![(int(1), cert)])),
+        ]);
+        let payload = serde_cbor::to_vec(&claims).unwrap();
+        let mut protected = BTreeMap::new();
+        protected.insert(Value::Integer(HEADER_ALG), Value::Integer(ALG_ES256));
+        let protected = serde_cbor::to_vec(&Value::Map(protected)).unwrap();
+
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &signer.pkcs8).unwrap();
+        let rng = ring::rand::SystemRandom::new();
+        let sig_structure = crate::core::cose::sig_structure(&protected, &payload);
+        let signature = key_pair.sign(&rng, &sig_structure).unwrap();
+        let message = Value::Tag(
+            18,
+            Box::new(Value::Array(vec![
+                Value::Bytes(protected),
+                Value::Map(BTreeMap::new()),
+                Value::Bytes(payload),
+                Value::Bytes(signature.as_ref().to_vec()),
+            ])),
+        );
+        serde_cbor::to_vec(&message).unwrap()
+    }
+
+    #[test]
+    fn blocked_signer_without_kid() {
+        let signer = TestSigner::generate("DE", "Leaked DSC", 30).unwrap();
+        let dsc = SignerCert::from_der(signer.certificate.clone());
+        let kid = dsc.kid.clone();
+        let other = TestSigner::generate("DE", "Other DSC", 30).unwrap();
+        let trust: MemoryTrust = vec![SignerCert::from_der(other.certificate), dsc]
+            .into_iter()
+            .collect();
+
+        let sign1 = parse_sign1(&sign_without_kid(&signer)).unwrap();
+        let mut ctx = DecodeContext {
+            trust: Some(Box::new(trust)),
+            ..DecodeContext::default()
+        };
+        let payload = ctx.decode_payload(&sign1).unwrap();
+
+        let (verification, info) = ctx.verify_signer(&sign1, &payload).unwrap();
+        assert_eq!(verification, Verification::Verified);
+        assert_eq!(info.unwrap().kid, base64::encode(&kid));
+
+        // The leaked key is blocked, even though the message does not name its kid
+        ctx.blocklist.insert_kid(kid);
+        let (verification, _) = ctx.verify_signer(&sign1, &payload).unwrap();
+        assert!(matches!(verification, Verification::Blocked(_)));
+    }
+}
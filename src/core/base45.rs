@@ -0,0 +1,207 @@
+//! Base45 decoding (RFC 9285)
+use alloc::vec::Vec;
+use core::{convert::TryFrom, fmt};
+
+#[derive(Debug)]
+pub enum Base45Error {
+    /// A single character is left over at the end of the input
+    InvalidLength,
+    InvalidChar(u8),
+    InvalidTriple(u32),
+}
+
+impl fmt::Display for Base45Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength => write!(f, "Invalid length"),
+            Self::InvalidChar(c) => write!(f, "Invalid character '{}'", c.escape_ascii()),
+            Self::InvalidTriple(t) => write!(f, "Invalid sum: {}", t),
+        }
+    }
+}
+
+/// A [`Base45Error`] at a character offset of the input of [`base45_decode`]
+#[derive(Debug)]
+pub struct Base45DecodeError {
+    /// The offset of the invalid character, or of the first character of the invalid group
+    pub offset: usize,
+    pub error: Base45Error,
+}
+
+impl fmt::Display for Base45DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.error, self.offset)
+    }
+}
+
+impl Base45DecodeError {
+    /// Locate an error in the group of characters `chunk` at `offset`
+    fn new(error: Base45Error, offset: usize, chunk: &[u8]) -> Self {
+        let offset = match error {
+            Base45Error::InvalidChar(c) => offset + chunk.iter().position(|&x| x == c).unwrap_or(0),
+            _ => offset,
+        };
+        Self { offset, error }
+    }
+}
+
+/// The characters of the base45 alphabet, in the order of their values
+const ALPHABET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Marks the bytes that are not in [`ALPHABET`] in [`VALUES`]
+const INVALID: u8 = 0xFF;
+
+/// The value of each byte in the base45 alphabet, or [`INVALID`]
+const VALUES: [u8; 256] = {
+    let mut values = [INVALID; 256];
+    let mut i = 0;
+    while i < ALPHABET.len() {
+        values[ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    values
+};
+
+#[inline]
+pub fn base45_cval(input: u8) -> Result<u32, Base45Error> {
+    match VALUES[usize::from(input)] {
+        INVALID => Err(Base45Error::InvalidChar(input)),
+        value => Ok(u32::from(value)),
+    }
+}
+
+#[inline]
+pub fn base45_cdec([c, d, e]: [u8; 3]) -> Result<[u8; 2], Base45Error> {
+    let c = base45_cval(c)?;
+    let d = base45_cval(d)?;
+    let e = base45_cval(e)?;
+
+    let sum = c + 45 * d + 45 * 45 * e;
+    let r = u16::try_from(sum).map_err(|_| Base45Error::InvalidTriple(sum))?;
+    Ok(r.to_be_bytes())
+}
+
+/// Decode the last two characters of an input into a single byte
+#[inline]
+pub fn base45_pdec([c, d]: [u8; 2]) -> Result<u8, Base45Error> {
+    let sum = base45_cval(c)? + 45 * base45_cval(d)?;
+    u8::try_from(sum).map_err(|_| Base45Error::InvalidTriple(sum))
+}
+
+pub fn base45_decode(input: &str) -> Result<Vec<u8>, Base45DecodeError> {
+    let mut out = Vec::new();
+    base45_decode_into(input, &mut out)?;
+    Ok(out)
+}
+
+/// Decode `input`, appending the bytes to `out`
+///
+/// This allows reusing one buffer for many inputs. On error, `out` is left as it was.
+pub fn base45_decode_into(input: &str, out: &mut Vec<u8>) -> Result<(), Base45DecodeError> {
+    let start = out.len();
+    let result = decode_groups(input.as_bytes(), out);
+    if result.is_err() {
+        out.truncate(start);
+    }
+    result
+}
+
+fn decode_groups(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), Base45DecodeError> {
+    out.reserve(bytes.len() / 3 * 2 + 1);
+
+    let mut triples = bytes.chunks_exact(3);
+    for (i, triple) in triples.by_ref().enumerate() {
+        let [a, b] = base45_cdec([triple[0], triple[1], triple[2]])
+            .map_err(|e| Base45DecodeError::new(e, 3 * i, triple))?;
+        out.extend_from_slice(&[a, b]);
+    }
+    let offset = bytes.len() - triples.remainder().len();
+    match *triples.remainder() {
+        [] => {}
+        [c, d] => {
+            out.push(base45_pdec([c, d]).map_err(|e| Base45DecodeError::new(e, offset, &[c, d]))?)
+        }
+        _ => {
+            let error = Base45Error::InvalidLength;
+            return Err(Base45DecodeError { offset, error });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{base45_decode, base45_decode_into, Base45DecodeError, Base45Error};
+
+    /// The examples of RFC 9285, section 4.3
+    const VECTORS: &[(&str, &[u8])] = &[
+        ("BB8", b"AB"),
+        ("%69 VD92EX0", b"Hello!!"),
+        ("UJCLQE7W581", b"base-45"),
+        ("QED8WEX0", b"ietf!"),
+    ];
+
+    fn error(input: &str) -> Base45DecodeError {
+        base45_decode(input).unwrap_err()
+    }
+
+    #[test]
+    fn rfc_vectors() {
+        for (input, bytes) in VECTORS {
+            assert_eq!(base45_decode(input).unwrap(), *bytes, "{}", input);
+        }
+        assert!(base45_decode("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn decode_into() {
+        let mut out = Vec::new();
+        for (input, _) in VECTORS {
+            base45_decode_into(input, &mut out).unwrap();
+        }
+        assert_eq!(out, b"ABHello!!base-45ietf!");
+
+        assert!(base45_decode_into("BB8GGW", &mut out).is_err());
+        assert_eq!(out, b"ABHello!!base-45ietf!");
+    }
+
+    #[test]
+    fn invalid_length() {
+        let e = error("BB8A");
+        assert!(matches!(e.error, Base45Error::InvalidLength));
+        assert_eq!(e.offset, 3);
+        assert!(matches!(error("A").error, Base45Error::InvalidLength));
+    }
+
+    #[test]
+    fn invalid_char() {
+        let e = error("BB8AbC");
+        assert!(matches!(e.error, Base45Error::InvalidChar(b'b')));
+        assert_eq!(e.offset, 4);
+        let e = error("bB8");
+        assert!(matches!(e.error, Base45Error::InvalidChar(b'b')));
+        assert_eq!(e.offset, 0);
+        // In the last two characters
+        let e = error("BB8A#");
+        assert!(matches!(e.error, Base45Error::InvalidChar(b'#')));
+        assert_eq!(e.offset, 4);
+    }
+
+    #[test]
+    fn invalid_triple() {
+        // 16 + 16 * 45 + 32 * 45 * 45 = 65536
+        let e = error("GGW");
+        assert!(matches!(e.error, Base45Error::InvalidTriple(65536)));
+        assert_eq!(e.offset, 0);
+        let e = error("BB8:::");
+        assert!(matches!(e.error, Base45Error::InvalidTriple(91124)));
+        assert_eq!(e.offset, 3);
+        // The last two characters only decode to a single byte
+        let e = error("BB8:5");
+        assert!(matches!(e.error, Base45Error::InvalidTriple(269)));
+        assert_eq!(e.offset, 3);
+    }
+}
@@ -0,0 +1,333 @@
+//! The CBOR item parser
+//!
+//! Unlike `serde_cbor::Value`, an [`Item`] keeps the order of map entries (and
+//! duplicates), indefinite-length items and the position of each item.
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{convert::TryFrom, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CborError {
+    /// Offset of the item that could not be parsed
+    pub offset: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.msg, self.offset)
+    }
+}
+
+/// A single CBOR data item
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    /// Offset of the first byte of the item
+    pub offset: usize,
+    /// Whether the item was encoded with indefinite length
+    pub indefinite: bool,
+    pub kind: ItemKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemKind {
+    Unsigned(u64),
+    /// A negative integer `-1 - n`
+    Negative(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Item>),
+    Map(Vec<(Item, Item)>),
+    Tag(u64, Box<Item>),
+    Bool(bool),
+    Null,
+    Undefined,
+    Simple(u8),
+    Float(f64),
+    /// A byte string that contains an encoded CBOR item (shown as `<<...>>`)
+    ///
+    /// The offsets of the inner item are relative to the start of the byte string content.
+    Embedded(Box<Item>),
+}
+
+const BREAK: u8 = 0xff;
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn err(&self, offset: usize, msg: &'static str) -> CborError {
+        CborError { offset, msg }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CborError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| self.err(self.pos, "unexpected end of input"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, CborError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read the argument of an item head, `None` for indefinite length
+    fn argument(&mut self, info: u8, offset: usize) -> Result<Option<u64>, CborError> {
+        let arg = match info {
+            0..=23 => u64::from(info),
+            24 => u64::from(self.byte()?),
+            25 => u64::from(u16::from_be_bytes([self.byte()?, self.byte()?])),
+            26 => {
+                let b = self.take(4)?;
+                u64::from(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            }
+            27 => {
+                let mut buf = [0; 8];
+                buf.copy_from_slice(self.take(8)?);
+                u64::from_be_bytes(buf)
+            }
+            31 => return Ok(None),
+            _ => return Err(self.err(offset, "reserved additional information")),
+        };
+        Ok(Some(arg))
+    }
+
+    fn length(&self, arg: u64, offset: usize) -> Result<usize, CborError> {
+        usize::try_from(arg)
+            .ok()
+            .filter(|len| *len <= self.data.len() - self.pos)
+            .ok_or_else(|| self.err(offset, "length exceeds input"))
+    }
+
+    fn at_break(&mut self) -> Result<bool, CborError> {
+        if *self.data.get(self.pos).ok_or_else(|| {
+            self.err(
+                self.pos,
+                "unexpected end of input in indefinite-length item",
+            )
+        })? == BREAK
+        {
+            self.pos += 1;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Read the chunks of an indefinite-length byte or text string
+    fn chunks(&mut self, major: u8) -> Result<Vec<u8>, CborError> {
+        let mut out = Vec::new();
+        while !self.at_break()? {
+            let chunk_offset = self.pos;
+            let head = self.byte()?;
+            if head >> 5 != major {
+                return Err(self.err(chunk_offset, "invalid chunk in indefinite-length string"));
+            }
+            let len = match self.argument(head & 0x1f, chunk_offset)? {
+                Some(len) => self.length(len, chunk_offset)?,
+                None => return Err(self.err(chunk_offset, "nested indefinite-length string")),
+            };
+            out.extend_from_slice(self.take(len)?);
+        }
+        Ok(out)
+    }
+
+    fn item(&mut self) -> Result<Item, CborError> {
+        let offset = self.pos;
+        let head = self.byte()?;
+        let (major, info) = (head >> 5, head & 0x1f);
+        if head == BREAK {
+            return Err(self.err(offset, "unexpected break"));
+        }
+
+        let mut indefinite = false;
+        let kind = match major {
+            0 | 1 | 6 => {
+                let arg = self
+                    .argument(info, offset)?
+                    .ok_or_else(|| self.err(offset, "invalid indefinite length"))?;
+                match major {
+                    0 => ItemKind::Unsigned(arg),
+                    1 => ItemKind::Negative(arg),
+                    _ => ItemKind::Tag(arg, Box::new(self.item()?)),
+                }
+            }
+            2 | 3 => {
+                let bytes = match self.argument(info, offset)? {
+                    Some(len) => {
+                        let len = self.length(len, offset)?;
+                        self.take(len)?.to_vec()
+                    }
+                    None => {
+                        indefinite = true;
+                        self.chunks(major)?
+                    }
+                };
+                if major == 2 {
+                    ItemKind::Bytes(bytes)
+                } else {
+                    let text = String::from_utf8(bytes)
+                        .map_err(|_| self.err(offset, "invalid UTF-8 in text string"))?;
+                    ItemKind::Text(text)
+                }
+            }
+            4 => {
+                let mut items = Vec::new();
+                match self.argument(info, offset)? {
+                    Some(len) => {
+                        let len = self.length(len, offset)?;
+                        for _ in 0..len {
+                            items.push(self.item()?);
+                        }
+                    }
+                    None => {
+                        indefinite = true;
+                        while !self.at_break()? {
+                            items.push(self.item()?);
+                        }
+                    }
+                }
+                ItemKind::Array(items)
+            }
+            5 => {
+                let mut entries = Vec::new();
+                match self.argument(info, offset)? {
+                    Some(len) => {
+                        let len = self.length(len, offset)?;
+                        for _ in 0..len {
+                            entries.push((self.item()?, self.item()?));
+                        }
+                    }
+                    None => {
+                        indefinite = true;
+                        while !self.at_break()? {
+                            entries.push((self.item()?, self.item()?));
+                        }
+                    }
+                }
+                ItemKind::Map(entries)
+            }
+            _ => match info {
+                20 => ItemKind::Bool(false),
+                21 => ItemKind::Bool(true),
+                22 => ItemKind::Null,
+                23 => ItemKind::Undefined,
+                24 => ItemKind::Simple(self.byte()?),
+                25 => {
+                    let half = u16::from_be_bytes([self.byte()?, self.byte()?]);
+                    ItemKind::Float(f16_to_f64(half))
+                }
+                26 => {
+                    let b = self.take(4)?;
+                    ItemKind::Float(f64::from(f32::from_be_bytes([b[0], b[1], b[2], b[3]])))
+                }
+                27 => {
+                    let mut buf = [0; 8];
+                    buf.copy_from_slice(self.take(8)?);
+                    ItemKind::Float(f64::from_be_bytes(buf))
+                }
+                0..=19 => ItemKind::Simple(info),
+                _ => return Err(self.err(offset, "reserved additional information")),
+            },
+        };
+        Ok(Item {
+            offset,
+            indefinite,
+            kind,
+        })
+    }
+}
+
+/// `2^exp` for a normal `f64` exponent, without `powi` from std
+fn exp2(exp: i32) -> f64 {
+    f64::from_bits(((exp + 1023) as u64) << 52)
+}
+
+pub(crate) fn f16_to_f64(half: u16) -> f64 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = i32::from((half >> 10) & 0x1f);
+    let mant = f64::from(half & 0x3ff);
+    sign * match exp {
+        0 => mant * exp2(-24),
+        31 if mant == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + mant / 1024.0) * exp2(exp - 15),
+    }
+}
+
+/// Parse a single CBOR item that spans all of `data`
+pub fn parse(data: &[u8]) -> Result<Item, CborError> {
+    let mut parser = Parser { data, pos: 0 };
+    let item = parser.item()?;
+    if parser.pos != data.len() {
+        return Err(parser.err(parser.pos, "trailing bytes after item"));
+    }
+    Ok(item)
+}
+
+impl ItemKind {
+    /// The name of the type of the item, e.g. `map` or `text`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Unsigned(_) | Self::Negative(_) => "integer",
+            Self::Bytes(_) | Self::Embedded(_) => "bytes",
+            Self::Text(_) => "text",
+            Self::Array(_) => "array",
+            Self::Map(_) => "map",
+            Self::Tag(..) => "tag",
+            Self::Bool(_) => "bool",
+            Self::Null => "null",
+            Self::Undefined => "undefined",
+            Self::Simple(_) => "simple",
+            Self::Float(_) => "float",
+        }
+    }
+}
+
+impl Item {
+    /// The value of an integer item
+    pub fn as_integer(&self) -> Option<i128> {
+        match self.kind {
+            ItemKind::Unsigned(n) => Some(i128::from(n)),
+            ItemKind::Negative(n) => Some(-1 - i128::from(n)),
+            _ => None,
+        }
+    }
+
+    /// The content of a byte string item
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match &self.kind {
+            ItemKind::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+/// The additional information of a head with the shortest encoding of `arg`
+pub(crate) fn shortest_info(arg: u64) -> u8 {
+    match arg {
+        0..=23 => arg as u8,
+        24..=0xff => 24,
+        0x100..=0xffff => 25,
+        0x1_0000..=0xffff_ffff => 26,
+        _ => 27,
+    }
+}
+
+pub(crate) fn write_head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let info = shortest_info(arg);
+    out.push(major << 5 | info);
+    let bytes = arg.to_be_bytes();
+    match info {
+        24 => out.push(arg as u8),
+        25 => out.extend_from_slice(&bytes[6..]),
+        26 => out.extend_from_slice(&bytes[4..]),
+        27 => out.extend_from_slice(&bytes),
+        _ => {}
+    }
+}
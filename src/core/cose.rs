@@ -0,0 +1,183 @@
+//! The structure of a COSE_Sign1 message (RFC 8152)
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::cbor::{self, CborError, Item, ItemKind};
+
+/// COSE header label for the algorithm
+pub const HEADER_ALG: i128 = 1;
+/// COSE header label for the critical header parameters
+pub const HEADER_CRIT: i128 = 2;
+/// COSE header label for the content type
+pub const HEADER_CONTENT_TYPE: i128 = 3;
+/// COSE header label for the key identifier
+pub const HEADER_KID: i128 = 4;
+
+/// COSE algorithm identifier for ECDSA w/ SHA-256
+pub const ALG_ES256: i128 = -7;
+/// COSE algorithm identifier for ECDSA w/ SHA-384
+pub const ALG_ES384: i128 = -35;
+/// COSE algorithm identifier for ECDSA w/ SHA-512
+pub const ALG_ES512: i128 = -36;
+/// COSE algorithm identifier for RSASSA-PSS w/ SHA-256
+pub const ALG_PS256: i128 = -37;
+/// COSE algorithm identifier for RSASSA-PSS w/ SHA-384
+pub const ALG_PS384: i128 = -38;
+/// COSE algorithm identifier for RSASSA-PSS w/ SHA-512
+pub const ALG_PS512: i128 = -39;
+
+/// The name of a registered COSE header label
+pub fn header_name(label: i128) -> Option<&'static str> {
+    match label {
+        HEADER_ALG => Some("alg"),
+        HEADER_CRIT => Some("crit"),
+        HEADER_CONTENT_TYPE => Some("content type"),
+        HEADER_KID => Some("kid"),
+        5 => Some("IV"),
+        6 => Some("Partial IV"),
+        7 => Some("counter signature"),
+        9 => Some("CounterSignature0"),
+        11 => Some("Countersignature version 2"),
+        12 => Some("Countersignature0 version 2"),
+        _ => None,
+    }
+}
+
+/// The name of a COSE signature algorithm
+pub fn alg_name(alg: i128) -> Option<&'static str> {
+    match alg {
+        ALG_ES256 => Some("ES256"),
+        ALG_ES384 => Some("ES384"),
+        ALG_ES512 => Some("ES512"),
+        -8 => Some("EdDSA"),
+        ALG_PS256 => Some("PS256"),
+        ALG_PS384 => Some("PS384"),
+        ALG_PS512 => Some("PS512"),
+        -257 => Some("RS256"),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum Sign1Error {
+    Cbor(CborError),
+    Structure(&'static str),
+}
+
+impl fmt::Display for Sign1Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cbor(e) => write!(f, "Invalid CBOR: {}", e),
+            Self::Structure(msg) => write!(f, "Invalid COSE_Sign1: {}", msg),
+        }
+    }
+}
+
+/// A COSE_Sign1 message, with the header maps as parsed CBOR items
+#[derive(Debug, Clone)]
+pub struct Sign1 {
+    /// The serialized protected header, as signed
+    pub protected_raw: Vec<u8>,
+    pub protected: Vec<(Item, Item)>,
+    pub unprotected: Vec<(Item, Item)>,
+    pub payload: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn into_bytes(item: Item, what: &'static str) -> Result<Vec<u8>, Sign1Error> {
+    match item.kind {
+        ItemKind::Bytes(bytes) => Ok(bytes),
+        _ => Err(Sign1Error::Structure(what)),
+    }
+}
+
+fn into_map(item: Item, what: &'static str) -> Result<Vec<(Item, Item)>, Sign1Error> {
+    match item.kind {
+        ItemKind::Map(entries) => Ok(entries),
+        _ => Err(Sign1Error::Structure(what)),
+    }
+}
+
+fn find_header(entries: &[(Item, Item)], label: i128) -> Option<&Item> {
+    entries
+        .iter()
+        .find(|(key, _)| key.as_integer() == Some(label))
+        .map(|(_, value)| value)
+}
+
+impl Sign1 {
+    /// Parse a (tagged or untagged) COSE_Sign1 message
+    ///
+    /// The payload is returned as is, i.e. it is not decompressed or decoded.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Sign1Error> {
+        let item = cbor::parse(bytes).map_err(Sign1Error::Cbor)?;
+        let item = match item.kind {
+            ItemKind::Tag(18, inner) => *inner,
+            ItemKind::Tag(_, _) => return Err(Sign1Error::Structure("unexpected tag")),
+            _ => item,
+        };
+        let mut items = match item.kind {
+            ItemKind::Array(items) if items.len() == 4 => items.into_iter(),
+            _ => return Err(Sign1Error::Structure("expected an array of 4 items")),
+        };
+
+        let protected_raw = into_bytes(items.next().unwrap(), "protected header is not a bstr")?;
+        let protected = if protected_raw.is_empty() {
+            Vec::new()
+        } else {
+            let header = cbor::parse(&protected_raw).map_err(Sign1Error::Cbor)?;
+            into_map(header, "protected header is not a map")?
+        };
+        let unprotected = into_map(items.next().unwrap(), "unprotected header is not a map")?;
+        let payload = into_bytes(items.next().unwrap(), "payload is not a bstr")?;
+        let signature = into_bytes(items.next().unwrap(), "signature is not a bstr")?;
+
+        Ok(Self {
+            protected_raw,
+            protected,
+            unprotected,
+            payload,
+            signature,
+        })
+    }
+
+    /// Get a header parameter, preferring the protected header
+    pub fn header(&self, label: i128) -> Option<&Item> {
+        find_header(&self.protected, label).or_else(|| find_header(&self.unprotected, label))
+    }
+
+    /// Whether a header parameter is in the protected header, i.e. covered by the signature
+    pub fn is_protected(&self, label: i128) -> bool {
+        find_header(&self.protected, label).is_some()
+    }
+
+    /// The algorithm identifier (`alg`)
+    pub fn alg(&self) -> Option<i128> {
+        self.header(HEADER_ALG)?.as_integer()
+    }
+
+    /// The key identifier (`kid`)
+    pub fn kid(&self) -> Option<&[u8]> {
+        self.header(HEADER_KID)?.as_bytes()
+    }
+
+    /// The `Sig_structure` for this message, i.e. the bytes that were signed
+    pub fn sig_structure(&self) -> Vec<u8> {
+        sig_structure(&self.protected_raw, &self.payload)
+    }
+}
+
+/// The `Sig_structure` of a COSE_Sign1 message without external data
+pub fn sig_structure(protected_raw: &[u8], payload: &[u8]) -> Vec<u8> {
+    const CONTEXT: &str = "Signature1";
+    let mut out = Vec::with_capacity(16 + protected_raw.len() + payload.len());
+    cbor::write_head(&mut out, 4, 4);
+    cbor::write_head(&mut out, 3, CONTEXT.len() as u64);
+    out.extend_from_slice(CONTEXT.as_bytes());
+    cbor::write_head(&mut out, 2, protected_raw.len() as u64);
+    out.extend_from_slice(protected_raw);
+    cbor::write_head(&mut out, 2, 0);
+    cbor::write_head(&mut out, 2, payload.len() as u64);
+    out.extend_from_slice(payload);
+    out
+}
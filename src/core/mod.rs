@@ -0,0 +1,9 @@
+//! The decoding core, which builds with `no_std` (and `alloc`)
+//!
+//! This covers the steps of decoding a certificate that don't need the standard library:
+//! base45, the structure of CBOR items and of the COSE_Sign1 message. Decompressing the
+//! zlib stream between base45 and COSE, deserializing the payload and verifying the
+//! signature are left to the caller, or to the modules behind the `std` feature.
+pub mod base45;
+pub mod cbor;
+pub mod cose;
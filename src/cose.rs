@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+
+use crate::cbor::Item;
+
+pub use crate::core::cose::{
+    alg_name, header_name, Sign1Error, ALG_ES256, ALG_ES384, ALG_ES512, ALG_PS256, ALG_PS384,
+    ALG_PS512, HEADER_ALG, HEADER_CONTENT_TYPE, HEADER_CRIT, HEADER_KID,
+};
+
+impl Error for Sign1Error {}
+
+#[derive(Debug)]
+pub enum CoseError {
+    Cbor(serde_cbor::Error),
+    Structure(&'static str),
+}
+
+impl Error for CoseError {}
+impl fmt::Display for CoseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cbor(e) => write!(f, "Invalid CBOR: {}", e),
+            Self::Structure(msg) => write!(f, "Invalid COSE_Sign1: {}", msg),
+        }
+    }
+}
+
+/// A COSE_Sign1 message with complete header maps
+#[derive(Debug, Clone)]
+pub struct CoseSign1 {
+    /// The serialized protected header, as signed
+    pub protected_raw: Vec<u8>,
+    pub protected: BTreeMap<Value, Value>,
+    pub unprotected: BTreeMap<Value, Value>,
+    pub payload: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn into_bytes(value: Value, what: &'static str) -> Result<Vec<u8>, CoseError> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes),
+        _ => Err(CoseError::Structure(what)),
+    }
+}
+
+impl CoseSign1 {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, CoseError> {
+        let value: Value = serde_cbor::from_slice(bytes).map_err(CoseError::Cbor)?;
+        let value = match value {
+            Value::Tag(18, inner) => *inner,
+            Value::Tag(_, _) => return Err(CoseError::Structure("unexpected tag")),
+            value => value,
+        };
+        let mut items = match value {
+            Value::Array(items) if items.len() == 4 => items.into_iter(),
+            _ => return Err(CoseError::Structure("expected an array of 4 items")),
+        };
+
+        let protected_raw = into_bytes(items.next().unwrap(), "protected header is not a bstr")?;
+        let protected = if protected_raw.is_empty() {
+            BTreeMap::new()
+        } else {
+            serde_cbor::from_slice(&protected_raw).map_err(CoseError::Cbor)?
+        };
+        let unprotected = match items.next().unwrap() {
+            Value::Map(map) => map,
+            _ => return Err(CoseError::Structure("unprotected header is not a map")),
+        };
+        let payload = into_bytes(items.next().unwrap(), "payload is not a bstr")?;
+        let signature = into_bytes(items.next().unwrap(), "signature is not a bstr")?;
+
+        Ok(Self {
+            protected_raw,
+            protected,
+            unprotected,
+            payload,
+            signature,
+        })
+    }
+
+    /// Get a header parameter, preferring the protected header
+    pub fn header(&self, label: i128) -> Option<&Value> {
+        let key = Value::Integer(label);
+        self.protected
+            .get(&key)
+            .or_else(|| self.unprotected.get(&key))
+    }
+
+    /// The algorithm identifier (`alg`)
+    pub fn alg(&self) -> Option<i128> {
+        match self.header(HEADER_ALG) {
+            Some(Value::Integer(alg)) => Some(*alg),
+            _ => None,
+        }
+    }
+
+    /// The key identifier (`kid`)
+    pub fn kid(&self) -> Option<&[u8]> {
+        match self.header(HEADER_KID) {
+            Some(Value::Bytes(kid)) => Some(kid),
+            _ => None,
+        }
+    }
+
+    /// All header parameters, for display
+    pub fn headers(&self) -> Headers {
+        Headers {
+            protected: self.protected.iter().map(HeaderParam::new).collect(),
+            unprotected: self.unprotected.iter().map(HeaderParam::new).collect(),
+        }
+    }
+
+    /// The `Sig_structure` for a COSE_Sign1 message, i.e. the bytes that were signed
+    pub fn sig_structure(&self) -> Vec<u8> {
+        crate::core::cose::sig_structure(&self.protected_raw, &self.payload)
+    }
+}
+
+/// A header parameter with its value in CBOR diagnostic notation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderParam {
+    pub label: String,
+    /// The name of a registered label
+    pub name: Option<String>,
+    pub value: String,
+}
+
+impl HeaderParam {
+    pub(crate) fn new((label, value): (&Value, &Value)) -> Self {
+        let name = match label {
+            Value::Integer(label) => header_name(*label).map(str::to_string),
+            _ => None,
+        };
+        Self {
+            label: Item::from(label).to_diag(),
+            name,
+            value: Item::from(value).to_diag(),
+        }
+    }
+}
+
+impl fmt::Display for HeaderParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({}): {}", name, self.label, self.value)?,
+            None => write!(f, "{}: {}", self.label, self.value)?,
+        }
+        if self.name.as_deref() == Some("alg") {
+            if let Some(alg) = self.value.parse().ok().and_then(alg_name) {
+                write!(f, " ({})", alg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The protected and unprotected header of a COSE message
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Headers {
+    pub protected: Vec<HeaderParam>,
+    pub unprotected: Vec<HeaderParam>,
+}
+
+impl fmt::Display for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Protected header:")?;
+        for param in &self.protected {
+            writeln!(f, "  {}", param)?;
+        }
+        writeln!(f, "Unprotected header:")?;
+        for param in &self.unprotected {
+            writeln!(f, "  {}", param)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! COSE countersignatures in the headers of a certificate
+//!
+//! A countersignature signs the body and (in version 2) the signature of the message,
+//! e.g. by a gateway or a timestamping service. Both the countersignatures of RFC 9338
+//! (labels 11 and 12) and the earlier ones of RFC 8152 (labels 7 and 9) are read. The
+//! abbreviated form (`Countersignature0`) has no headers, so it is checked against all
+//! given certificates.
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use x509_parser::parse_x509_certificate;
+
+use crate::{
+    cert::{self, Algorithm},
+    cose::{alg_name, CoseError, CoseSign1, HeaderParam, Headers, ALG_ES256, ALG_PS256},
+    crypto::{self, SignatureAlgorithm},
+};
+
+/// The countersignature of RFC 8152 (deprecated by RFC 9338)
+pub const HEADER_COUNTER_SIGNATURE: i128 = 7;
+/// The abbreviated countersignature of RFC 8152
+pub const HEADER_COUNTER_SIGNATURE0: i128 = 9;
+/// The countersignature of RFC 9338, which also covers the signature of the message
+pub const HEADER_COUNTERSIGNATURE_V2: i128 = 11;
+/// The abbreviated countersignature of RFC 9338
+pub const HEADER_COUNTERSIGNATURE0_V2: i128 = 12;
+
+/// A countersignature of a COSE_Sign1 message
+#[derive(Debug, Clone)]
+pub struct Countersignature {
+    /// 1 for RFC 8152, 2 for RFC 9338
+    pub version: u8,
+    /// Whether this is a `Countersignature0`, which is only the signature
+    pub abbreviated: bool,
+    pub protected_raw: Vec<u8>,
+    pub protected: BTreeMap<Value, Value>,
+    pub unprotected: BTreeMap<Value, Value>,
+    pub signature: Vec<u8>,
+}
+
+/// The outcome of checking a countersignature
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountersignatureStatus {
+    /// No certificates to check countersignatures against were given
+    NotChecked,
+    Verified,
+    /// None of the certificates (with a matching kid) verify the countersignature
+    Invalid,
+    /// No certificate with the kid of the countersignature was given
+    KeyNotFound,
+    UnsupportedAlgorithm,
+}
+
+impl fmt::Display for CountersignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotChecked => "not checked",
+            Self::Verified => "verified",
+            Self::Invalid => "INVALID",
+            Self::KeyNotFound => "no certificate with this kid",
+            Self::UnsupportedAlgorithm => "unsupported algorithm",
+        })
+    }
+}
+
+/// A countersignature with the outcome of its check, for the result of a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CountersignatureInfo {
+    pub version: u8,
+    pub abbreviated: bool,
+    /// The base64 encoded kid, if any
+    pub kid: Option<String>,
+    pub headers: Headers,
+    pub status: CountersignatureStatus,
+}
+
+impl fmt::Display for CountersignatureInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.abbreviated {
+            "Countersignature0"
+        } else {
+            "Countersignature"
+        };
+        write!(f, "{} (version {}", kind, self.version)?;
+        let alg = self.headers.protected.iter().find(|p| p.label == "1");
+        if let Some(alg) = alg {
+            match alg.value.parse().ok().and_then(alg_name) {
+                Some(name) => write!(f, ", {}", name)?,
+                None => write!(f, ", alg {}", alg.value)?,
+            }
+        }
+        if let Some(kid) = &self.kid {
+            write!(f, ", kid '{}'", kid)?;
+        }
+        write!(f, "): {}", self.status)
+    }
+}
+
+fn into_bytes(value: &Value, what: &'static str) -> Result<Vec<u8>, CoseError> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes.clone()),
+        _ => Err(CoseError::Structure(what)),
+    }
+}
+
+impl Countersignature {
+    /// Read a `COSE_Countersignature`, i.e. `[protected, unprotected, signature]`
+    fn from_value(value: &Value, version: u8) -> Result<Self, CoseError> {
+        let items = match value {
+            Value::Array(items) if items.len() == 3 => items,
+            _ => {
+                return Err(CoseError::Structure(
+                    "countersignature is not an array of 3",
+                ))
+            }
+        };
+        let protected_raw = into_bytes(&items[0], "countersignature header is not a bstr")?;
+        let protected = if protected_raw.is_empty() {
+            BTreeMap::new()
+        } else {
+            serde_cbor::from_slice(&protected_raw).map_err(CoseError::Cbor)?
+        };
+        let unprotected = match &items[1] {
+            Value::Map(map) => map.clone(),
+            _ => return Err(CoseError::Structure("countersignature header is not a map")),
+        };
+        let signature = into_bytes(&items[2], "countersignature is not a bstr")?;
+        Ok(Self {
+            version,
+            abbreviated: false,
+            protected_raw,
+            protected,
+            unprotected,
+            signature,
+        })
+    }
+
+    fn abbreviated(value: &Value, version: u8) -> Result<Self, CoseError> {
+        Ok(Self {
+            version,
+            abbreviated: true,
+            protected_raw: Vec::new(),
+            protected: BTreeMap::new(),
+            unprotected: BTreeMap::new(),
+            signature: into_bytes(value, "Countersignature0 is not a bstr")?,
+        })
+    }
+
+    fn header(&self, label: i128) -> Option<&Value> {
+        let key = Value::Integer(label);
+        self.protected
+            .get(&key)
+            .or_else(|| self.unprotected.get(&key))
+    }
+
+    pub fn alg(&self) -> Option<i128> {
+        match self.header(crate::cose::HEADER_ALG) {
+            Some(Value::Integer(alg)) => Some(*alg),
+            _ => None,
+        }
+    }
+
+    pub fn kid(&self) -> Option<&[u8]> {
+        match self.header(crate::cose::HEADER_KID) {
+            Some(Value::Bytes(kid)) => Some(kid),
+            _ => None,
+        }
+    }
+
+    pub fn headers(&self) -> Headers {
+        Headers {
+            protected: self.protected.iter().map(HeaderParam::new).collect(),
+            unprotected: self.unprotected.iter().map(HeaderParam::new).collect(),
+        }
+    }
+
+    /// The countersigned bytes for the message `body`
+    ///
+    /// `["CounterSignature", body_protected, sign_protected, external_aad, payload]`, with
+    /// the signature of the body appended in version 2 and without `sign_protected` for
+    /// `Countersignature0`.
+    pub fn to_be_signed(&self, body: &CoseSign1) -> Vec<u8> {
+        let context = if self.abbreviated {
+            "CounterSignature0"
+        } else {
+            "CounterSignature"
+        };
+        let mut items = vec![
+            Value::Text(context.to_string()),
+            Value::Bytes(body.protected_raw.clone()),
+        ];
+        if !self.abbreviated {
+            items.push(Value::Bytes(self.protected_raw.clone()));
+        }
+        items.push(Value::Bytes(Vec::new()));
+        items.push(Value::Bytes(body.payload.clone()));
+        if self.version >= 2 {
+            items.push(Value::Array(vec![Value::Bytes(body.signature.clone())]));
+        }
+        // Serializing a value without floats or non-text keys can't fail
+        serde_cbor::to_vec(&Value::Array(items)).unwrap_or_default()
+    }
+
+    /// Check the countersignature against the DER encoded `certificates`
+    pub fn verify(&self, body: &CoseSign1, certificates: &[Vec<u8>]) -> CountersignatureStatus {
+        if certificates.is_empty() {
+            return CountersignatureStatus::NotChecked;
+        }
+        let candidates: Vec<&Vec<u8>> = certificates
+            .iter()
+            .filter(|der| {
+                self.kid()
+                    .is_none_or(|kid| cert::compute_kid(der).as_slice() == kid)
+            })
+            .collect();
+        if candidates.is_empty() {
+            return CountersignatureStatus::KeyNotFound;
+        }
+        let message = self.to_be_signed(body);
+        for der in candidates {
+            let cert = match parse_x509_certificate(der) {
+                Ok((_, cert)) => cert,
+                Err(_) => continue,
+            };
+            let spki = &cert.tbs_certificate.subject_pki;
+            // Without an `alg` header, the type of the key decides
+            let alg = match self.alg() {
+                Some(ALG_ES256) => SignatureAlgorithm::EcdsaP256Sha256Fixed,
+                Some(ALG_PS256) => SignatureAlgorithm::RsaPssSha256,
+                Some(_) => return CountersignatureStatus::UnsupportedAlgorithm,
+                None => match cert::get_pk_sig_algorithm(spki) {
+                    Ok(Algorithm::IdEcPublicKey(_)) => SignatureAlgorithm::EcdsaP256Sha256Fixed,
+                    Ok(Algorithm::RsaEncryption) => SignatureAlgorithm::RsaPssSha256,
+                    Err(_) => continue,
+                },
+            };
+            let key = spki.subject_public_key.data;
+            if crypto::verify(alg, key, &message, &self.signature).is_ok() {
+                return CountersignatureStatus::Verified;
+            }
+        }
+        CountersignatureStatus::Invalid
+    }
+
+    /// The countersignature with the outcome of checking it against `certificates`
+    pub fn check(&self, body: &CoseSign1, certificates: &[Vec<u8>]) -> CountersignatureInfo {
+        CountersignatureInfo {
+            version: self.version,
+            abbreviated: self.abbreviated,
+            kid: self.kid().map(base64::encode),
+            headers: self.headers(),
+            status: self.verify(body, certificates),
+        }
+    }
+}
+
+/// All countersignatures in the headers of `sign1`
+pub fn countersignatures(sign1: &CoseSign1) -> Result<Vec<Countersignature>, CoseError> {
+    let mut countersignatures = Vec::new();
+    for (label, version) in [
+        (HEADER_COUNTER_SIGNATURE, 1),
+        (HEADER_COUNTERSIGNATURE_V2, 2),
+    ] {
+        match sign1.header(label) {
+            // A single countersignature starts with its protected header
+            Some(value @ Value::Array(items)) if matches!(items.first(), Some(Value::Bytes(_))) => {
+                countersignatures.push(Countersignature::from_value(value, version)?);
+            }
+            Some(Value::Array(items)) => {
+                for item in items {
+                    countersignatures.push(Countersignature::from_value(item, version)?);
+                }
+            }
+            Some(_) => return Err(CoseError::Structure("countersignature is not an array")),
+            None => {}
+        }
+    }
+    for (label, version) in [
+        (HEADER_COUNTER_SIGNATURE0, 1),
+        (HEADER_COUNTERSIGNATURE0_V2, 2),
+    ] {
+        if let Some(value) = sign1.header(label) {
+            countersignatures.push(Countersignature::abbreviated(value, version)?);
+        }
+    }
+    Ok(countersignatures)
+}
@@ -0,0 +1,156 @@
+//! Revocation of DSCs by the certificate revocation lists (CRLs) of the CSCAs
+//!
+//! A DSC is revoked if its serial number is listed in a CRL of its issuer. CRLs are
+//! matched to a certificate by the issuer name, and checked against the CSCAs if they
+//! are given. An outdated CRL (after its `nextUpdate`) is still used, with a warning.
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
+use color_eyre::eyre::eyre;
+use tracing::{debug, warn};
+use x509_parser::{
+    parse_x509_certificate, parse_x509_crl, pem::Pem, revocation_list::CertificateRevocationList,
+    time::ASN1Time,
+};
+
+use crate::cert::CscaStore;
+
+/// A revoked certificate from a CRL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revocation {
+    /// The issuer of the CRL and of the certificate
+    pub issuer: String,
+    /// The serial number of the certificate, as hex bytes separated by `:`
+    pub serial: String,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// The reason code, e.g. `KeyCompromise`
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for Revocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Certificate {} of '{}' was revoked",
+            self.serial, self.issuer
+        )?;
+        if let Some(revoked_at) = self.revoked_at {
+            write!(f, " on {}", revoked_at)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Crl {
+    /// The DER encoded issuer name
+    issuer: Vec<u8>,
+    /// The raw serial numbers of the revoked certificates
+    revoked: Vec<(Vec<u8>, Revocation)>,
+}
+
+/// The CRLs to check the DSCs against
+#[derive(Debug, Clone, Default)]
+pub struct CrlStore {
+    lists: Vec<Crl>,
+}
+
+fn time(t: &ASN1Time) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(t.timestamp(), 0).single()
+}
+
+impl CrlStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the CRLs from a PEM file (with one or more `X509 CRL` blocks) or a DER file
+    ///
+    /// If `csca` is given, each CRL must be signed by one of its CSCAs. Returns the number
+    /// of CRLs that were added.
+    pub fn add(&mut self, bytes: &[u8], csca: Option<&CscaStore>) -> color_eyre::Result<usize> {
+        let mut lists = Vec::new();
+        if bytes.starts_with(b"-----BEGIN") {
+            // The PEM parser only keeps the first word of the label
+            for pem in Pem::iter_from_buffer(bytes) {
+                let pem = pem?;
+                if pem.label == "X509" {
+                    lists.push(pem.contents);
+                }
+            }
+        } else {
+            lists.push(bytes.to_vec());
+        }
+        if lists.is_empty() {
+            return Err(eyre!("Expected an 'X509 CRL' PEM block"));
+        }
+        for der in &lists {
+            let (_, crl) = parse_x509_crl(der)?;
+            match csca {
+                Some(csca) => csca.verify_crl(&crl)?,
+                None => debug!("No CSCAs to check the CRL of '{}' against", crl.issuer()),
+            }
+            self.push(&crl);
+        }
+        Ok(lists.len())
+    }
+
+    fn push(&mut self, crl: &CertificateRevocationList) {
+        let issuer = crl.issuer().to_string();
+        if let Some(next_update) = crl.next_update() {
+            if next_update.timestamp() < Utc::now().timestamp() {
+                warn!(
+                    "The CRL of '{}' is outdated since {}",
+                    issuer,
+                    next_update.to_rfc2822()
+                );
+            }
+        }
+        let revoked = crl
+            .iter_revoked_certificates()
+            .map(|revoked| {
+                let revocation = Revocation {
+                    issuer: issuer.clone(),
+                    serial: revoked.raw_serial_as_string(),
+                    revoked_at: time(&revoked.revocation_date),
+                    reason: revoked.reason_code().map(|(_, code)| code.to_string()),
+                };
+                (revoked.raw_serial().to_vec(), revocation)
+            })
+            .collect::<Vec<_>>();
+        debug!(
+            "Loaded the CRL of '{}' with {} revoked certificates",
+            issuer,
+            revoked.len()
+        );
+        self.lists.push(Crl {
+            issuer: crl.issuer().as_raw().to_vec(),
+            revoked,
+        });
+    }
+
+    /// The number of CRLs
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+
+    /// The revocation of the DER encoded certificate `der`, if it is revoked
+    pub fn check(&self, der: &[u8]) -> Option<&Revocation> {
+        let (_, cert) = parse_x509_certificate(der).ok()?;
+        let issuer = cert.issuer().as_raw();
+        let serial = cert.tbs_certificate.raw_serial();
+        self.lists
+            .iter()
+            .filter(|crl| crl.issuer == issuer)
+            .flat_map(|crl| &crl.revoked)
+            .find(|(revoked, _)| revoked.as_slice() == serial)
+            .map(|(_, revocation)| revocation)
+    }
+}
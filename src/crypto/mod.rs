@@ -0,0 +1,189 @@
+//! Signature verification and hashing
+//!
+//! All verification goes through the [`Verifier`] of the backend that was selected at
+//! build time: `ring` (the default), the pure-Rust RustCrypto crates (`rustcrypto`) for
+//! targets where ring does not build, or `openssl` where a (FIPS validated) system
+//! OpenSSL is required. If several are enabled, `openssl` is preferred, then `ring`.
+use std::fmt;
+
+use x509_parser::{
+    certificate::X509Certificate, der_parser::oid::Oid, oid_registry::*,
+    revocation_list::CertificateRevocationList, x509::SubjectPublicKeyInfo,
+};
+
+use crate::cert::{self, Algorithm, Prime};
+
+#[cfg(feature = "openssl")]
+mod openssl;
+#[cfg(all(feature = "ring", not(feature = "openssl")))]
+mod ring;
+#[cfg(all(
+    feature = "rustcrypto",
+    not(any(feature = "ring", feature = "openssl"))
+))]
+mod rustcrypto;
+
+#[cfg(not(any(feature = "ring", feature = "rustcrypto", feature = "openssl")))]
+compile_error!(
+    "the `verify` feature needs a crypto backend: enable `ring`, `rustcrypto` or `openssl`"
+);
+
+/// A signature algorithm, including the encoding of the signature
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum SignatureAlgorithm {
+    /// ECDSA with P-256 and SHA-256, with the signature as `r || s` (COSE, JWS)
+    EcdsaP256Sha256Fixed,
+    /// ECDSA with P-256 and SHA-256, with a DER encoded signature (X.509)
+    EcdsaP256Sha256Asn1,
+    /// ECDSA with P-256 and SHA-384, with a DER encoded signature (X.509)
+    EcdsaP256Sha384Asn1,
+    /// ECDSA with P-384 and SHA-256, with a DER encoded signature (X.509)
+    EcdsaP384Sha256Asn1,
+    /// ECDSA with P-384 and SHA-384, with a DER encoded signature (X.509)
+    EcdsaP384Sha384Asn1,
+    /// RSASSA-PSS with SHA-256 and a salt of the same length
+    RsaPssSha256,
+    RsaPkcs1Sha256,
+    RsaPkcs1Sha384,
+    RsaPkcs1Sha512,
+}
+
+impl SignatureAlgorithm {
+    /// The algorithm of the signature on a certificate, signed with the `issuer` key
+    pub(crate) fn of_certificate(
+        cert: &X509Certificate,
+        issuer: &SubjectPublicKeyInfo,
+    ) -> Option<Self> {
+        Self::of_oid(&cert.signature_algorithm.algorithm, issuer)
+    }
+
+    /// The algorithm of a signature on a certificate or CRL, by its OID and the issuer key
+    ///
+    /// The OID of an ECDSA signature only names the hash, the curve is the one of the
+    /// issuer key, e.g. a P-384 CSCA may sign with `ecdsa-with-SHA256`.
+    pub(crate) fn of_oid(oid: &Oid, issuer: &SubjectPublicKeyInfo) -> Option<Self> {
+        let key = cert::get_pk_sig_algorithm(issuer).ok()?;
+        let p256 = Algorithm::IdEcPublicKey(Prime::Prime256v1);
+        let p384 = Algorithm::IdEcPublicKey(Prime::Secp384r1);
+        let rsa = Algorithm::RsaEncryption;
+        let known = [
+            (OID_SIG_ECDSA_WITH_SHA256, p256, Self::EcdsaP256Sha256Asn1),
+            (OID_SIG_ECDSA_WITH_SHA384, p256, Self::EcdsaP256Sha384Asn1),
+            (OID_SIG_ECDSA_WITH_SHA256, p384, Self::EcdsaP384Sha256Asn1),
+            (OID_SIG_ECDSA_WITH_SHA384, p384, Self::EcdsaP384Sha384Asn1),
+            (OID_PKCS1_SHA256WITHRSA, rsa, Self::RsaPkcs1Sha256),
+            (OID_PKCS1_SHA384WITHRSA, rsa, Self::RsaPkcs1Sha384),
+            (OID_PKCS1_SHA512WITHRSA, rsa, Self::RsaPkcs1Sha512),
+        ];
+        known
+            .iter()
+            .find(|(known, known_key, _)| known == oid && *known_key == key)
+            .map(|(_, _, alg)| *alg)
+    }
+}
+
+/// The signature did not verify (or the key could not be used)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct InvalidSignature;
+
+impl std::error::Error for InvalidSignature {}
+impl fmt::Display for InvalidSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid signature")
+    }
+}
+
+/// The operations of a crypto backend
+pub(crate) trait Verifier: Sync {
+    /// Verify `signature` over `message`
+    ///
+    /// `public_key` is the content of the `subjectPublicKey` of a certificate, i.e. the
+    /// uncompressed EC point or the DER encoded `RSAPublicKey`.
+    fn verify(
+        &self,
+        alg: SignatureAlgorithm,
+        public_key: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), InvalidSignature>;
+
+    fn sha256(&self, data: &[u8]) -> [u8; 32];
+}
+
+/// The backend selected at build time
+pub(crate) fn backend() -> &'static dyn Verifier {
+    #[cfg(feature = "openssl")]
+    return &self::openssl::OpenSsl;
+    #[cfg(all(feature = "ring", not(feature = "openssl")))]
+    return &self::ring::Ring;
+    #[cfg(all(
+        feature = "rustcrypto",
+        not(any(feature = "ring", feature = "openssl"))
+    ))]
+    return &rustcrypto::RustCrypto;
+}
+
+/// Verify a signature with the selected backend
+pub(crate) fn verify(
+    alg: SignatureAlgorithm,
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), InvalidSignature> {
+    backend().verify(alg, public_key, message, signature)
+}
+
+/// The error for a signature algorithm that is not supported with the `issuer` key
+fn unsupported(oid: &Oid, issuer: &SubjectPublicKeyInfo) -> color_eyre::Report {
+    match cert::get_pk_sig_algorithm(issuer) {
+        Ok(key) => color_eyre::eyre::eyre!(
+            "Unsupported signature algorithm {} with an {} key",
+            oid,
+            key
+        ),
+        Err(e) => color_eyre::eyre::eyre!("Unsupported signature algorithm {}: {}", oid, e),
+    }
+}
+
+/// Verify the signature of `cert` with the key of its issuer
+pub(crate) fn verify_certificate(
+    cert: &X509Certificate,
+    issuer: &SubjectPublicKeyInfo,
+) -> color_eyre::Result<()> {
+    let oid = &cert.signature_algorithm.algorithm;
+    let alg =
+        SignatureAlgorithm::of_certificate(cert, issuer).ok_or_else(|| unsupported(oid, issuer))?;
+    verify(
+        alg,
+        issuer.subject_public_key.data,
+        cert.tbs_certificate.as_ref(),
+        cert.signature_value.data,
+    )?;
+    Ok(())
+}
+
+/// Verify the signature of `crl` with the key of its issuer
+pub(crate) fn verify_crl(
+    crl: &CertificateRevocationList,
+    issuer: &SubjectPublicKeyInfo,
+) -> color_eyre::Result<()> {
+    let oid = &crl.signature_algorithm.algorithm;
+    let alg = SignatureAlgorithm::of_oid(oid, issuer).ok_or_else(|| unsupported(oid, issuer))?;
+    verify(
+        alg,
+        issuer.subject_public_key.data,
+        crl.tbs_cert_list.as_ref(),
+        crl.signature_value.data,
+    )?;
+    Ok(())
+}
+
+/// The SHA-256 digest of `data`
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    backend().sha256(data)
+}
+
+/// The hex encoded SHA-256 digest of `data`
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    sha256(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
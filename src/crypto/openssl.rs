@@ -0,0 +1,96 @@
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcPoint},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Public},
+    rsa::{Padding, Rsa},
+    sign::RsaPssSaltlen,
+};
+
+use super::{InvalidSignature, SignatureAlgorithm, Verifier};
+
+/// The OpenSSL backend, e.g. for a FIPS validated build
+pub(crate) struct OpenSsl;
+
+/// The smallest RSA modulus that is accepted, as with ring
+const MIN_RSA_BITS: u32 = 2048;
+
+fn ec_key(curve: Nid, point: &[u8]) -> Result<PKey<Public>, openssl::error::ErrorStack> {
+    let group = EcGroup::from_curve_name(curve)?;
+    let mut ctx = BigNumContext::new()?;
+    let point = EcPoint::from_bytes(&group, point, &mut ctx)?;
+    PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)
+}
+
+/// Convert a `r || s` signature to DER
+fn fixed_to_der(sig: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let (r, s) = sig.split_at(sig.len() / 2);
+    EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?.to_der()
+}
+
+fn verify(
+    alg: SignatureAlgorithm,
+    public_key: &[u8],
+    message: &[u8],
+    sig: &[u8],
+) -> Result<bool, openssl::error::ErrorStack> {
+    use SignatureAlgorithm::*;
+
+    let (key, digest) = match alg {
+        EcdsaP256Sha256Fixed | EcdsaP256Sha256Asn1 => (
+            ec_key(Nid::X9_62_PRIME256V1, public_key)?,
+            MessageDigest::sha256(),
+        ),
+        EcdsaP256Sha384Asn1 => (
+            ec_key(Nid::X9_62_PRIME256V1, public_key)?,
+            MessageDigest::sha384(),
+        ),
+        EcdsaP384Sha256Asn1 => (ec_key(Nid::SECP384R1, public_key)?, MessageDigest::sha256()),
+        EcdsaP384Sha384Asn1 => (ec_key(Nid::SECP384R1, public_key)?, MessageDigest::sha384()),
+        RsaPssSha256 | RsaPkcs1Sha256 | RsaPkcs1Sha384 | RsaPkcs1Sha512 => {
+            let rsa = Rsa::public_key_from_der_pkcs1(public_key)?;
+            if rsa.n().num_bits() < MIN_RSA_BITS as i32 {
+                return Ok(false);
+            }
+            let digest = match alg {
+                RsaPkcs1Sha384 => MessageDigest::sha384(),
+                RsaPkcs1Sha512 => MessageDigest::sha512(),
+                _ => MessageDigest::sha256(),
+            };
+            (PKey::from_rsa(rsa)?, digest)
+        }
+    };
+    let mut verifier = openssl::sign::Verifier::new(digest, &key)?;
+    if alg == RsaPssSha256 {
+        verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+        verifier.set_rsa_mgf1_md(MessageDigest::sha256())?;
+        verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+    }
+    verifier.update(message)?;
+    match alg {
+        EcdsaP256Sha256Fixed if sig.len() != 64 => Ok(false),
+        EcdsaP256Sha256Fixed => verifier.verify(&fixed_to_der(sig)?),
+        _ => verifier.verify(sig),
+    }
+}
+
+impl Verifier for OpenSsl {
+    fn verify(
+        &self,
+        alg: SignatureAlgorithm,
+        public_key: &[u8],
+        message: &[u8],
+        sig: &[u8],
+    ) -> Result<(), InvalidSignature> {
+        match verify(alg, public_key, message, sig) {
+            Ok(true) => Ok(()),
+            Ok(false) | Err(_) => Err(InvalidSignature),
+        }
+    }
+
+    fn sha256(&self, data: &[u8]) -> [u8; 32] {
+        openssl::sha::sha256(data)
+    }
+}
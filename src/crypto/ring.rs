@@ -0,0 +1,37 @@
+use ring::{digest, signature};
+
+use super::{InvalidSignature, SignatureAlgorithm, Verifier};
+
+/// The `ring` backend
+pub(crate) struct Ring;
+
+impl Verifier for Ring {
+    fn verify(
+        &self,
+        alg: SignatureAlgorithm,
+        public_key: &[u8],
+        message: &[u8],
+        sig: &[u8],
+    ) -> Result<(), InvalidSignature> {
+        let params: &dyn signature::VerificationAlgorithm = match alg {
+            SignatureAlgorithm::EcdsaP256Sha256Fixed => &signature::ECDSA_P256_SHA256_FIXED,
+            SignatureAlgorithm::EcdsaP256Sha256Asn1 => &signature::ECDSA_P256_SHA256_ASN1,
+            SignatureAlgorithm::EcdsaP256Sha384Asn1 => &signature::ECDSA_P256_SHA384_ASN1,
+            SignatureAlgorithm::EcdsaP384Sha256Asn1 => &signature::ECDSA_P384_SHA256_ASN1,
+            SignatureAlgorithm::EcdsaP384Sha384Asn1 => &signature::ECDSA_P384_SHA384_ASN1,
+            SignatureAlgorithm::RsaPssSha256 => &signature::RSA_PSS_2048_8192_SHA256,
+            SignatureAlgorithm::RsaPkcs1Sha256 => &signature::RSA_PKCS1_2048_8192_SHA256,
+            SignatureAlgorithm::RsaPkcs1Sha384 => &signature::RSA_PKCS1_2048_8192_SHA384,
+            SignatureAlgorithm::RsaPkcs1Sha512 => &signature::RSA_PKCS1_2048_8192_SHA512,
+        };
+        signature::UnparsedPublicKey::new(params, public_key)
+            .verify(message, sig)
+            .map_err(|_| InvalidSignature)
+    }
+
+    fn sha256(&self, data: &[u8]) -> [u8; 32] {
+        let mut out = [0; 32];
+        out.copy_from_slice(digest::digest(&digest::SHA256, data).as_ref());
+        out
+    }
+}
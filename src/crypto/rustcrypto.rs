@@ -0,0 +1,128 @@
+use rsa::signature::{hazmat::PrehashVerifier as _, Verifier as _};
+use std::convert::TryFrom;
+
+use rsa::{pkcs1::DecodeRsaPublicKey, traits::PublicKeyParts, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use super::{InvalidSignature, SignatureAlgorithm, Verifier};
+
+/// The pure-Rust backend, with the RustCrypto crates
+pub(crate) struct RustCrypto;
+
+/// The smallest RSA modulus that is accepted, as with ring
+const MIN_RSA_BITS: usize = 2048;
+
+fn rsa_key(public_key: &[u8]) -> Result<RsaPublicKey, InvalidSignature> {
+    let key = RsaPublicKey::from_pkcs1_der(public_key).map_err(|_| InvalidSignature)?;
+    if key.n().bits() < MIN_RSA_BITS {
+        return Err(InvalidSignature);
+    }
+    Ok(key)
+}
+
+fn pkcs1<D>(public_key: &[u8], message: &[u8], sig: &[u8]) -> Result<(), InvalidSignature>
+where
+    D: Digest + rsa::pkcs8::AssociatedOid,
+{
+    let key = rsa::pkcs1v15::VerifyingKey::<D>::new(rsa_key(public_key)?);
+    let sig = rsa::pkcs1v15::Signature::try_from(sig).map_err(|_| InvalidSignature)?;
+    key.verify(message, &sig).map_err(|_| InvalidSignature)
+}
+
+impl Verifier for RustCrypto {
+    fn verify(
+        &self,
+        alg: SignatureAlgorithm,
+        public_key: &[u8],
+        message: &[u8],
+        sig: &[u8],
+    ) -> Result<(), InvalidSignature> {
+        let res = match alg {
+            SignatureAlgorithm::EcdsaP256Sha256Fixed | SignatureAlgorithm::EcdsaP256Sha256Asn1 => {
+                use p256::ecdsa::{Signature, VerifyingKey};
+                let key =
+                    VerifyingKey::from_sec1_bytes(public_key).map_err(|_| InvalidSignature)?;
+                let sig = if alg == SignatureAlgorithm::EcdsaP256Sha256Fixed {
+                    Signature::from_slice(sig)
+                } else {
+                    Signature::from_der(sig)
+                };
+                key.verify(message, &sig.map_err(|_| InvalidSignature)?)
+            }
+            SignatureAlgorithm::EcdsaP256Sha384Asn1 => {
+                use p256::ecdsa::{Signature, VerifyingKey};
+                let key =
+                    VerifyingKey::from_sec1_bytes(public_key).map_err(|_| InvalidSignature)?;
+                let sig = Signature::from_der(sig).map_err(|_| InvalidSignature)?;
+                key.verify_prehash(&Sha384::digest(message), &sig)
+            }
+            SignatureAlgorithm::EcdsaP384Sha256Asn1 => {
+                use p384::ecdsa::{Signature, VerifyingKey};
+                let key =
+                    VerifyingKey::from_sec1_bytes(public_key).map_err(|_| InvalidSignature)?;
+                let sig = Signature::from_der(sig).map_err(|_| InvalidSignature)?;
+                key.verify_prehash(&Sha256::digest(message), &sig)
+            }
+            SignatureAlgorithm::EcdsaP384Sha384Asn1 => {
+                use p384::ecdsa::{Signature, VerifyingKey};
+                let key =
+                    VerifyingKey::from_sec1_bytes(public_key).map_err(|_| InvalidSignature)?;
+                let sig = Signature::from_der(sig).map_err(|_| InvalidSignature)?;
+                key.verify(message, &sig)
+            }
+            SignatureAlgorithm::RsaPssSha256 => {
+                let key = rsa::pss::VerifyingKey::<Sha256>::new(rsa_key(public_key)?);
+                let sig = rsa::pss::Signature::try_from(sig).map_err(|_| InvalidSignature)?;
+                key.verify(message, &sig)
+            }
+            SignatureAlgorithm::RsaPkcs1Sha256 => return pkcs1::<Sha256>(public_key, message, sig),
+            SignatureAlgorithm::RsaPkcs1Sha384 => return pkcs1::<Sha384>(public_key, message, sig),
+            SignatureAlgorithm::RsaPkcs1Sha512 => return pkcs1::<Sha512>(public_key, message, sig),
+        };
+        res.map_err(|_| InvalidSignature)
+    }
+
+    fn sha256(&self, data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rsa::{
+        pkcs1::EncodeRsaPublicKey,
+        pkcs1v15, pss,
+        rand_core::OsRng,
+        signature::{RandomizedSigner, SignatureEncoding},
+        RsaPrivateKey,
+    };
+    use sha2::Sha256;
+
+    use super::{InvalidSignature, RustCrypto, SignatureAlgorithm, Verifier};
+
+    /// Verify a signature of a new key with a modulus of `bits`, with RSASSA-PSS (as in
+    /// COSE) or PKCS#1 v1.5 (as in X.509)
+    fn verify(bits: usize, alg: SignatureAlgorithm) -> Result<(), InvalidSignature> {
+        let key = RsaPrivateKey::new(&mut OsRng, bits).unwrap();
+        let public_key = key.to_public_key().to_pkcs1_der().unwrap();
+        let sig = match alg {
+            SignatureAlgorithm::RsaPssSha256 => pss::BlindedSigningKey::<Sha256>::new(key)
+                .sign_with_rng(&mut OsRng, b"message")
+                .to_vec(),
+            _ => pkcs1v15::SigningKey::<Sha256>::new(key)
+                .sign_with_rng(&mut OsRng, b"message")
+                .to_vec(),
+        };
+        RustCrypto.verify(alg, public_key.as_bytes(), b"message", &sig)
+    }
+
+    #[test]
+    fn rsa_key_size() {
+        use SignatureAlgorithm::{RsaPkcs1Sha256, RsaPssSha256};
+
+        assert!(verify(2048, RsaPssSha256).is_ok());
+        assert!(verify(1024, RsaPssSha256).is_err());
+        assert!(verify(1024, RsaPkcs1Sha256).is_err());
+        assert!(verify(512, RsaPkcs1Sha256).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! One CSV row per input of a batch run
+use std::fmt::Write;
+
+use crate::session::SessionResult;
+use chrono::{DateTime, Utc};
+
+/// The columns of a row
+pub const HEADER: &[&str] = &[
+    "file",
+    "kid",
+    "country",
+    "type",
+    "issued_at",
+    "expires_at",
+    "date",
+    "signature",
+    "expiry",
+    "error",
+];
+
+/// The field with the date of each entry type
+fn date_field(kind: &str) -> &'static str {
+    match kind {
+        "v" => "dt",
+        "t" => "sc",
+        _ => "fr",
+    }
+}
+
+/// Render the header line
+pub fn header() -> String {
+    HEADER.join(",")
+}
+
+/// Render the row for the result of one input
+///
+/// The `date` column is the date of the vaccination, the sample collection of a test,
+/// or the first positive test of a recovery.
+pub fn row(file: &str, result: &SessionResult, now: DateTime<Utc>) -> String {
+    let entries = result.entries();
+    let kinds: Vec<&str> = entries.iter().map(|(kind, _)| *kind).collect();
+    let dates: Vec<&str> = entries
+        .iter()
+        .filter_map(|(kind, entry)| entry.get(date_field(kind))?.as_str())
+        .collect();
+
+    let claims = result.claims.as_ref();
+    let expiry = match claims {
+        Some(claims) if claims.is_expired(now) => "expired",
+        Some(_) => "valid",
+        None => "",
+    };
+    let time = |t: DateTime<Utc>| t.to_rfc3339();
+
+    let fields = [
+        file.to_string(),
+        result.kid.clone().unwrap_or_default(),
+        claims.map(|c| c.issuer.clone()).unwrap_or_default(),
+        kinds.join(" "),
+        claims.map(|c| time(c.issued_at)).unwrap_or_default(),
+        claims.map(|c| time(c.expiration_time)).unwrap_or_default(),
+        dates.join(" "),
+        result.signature_status().to_string(),
+        expiry.to_string(),
+        result.error.clone().unwrap_or_default(),
+    ];
+    format_row(&fields)
+}
+
+/// Join the fields of a row, quoting them if needed
+pub fn format_row<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut out = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_field(&mut out, field.as_ref());
+    }
+    out
+}
+
+/// Write a field, quoted if needed (RFC 4180)
+fn write_field(out: &mut String, field: &str) {
+    if field.contains([',', '"', '\n', '\r']) {
+        write!(out, "\"{}\"", field.replace('"', "\"\"")).unwrap();
+    } else {
+        out.push_str(field);
+    }
+}
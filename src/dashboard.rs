@@ -0,0 +1,298 @@
+//! A terminal dashboard for continuous scanning
+//!
+//! Meant for a venue with a USB barcode scanner that types each `HC1:` string into the
+//! terminal, followed by Enter. The dashboard shows the running counts, a feed of the
+//! recent scans and the details of the last one.
+use std::{collections::VecDeque, io, time::Duration};
+
+use chrono::{DateTime, Local, Utc};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph},
+    Frame,
+};
+
+use crate::{
+    context::DecodeContext,
+    dcc::{
+        inflate,
+        input::{decode_transport, DecodedInput, InputFormat},
+        parse_sign1, CertPayload,
+    },
+    redact::MASK,
+    verify::Verification,
+};
+
+/// The number of scans that are kept in the feed
+const HISTORY: usize = 100;
+
+/// The verdict of a scan, as counted on the dashboard
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Verified and not expired
+    Valid,
+    /// Verified, but expired
+    Expired,
+    /// Not verified, or not decoded at all
+    Invalid,
+}
+
+impl Outcome {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Valid => "✓",
+            Self::Expired => "⌛",
+            Self::Invalid => "✗",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Valid => Color::Green,
+            Self::Expired => Color::Yellow,
+            Self::Invalid => Color::Red,
+        }
+    }
+}
+
+/// The result of decoding one scanned line
+#[derive(Debug)]
+pub struct Scan {
+    pub time: DateTime<Utc>,
+    pub outcome: Outcome,
+    /// The verification result, or why the line could not be decoded
+    pub message: String,
+    pub payload: Option<CertPayload>,
+}
+
+impl Scan {
+    /// Decode and verify an `HC1:` string
+    pub fn decode(ctx: &DecodeContext, line: &str, max_size: u64, now: DateTime<Utc>) -> Self {
+        match decode(ctx, line, max_size) {
+            Ok((payload, verification)) => {
+                let outcome = match (verification.is_verified(), payload.expiration_time <= now) {
+                    (true, false) => Outcome::Valid,
+                    (true, true) => Outcome::Expired,
+                    (false, _) => Outcome::Invalid,
+                };
+                Self {
+                    time: now,
+                    outcome,
+                    message: verification.to_string(),
+                    payload: Some(payload),
+                }
+            }
+            Err(e) => Self {
+                time: now,
+                outcome: Outcome::Invalid,
+                message: e.to_string(),
+                payload: None,
+            },
+        }
+    }
+}
+
+fn decode(
+    ctx: &DecodeContext,
+    line: &str,
+    max_size: u64,
+) -> color_eyre::Result<(CertPayload, Verification)> {
+    match decode_transport(line.as_bytes(), InputFormat::Hc1)? {
+        DecodedInput::Cose(data) => {
+            let (cose, _) = inflate(data, max_size)?;
+            let sign1 = parse_sign1(&cose)?;
+            let payload = ctx.decode_payload(&sign1)?;
+            let verification = ctx.verify(&sign1, &payload)?;
+            Ok((payload, verification))
+        }
+        DecodedInput::Claims(_) => Err(color_eyre::eyre::eyre!("Not a signed certificate")),
+    }
+}
+
+/// The running counts of the outcomes
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Counts {
+    pub valid: usize,
+    pub expired: usize,
+    pub invalid: usize,
+}
+
+/// The state of the dashboard
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    /// The most recent scan first
+    scans: VecDeque<Scan>,
+    counts: Counts,
+    /// Mask the name and date of birth
+    redact: bool,
+    /// The line that is being typed by the scanner
+    input: String,
+}
+
+impl Dashboard {
+    pub fn new(redact: bool) -> Self {
+        Self {
+            redact,
+            ..Self::default()
+        }
+    }
+
+    pub fn counts(&self) -> Counts {
+        self.counts
+    }
+
+    pub fn push(&mut self, scan: Scan) {
+        match scan.outcome {
+            Outcome::Valid => self.counts.valid += 1,
+            Outcome::Expired => self.counts.expired += 1,
+            Outcome::Invalid => self.counts.invalid += 1,
+        }
+        self.scans.push_front(scan);
+        self.scans.truncate(HISTORY);
+    }
+
+    fn personal(&self, value: String) -> String {
+        if self.redact {
+            MASK.to_string()
+        } else {
+            value
+        }
+    }
+
+    fn name(&self, payload: &CertPayload) -> String {
+        let name = &payload.health_claim.cert.name;
+        self.personal(format!("{}, {}", name.first_name, name.given_name))
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [counts, main, input] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+        let [feed, details] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(main);
+
+        let count = |outcome: Outcome, label: &str, n: usize| {
+            let style = Style::default().fg(outcome.color());
+            Span::styled(format!(" {} {}: {} ", outcome.symbol(), label, n), style)
+        };
+        let line = Line::from(vec![
+            count(Outcome::Valid, "Valid", self.counts.valid),
+            count(Outcome::Expired, "Expired", self.counts.expired),
+            count(Outcome::Invalid, "Invalid", self.counts.invalid),
+        ]);
+        let block = Block::default().borders(Borders::ALL).title(" dcc-decode ");
+        frame.render_widget(Paragraph::new(line).block(block), counts);
+
+        let items: Vec<ListItem> = self
+            .scans
+            .iter()
+            .map(|scan| {
+                let time = scan.time.with_timezone(&Local).format("%H:%M:%S");
+                let text = match &scan.payload {
+                    Some(payload) => self.name(payload),
+                    None => scan.message.clone(),
+                };
+                let style = Style::default().fg(scan.outcome.color());
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{} ", time)),
+                    Span::styled(format!("{} ", scan.outcome.symbol()), style),
+                    Span::raw(text),
+                ]))
+            })
+            .collect();
+        let block = Block::default().borders(Borders::ALL).title(" Scans ");
+        frame.render_widget(List::new(items).block(block), feed);
+
+        let block = Block::default().borders(Borders::ALL).title(" Last scan ");
+        let lines = self.scans.front().map(|scan| self.details(scan));
+        let paragraph = Paragraph::new(lines.unwrap_or_default()).block(block);
+        frame.render_widget(paragraph, details);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Scan a certificate (Esc to quit) ");
+        let paragraph = Paragraph::new(format!("> {}", self.input)).block(block);
+        frame.render_widget(paragraph, input);
+    }
+
+    fn details(&self, scan: &Scan) -> Vec<Line<'static>> {
+        let bold = Style::default()
+            .fg(scan.outcome.color())
+            .add_modifier(Modifier::BOLD);
+        let mut lines = vec![Line::from(Span::styled(
+            format!("{} {}", scan.outcome.symbol(), scan.message),
+            bold,
+        ))];
+        let payload = match &scan.payload {
+            Some(payload) => payload,
+            None => return lines,
+        };
+        let cert = &payload.health_claim.cert;
+        let row = |key: &str, value: String| {
+            Line::from(vec![
+                Span::styled(format!("{:<14}", key), Style::default().fg(Color::DarkGray)),
+                Span::raw(value),
+            ])
+        };
+        lines.push(Line::default());
+        lines.push(row("Name", self.name(payload)));
+        lines.push(row(
+            "Date of birth",
+            self.personal(cert.date_of_birth.to_string()),
+        ));
+        if let Some(entry) = cert.relevant_entry() {
+            lines.push(row(
+                "Entry",
+                format!("{} on {}", entry.kind(), entry.date()),
+            ));
+        }
+        lines.push(row("Issuer", payload.issuer.clone()));
+        lines.push(row("Expires", payload.expiration_time.to_string()));
+        lines
+    }
+}
+
+/// Run the dashboard until Esc (or Ctrl-C) is pressed
+///
+/// Each line that is typed (or sent by a barcode scanner) is decoded with `ctx`. Log
+/// messages should not be written to the terminal while the dashboard is shown.
+pub fn run(ctx: &DecodeContext, redact: bool, max_size: u64) -> io::Result<Counts> {
+    let mut terminal = ratatui::init();
+    let mut dashboard = Dashboard::new(redact);
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| dashboard.draw(frame)) {
+            break Err(e);
+        }
+        let key = match event::poll(Duration::from_millis(250))
+            .and_then(|ready| ready.then(event::read).transpose())
+        {
+            Ok(Some(Event::Key(key))) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+        match key.code {
+            KeyCode::Esc => break Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break Ok(()),
+            KeyCode::Char(c) => dashboard.input.push(c),
+            KeyCode::Backspace => {
+                dashboard.input.pop();
+            }
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut dashboard.input);
+                if !line.trim().is_empty() {
+                    dashboard.push(Scan::decode(ctx, line.trim(), max_size, Utc::now()));
+                }
+            }
+            _ => {}
+        }
+    };
+    ratatui::restore();
+    result.map(|()| dashboard.counts)
+}
@@ -0,0 +1,81 @@
+//! Persistence of verification results in an SQLite database
+//!
+//! Only metadata without personal data is stored. The UVCI is kept as its SHA-256 hash,
+//! which is enough to detect repeated scans of the same certificate.
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+use crate::session::SessionResult;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS results (
+    id INTEGER PRIMARY KEY,
+    scanned_at TEXT NOT NULL,
+    kid TEXT,
+    country TEXT,
+    type TEXT,
+    issued_at TEXT,
+    expires_at TEXT,
+    uvci_sha256 TEXT,
+    signature TEXT NOT NULL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS results_uvci ON results (uvci_sha256);";
+
+/// A database of verification results
+pub struct ResultDb {
+    conn: Connection,
+}
+
+impl ResultDb {
+    /// Open (or create) the database at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Store the result of one input
+    ///
+    /// Returns the number of earlier scans of the same certificate (by UVCI).
+    pub fn insert(
+        &self,
+        result: &SessionResult,
+        scanned_at: DateTime<Utc>,
+    ) -> rusqlite::Result<u64> {
+        let uvci_sha256 = result.uvci().map(uvci_hash);
+        let previous: u64 = match &uvci_sha256 {
+            Some(hash) => self.conn.query_row(
+                "SELECT COUNT(*) FROM results WHERE uvci_sha256 = ?1",
+                params![hash],
+                |row| row.get(0),
+            )?,
+            None => 0,
+        };
+        let kinds: Vec<&str> = result.entries().iter().map(|(kind, _)| *kind).collect();
+        let claims = result.claims.as_ref();
+        self.conn.execute(
+            "INSERT INTO results (scanned_at, kid, country, type, issued_at, expires_at,
+                uvci_sha256, signature, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                scanned_at.to_rfc3339(),
+                result.kid,
+                claims.map(|c| c.issuer.as_str()),
+                Some(kinds.join(" ")).filter(|kinds| !kinds.is_empty()),
+                claims.map(|c| c.issued_at.to_rfc3339()),
+                claims.map(|c| c.expiration_time.to_rfc3339()),
+                uvci_sha256,
+                result.signature_status(),
+                result.error,
+            ],
+        )?;
+        Ok(previous)
+    }
+}
+
+/// Hex-encoded SHA-256 of a UVCI
+fn uvci_hash(uvci: &str) -> String {
+    crate::crypto::sha256_hex(uvci.as_bytes())
+}
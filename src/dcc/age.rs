@@ -0,0 +1,343 @@
+//! The date of birth of the holder, and their age at the time of a validation
+//!
+//! The schema allows partial dates of birth (`1964`, `1964-08`) and an empty one, so the
+//! age is a range from the latest to the earliest possible birthday. Like the
+//! `dccDateOfBirth` operation of CertLogic, single values (e.g. the [`AgeBracket`]) are
+//! based on the latest possible date of birth, i.e. the youngest possible age.
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use chrono::{Datelike, NaiveDate};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The date of birth (`dob`) of the holder, which can be partial or empty
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DateOfBirth {
+    /// An empty `dob`, if the date of birth is not known
+    Unknown,
+    /// Only the year, e.g. `1964`
+    Year(i32),
+    /// The year and month, e.g. `1964-08`
+    Month(i32, u32),
+    /// The full date, e.g. `1964-08-12`
+    Date(NaiveDate),
+}
+
+impl DateOfBirth {
+    /// The year of birth, if it is known
+    pub fn year(&self) -> Option<i32> {
+        match *self {
+            Self::Unknown => None,
+            Self::Year(year) | Self::Month(year, _) => Some(year),
+            Self::Date(date) => Some(date.year()),
+        }
+    }
+
+    /// The earliest and the latest possible date of birth
+    pub fn range(&self) -> Option<(NaiveDate, NaiveDate)> {
+        match *self {
+            Self::Unknown => None,
+            Self::Year(year) => Some((
+                NaiveDate::from_ymd_opt(year, 1, 1)?,
+                NaiveDate::from_ymd_opt(year, 12, 31)?,
+            )),
+            Self::Month(year, month) => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let next = match month {
+                    12 => NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+                    _ => NaiveDate::from_ymd_opt(year, month + 1, 1)?,
+                };
+                Some((first, next.pred_opt()?))
+            }
+            Self::Date(date) => Some((date, date)),
+        }
+    }
+
+    /// The age of the holder on `date`, `None` if the date of birth is unknown or later
+    pub fn age_at(&self, date: NaiveDate) -> Option<Age> {
+        let (earliest, latest) = self.range()?;
+        Some(Age {
+            min: years_between(latest, date)?,
+            max: years_between(earliest, date)?,
+        })
+    }
+}
+
+/// The number of full years from `birth` to `date`
+fn years_between(birth: NaiveDate, date: NaiveDate) -> Option<u32> {
+    let had_birthday = (date.month(), date.day()) >= (birth.month(), birth.day());
+    let years = date.year() - birth.year() - i32::from(!had_birthday);
+    u32::try_from(years).ok()
+}
+
+impl fmt::Display for DateOfBirth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => Ok(()),
+            Self::Year(year) => write!(f, "{:04}", year),
+            Self::Month(year, month) => write!(f, "{:04}-{:02}", year, month),
+            Self::Date(date) => write!(f, "{}", date),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidDateOfBirth(String);
+
+impl std::error::Error for InvalidDateOfBirth {}
+impl fmt::Display for InvalidDateOfBirth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid date of birth '{}' (expected YYYY-MM-DD, YYYY-MM, YYYY or empty)",
+            self.0
+        )
+    }
+}
+
+impl FromStr for DateOfBirth {
+    type Err = InvalidDateOfBirth;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidDateOfBirth(s.to_string());
+        let number = |part: &str, len: usize| {
+            (part.len() == len && part.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| part.parse().ok())
+                .flatten()
+                .ok_or_else(invalid)
+        };
+        let parts: Vec<&str> = s.split('-').collect();
+        match parts.as_slice() {
+            [""] => Ok(Self::Unknown),
+            [year] => Ok(Self::Year(number(year, 4)? as i32)),
+            [year, month] => {
+                let (year, month) = (number(year, 4)? as i32, number(month, 2)?);
+                match NaiveDate::from_ymd_opt(year, month, 1) {
+                    Some(_) => Ok(Self::Month(year, month)),
+                    None => Err(invalid()),
+                }
+            }
+            [_, _, _] => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(Self::Date)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Serialize for DateOfBirth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DateOfBirth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = DateOfBirth;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a (partial) date of birth")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// The age of the holder in full years, a range if the date of birth is partial
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct Age {
+    /// The age for the latest possible date of birth
+    pub min: u32,
+    /// The age for the earliest possible date of birth
+    pub max: u32,
+}
+
+impl Age {
+    /// The age in years, for the latest possible date of birth
+    pub fn years(&self) -> u32 {
+        self.min
+    }
+
+    /// Whether the holder is younger than `years`, `None` if that depends on the missing
+    /// parts of a partial date of birth
+    ///
+    /// e.g. `age.is_under(12) == Some(true)` for a policy that exempts children under 12
+    /// from a test requirement.
+    pub fn is_under(&self, years: u32) -> Option<bool> {
+        if self.max < years {
+            Some(true)
+        } else if self.min >= years {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub fn bracket(&self) -> AgeBracket {
+        AgeBracket::of(self.years())
+    }
+}
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}–{}", self.min, self.max)
+        }
+    }
+}
+
+/// A coarse age group, e.g. to show in a redacted report
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgeBracket {
+    /// Younger than 12
+    Child,
+    /// 12 to 17
+    Adolescent,
+    /// 18 to 64
+    Adult,
+    /// 65 or older
+    Senior,
+}
+
+impl AgeBracket {
+    pub fn of(years: u32) -> Self {
+        match years {
+            0..=11 => Self::Child,
+            12..=17 => Self::Adolescent,
+            18..=64 => Self::Adult,
+            _ => Self::Senior,
+        }
+    }
+}
+
+impl fmt::Display for AgeBracket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Child => write!(f, "under 12"),
+            Self::Adolescent => write!(f, "12–17"),
+            Self::Adult => write!(f, "18–64"),
+            Self::Senior => write!(f, "65 or older"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{Age, AgeBracket, DateOfBirth};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn age(dob: &str, at: NaiveDate) -> Option<Age> {
+        dob.parse::<DateOfBirth>().unwrap().age_at(at)
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("".parse::<DateOfBirth>().unwrap(), DateOfBirth::Unknown);
+        assert_eq!(
+            "1964".parse::<DateOfBirth>().unwrap(),
+            DateOfBirth::Year(1964)
+        );
+        let month = "1964-08".parse::<DateOfBirth>().unwrap();
+        assert_eq!(month, DateOfBirth::Month(1964, 8));
+        assert_eq!(month.to_string(), "1964-08");
+        let full = "1964-08-12".parse::<DateOfBirth>().unwrap();
+        assert_eq!(full, DateOfBirth::Date(date(1964, 8, 12)));
+
+        for invalid in [
+            "64",
+            "1964-8",
+            "1964-13",
+            "1964-00",
+            "2021-02-29",
+            "1964-08-12-1",
+        ] {
+            assert!(invalid.parse::<DateOfBirth>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn year_only() {
+        let partial = age("1964", date(2021, 6, 1)).unwrap();
+        assert_eq!(partial, Age { min: 56, max: 57 });
+        assert_eq!(partial.years(), 56);
+        assert_eq!(partial.to_string(), "56–57");
+        assert_eq!(partial.is_under(57), None);
+        assert_eq!(partial.is_under(58), Some(true));
+        assert_eq!(partial.is_under(56), Some(false));
+        // On the last day of the year, all possible birthdays have passed
+        assert_eq!(
+            age("1964", date(2021, 12, 31)),
+            Some(Age { min: 57, max: 57 })
+        );
+    }
+
+    #[test]
+    fn year_and_month() {
+        let dob = "1964-08".parse::<DateOfBirth>().unwrap();
+        assert_eq!(dob.range(), Some((date(1964, 8, 1), date(1964, 8, 31))));
+        assert_eq!(
+            dob.age_at(date(2021, 7, 31)),
+            Some(Age { min: 56, max: 56 })
+        );
+        assert_eq!(
+            dob.age_at(date(2021, 8, 15)),
+            Some(Age { min: 56, max: 57 })
+        );
+        assert_eq!(dob.age_at(date(2021, 9, 1)), Some(Age { min: 57, max: 57 }));
+
+        let december = DateOfBirth::Month(2000, 12);
+        assert_eq!(
+            december.range(),
+            Some((date(2000, 12, 1), date(2000, 12, 31)))
+        );
+        let february = DateOfBirth::Month(2000, 2);
+        assert_eq!(
+            february.range(),
+            Some((date(2000, 2, 1), date(2000, 2, 29)))
+        );
+    }
+
+    #[test]
+    fn birthday() {
+        assert_eq!(age("2003-06-15", date(2021, 6, 14)).unwrap().years(), 17);
+        assert_eq!(age("2003-06-15", date(2021, 6, 15)).unwrap().years(), 18);
+        assert_eq!(age("2003-06-15", date(2003, 6, 15)).unwrap().years(), 0);
+        assert_eq!(age("2003-06-15", date(2003, 6, 14)), None);
+        assert_eq!(age("", date(2021, 6, 15)), None);
+    }
+
+    #[test]
+    fn leap_day() {
+        // Without a Feb 29, the birthday is on Mar 1
+        assert_eq!(age("2000-02-29", date(2021, 2, 28)).unwrap().years(), 20);
+        assert_eq!(age("2000-02-29", date(2021, 3, 1)).unwrap().years(), 21);
+        assert_eq!(age("2000-02-29", date(2024, 2, 28)).unwrap().years(), 23);
+        assert_eq!(age("2000-02-29", date(2024, 2, 29)).unwrap().years(), 24);
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(AgeBracket::of(11), AgeBracket::Child);
+        assert_eq!(AgeBracket::of(12), AgeBracket::Adolescent);
+        assert_eq!(AgeBracket::of(18), AgeBracket::Adult);
+        assert_eq!(AgeBracket::of(65), AgeBracket::Senior);
+        // Based on the youngest possible age
+        let partial = age("2009", date(2021, 6, 1)).unwrap();
+        assert_eq!(partial.bracket(), AgeBracket::Child);
+    }
+}
@@ -0,0 +1,255 @@
+//! Borrowed variants of the payload structs, for decoding many certificates quickly
+//!
+//! The text fields point into the CBOR encoded payload instead of being copied into a
+//! `String` each, so that decoding only allocates for the lists of entries. This is meant
+//! for batch jobs that look at a few fields of millions of payloads; value set codes are
+//! not resolved, the schema version is not checked and unknown fields are skipped.
+//!
+//! Text must be encoded with a definite length to be borrowed. Payloads with chunked
+//! strings fail to decode here and need [`CertPayload`](super::CertPayload) instead.
+use std::convert::TryFrom;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use serde::{
+    de::{Error, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use super::{DateOfBirth, DecodeError, Timestamp};
+
+/// The CWT claims of a DCC, borrowing from the encoded payload
+#[derive(Debug)]
+pub struct CertPayloadRef<'a> {
+    pub issuer: &'a str,
+    pub expiration_time: DateTime<Utc>,
+    pub issued_at: DateTime<Utc>,
+    /// The certificate from the `hcert` claim
+    pub cert: DigitalCovidCertificateRef<'a>,
+}
+
+impl<'a> CertPayloadRef<'a> {
+    /// Decode the CWT claims from their CBOR encoding, borrowing the text from `bytes`
+    pub fn from_slice(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        serde_cbor::from_slice(bytes).map_err(|e| DecodeError::payload(bytes, e))
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expiration_time <= now
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DigitalCovidCertificateRef<'a> {
+    #[serde(rename = "v", default, borrow)]
+    pub vaccine: Vec<VaccinationRef<'a>>,
+    #[serde(rename = "t", default, borrow)]
+    pub test: Vec<TestRef<'a>>,
+    #[serde(rename = "r", default, borrow)]
+    pub recovery: Vec<RecoveryRef<'a>>,
+    #[serde(rename = "dob")]
+    pub date_of_birth: DateOfBirth,
+    #[serde(rename = "nam", borrow)]
+    pub name: NameRef<'a>,
+    #[serde(rename = "ver")]
+    pub version: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NameRef<'a> {
+    #[serde(rename = "fn")]
+    pub first_name: &'a str,
+    #[serde(rename = "gn")]
+    pub given_name: &'a str,
+    #[serde(rename = "fnt")]
+    pub first_name_transliterated: &'a str,
+    #[serde(rename = "gnt")]
+    pub given_name_transliterated: &'a str,
+}
+
+/// A vaccination entry, with the codes of the value set entries
+#[derive(Debug, Deserialize)]
+pub struct VaccinationRef<'a> {
+    #[serde(rename = "tg")]
+    pub disease_agent_targeted: &'a str,
+    #[serde(rename = "vp")]
+    pub vaccine_or_prophylaxis: &'a str,
+    #[serde(rename = "mp")]
+    pub medicinal_product: &'a str,
+    #[serde(rename = "ma")]
+    pub manufacturer: &'a str,
+    #[serde(rename = "dn")]
+    pub dose_number: u32,
+    #[serde(rename = "sd")]
+    pub series_dose_number: u32,
+    #[serde(rename = "dt")]
+    pub date: NaiveDate,
+    #[serde(rename = "co")]
+    pub country: &'a str,
+    #[serde(rename = "is")]
+    pub issuer: &'a str,
+    #[serde(rename = "ci")]
+    pub cert_identifier: &'a str,
+}
+
+/// A test entry, with the codes of the value set entries
+#[derive(Debug, Deserialize)]
+pub struct TestRef<'a> {
+    #[serde(rename = "tg")]
+    pub disease_agent_targeted: &'a str,
+    #[serde(rename = "tt")]
+    pub test_type: &'a str,
+    #[serde(rename = "nm", default, borrow)]
+    pub name: Option<&'a str>,
+    #[serde(rename = "ma", default, borrow)]
+    pub manufacturer: Option<&'a str>,
+    #[serde(rename = "sc")]
+    pub sample_collection: DateTime<FixedOffset>,
+    #[serde(rename = "dr", default)]
+    pub result_date: Option<DateTime<FixedOffset>>,
+    #[serde(rename = "tr")]
+    pub result: &'a str,
+    #[serde(rename = "tc", default, borrow)]
+    pub testing_centre: Option<&'a str>,
+    #[serde(rename = "co")]
+    pub country: &'a str,
+    #[serde(rename = "is")]
+    pub issuer: &'a str,
+    #[serde(rename = "ci")]
+    pub cert_identifier: &'a str,
+}
+
+/// A recovery entry, with the codes of the value set entries
+#[derive(Debug, Deserialize)]
+pub struct RecoveryRef<'a> {
+    #[serde(rename = "tg")]
+    pub disease_agent_targeted: &'a str,
+    #[serde(rename = "fr")]
+    pub first_positive: NaiveDate,
+    #[serde(rename = "co")]
+    pub country: &'a str,
+    #[serde(rename = "is")]
+    pub issuer: &'a str,
+    #[serde(rename = "df")]
+    pub valid_from: NaiveDate,
+    #[serde(rename = "du")]
+    pub valid_until: NaiveDate,
+    #[serde(rename = "ci")]
+    pub cert_identifier: &'a str,
+}
+
+/// A CWT claim key, which can be an integer or a text (RFC 8392)
+///
+/// Only integer keys are known, any other key is skipped with its value.
+struct ClaimKey(Option<i64>);
+
+impl<'de> Deserialize<'de> for ClaimKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = ClaimKey;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(formatter, "an integer or text claim key")
+            }
+
+            fn visit_i64<E: Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(ClaimKey(Some(v)))
+            }
+
+            fn visit_u64<E: Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(ClaimKey(i64::try_from(v).ok()))
+            }
+
+            fn visit_i128<E: Error>(self, _: i128) -> Result<Self::Value, E> {
+                Ok(ClaimKey(None))
+            }
+
+            fn visit_str<E: Error>(self, _: &str) -> Result<Self::Value, E> {
+                Ok(ClaimKey(None))
+            }
+        }
+
+        deserializer.deserialize_any(KeyVisitor)
+    }
+}
+
+struct PayloadVisitor;
+
+impl<'de> Visitor<'de> for PayloadVisitor {
+    type Value = CertPayloadRef<'de>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "a eHealth certification payload")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut issuer = None;
+        let mut expiration_time: Option<Timestamp> = None;
+        let mut issued_at: Option<Timestamp> = None;
+        let mut cert: Option<HealthClaimRef> = None;
+
+        while let Some(ClaimKey(key)) = map.next_key()? {
+            match key {
+                Some(1) => issuer = Some(map.next_value()?),
+                Some(4) => expiration_time = Some(map.next_value()?),
+                Some(6) => issued_at = Some(map.next_value()?),
+                Some(-260) => cert = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(CertPayloadRef {
+            issuer: issuer.ok_or_else(|| A::Error::missing_field("issuer (1)"))?,
+            expiration_time: expiration_time
+                .ok_or_else(|| A::Error::missing_field("expiration_time (4)"))?
+                .0,
+            issued_at: issued_at
+                .ok_or_else(|| A::Error::missing_field("issued_at (6)"))?
+                .0,
+            cert: cert
+                .ok_or_else(|| A::Error::missing_field("health_claim (-260)"))?
+                .0,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for CertPayloadRef<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(PayloadVisitor)
+    }
+}
+
+/// The `hcert` claim, with the certificate at key 1
+struct HealthClaimRef<'a>(DigitalCovidCertificateRef<'a>);
+
+struct HealthClaimVisitor;
+
+impl<'de> Visitor<'de> for HealthClaimVisitor {
+    type Value = HealthClaimRef<'de>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "a eHealth certification payload inner")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut cert = None;
+        while let Some(ClaimKey(key)) = map.next_key()? {
+            match key {
+                Some(1) => cert = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        cert.map(HealthClaimRef)
+            .ok_or_else(|| A::Error::missing_field("eu_dgc_v1 (1)"))
+    }
+}
+
+impl<'de> Deserialize<'de> for HealthClaimRef<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(HealthClaimVisitor)
+    }
+}
@@ -0,0 +1,59 @@
+//! Excerpts of the input of a decoding stage, to point at the position of an error
+use std::fmt;
+
+/// The number of characters (or bytes) that are shown on each side of the position
+const RADIUS: usize = 8;
+
+/// The input of a stage around the position of an error, with that position in brackets
+///
+/// e.g. `6BFOX[~]N0` for an invalid base45 character or `a2 01 [ff] 02` for binary data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Excerpt {
+    /// The offset of the error (in characters of text, or in bytes)
+    pub offset: usize,
+    context: String,
+}
+
+impl Excerpt {
+    /// An excerpt of an ASCII text, e.g. base45
+    pub fn text(text: &str, offset: usize) -> Self {
+        let bytes = text.as_bytes();
+        let (before, at, after) = split(bytes, offset);
+        let escape = |part: &[u8]| part.escape_ascii().to_string();
+        let context = match at {
+            Some(at) => format!("{}[{}]{}", escape(before), escape(&[at]), escape(after)),
+            None => format!("{}[]", escape(before)),
+        };
+        Self { offset, context }
+    }
+
+    /// An excerpt of binary data, in hex
+    pub fn bytes(data: &[u8], offset: usize) -> Self {
+        let (before, at, after) = split(data, offset);
+        let mut parts: Vec<String> = before.iter().map(|b| format!("{:02x}", b)).collect();
+        parts.push(match at {
+            Some(at) => format!("[{:02x}]", at),
+            None => "[]".to_string(),
+        });
+        parts.extend(after.iter().map(|b| format!("{:02x}", b)));
+        Self {
+            offset,
+            context: parts.join(" "),
+        }
+    }
+}
+
+/// The up to [`RADIUS`] items before `offset`, the one at `offset` and those after it
+fn split(data: &[u8], offset: usize) -> (&[u8], Option<u8>, &[u8]) {
+    let offset = offset.min(data.len());
+    let before = &data[offset.saturating_sub(RADIUS)..offset];
+    let at = data.get(offset).copied();
+    let after = data.get(offset + 1..).unwrap_or_default();
+    (before, at, &after[..after.len().min(RADIUS)])
+}
+
+impl fmt::Display for Excerpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "near `{}`", self.context)
+    }
+}
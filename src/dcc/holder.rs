@@ -0,0 +1,255 @@
+//! Matching the holder of a certificate against the names read from an ID document
+//!
+//! The transliterated names of a DCC (`fnt`, `gnt`) use the same ICAO 9303 alphabet as
+//! the machine readable zone (MRZ) of a passport: `A-Z`, with `<` between the parts of a
+//! name. Both can be truncated to fit their fields, so the names are compared part by
+//! part, and a name that is a prefix of the other counts as a truncated match.
+use serde::Serialize;
+
+use super::DigitalCovidCertificate;
+
+/// The minimal length of a prefix that counts as a truncated name
+const MIN_TRUNCATED_LEN: usize = 3;
+
+/// How one name of the certificate matches the one of the ID document
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NameMatch {
+    /// The names are the same (after normalizing the separators)
+    Exact,
+    /// One name is a prefix of the other, e.g. `MUSTERMANN<GABL` and `MUSTERMANN<GABLER`
+    Truncated,
+    /// Some parts of the names are the same, e.g. `MUSTERMANN<GABLER` and `MUSTERMANN`
+    Partial { matched: usize, total: usize },
+    /// Only one of the names is given, e.g. a DCC without a `gnt`
+    Missing,
+    /// None of the parts of the names are the same
+    Mismatch,
+}
+
+impl NameMatch {
+    /// Compare a transliterated name of the certificate with one of the MRZ
+    pub fn of(cert: &str, mrz: &str) -> Self {
+        let cert = normalize(cert);
+        let mrz = normalize(mrz);
+        if cert == mrz {
+            return Self::Exact;
+        }
+        if cert.is_empty() || mrz.is_empty() {
+            return Self::Missing;
+        }
+        let (shorter, longer) = if cert.len() < mrz.len() {
+            (&cert, &mrz)
+        } else {
+            (&mrz, &cert)
+        };
+        // A name that ends between two parts of the other one is a partial match instead
+        let cut_in_part = longer.as_bytes().get(shorter.len()) != Some(&b'<');
+        if shorter.len() >= MIN_TRUNCATED_LEN && longer.starts_with(shorter.as_str()) && cut_in_part
+        {
+            return Self::Truncated;
+        }
+        let cert_parts: Vec<&str> = cert.split('<').collect();
+        let mrz_parts: Vec<&str> = mrz.split('<').collect();
+        let total = cert_parts.len().max(mrz_parts.len());
+        let matched = mrz_parts
+            .iter()
+            .enumerate()
+            .filter(|(i, part)| {
+                // Only the last part of the MRZ can be truncated
+                let truncated = *i + 1 == mrz_parts.len() && part.len() >= MIN_TRUNCATED_LEN;
+                cert_parts.iter().any(|cert_part| {
+                    cert_part == *part || truncated && cert_part.starts_with(*part)
+                })
+            })
+            .count();
+        match matched {
+            0 => Self::Mismatch,
+            matched => Self::Partial { matched, total },
+        }
+    }
+
+    /// The score of the match, from `0.0` (mismatch) to `1.0` (exact)
+    pub fn score(&self) -> f64 {
+        match *self {
+            Self::Exact => 1.0,
+            Self::Truncated => 0.9,
+            Self::Partial { matched, total } => 0.8 * matched as f64 / total as f64,
+            Self::Missing => 0.5,
+            Self::Mismatch => 0.0,
+        }
+    }
+}
+
+/// The result of [`match_holder`]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct HolderMatch {
+    /// The mean of the scores of both names, from `0.0` to `1.0`
+    pub score: f64,
+    /// The match of the surname (`fnt`)
+    pub surname: NameMatch,
+    /// The match of the given names (`gnt`)
+    pub given_name: NameMatch,
+}
+
+impl HolderMatch {
+    /// Whether both names are the same, or truncated versions of each other
+    pub fn is_match(&self) -> bool {
+        let full = |m: NameMatch| matches!(m, NameMatch::Exact | NameMatch::Truncated);
+        full(self.surname) && full(self.given_name)
+    }
+}
+
+/// Normalize a name to the MRZ alphabet, with single `<` between its parts
+fn normalize(name: &str) -> String {
+    let upper = name.trim().to_ascii_uppercase();
+    let parts = upper.split(|c: char| !c.is_ascii_uppercase());
+    parts
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("<")
+}
+
+/// Match the holder of `cert` with the surname and given names of an ID document
+///
+/// The names are compared with the transliterated names of the certificate, following
+/// the eHN guidance for matching a DCC with an ID document: separators (`<`, spaces,
+/// hyphens) are equivalent, either name can be truncated to the length of its field,
+/// and names that only share some of their parts are a partial match.
+pub fn match_holder(
+    cert: &DigitalCovidCertificate,
+    mrz_surname: &str,
+    mrz_given: &str,
+) -> HolderMatch {
+    let surname = NameMatch::of(&cert.name.first_name_transliterated, mrz_surname);
+    let given_name = NameMatch::of(&cert.name.given_name_transliterated, mrz_given);
+    HolderMatch {
+        score: (surname.score() + given_name.score()) / 2.0,
+        surname,
+        given_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{match_holder, NameMatch};
+    use crate::dcc::DigitalCovidCertificate;
+
+    fn cert(fnt: &str, gnt: &str) -> DigitalCovidCertificate {
+        let cert = json!({
+            "ver": "1.3.0",
+            "dob": "1964-08-12",
+            "nam": { "fn": "Mustermann", "gn": "Erika", "fnt": fnt, "gnt": gnt },
+        });
+        serde_json::from_value(cert).unwrap()
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(NameMatch::of("MUSTERMANN", "MUSTERMANN"), NameMatch::Exact);
+        // Separators, case and surrounding whitespace are normalized
+        assert_eq!(
+            NameMatch::of("MUSTERMANN<GABLER", " mustermann-gabler "),
+            NameMatch::Exact
+        );
+        assert_eq!(
+            NameMatch::of("MUSTERMANN<<GABLER", "MUSTERMANN GABLER"),
+            NameMatch::Exact
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let name = NameMatch::of("MUSTERMANN<GABLER", "MUSTERMANN<GABL");
+        assert_eq!(name, NameMatch::Truncated);
+        assert_eq!(NameMatch::of("MUS", "MUSTERMANN"), NameMatch::Truncated);
+        // Too short to count as a truncated name
+        assert_eq!(NameMatch::of("MU", "MUSTERMANN"), NameMatch::Mismatch);
+    }
+
+    #[test]
+    fn partial() {
+        // A name that ends between two parts of the other one
+        let name = NameMatch::of("MUSTERMANN<GABLER", "MUSTERMANN");
+        assert_eq!(
+            name,
+            NameMatch::Partial {
+                matched: 1,
+                total: 2
+            }
+        );
+        assert!((name.score() - 0.4).abs() < 1e-9);
+        // Parts in another order, with the last part of the MRZ truncated
+        let name = NameMatch::of("ERIKA<MARIA", "MARIA<ERI");
+        assert_eq!(
+            name,
+            NameMatch::Partial {
+                matched: 2,
+                total: 2
+            }
+        );
+        // Only the last part of the MRZ can be truncated
+        let name = NameMatch::of("ERIKA<MARIA", "ERI<MARIA");
+        assert_eq!(
+            name,
+            NameMatch::Partial {
+                matched: 1,
+                total: 2
+            }
+        );
+    }
+
+    #[test]
+    fn missing_and_mismatch() {
+        assert_eq!(NameMatch::of("", "ERIKA"), NameMatch::Missing);
+        assert_eq!(NameMatch::of("ERIKA", "<<"), NameMatch::Missing);
+        assert_eq!(NameMatch::of("SCHMIDT", "MUSTERMANN"), NameMatch::Mismatch);
+        assert_eq!(
+            NameMatch::of("ERIKA<MARIA", "ANNA<LENA"),
+            NameMatch::Mismatch
+        );
+        assert_eq!(NameMatch::Mismatch.score(), 0.0);
+    }
+
+    #[test]
+    fn holder_matches() {
+        let cert = cert("MUSTERMANN<GABLER", "ERIKA<DOERTE");
+        let holder = match_holder(&cert, "MUSTERMANN<GABLER", "ERIKA<DOE");
+        assert_eq!(holder.surname, NameMatch::Exact);
+        assert_eq!(holder.given_name, NameMatch::Truncated);
+        assert!((holder.score - 0.95).abs() < 1e-9);
+        assert!(holder.is_match());
+
+        let holder = match_holder(&cert, "mustermann gabler", "erika doerte");
+        assert_eq!(holder.score, 1.0);
+        assert!(holder.is_match());
+    }
+
+    #[test]
+    fn holder_does_not_match() {
+        let cert = cert("MUSTERMANN<GABLER", "ERIKA<DOERTE");
+        let holder = match_holder(&cert, "SCHMIDT", "ERIKA");
+        assert_eq!(holder.surname, NameMatch::Mismatch);
+        assert_eq!(
+            holder.given_name,
+            NameMatch::Partial {
+                matched: 1,
+                total: 2
+            }
+        );
+        assert!((holder.score - 0.2).abs() < 1e-9);
+        assert!(!holder.is_match());
+
+        // A partial match of the surname is not a match of the holder
+        let holder = match_holder(&cert, "MUSTERMANN", "ERIKA<DOERTE");
+        assert!(!holder.is_match());
+
+        // Without a transliterated given name
+        let holder = match_holder(&self::cert("MUSTERMANN", ""), "MUSTERMANN", "ERIKA");
+        assert_eq!(holder.given_name, NameMatch::Missing);
+        assert!((holder.score - 0.75).abs() < 1e-9);
+        assert!(!holder.is_match());
+    }
+}
@@ -0,0 +1,196 @@
+//! Decoding of the intermediate stages of a DCC (e.g. a raw COSE message)
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use super::{decode_base45, inflate, DecodeError};
+use crate::b45::base45_decode;
+
+/// The stage of the decoding pipeline that the input comes from
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// The full `HC1:` string from the QR code
+    #[default]
+    Hc1,
+    /// The base45 encoded (compressed) COSE message, without the prefix
+    Base45,
+    /// The base64 encoded (compressed) COSE message
+    Base64,
+    /// The hex encoded (compressed) COSE message
+    Hex,
+    /// The binary (compressed) COSE message
+    Cose,
+    /// The binary CWT claims, without a signature
+    Cbor,
+}
+
+impl InputFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Hc1 => "hc1",
+            Self::Base45 => "base45",
+            Self::Base64 => "base64",
+            Self::Hex => "hex",
+            Self::Cose => "cose",
+            Self::Cbor => "cbor",
+        }
+    }
+
+    /// Whether the input is binary data instead of text
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Self::Cose | Self::Cbor)
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownInputFormat(String);
+
+impl std::error::Error for UnknownInputFormat {}
+impl fmt::Display for UnknownInputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown input format '{}' (expected 'hc1', 'base45', 'base64', 'hex', 'cose' or 'cbor')",
+            self.0
+        )
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = UnknownInputFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hc1" => Ok(Self::Hc1),
+            "base45" => Ok(Self::Base45),
+            "base64" => Ok(Self::Base64),
+            "hex" => Ok(Self::Hex),
+            "cose" => Ok(Self::Cose),
+            "cbor" => Ok(Self::Cbor),
+            _ => Err(UnknownInputFormat(s.to_string())),
+        }
+    }
+}
+
+/// The result of decoding the input up to the COSE message (or CWT claims)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedInput {
+    /// A COSE_Sign1 message
+    Cose(Vec<u8>),
+    /// The CBOR encoded CWT claims
+    Claims(Vec<u8>),
+}
+
+/// Decode `input` in the given format, limiting the decompressed size to `max_size` bytes
+pub fn decode_input(
+    input: &[u8],
+    format: InputFormat,
+    max_size: u64,
+) -> Result<DecodedInput, DecodeError> {
+    match decode_transport(input, format)? {
+        DecodedInput::Cose(data) => {
+            inflate(data, max_size).map(|(cose, _)| DecodedInput::Cose(cose))
+        }
+        claims => Ok(claims),
+    }
+}
+
+/// Undo the text encoding of `input`, without decompressing the COSE message
+///
+/// The text formats are cleaned up with [`normalize`] first, see [`decode_transport_strict`]
+/// for the exact input.
+pub fn decode_transport(input: &[u8], format: InputFormat) -> Result<DecodedInput, DecodeError> {
+    transport(input, format, true)
+}
+
+/// Undo the text encoding of `input` like [`decode_transport`], without [`normalize`]
+pub fn decode_transport_strict(
+    input: &[u8],
+    format: InputFormat,
+) -> Result<DecodedInput, DecodeError> {
+    transport(input, format, false)
+}
+
+#[instrument(name = "input", level = "debug", skip_all, fields(%format))]
+fn transport(
+    input: &[u8],
+    format: InputFormat,
+    tolerant: bool,
+) -> Result<DecodedInput, DecodeError> {
+    let text = || {
+        let text = std::str::from_utf8(input)
+            .map(str::trim)
+            .map_err(DecodeError::Text)?;
+        Ok::<_, DecodeError>(if tolerant {
+            normalize(text, format)
+        } else {
+            Cow::Borrowed(text)
+        })
+    };
+    let data = match format {
+        InputFormat::Hc1 => decode_base45(&text()?)?,
+        InputFormat::Base45 => {
+            let text = text()?;
+            base45_decode(&text).map_err(|e| DecodeError::base45(&text, e))?
+        }
+        InputFormat::Base64 => base64::decode(text()?.as_ref()).map_err(DecodeError::Base64)?,
+        InputFormat::Hex => decode_hex(&text()?)?,
+        InputFormat::Cose => input.to_vec(),
+        InputFormat::Cbor => return Ok(DecodedInput::Claims(input.to_vec())),
+    };
+    debug!("Decoded {} input ({} bytes)", format, data.len());
+    Ok(DecodedInput::Cose(data))
+}
+
+/// Undo common changes by barcode scanners to the text of a QR code
+///
+/// Line breaks and tabs are removed anywhere, so that payloads wrapped across several
+/// lines are joined, and a `hc1:` prefix in any case is replaced by `HC1:`. Spaces are
+/// part of the base45 alphabet, so they are kept inside the text.
+pub fn normalize(text: &str, format: InputFormat) -> Cow<'_, str> {
+    let text = text.trim();
+    let wrapped = text.contains(['\r', '\n', '\t']);
+    let prefix = format == InputFormat::Hc1
+        && text.len() >= 4
+        && text.is_char_boundary(4)
+        && text[..4].eq_ignore_ascii_case("HC1:")
+        && !text.starts_with("HC1:");
+    if !wrapped && !prefix {
+        return Cow::Borrowed(text);
+    }
+
+    let mut normalized: String = text
+        .chars()
+        .filter(|c| !matches!(c, '\r' | '\n' | '\t'))
+        .collect();
+    if prefix {
+        normalized.replace_range(..4, "HC1:");
+    }
+    debug!(wrapped, prefix, "Normalized the {} input", format);
+    Cow::Owned(normalized)
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, DecodeError> {
+    let digits: Vec<(usize, char)> = text
+        .char_indices()
+        .filter(|(_, c)| !c.is_ascii_whitespace())
+        .collect();
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let digit = |(pos, c): (usize, char)| c.to_digit(16).ok_or(DecodeError::Hex(pos));
+            let high = digit(pair[0])?;
+            // An odd number of digits is missing the last one
+            let low = digit(pair.get(1).copied().unwrap_or((text.len(), ' ')))?;
+            Ok((high << 4 | low) as u8)
+        })
+        .collect()
+}
@@ -1,56 +1,431 @@
-use std::{convert::TryFrom, io::Read};
+use std::{
+    collections::BTreeMap, convert::TryFrom, error::Error as StdError, fmt, io::Read, str::FromStr,
+};
 
-use chrono::{DateTime, NaiveDate, Utc};
-use color_eyre::eyre::eyre;
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
 use flate2::bufread::ZlibDecoder;
-use log::debug;
 use serde::{de::Error, Deserialize, Serialize};
 use serde_cose::Sign1;
+use tracing::{debug, instrument, warn};
 
-use self::valuesets::ValueSetEntry;
+use self::valuesets::{EhnData, ValueSetEntry};
+pub use self::{
+    age::{Age, AgeBracket, DateOfBirth, InvalidDateOfBirth},
+    excerpt::Excerpt,
+    holder::{match_holder, HolderMatch, NameMatch},
+    warning::{DecodeOutcome, Warning},
+};
+use crate::b45::{base45_decode, Base45DecodeError, Base45Error};
 
+mod age;
+pub mod borrowed;
+mod excerpt;
+mod holder;
+pub mod input;
+mod value;
 pub mod valuesets;
+mod warning;
 
-#[derive(Debug)]
+/// The CWT claims of a DCC
+///
+/// Serializes with the claim names from RFC 8392 and ISO-8601 timestamps.
+#[derive(Debug, Serialize)]
 pub struct CertPayload {
+    #[serde(rename = "iss")]
     pub issuer: String,
+    #[serde(rename = "exp")]
     pub expiration_time: DateTime<Utc>,
+    #[serde(rename = "iat")]
     pub issued_at: DateTime<Utc>,
+    #[serde(rename = "hcert")]
     pub health_claim: HealthClaim,
+    /// Unknown claims, by their (numeric) key
+    #[serde(flatten)]
+    pub extra: Extra,
+    /// The unknown claims (with their CBOR type) found while deserializing, which
+    /// [`DecodeOutcome::new`] takes over
+    #[serde(skip)]
+    pub warnings: Vec<Warning>,
 }
 
-pub fn load_sign1(buf: &str) -> color_eyre::Result<Sign1> {
-    let text = buf.trim_end_matches('\n');
-    let text = match text.strip_prefix("HC1:") {
-        Some(tail) => tail,
-        None => {
-            return Err(eyre!("Expected a string that starts with 'HC1:'"));
+/// Fields that are not part of the schema, kept to show what was encoded
+///
+/// Byte strings are converted to base64 and tags are dropped, so that the values can be
+/// written as JSON.
+pub type Extra = BTreeMap<String, serde_json::Value>;
+
+/// Convert a CBOR value of an unknown field to JSON
+fn cbor_to_json(value: serde_cbor::Value) -> serde_json::Value {
+    use serde_cbor::Value;
+    use serde_json::{json, Value as Json};
+
+    match value {
+        Value::Null => Json::Null,
+        Value::Bool(b) => Json::Bool(b),
+        Value::Integer(n) => match (i64::try_from(n), u64::try_from(n)) {
+            (Ok(n), _) => json!(n),
+            (_, Ok(n)) => json!(n),
+            _ => json!(n.to_string()),
+        },
+        Value::Float(f) => json!(f),
+        Value::Bytes(bytes) => json!(base64::encode(bytes)),
+        Value::Text(text) => Json::String(text),
+        Value::Array(items) => Json::Array(items.into_iter().map(cbor_to_json).collect()),
+        Value::Map(map) => Json::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let key = match cbor_to_json(key) {
+                        Json::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, cbor_to_json(value))
+                })
+                .collect(),
+        ),
+        Value::Tag(_, inner) => cbor_to_json(*inner),
+        _ => Json::Null,
+    }
+}
+
+/// The name of the CBOR type of a value, as in [`crate::cbor::ItemKind::type_name`]
+fn cbor_type_name(value: &serde_cbor::Value) -> &'static str {
+    use serde_cbor::Value;
+
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Integer(_) => "integer",
+        Value::Float(_) => "float",
+        Value::Bytes(_) => "bytes",
+        Value::Text(_) => "text",
+        Value::Array(_) => "array",
+        Value::Map(_) => "map",
+        Value::Tag(..) => "tag",
+        _ => "simple",
+    }
+}
+
+/// The name of a CWT claim key, which can be an integer or a text (RFC 8392)
+fn claim_name(key: &serde_cbor::Value) -> String {
+    match key {
+        serde_cbor::Value::Integer(n) => n.to_string(),
+        serde_cbor::Value::Text(text) => text.clone(),
+        key => cbor_to_json(key.clone()).to_string(),
+    }
+}
+
+/// Deserialize the unknown fields of a struct (with `#[serde(flatten)]`)
+fn deserialize_extra<'de, D>(deserializer: D) -> Result<Extra, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let fields = BTreeMap::<String, serde_cbor::Value>::deserialize(deserializer)?;
+    Ok(fields
+        .into_iter()
+        .map(|(key, value)| (key, cbor_to_json(value)))
+        .collect())
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    MissingPrefix,
+    /// The input is not valid UTF-8, but the format is a text format
+    Text(std::str::Utf8Error),
+    Base64(base64::DecodeError),
+    /// The input contains an invalid hex digit at the given position
+    Hex(usize),
+    /// Invalid base45, at the (character) offset of the excerpt
+    Base45(Base45Error, Excerpt),
+    /// An invalid zlib stream, at the offset of the excerpt in the compressed data
+    Zlib(std::io::Error, Excerpt),
+    /// The decompressed data exceeds the given limit (in bytes)
+    TooLarge(u64),
+    /// A malformed COSE message, with an excerpt if the error has an offset
+    Cose(serde_cbor::Error, Option<Excerpt>),
+    /// A malformed CWT payload, with an excerpt if the error has an offset
+    Payload(serde_cbor::Error, Option<Excerpt>),
+    /// The `ver` of the certificate is not a schema version that can be decoded
+    SchemaVersion(String),
+}
+
+impl StdError for DecodeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::MissingPrefix | Self::Hex(_) | Self::TooLarge(_) | Self::SchemaVersion(_) => None,
+            Self::Text(e) => Some(e),
+            Self::Base64(e) => Some(e),
+            Self::Base45(e, _) => Some(e),
+            Self::Zlib(e, _) => Some(e),
+            Self::Cose(e, _) => Some(e),
+            Self::Payload(e, _) => Some(e),
         }
-    };
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingPrefix => write!(f, "Expected a string that starts with 'HC1:'"),
+            Self::Text(e) => write!(f, "Input is not valid text: {}", e),
+            Self::Base64(e) => write!(f, "Base64 decoding failed: {}", e),
+            Self::Hex(pos) => write!(f, "Invalid hex digit at position {}", pos),
+            Self::Base45(e, at) => write!(
+                f,
+                "Base45 decoding failed at offset {}: {} ({})",
+                at.offset, e, at
+            ),
+            Self::Zlib(e, at) => write!(
+                f,
+                "zlib decoding failed at offset {}: {} ({})",
+                at.offset, e, at
+            ),
+            Self::TooLarge(limit) => {
+                write!(f, "Decompressed data exceeds the limit of {} bytes", limit)
+            }
+            Self::Cose(e, at) => {
+                write!(f, "COSE decoding failed: {}", e)?;
+                at.iter().try_for_each(|at| write!(f, " ({})", at))
+            }
+            Self::Payload(e, at) => {
+                write!(f, "CBOR payload decoding failed: {}", e)?;
+                at.iter().try_for_each(|at| write!(f, " ({})", at))
+            }
+            Self::SchemaVersion(ver) => write!(
+                f,
+                "Unsupported schema version '{}' (expected {}.x.y)",
+                ver,
+                SchemaVersion::SUPPORTED_MAJOR
+            ),
+        }
+    }
+}
+
+impl DecodeError {
+    /// Invalid base45 in `text`
+    pub(crate) fn base45(text: &str, e: Base45DecodeError) -> Self {
+        Self::Base45(e.error, Excerpt::text(text, e.offset))
+    }
+
+    /// A malformed CWT payload `bytes`
+    fn payload(bytes: &[u8], e: serde_cbor::Error) -> Self {
+        let at = cbor_excerpt(bytes, &e);
+        Self::Payload(e, at)
+    }
+}
+
+/// An excerpt at the offset of a CBOR error, if it has one
+///
+/// The offset is that of the reader, i.e. just after the byte that could not be read,
+/// or the end of the data if it is truncated.
+fn cbor_excerpt(bytes: &[u8], e: &serde_cbor::Error) -> Option<Excerpt> {
+    match usize::try_from(e.offset()) {
+        Ok(0) | Err(_) => None,
+        Ok(offset) if e.is_eof() => Some(Excerpt::bytes(bytes, offset)),
+        Ok(offset) => Some(Excerpt::bytes(bytes, offset - 1)),
+    }
+}
+
+/// First byte of a zlib stream (deflate with a 32K window)
+const ZLIB_CMF: u8 = 0x78;
+
+/// Default limit for the size of the decompressed COSE message (in bytes)
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 256 * 1024;
+
+pub fn load_sign1(buf: &str) -> Result<Sign1, DecodeError> {
+    parse_sign1(&decode_cose_bytes(buf, DEFAULT_MAX_DECOMPRESSED_SIZE)?)
+}
+
+/// Strip the prefix and undo the base45 and zlib encoding, returning the COSE message
+///
+/// Fails with [`DecodeError::TooLarge`] if the message would be larger than `max_size` bytes.
+pub fn decode_cose_bytes(buf: &str, max_size: u64) -> Result<Vec<u8>, DecodeError> {
+    let (cose, _) = inflate(decode_base45(buf)?, max_size)?;
+    Ok(cose)
+}
+
+/// Strip the prefix and undo the base45 encoding, returning the compressed COSE message
+#[instrument(name = "base45", level = "debug", skip_all)]
+pub fn decode_base45(buf: &str) -> Result<Vec<u8>, DecodeError> {
+    let text = buf.trim_end_matches('\n');
+    let text = text
+        .strip_prefix("HC1:")
+        .ok_or(DecodeError::MissingPrefix)?;
     debug!("HealthCertificate v1 prefix valid");
 
-    let decoded = base45::decode(text)?;
+    let decoded = base45_decode(text).map_err(|e| DecodeError::base45(text, e))?;
     debug!("Base45 decoding successful");
+    Ok(decoded)
+}
 
-    let mut z = ZlibDecoder::new(&decoded[..]);
+/// Undo the zlib compression of a COSE message, if there is one
+///
+/// Returns [`Warning::Uncompressed`] with the message if it was not compressed.
+#[instrument(name = "inflate", level = "debug", skip_all)]
+pub fn inflate(data: Vec<u8>, max_size: u64) -> Result<(Vec<u8>, Option<Warning>), DecodeError> {
+    // Some early issuers did not compress the COSE message
+    if data.first() != Some(&ZLIB_CMF) {
+        debug!("Data is not zlib compressed, reading the COSE message directly");
+        if data.len() as u64 > max_size {
+            return Err(DecodeError::TooLarge(max_size));
+        }
+        return Ok((data, Some(Warning::Uncompressed)));
+    }
+
+    // Read one byte more than allowed to detect oversized data
+    let mut decoder = ZlibDecoder::new(&data[..]);
     let mut s = Vec::new();
-    z.read_to_end(&mut s)?;
+    if let Err(e) = (&mut decoder)
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut s)
+    {
+        let offset = usize::try_from(decoder.total_in()).unwrap_or(usize::MAX);
+        return Err(DecodeError::Zlib(e, Excerpt::bytes(&data, offset)));
+    }
+    if s.len() as u64 > max_size {
+        return Err(DecodeError::TooLarge(max_size));
+    }
     debug!("zlib decoding successful");
+    Ok((s, None))
+}
 
-    let sign1 = serde_cose::from_slice(&s)?;
-    Ok(sign1)
+#[instrument(name = "cose", level = "debug", skip_all)]
+pub fn parse_sign1(bytes: &[u8]) -> Result<Sign1, DecodeError> {
+    serde_cose::from_slice(bytes)
+        .or_else(|e| {
+            // Only the alg, content type and kid parameters are known to `Sign1`, so others
+            // in the unprotected header (e.g. countersignatures) are dropped here and read
+            // with `CoseSign1` instead
+            match without_unknown_headers(bytes) {
+                Some(bytes) => serde_cose::from_slice(&bytes).map_err(|_| e),
+                None => Err(e),
+            }
+        })
+        .map_err(|e| {
+            let at = cbor_excerpt(bytes, &e);
+            DecodeError::Cose(e, at)
+        })
 }
 
-impl TryFrom<&Sign1> for CertPayload {
-    type Error = color_eyre::Report;
+/// The kid of `sign1`, `None` if it is missing or empty
+///
+/// Unlike [`Sign1::kid`], this does not panic on a message without a kid.
+pub fn sign1_kid(sign1: &Sign1) -> Option<&[u8]> {
+    sign1
+        .unprotected
+        .0
+        .get(&serde_cose::HeaderParameter::KID)
+        .map(Vec::as_slice)
+        .filter(|kid| !kid.is_empty())
+}
+
+/// The COSE message without the unprotected header parameters that `Sign1` can't read,
+/// `None` if there are none
+fn without_unknown_headers(bytes: &[u8]) -> Option<Vec<u8>> {
+    use serde_cbor::Value;
 
-    fn try_from(sign1: &Sign1) -> color_eyre::Result<Self> {
-        let v = serde_cbor::from_slice(&sign1.payload)?;
+    let mut message: Value = serde_cbor::from_slice(bytes).ok()?;
+    let items = match &mut message {
+        Value::Tag(18, inner) => match inner.as_mut() {
+            Value::Array(items) => items,
+            _ => return None,
+        },
+        Value::Array(items) => items,
+        _ => return None,
+    };
+    let unprotected = match items.get_mut(1)? {
+        Value::Map(unprotected) => unprotected,
+        _ => return None,
+    };
+    let count = unprotected.len();
+    unprotected.retain(|label, _| {
+        matches!(
+            label,
+            Value::Integer(1) | Value::Integer(3) | Value::Integer(4)
+        )
+    });
+    if unprotected.len() == count {
+        return None;
+    }
+    debug!(
+        "Dropped {} unknown unprotected header parameters",
+        count - unprotected.len()
+    );
+    serde_cbor::to_vec(&message).ok()
+}
+
+impl CertPayload {
+    /// Look up the display values of all value set entries
+    pub fn resolve(&mut self, data: &EhnData) {
+        self.health_claim.cert.resolve(data);
+    }
+}
+
+impl CertPayload {
+    /// Decode the CWT claims from their CBOR encoding
+    ///
+    /// Certificates of an unknown major schema version (`ver`) fail with
+    /// [`DecodeError::SchemaVersion`] instead of a missing field.
+    #[instrument(name = "payload", level = "debug", skip_all)]
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let versioned =
+            serde_cbor::from_slice(bytes).map_err(|e| DecodeError::payload(bytes, e))?;
+        let mut v: Self = match versioned {
+            Versioned::Supported(v) => v,
+            Versioned::Unsupported(ver) => return Err(DecodeError::SchemaVersion(ver)),
+        };
         debug!("CBOR certificate payload decoding successful");
+        for vaccination in &mut v.health_claim.cert.vaccine {
+            vaccination.series = Some(vaccination.series());
+        }
+        if let Some(version) = v.health_claim.cert.schema_version() {
+            debug!("Schema version {}", version);
+        }
         Ok(v)
     }
 }
 
+/// A decoded claim, or the `ver` of a certificate with an unsupported schema version
+enum Versioned<T> {
+    Supported(T),
+    Unsupported(String),
+}
+
+impl<T> Versioned<T> {
+    /// The decoded claim, or the [`DecodeError::SchemaVersion`] as a serde error
+    fn supported<E: serde::de::Error>(self) -> Result<T, E> {
+        match self {
+            Self::Supported(value) => Ok(value),
+            Self::Unsupported(ver) => Err(E::custom(DecodeError::SchemaVersion(ver))),
+        }
+    }
+}
+
+/// The `ver` of a certificate, if it is not a supported schema version
+fn unsupported_version(cert: &serde_cbor::Value) -> Option<String> {
+    use serde_cbor::Value;
+
+    let ver = match cert {
+        Value::Map(cert) => cert.get(&Value::Text("ver".to_string()))?,
+        _ => return None,
+    };
+    match ver {
+        Value::Text(ver) => match ver.parse::<SchemaVersion>() {
+            Ok(version) if version.is_supported() => None,
+            _ => Some(ver.clone()),
+        },
+        // Reported as an invalid type when decoding the certificate
+        _ => None,
+    }
+}
+
+impl TryFrom<&Sign1> for CertPayload {
+    type Error = DecodeError;
+
+    fn try_from(sign1: &Sign1) -> Result<Self, DecodeError> {
+        Self::from_slice(&sign1.payload)
+    }
+}
+
 struct Timestamp(DateTime<Utc>);
 
 impl<'de> Deserialize<'de> for Timestamp {
@@ -65,7 +440,7 @@ impl<'de> Deserialize<'de> for Timestamp {
 struct CertVisitor;
 
 impl<'de> serde::de::Visitor<'de> for CertVisitor {
-    type Value = CertPayload;
+    type Value = Versioned<CertPayload>;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(formatter, "a eHealth certification payload")
@@ -79,27 +454,40 @@ impl<'de> serde::de::Visitor<'de> for CertVisitor {
         let mut expiration_time: Option<Timestamp> = None;
         let mut issued_at: Option<Timestamp> = None;
         let mut health_claim = None;
+        let mut extra = Extra::new();
+        let mut warnings = Vec::new();
 
-        while let Some(key) = map.next_key()? {
+        while let Some(key) = map.next_key::<serde_cbor::Value>()? {
+            use serde_cbor::Value;
             match key {
-                1 => {
+                Value::Integer(1) => {
                     issuer = Some(map.next_value()?);
                 }
-                4 => {
+                Value::Integer(4) => {
                     expiration_time = Some(map.next_value()?);
                 }
-                6 => {
+                Value::Integer(6) => {
                     issued_at = Some(map.next_value()?);
                 }
-                -260 => {
+                Value::Integer(-260) => {
                     health_claim = Some(map.next_value()?);
                 }
-                _ => {
-                    println!("unknown field: {}", key);
-                    let _: serde::de::IgnoredAny = map.next_value()?;
+                key => {
+                    let value: Value = map.next_value()?;
+                    let claim = claim_name(&key);
+                    warnings.push(Warning::UnknownClaim {
+                        claim: claim.clone(),
+                        cbor_type: Some(cbor_type_name(&value).to_string()),
+                    });
+                    extra.insert(claim, cbor_to_json(value));
                 }
             }
         }
+        let health_claim: HealthClaim = match health_claim {
+            Some(Versioned::Supported(health_claim)) => health_claim,
+            Some(Versioned::Unsupported(ver)) => return Ok(Versioned::Unsupported(ver)),
+            None => return Err(A::Error::missing_field("health_claim (-260)")),
+        };
         let issuer = issuer.ok_or_else(|| A::Error::missing_field("issuer (1)"))?;
         let expiration_time = expiration_time
             .ok_or_else(|| A::Error::missing_field("expiration_time (4)"))?
@@ -107,15 +495,23 @@ impl<'de> serde::de::Visitor<'de> for CertVisitor {
         let issued_at = issued_at
             .ok_or_else(|| A::Error::missing_field("issued_at (6)"))?
             .0;
-        let health_claim =
-            health_claim.ok_or_else(|| A::Error::missing_field("health_claim (-260)"))?;
+        // The other versions were kept as CBOR, which is valid
+        for (version, payload) in &health_claim.other_versions {
+            let cbor_type = crate::cbor::parse(payload).map(|item| item.kind.type_name());
+            warnings.push(Warning::UnknownClaim {
+                claim: format!("-260.{}", version),
+                cbor_type: cbor_type.ok().map(str::to_string),
+            });
+        }
 
-        Ok(CertPayload {
+        Ok(Versioned::Supported(CertPayload {
             issuer,
             expiration_time,
             issued_at,
             health_claim,
-        })
+            extra,
+            warnings,
+        }))
     }
 }
 
@@ -124,73 +520,554 @@ pub struct Vaccination {
     /// Disease or agent targeted
     ///
     ///  => COVID-19 (SARS-CoV or one of its variants)
-    #[serde(rename = "tg", deserialize_with = "valuesets::deserialize_agent")]
-    disease_agent_targeted: ValueSetEntry,
+    #[serde(rename = "tg")]
+    pub disease_agent_targeted: ValueSetEntry,
     /// vaccine or prophylaxis
-    #[serde(rename = "vp", deserialize_with = "valuesets::deserialize_vaccine")]
-    vaccine_or_prophylaxis: ValueSetEntry,
+    #[serde(rename = "vp")]
+    pub vaccine_or_prophylaxis: ValueSetEntry,
     /// vaccine product
-    #[serde(
-        rename = "mp",
-        deserialize_with = "valuesets::deserialize_medicinal_product"
-    )]
-    medicinal_product: ValueSetEntry,
+    #[serde(rename = "mp")]
+    pub medicinal_product: ValueSetEntry,
     /// marketing authorisation holder or manufacturer
-    #[serde(rename = "ma", deserialize_with = "valuesets::deserialize_mah_manf")]
-    manufacturer: ValueSetEntry,
+    #[serde(rename = "ma")]
+    pub manufacturer: ValueSetEntry,
     /// Number in a series of doses
     #[serde(rename = "dn")]
-    dose_number: u32,
+    pub dose_number: u32,
     /// The overall number of doses in the series
     #[serde(rename = "sd")]
-    series_dose_number: u32,
+    pub series_dose_number: u32,
     /// Date of vaccination
     #[serde(rename = "dt")]
-    date: NaiveDate,
+    pub date: NaiveDate,
     /// Member State or third country in which the vaccine was administered
     #[serde(rename = "co")]
-    country: String,
+    pub country: ValueSetEntry,
     /// Certificate issuer
     #[serde(rename = "is")]
-    issuer: String,
+    pub issuer: String,
     /// Unique certificate identifier
     #[serde(rename = "ci")]
-    cert_identifier: String,
+    pub cert_identifier: String,
+    /// Whether this is a dose of the primary series or a booster, see [`Vaccination::series`]
+    ///
+    /// This is not part of the schema, it is only added to the JSON output.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub series: Option<Series>,
+    /// Fields that are not part of the schema
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: Extra,
+}
+
+/// The medicinal product of the single-dose vaccine by Janssen
+pub const JANSSEN: &str = "EU/1/20/1525";
+
+/// The place of a vaccination in the primary series or after it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Series {
+    /// A dose before the last one of the primary series, e.g. 1/2
+    Incomplete,
+    /// The last dose of the primary series, e.g. 2/2 or 1/1
+    Completed,
+    /// A dose after the primary series, e.g. 3/3, or 2/1 after Janssen or a recovery
+    Booster,
+}
+
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Incomplete => write!(f, "primary series incomplete"),
+            Self::Completed => write!(f, "primary series completed"),
+            Self::Booster => write!(f, "booster"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Test {
+    /// Disease or agent targeted
+    #[serde(rename = "tg")]
+    pub disease_agent_targeted: ValueSetEntry,
+    /// The type of test
+    #[serde(rename = "tt")]
+    pub test_type: ValueSetEntry,
+    /// Test name (NAAT tests only)
+    #[serde(rename = "nm", default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Test device identifier (RAT tests only)
+    #[serde(rename = "ma", default, skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<ValueSetEntry>,
+    /// Date and time of the test sample collection
+    #[serde(rename = "sc")]
+    pub sample_collection: DateTime<FixedOffset>,
+    /// Date and time of the test result (only in schema versions before 1.3.0)
+    #[serde(rename = "dr", default, skip_serializing_if = "Option::is_none")]
+    pub result_date: Option<DateTime<FixedOffset>>,
+    /// Result of the test
+    #[serde(rename = "tr")]
+    pub result: ValueSetEntry,
+    /// Testing centre or facility
+    #[serde(rename = "tc", default, skip_serializing_if = "Option::is_none")]
+    pub testing_centre: Option<String>,
+    /// Member State or third country in which the test was carried out
+    #[serde(rename = "co")]
+    pub country: ValueSetEntry,
+    /// Certificate issuer
+    #[serde(rename = "is")]
+    pub issuer: String,
+    /// Unique certificate identifier
+    #[serde(rename = "ci")]
+    pub cert_identifier: String,
+    /// Fields that are not part of the schema
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Recovery {
+    /// Disease or agent the citizen has recovered from
+    #[serde(rename = "tg")]
+    pub disease_agent_targeted: ValueSetEntry,
+    /// Date of the holder's first positive NAAT test result
+    #[serde(rename = "fr")]
+    pub first_positive: NaiveDate,
+    /// Member State or third country in which the test was carried out
+    #[serde(rename = "co")]
+    pub country: ValueSetEntry,
+    /// Certificate issuer
+    #[serde(rename = "is")]
+    pub issuer: String,
+    /// Certificate valid from
+    #[serde(rename = "df")]
+    pub valid_from: NaiveDate,
+    /// Certificate valid until
+    #[serde(rename = "du")]
+    pub valid_until: NaiveDate,
+    /// Unique certificate identifier
+    #[serde(rename = "ci")]
+    pub cert_identifier: String,
+    /// Fields that are not part of the schema
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: Extra,
+}
+
+impl Vaccination {
+    /// Classify the dose by its `dn/sd` combination
+    ///
+    /// Following the EU guidance on boosters, a dose beyond the series (e.g. 2/1 or 3/2)
+    /// is a booster, as is any `n/n` with three or more doses and 2/2 of Janssen, which
+    /// is a single-dose vaccine.
+    pub fn series(&self) -> Series {
+        let (dn, sd) = (self.dose_number, self.series_dose_number);
+        if dn < sd {
+            Series::Incomplete
+        } else if dn > sd || dn >= 3 || (dn == 2 && self.medicinal_product.key == JANSSEN) {
+            Series::Booster
+        } else {
+            Series::Completed
+        }
+    }
+
+    fn resolve(&mut self, data: &EhnData) {
+        data.resolve(
+            &mut self.disease_agent_targeted,
+            &data.disease_agent_targeted,
+        );
+        data.resolve(&mut self.vaccine_or_prophylaxis, &data.vaccine_prophylaxis);
+        data.resolve(&mut self.medicinal_product, &data.vaccine_medicinal_product);
+        data.resolve(&mut self.manufacturer, &data.vaccine_mah_manf);
+        data.resolve(&mut self.country, &data.country_codes);
+    }
+
+    /// The value set entries by their field name
+    fn codes(&self) -> Vec<(&'static str, &ValueSetEntry)> {
+        vec![
+            ("tg", &self.disease_agent_targeted),
+            ("vp", &self.vaccine_or_prophylaxis),
+            ("mp", &self.medicinal_product),
+            ("ma", &self.manufacturer),
+            ("co", &self.country),
+        ]
+    }
+}
+
+impl Test {
+    fn resolve(&mut self, data: &EhnData) {
+        data.resolve(
+            &mut self.disease_agent_targeted,
+            &data.disease_agent_targeted,
+        );
+        data.resolve(&mut self.test_type, &data.test_type);
+        if let Some(manufacturer) = &mut self.manufacturer {
+            data.resolve(manufacturer, &data.test_manf);
+        }
+        data.resolve(&mut self.result, &data.test_result);
+        data.resolve(&mut self.country, &data.country_codes);
+    }
+
+    /// The value set entries by their field name
+    fn codes(&self) -> Vec<(&'static str, &ValueSetEntry)> {
+        let mut codes = vec![
+            ("tg", &self.disease_agent_targeted),
+            ("tt", &self.test_type),
+        ];
+        codes.extend(self.manufacturer.as_ref().map(|ma| ("ma", ma)));
+        codes.extend([("tr", &self.result), ("co", &self.country)]);
+        codes
+    }
+}
+
+impl Recovery {
+    fn resolve(&mut self, data: &EhnData) {
+        data.resolve(
+            &mut self.disease_agent_targeted,
+            &data.disease_agent_targeted,
+        );
+        data.resolve(&mut self.country, &data.country_codes);
+    }
+
+    /// The value set entries by their field name
+    fn codes(&self) -> Vec<(&'static str, &ValueSetEntry)> {
+        vec![("tg", &self.disease_agent_targeted), ("co", &self.country)]
+    }
+}
+
+/// The kind of entry in a DCC (vaccination, test or recovery)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    Test,
+    Vaccination,
+    Recovery,
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Test => write!(f, "test"),
+            Self::Vaccination => write!(f, "vaccination"),
+            Self::Recovery => write!(f, "recovery"),
+        }
+    }
+}
+
+/// A vaccination, test or recovery entry of a DCC
+#[derive(Debug, Copy, Clone)]
+pub enum Entry<'a> {
+    Vaccination(&'a Vaccination),
+    Test(&'a Test),
+    Recovery(&'a Recovery),
+}
+
+impl<'a> Entry<'a> {
+    pub fn kind(&self) -> EntryKind {
+        match self {
+            Self::Vaccination(_) => EntryKind::Vaccination,
+            Self::Test(_) => EntryKind::Test,
+            Self::Recovery(_) => EntryKind::Recovery,
+        }
+    }
+
+    /// The date of the vaccination, the sample collection or the first positive test
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            Self::Vaccination(v) => v.date,
+            Self::Test(t) => t.sample_collection.date_naive(),
+            Self::Recovery(r) => r.first_positive,
+        }
+    }
+
+    /// The unique certificate identifier (UVCI)
+    pub fn cert_identifier(&self) -> &'a str {
+        match self {
+            Self::Vaccination(v) => &v.cert_identifier,
+            Self::Test(t) => &t.cert_identifier,
+            Self::Recovery(r) => &r.cert_identifier,
+        }
+    }
+
+    /// Order by date, then by the dose number or the time of the sample collection
+    fn recency(&self) -> (NaiveDate, i64) {
+        let tiebreak = match self {
+            Self::Vaccination(v) => i64::from(v.dose_number),
+            Self::Test(t) => t.sample_collection.timestamp(),
+            Self::Recovery(_) => 0,
+        };
+        (self.date(), tiebreak)
+    }
+}
+
+/// Entries are equal if they refer to the same entry of a certificate
+impl PartialEq for Entry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Vaccination(a), Self::Vaccination(b)) => std::ptr::eq(*a, *b),
+            (Self::Test(a), Self::Test(b)) => std::ptr::eq(*a, *b),
+            (Self::Recovery(a), Self::Recovery(b)) => std::ptr::eq(*a, *b),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DigitalCovidCertificate {
-    #[serde(rename = "v")]
-    vaccine: Vec<Vaccination>,
+    #[serde(rename = "v", default, skip_serializing_if = "Vec::is_empty")]
+    pub vaccine: Vec<Vaccination>,
+    #[serde(rename = "t", default, skip_serializing_if = "Vec::is_empty")]
+    pub test: Vec<Test>,
+    #[serde(rename = "r", default, skip_serializing_if = "Vec::is_empty")]
+    pub recovery: Vec<Recovery>,
     #[serde(rename = "dob")]
-    date_of_birth: NaiveDate,
+    pub date_of_birth: DateOfBirth,
     #[serde(rename = "nam")]
-    name: Name,
+    pub name: Name,
     #[serde(rename = "ver")]
-    version: String,
+    pub version: String,
+    /// Fields that are not part of the schema
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: Extra,
+}
+
+/// The version of the DCC JSON schema (`ver`), e.g. `1.3.0`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SchemaVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl SchemaVersion {
+    /// The major version of the schema this crate can decode
+    pub const SUPPORTED_MAJOR: u32 = 1;
+    /// The latest schema version this crate knows the rules of
+    pub const LATEST: Self = Self::new(1, 3, 0);
+    /// The version that removed the date of the test result (`dr`)
+    const WITHOUT_RESULT_DATE: Self = Self::new(1, 3, 0);
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.major == Self::SUPPORTED_MAJOR
+    }
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for SchemaVersion {
+    type Err = std::num::ParseIntError;
+
+    /// Parse a semantic version, ignoring a pre-release or build suffix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let core = s.split(['-', '+']).next().unwrap_or_default();
+        let mut parts = core.splitn(3, '.');
+        let mut next = || parts.next().unwrap_or_default().parse::<u32>();
+        Ok(Self::new(next()?, next()?, next()?))
+    }
+}
+
+impl DigitalCovidCertificate {
+    /// The parsed schema version, if it is a valid version
+    pub fn schema_version(&self) -> Option<SchemaVersion> {
+        self.version.parse().ok()
+    }
+
+    /// The fields that don't belong to the schema version of the certificate, and a version
+    /// that is newer than the supported ones
+    pub fn schema_issues(&self) -> Vec<String> {
+        let version = match self.schema_version() {
+            Some(version) => version,
+            None => return Vec::new(),
+        };
+        let mut issues = Vec::new();
+        // A patch version of the latest minor version has the same rules
+        if (version.major, version.minor)
+            > (SchemaVersion::LATEST.major, SchemaVersion::LATEST.minor)
+        {
+            issues.push(format!(
+                "Schema version {} is newer than {}, decoding it with the rules of {}",
+                version,
+                SchemaVersion::LATEST,
+                SchemaVersion::LATEST
+            ));
+        }
+        if version >= SchemaVersion::WITHOUT_RESULT_DATE
+            && self.test.iter().any(|t| t.result_date.is_some())
+        {
+            issues.push(format!(
+                "Test entry has a result date (dr), which was removed in schema version {}",
+                SchemaVersion::WITHOUT_RESULT_DATE
+            ));
+        }
+        issues
+    }
+
+    /// The paths of all fields that are not part of the schema, e.g. `v[0].xy`
+    pub fn unknown_fields(&self) -> Vec<String> {
+        let entries = (self.vaccine.iter().map(|v| &v.extra).enumerate())
+            .map(|(i, extra)| (format!("v[{}].", i), extra))
+            .chain(
+                (self.test.iter().map(|t| &t.extra).enumerate())
+                    .map(|(i, extra)| (format!("t[{}].", i), extra)),
+            )
+            .chain(
+                (self.recovery.iter().map(|r| &r.extra).enumerate())
+                    .map(|(i, extra)| (format!("r[{}].", i), extra)),
+            );
+        std::iter::once((String::new(), &self.extra))
+            .chain(std::iter::once(("nam.".to_string(), &self.name.extra)))
+            .chain(entries)
+            .flat_map(|(prefix, extra)| extra.keys().map(move |key| format!("{}{}", prefix, key)))
+            .collect()
+    }
+
+    /// The paths and keys of all value set entries without a value, e.g. `v[0].mp`
+    pub fn unresolved_codes(&self) -> Vec<(String, &str)> {
+        let entries = (self.vaccine.iter().map(Vaccination::codes).enumerate())
+            .map(|(i, codes)| (format!("v[{}]", i), codes))
+            .chain(
+                (self.test.iter().map(Test::codes).enumerate())
+                    .map(|(i, codes)| (format!("t[{}]", i), codes)),
+            )
+            .chain(
+                (self.recovery.iter().map(Recovery::codes).enumerate())
+                    .map(|(i, codes)| (format!("r[{}]", i), codes)),
+            );
+        entries
+            .flat_map(|(prefix, codes)| {
+                codes
+                    .into_iter()
+                    .filter(|(_, entry)| entry.value.is_none())
+                    .map(move |(key, entry)| (format!("{}.{}", prefix, key), entry.key.as_str()))
+            })
+            .collect()
+    }
+
+    /// The age of the holder on `date`, see [`DateOfBirth::age_at`]
+    pub fn age_at(&self, date: NaiveDate) -> Option<Age> {
+        self.date_of_birth.age_at(date)
+    }
+
+    /// The country (`co`) of each entry, at a path like `v[0].co`
+    pub fn countries(&self) -> Vec<(String, &str)> {
+        let vaccines = (self.vaccine.iter().enumerate())
+            .map(|(i, v)| (format!("v[{}].co", i), v.country.key.as_str()));
+        let tests = (self.test.iter().enumerate())
+            .map(|(i, t)| (format!("t[{}].co", i), t.country.key.as_str()));
+        let recoveries = (self.recovery.iter().enumerate())
+            .map(|(i, r)| (format!("r[{}].co", i), r.country.key.as_str()));
+        vaccines.chain(tests).chain(recoveries).collect()
+    }
+
+    /// Look up the display values of all value set entries
+    pub fn resolve(&mut self, data: &EhnData) {
+        self.vaccine.iter_mut().for_each(|v| v.resolve(data));
+        self.test.iter_mut().for_each(|t| t.resolve(data));
+        self.recovery.iter_mut().for_each(|r| r.resolve(data));
+    }
+
+    /// All vaccination, test and recovery entries, in this order
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'_>> {
+        (self.vaccine.iter().map(Entry::Vaccination))
+            .chain(self.test.iter().map(Entry::Test))
+            .chain(self.recovery.iter().map(Entry::Recovery))
+    }
+
+    /// The entry that decisions about the validity are based on
+    ///
+    /// A certificate should only have one entry. If it has more, the most recent one is
+    /// used: the latest vaccination (with the highest dose number on the same day), the
+    /// latest test or recovery, and among different kinds the one with the latest date.
+    pub fn relevant_entry(&self) -> Option<Entry<'_>> {
+        self.entries().max_by_key(Entry::recency)
+    }
+
+    /// The kinds of entries present in this certificate
+    pub fn entry_kinds(&self) -> Vec<EntryKind> {
+        let mut kinds = Vec::new();
+        if !self.vaccine.is_empty() {
+            kinds.push(EntryKind::Vaccination);
+        }
+        if !self.test.is_empty() {
+            kinds.push(EntryKind::Test);
+        }
+        if !self.recovery.is_empty() {
+            kinds.push(EntryKind::Recovery);
+        }
+        kinds
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-struct Name {
+pub struct Name {
     #[serde(rename = "fn")]
-    first_name: String,
+    pub first_name: String,
     #[serde(rename = "gn")]
-    given_name: String,
+    pub given_name: String,
     #[serde(rename = "fnt")]
-    first_name_transliterated: String,
+    pub first_name_transliterated: String,
     #[serde(rename = "gnt")]
-    given_name_transliterated: String,
+    pub given_name_transliterated: String,
+    /// Fields that are not part of the schema
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: Extra,
 }
 
+/// The keys of the `hcert` claim whose payload can be decoded, `1` for `eu_dgc_v1`
+pub const SUPPORTED_HCERT_VERSIONS: &[i64] = &[1];
+
+/// The `hcert` claim (`-260`), which holds one payload per version
+///
+/// The highest supported version is decoded into `cert`, the payloads of all other
+/// versions (e.g. future ones) are kept as CBOR.
 #[derive(Debug)]
 pub struct HealthClaim {
+    /// The key of the decoded payload, e.g. `1` for `eu_dgc_v1`
+    pub version: i64,
     pub cert: DigitalCovidCertificate,
+    /// The CBOR encoded payloads of the other versions, by their key (e.g. `2`)
+    pub other_versions: BTreeMap<String, Vec<u8>>,
+}
+
+impl HealthClaim {
+    /// The name of the decoded version, as in the JSON output
+    pub fn version_name(&self) -> String {
+        match self.version {
+            1 => "eu_dgc_v1".to_string(),
+            version => version.to_string(),
+        }
+    }
+}
+
+impl Serialize for HealthClaim {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(1 + self.other_versions.len()))?;
+        map.serialize_entry(&self.version_name(), &self.cert)?;
+        for (version, payload) in &self.other_versions {
+            // The payloads were decoded as CBOR values before, so they decode again
+            let value =
+                serde_cbor::from_slice(payload).map_or(serde_json::Value::Null, cbor_to_json);
+            map.serialize_entry(version, &value)?;
+        }
+        map.end()
+    }
 }
 
 struct CertInnerVisitor;
 
 impl<'de> serde::de::Visitor<'de> for CertInnerVisitor {
-    type Value = HealthClaim;
+    type Value = Versioned<HealthClaim>;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(formatter, "a eHealth certification payload inner")
@@ -200,26 +1077,70 @@ impl<'de> serde::de::Visitor<'de> for CertInnerVisitor {
     where
         A: serde::de::MapAccess<'de>,
     {
-        let mut cert = None;
+        let mut cert: Option<(i64, DigitalCovidCertificate)> = None;
+        let mut other_versions = BTreeMap::new();
+        let mut unsupported = None;
 
-        while let Some(key) = map.next_key()? {
-            match key {
-                1 => {
-                    cert = Some(map.next_value()?);
+        while let Some(key) = map.next_key::<serde_cbor::Value>()? {
+            let version = match key {
+                serde_cbor::Value::Integer(n) => i64::try_from(n).ok(),
+                _ => None,
+            };
+            if let Some(key) = version.filter(|v| SUPPORTED_HCERT_VERSIONS.contains(v)) {
+                // Check the schema version before the fields that depend on it
+                let payload: serde_cbor::Value = map.next_value()?;
+                if let Some(ver) = unsupported_version(&payload) {
+                    unsupported = Some(ver);
+                    continue;
                 }
-                _ => {
-                    println!("Unknown key: {}", key);
-                    let _: serde::de::IgnoredAny = map.next_value()?;
+                let payload: DigitalCovidCertificate =
+                    value::from_value(payload).map_err(A::Error::custom)?;
+                // Keep the highest supported version, and the others as CBOR
+                match cert.take() {
+                    Some((version, previous)) if version > key => {
+                        cert = Some((version, previous));
+                        other_versions.insert(key.to_string(), to_cbor::<A::Error, _>(&payload)?);
+                    }
+                    Some((version, previous)) => {
+                        cert = Some((key, payload));
+                        let previous = to_cbor::<A::Error, _>(&previous)?;
+                        other_versions.insert(version.to_string(), previous);
+                    }
+                    None => cert = Some((key, payload)),
                 }
+            } else {
+                let payload: serde_cbor::Value = map.next_value()?;
+                other_versions.insert(claim_name(&key), to_cbor::<A::Error, _>(&payload)?);
             }
         }
-        let cert = cert.ok_or_else(|| A::Error::missing_field("cert (1)"))?;
+        let (version, cert) = match (cert, unsupported) {
+            (Some(cert), _) => cert,
+            (None, Some(ver)) => return Ok(Versioned::Unsupported(ver)),
+            (None, None) => return Err(A::Error::missing_field("cert (1)")),
+        };
 
-        Ok(HealthClaim { cert })
+        Ok(Versioned::Supported(HealthClaim {
+            version,
+            cert,
+            other_versions,
+        }))
     }
 }
 
+fn to_cbor<E: serde::de::Error, T: Serialize>(value: &T) -> Result<Vec<u8>, E> {
+    serde_cbor::to_vec(value).map_err(E::custom)
+}
+
 impl<'de> serde::Deserialize<'de> for HealthClaim {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(CertInnerVisitor)?.supported()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Versioned<HealthClaim> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
@@ -229,6 +1150,15 @@ impl<'de> serde::Deserialize<'de> for HealthClaim {
 }
 
 impl<'de> serde::Deserialize<'de> for CertPayload {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(CertVisitor)?.supported()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Versioned<CertPayload> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
@@ -236,3 +1166,76 @@ impl<'de> serde::Deserialize<'de> for CertPayload {
         deserializer.deserialize_map(CertVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_cbor::Value;
+
+    use super::{borrowed::CertPayloadRef, warning::Warning, CertPayload};
+
+    fn map(entries: Vec<(Value, Value)>) -> Value {
+        Value::Map(entries.into_iter().collect())
+    }
+
+    fn int(n: i64) -> Value {
+        Value::Integer(n.into())
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    /// CWT claims with a text key next to the known claims, and one in the `hcert` claim
+    fn claims_with_text_keys() -> Vec<u8> {
+        let cert = map(vec![
+            (text("ver"), text("1.3.0")),
+            (text("dob"), text("1964-08-12")),
+            (
+                text("nam"),
+                map(vec![
+                    (text("fn"), text("Mustermann")),
+                    (text("fnt"), text("MUSTERMANN")),
+                    (text("gn"), text("Erika")),
+                    (text("gnt"), text("ERIKA")),
+                ]),
+            ),
+        ]);
+        let claims = map(vec![
+            (int(1), text("DE")),
+            (int(4), int(1654041600)),
+            (int(6), int(1622505600)),
+            (
+                int(-260),
+                map(vec![(int(1), cert), (text("note"), text("test"))]),
+            ),
+            (text("nonce"), Value::Bytes(vec![1, 2, 3])),
+            (int(7), int(42)),
+        ]);
+        serde_cbor::to_vec(&claims).unwrap()
+    }
+
+    #[test]
+    fn text_claim_keys() {
+        let bytes = claims_with_text_keys();
+        let payload = CertPayload::from_slice(&bytes).unwrap();
+        assert_eq!(payload.issuer, "DE");
+        assert_eq!(payload.extra["nonce"], "AQID");
+        assert_eq!(payload.extra["7"], 42);
+        assert!(payload.health_claim.other_versions.contains_key("note"));
+
+        let claims: Vec<(&str, Option<&str>)> = (payload.warnings.iter())
+            .filter_map(|warning| match warning {
+                Warning::UnknownClaim { claim, cbor_type } => {
+                    Some((claim.as_str(), cbor_type.as_deref()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert!(claims.contains(&("nonce", Some("bytes"))));
+        assert!(claims.contains(&("7", Some("integer"))));
+        assert!(claims.contains(&("-260.note", Some("text"))));
+
+        let borrowed = CertPayloadRef::from_slice(&bytes).unwrap();
+        assert_eq!(borrowed.issuer, "DE");
+    }
+}
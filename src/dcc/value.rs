@@ -0,0 +1,107 @@
+//! Deserializing from a `serde_cbor::Value` that was already decoded
+//!
+//! `serde_cbor` can only deserialize a value by encoding it again (`from_value`), so a
+//! certificate that was buffered to look at its `ver` first is deserialized with this
+//! instead. Tags are skipped, as when deserializing from the encoded bytes.
+use std::{convert::TryFrom, vec};
+
+use serde::de::{
+    self,
+    value::{Error, MapDeserializer, SeqDeserializer},
+    IntoDeserializer, Visitor,
+};
+use serde_cbor::Value;
+
+/// Deserialize a `T` from `value`
+pub(crate) fn from_value<'de, T: de::Deserialize<'de>>(value: Value) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer(value))
+}
+
+struct ValueDeserializer(Value);
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn entries(
+    map: impl IntoIterator<Item = (Value, Value)>,
+) -> vec::IntoIter<(ValueDeserializer, ValueDeserializer)> {
+    let entries = map.into_iter();
+    let entries = entries.map(|(key, value)| (ValueDeserializer(key), ValueDeserializer(value)));
+    entries.collect::<Vec<_>>().into_iter()
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Integer(n) => match (u64::try_from(n), i64::try_from(n)) {
+                (Ok(n), _) => visitor.visit_u64(n),
+                (_, Ok(n)) => visitor.visit_i64(n),
+                _ => visitor.visit_i128(n),
+            },
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+            Value::Text(text) => visitor.visit_string(text),
+            Value::Array(items) => {
+                let items = items.into_iter().map(ValueDeserializer);
+                let mut seq = SeqDeserializer::new(items);
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Map(map) => {
+                let mut map = MapDeserializer::new(entries(map));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            Value::Tag(_, inner) => ValueDeserializer(*inner).deserialize_any(visitor),
+            _ => Err(de::Error::custom("unsupported CBOR value")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            Value::Tag(_, inner) => ValueDeserializer(*inner).deserialize_option(visitor),
+            value => visitor.visit_some(ValueDeserializer(value)),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Text(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Tag(_, inner) => {
+                ValueDeserializer(*inner).deserialize_enum(name, variants, visitor)
+            }
+            value => ValueDeserializer(value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
@@ -1,35 +1,77 @@
-use std::{collections::BTreeMap, fmt};
+use std::{collections::BTreeMap, sync::Arc};
 
 use chrono::NaiveDate;
 use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::{json::Loadable, EHN_DATA};
+use crate::{json::Loadable, lang::Lang};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Value {
-    display: String,
-    lang: String,
-    active: bool,
-    version: String,
-    system: String,
+    pub display: String,
+    pub lang: String,
+    pub active: bool,
+    pub version: String,
+    pub system: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ValueSet {
     #[serde(rename = "valueSetId")]
-    id: String,
+    pub id: String,
     #[serde(rename = "valueSetDate")]
-    date: NaiveDate,
+    pub date: NaiveDate,
     #[serde(rename = "valueSetValues")]
-    values: BTreeMap<String, Value>,
+    pub values: BTreeMap<String, Arc<Value>>,
+    /// Translated values, by language code and key
+    #[serde(skip)]
+    pub translations: BTreeMap<String, BTreeMap<String, Arc<Value>>>,
 }
 
 impl Loadable for ValueSet {}
 
+impl ValueSet {
+    /// Add the values of a translated copy of this value set
+    pub fn add_translation(&mut self, other: ValueSet) {
+        for (key, value) in other.values {
+            self.translations
+                .entry(value.lang.clone())
+                .or_default()
+                .insert(key, value);
+        }
+    }
+
+    /// Get the value for `key`, preferring a translation to `lang`
+    pub fn get(&self, key: &str, lang: Lang) -> Option<&Arc<Value>> {
+        self.translations
+            .get(lang.code())
+            .and_then(|values| values.get(key))
+            .or_else(|| self.values.get(key))
+    }
+}
+
+/// A key of a value set, with the value it resolved to (if any)
+///
+/// The value is shared with the value set, but does not borrow from it, so value sets
+/// can be replaced (e.g. reloaded) while decoded certificates are still around.
 #[derive(Debug, Clone)]
 pub struct ValueSetEntry {
-    key: String,
-    value: Option<&'static Value>,
+    pub key: String,
+    pub value: Option<Arc<Value>>,
+}
+
+impl ValueSetEntry {
+    /// The display name of the entry, or the key if it's not in the value set
+    pub fn display(&self) -> &str {
+        self.value
+            .as_ref()
+            .map(|v| v.display.as_str())
+            .unwrap_or(&self.key)
+    }
+
+    /// Look up the value for this entry in `set`, preferring a translation to `lang`
+    pub fn resolve(&mut self, set: Option<&ValueSet>, lang: Lang) {
+        self.value = set.and_then(|set| set.get(&self.key, lang)).cloned();
+    }
 }
 
 impl Serialize for ValueSetEntry {
@@ -41,80 +83,54 @@ impl Serialize for ValueSetEntry {
     }
 }
 
-struct ValueSetVisitor(Option<&'static ValueSet>);
-
-impl<'de> serde::de::Visitor<'de> for ValueSetVisitor {
-    type Value = ValueSetEntry;
-
-    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(formatter, "a value set key string")
-    }
-
-    fn visit_string<E>(self, key: String) -> Result<Self::Value, E>
-    where
-        E: serde::de::Error,
-    {
-        let mut value = None;
-        if let Some(set) = self.0 {
-            if let Some(entry) = set.values.get(&key) {
-                value = Some(entry);
-            }
-        }
-        Ok(ValueSetEntry { key, value })
-    }
-
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+/// Deserializes only the key, use [`ValueSetEntry::resolve`] to look up the value
+impl<'de> Deserialize<'de> for ValueSetEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
-        E: serde::de::Error,
+        D: Deserializer<'de>,
     {
-        self.visit_string(v.to_string())
+        let key = String::deserialize(deserializer)?;
+        Ok(ValueSetEntry { key, value: None })
     }
 }
 
-fn deserialize_set_value<'de, D, F>(deserializer: D, f: F) -> Result<ValueSetEntry, D::Error>
-where
-    D: Deserializer<'de>,
-    F: for<'r> FnOnce(&'r EhnData) -> &'r Option<ValueSet>,
-{
-    deserializer.deserialize_str(ValueSetVisitor(
-        EHN_DATA.get().and_then(move |e| f(e).as_ref()),
-    ))
-}
-
-pub fn deserialize_agent<'de, D>(deserializer: D) -> Result<ValueSetEntry, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    deserialize_set_value(deserializer, |e| &e.disease_agent_targeted)
-}
-
-pub fn deserialize_vaccine<'de, D>(deserializer: D) -> Result<ValueSetEntry, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    deserialize_set_value(deserializer, |e| &e.vaccine_prophylaxis)
-}
-
-pub fn deserialize_medicinal_product<'de, D>(deserializer: D) -> Result<ValueSetEntry, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    deserialize_set_value(deserializer, |e| &e.vaccine_medicinal_product)
-}
-
-pub fn deserialize_mah_manf<'de, D>(deserializer: D) -> Result<ValueSetEntry, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    deserialize_set_value(deserializer, |e| &e.vaccine_mah_manf)
-}
-
 #[derive(Default, Debug, Clone)]
 pub struct EhnData {
     pub vaccine_prophylaxis: Option<ValueSet>,
     pub disease_agent_targeted: Option<ValueSet>,
     pub vaccine_mah_manf: Option<ValueSet>,
     pub vaccine_medicinal_product: Option<ValueSet>,
+    /// `covid-19-lab-test-type`
+    pub test_type: Option<ValueSet>,
+    /// `covid-19-lab-test-manufacturer-and-name`
+    pub test_manf: Option<ValueSet>,
+    /// `covid-19-lab-result`
+    pub test_result: Option<ValueSet>,
+    /// `country-2-codes`
+    pub country_codes: Option<ValueSet>,
+    /// The preferred language of display names
+    pub lang: Lang,
 }
 
-impl EhnData {}
+impl EhnData {
+    /// Whether none of the value sets were loaded
+    pub fn is_empty(&self) -> bool {
+        [
+            &self.vaccine_prophylaxis,
+            &self.disease_agent_targeted,
+            &self.vaccine_mah_manf,
+            &self.vaccine_medicinal_product,
+            &self.test_type,
+            &self.test_manf,
+            &self.test_result,
+            &self.country_codes,
+        ]
+        .iter()
+        .all(|set| set.is_none())
+    }
+
+    /// Resolve `entry` against `set` (one of the value sets of `self`)
+    pub(crate) fn resolve(&self, entry: &mut ValueSetEntry, set: &Option<ValueSet>) {
+        entry.resolve(set.as_ref(), self.lang);
+    }
+}
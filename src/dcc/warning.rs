@@ -0,0 +1,219 @@
+//! Non-fatal issues found while decoding a certificate
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{valuesets::EhnData, CertPayload};
+use crate::{
+    cbor,
+    core::cose::{self, HEADER_KID},
+};
+
+/// An issue that does not stop the decoding, but should be reported with the result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// No value sets were loaded, so the codes are shown without their display names
+    NoValueSets,
+    /// A code that is not in its value set, at a path like `v[0].mp`
+    UnknownCode { field: String, code: String },
+    /// A field of the certificate that is not part of the schema, e.g. `v[0].xy`
+    UnknownField { field: String },
+    /// A field that is not part of the schema version of the certificate, or a version
+    /// that is newer than the supported ones
+    Schema { issue: String },
+    /// An unknown CWT claim (e.g. `5`) or key of the hcert claim (e.g. `-260.2`), with the
+    /// type of its CBOR value (e.g. `map`)
+    UnknownClaim {
+        claim: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cbor_type: Option<String>,
+    },
+    /// The COSE message is not zlib compressed, as by some early issuers
+    Uncompressed,
+    /// The CWT payload is not in deterministic CBOR encoding, with the violations
+    NotCanonical { violations: Vec<String> },
+    /// The kid is in the unprotected header, which the signature does not cover
+    UnprotectedKid,
+    /// The COSE message has no kid, so the DSCs of the issuing country were tried
+    MissingKid {
+        country: String,
+        /// The kid of the DSC that verified the signature, if any
+        matched_kid: Option<String>,
+    },
+    /// A country that differs from the issuer (the `iss` claim): the `co` of an entry at a
+    /// path like `v[0].co`, or `dsc` for the country of the signing DSC
+    CountryMismatch {
+        field: String,
+        country: String,
+        issuer: String,
+    },
+    /// The certificate (by UVCI) was scanned before, in an earlier input of the run or
+    /// as stored in the result database
+    Duplicate {
+        earlier_scans: u64,
+        /// The first input of the run with the same certificate
+        first_input: Option<String>,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoValueSets => write!(f, "No value sets were loaded"),
+            Self::UnknownCode { field, code } => {
+                write!(f, "Code '{}' of {} is not in the value set", code, field)
+            }
+            Self::UnknownField { field } => {
+                write!(f, "Unknown field in the certificate: {}", field)
+            }
+            Self::Schema { issue } => f.write_str(issue),
+            Self::UnknownClaim {
+                claim,
+                cbor_type: Some(cbor_type),
+            } => write!(
+                f,
+                "Unknown claim in the CWT payload: {} ({})",
+                claim, cbor_type
+            ),
+            Self::UnknownClaim {
+                claim,
+                cbor_type: None,
+            } => write!(f, "Unknown claim in the CWT payload: {}", claim),
+            Self::NotCanonical { violations } => write!(
+                f,
+                "CWT payload is not in deterministic CBOR encoding ({} violations, first: {})",
+                violations.len(),
+                violations.first().map(String::as_str).unwrap_or_default()
+            ),
+            Self::Uncompressed => write!(f, "The COSE message is not zlib compressed"),
+            Self::UnprotectedKid => write!(f, "The kid is only in the unprotected header"),
+            Self::MissingKid {
+                country,
+                matched_kid: Some(kid),
+            } => write!(
+                f,
+                "The COSE message has no kid, the signature matched the DSC '{}' of {}",
+                kid, country
+            ),
+            Self::MissingKid {
+                country,
+                matched_kid: None,
+            } => write!(f, "The COSE message has no kid (issued by {})", country),
+            Self::CountryMismatch {
+                field,
+                country,
+                issuer,
+            } if field == "dsc" => write!(
+                f,
+                "The DSC is from {}, but the certificate was issued by {}",
+                country, issuer
+            ),
+            Self::CountryMismatch {
+                field,
+                country,
+                issuer,
+            } => write!(
+                f,
+                "The country {} of {} differs from the issuer {}",
+                country, field, issuer
+            ),
+            Self::Duplicate {
+                earlier_scans,
+                first_input,
+            } => {
+                write!(
+                    f,
+                    "Certificate was scanned before ({} earlier scans",
+                    earlier_scans
+                )?;
+                match first_input {
+                    Some(input) => write!(f, ", first in '{}')", input),
+                    None => write!(f, ")"),
+                }
+            }
+        }
+    }
+}
+
+impl Warning {
+    /// A [`Warning::CountryMismatch`] if `country` (of `field`) is not `issuer`
+    ///
+    /// Missing values are not compared, and the case of the codes is ignored.
+    pub fn country_mismatch(field: &str, country: &str, issuer: &str) -> Option<Self> {
+        if country.is_empty() || issuer.is_empty() || country.eq_ignore_ascii_case(issuer) {
+            return None;
+        }
+        Some(Self::CountryMismatch {
+            field: field.to_string(),
+            country: country.to_string(),
+            issuer: issuer.to_string(),
+        })
+    }
+}
+
+/// A decoded certificate with the issues that were found on the way
+#[derive(Debug)]
+pub struct DecodeOutcome {
+    pub payload: CertPayload,
+    pub warnings: Vec<Warning>,
+}
+
+impl DecodeOutcome {
+    /// Check a payload that was decoded from `bytes` and resolved against `data`
+    pub fn new(mut payload: CertPayload, bytes: &[u8], data: &EhnData) -> Self {
+        let mut warnings = std::mem::take(&mut payload.warnings);
+        let cert = &payload.health_claim.cert;
+        warnings.extend(
+            cert.unknown_fields()
+                .into_iter()
+                .map(|field| Warning::UnknownField { field }),
+        );
+        warnings.extend(
+            cert.schema_issues()
+                .into_iter()
+                .map(|issue| Warning::Schema { issue }),
+        );
+        let issuer = &payload.issuer;
+        warnings.extend(
+            cert.countries()
+                .into_iter()
+                .filter_map(|(field, country)| Warning::country_mismatch(&field, country, issuer)),
+        );
+        if data.is_empty() {
+            warnings.push(Warning::NoValueSets);
+        } else {
+            warnings.extend(cert.unresolved_codes().into_iter().map(|(field, code)| {
+                Warning::UnknownCode {
+                    field,
+                    code: code.to_string(),
+                }
+            }));
+        }
+        // The payload was already decoded, so it is valid CBOR
+        match cbor::check_deterministic(bytes) {
+            Ok(violations) if !violations.is_empty() => warnings.push(Warning::NotCanonical {
+                violations: violations.iter().map(ToString::to_string).collect(),
+            }),
+            _ => {}
+        }
+        Self { payload, warnings }
+    }
+
+    /// Add the warnings about the headers of the COSE message of the payload
+    ///
+    /// The headers are read from the message as it was encoded, since `serde_cose` only
+    /// keeps the kid of the unprotected header.
+    pub fn check_headers(&mut self, cose: &cose::Sign1) {
+        match cose.kid().filter(|kid| !kid.is_empty()) {
+            Some(_) if !cose.is_protected(HEADER_KID) => {
+                self.warnings.push(Warning::UnprotectedKid)
+            }
+            Some(_) => {}
+            None => self.warnings.push(Warning::MissingKid {
+                country: self.payload.issuer.clone(),
+                matched_kid: None,
+            }),
+        }
+    }
+}
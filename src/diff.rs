@@ -0,0 +1,99 @@
+//! Field-by-field comparison of two decoded certificates
+//!
+//! Both certificates are brought into the same JSON form (see [`comparable`]) with the
+//! sections `headers`, `claims` and `certificate`, which is then compared recursively.
+//! Arrays (like the vaccination entries) are compared by index.
+use std::{collections::BTreeSet, fmt};
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    cose::{HeaderParam, Headers},
+    dcc::CertPayload,
+};
+
+/// A value that differs between the left and the right certificate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    /// The path of the value, e.g. `certificate.v[0].dn`
+    pub path: String,
+    /// The value in the left certificate, `None` if it is missing there
+    pub left: Option<Value>,
+    /// The value in the right certificate, `None` if it is missing there
+    pub right: Option<Value>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => write!(f, "~ {}: {} -> {}", self.path, left, right),
+            (Some(left), None) => write!(f, "- {}: {}", self.path, left),
+            (None, Some(right)) => write!(f, "+ {}: {}", self.path, right),
+            (None, None) => write!(f, "  {}", self.path),
+        }
+    }
+}
+
+/// The form of a certificate that is compared: the header parameters by name, the CWT
+/// claims (including unknown ones) and the DCC
+pub fn comparable(headers: Option<&Headers>, payload: &CertPayload) -> serde_json::Result<Value> {
+    let headers = headers.map(|headers| {
+        let params = |params: &[HeaderParam]| {
+            let params = params.iter().map(|param| {
+                let name = param.name.clone().unwrap_or_else(|| param.label.clone());
+                (name, Value::String(param.value.clone()))
+            });
+            Value::Object(params.collect())
+        };
+        json!({
+            "protected": params(&headers.protected),
+            "unprotected": params(&headers.unprotected),
+        })
+    });
+    let mut claims = match serde_json::to_value(payload)? {
+        Value::Object(claims) => claims,
+        _ => Map::new(),
+    };
+    claims.remove("hcert");
+    let certificate = serde_json::to_value(&payload.health_claim.cert)?;
+    Ok(json!({
+        "headers": headers,
+        "claims": claims,
+        "certificate": certificate,
+    }))
+}
+
+/// The differences between `left` and `right`, in the order of the paths
+pub fn diff(left: &Value, right: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_at("", Some(left), Some(right), &mut differences);
+    differences
+}
+
+fn diff_at(path: &str, left: Option<&Value>, right: Option<&Value>, out: &mut Vec<Difference>) {
+    match (left, right) {
+        (Some(Value::Object(left)), Some(Value::Object(right))) => {
+            let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+            for key in keys {
+                let path = match path {
+                    "" => key.clone(),
+                    path => format!("{}.{}", path, key),
+                };
+                diff_at(&path, left.get(key), right.get(key), out);
+            }
+        }
+        (Some(Value::Array(left)), Some(Value::Array(right))) => {
+            for i in 0..left.len().max(right.len()) {
+                let path = format!("{}[{}]", path, i);
+                diff_at(&path, left.get(i), right.get(i), out);
+            }
+        }
+        (left, right) if left != right => out.push(Difference {
+            path: path.to_string(),
+            left: left.cloned(),
+            right: right.cloned(),
+        }),
+        _ => {}
+    }
+}
@@ -0,0 +1,68 @@
+//! Platform specific locations for the trust list and value sets
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use tracing::debug;
+
+use crate::config::Config;
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "dcc-decode")
+}
+
+/// The directory of the default config file (e.g. `~/.config/dcc-decode`)
+pub fn config_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// The directories that contain the (downloaded) trust data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDirs {
+    /// For data that can be refreshed, like the trust list (e.g. `~/.cache/dcc-decode`)
+    pub cache: PathBuf,
+    /// For data that is kept, like the value sets (e.g. `~/.local/share/dcc-decode`)
+    pub data: PathBuf,
+}
+
+impl DataDirs {
+    /// The platform directories, with the given overrides
+    pub fn new(cache: Option<PathBuf>, data: Option<PathBuf>) -> Option<Self> {
+        let dirs = project_dirs();
+        let cache = cache.or_else(|| Some(dirs.as_ref()?.cache_dir().to_path_buf()))?;
+        let data = data.or_else(|| Some(dirs.as_ref()?.data_dir().to_path_buf()))?;
+        Some(Self { cache, data })
+    }
+
+    pub fn trustlist(&self) -> PathBuf {
+        self.cache.join("trustlist.json")
+    }
+
+    pub fn valuesets(&self) -> PathBuf {
+        self.data.join("ehn-dcc-valuesets")
+    }
+
+    /// The default locations as a config
+    ///
+    /// For compatibility, files in the working directory are used if there are none in the
+    /// platform directories.
+    pub fn defaults(&self) -> Config {
+        Config {
+            trustlist: Some(with_fallback(self.trustlist(), "trustlist.json")),
+            valuesets: Some(with_fallback(self.valuesets(), "ehn-dcc-valuesets")),
+            ..Config::default()
+        }
+    }
+}
+
+fn with_fallback(path: PathBuf, fallback: &str) -> PathBuf {
+    let fallback = Path::new(fallback);
+    if !path.exists() && fallback.exists() {
+        debug!(
+            "'{}' does not exist, using '{}' from the working directory",
+            path.display(),
+            fallback.display()
+        );
+        return fallback.to_path_buf();
+    }
+    path
+}
@@ -0,0 +1,284 @@
+//! Downloads of trust data, with HTTP caching
+use std::{
+    error::Error,
+    fmt, io,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::json::Loadable;
+
+/// Maximum size of a downloaded file
+const MAX_DOWNLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum DownloadError {
+    Http(Box<ureq::Error>),
+    #[cfg(feature = "async")]
+    AsyncHttp(reqwest::Error),
+    Io(io::Error),
+    TooLarge,
+}
+
+impl Error for DownloadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            #[cfg(feature = "async")]
+            Self::AsyncHttp(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::TooLarge => None,
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "Download failed: {}", e),
+            #[cfg(feature = "async")]
+            Self::AsyncHttp(e) => write!(f, "Download failed: {}", e),
+            Self::Io(e) => write!(f, "Failed to store download: {}", e),
+            Self::TooLarge => write!(f, "Download exceeds {} bytes", MAX_DOWNLOAD_SIZE),
+        }
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// The HTTP caching headers of a downloaded file, stored next to it as `<file>.meta.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheMeta {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl Loadable for CacheMeta {}
+
+impl CacheMeta {
+    pub fn path_for(file: &Path) -> PathBuf {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".meta.json");
+        PathBuf::from(path)
+    }
+
+    /// The meta data of the previous download of `url` to `path`, unless `force` is set
+    fn cached(url: &str, path: &Path, force: bool) -> Option<Self> {
+        if path.exists() && !force {
+            Self::read_from_file(Self::path_for(path))
+                .ok()
+                .filter(|meta| meta.url == url)
+        } else {
+            None
+        }
+    }
+
+    /// The headers that make a request conditional on this download
+    fn conditional_headers(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        let etag = self.etag.as_deref().map(|etag| ("If-None-Match", etag));
+        let last_modified = self
+            .last_modified
+            .as_deref()
+            .map(|last_modified| ("If-Modified-Since", last_modified));
+        etag.into_iter().chain(last_modified)
+    }
+}
+
+/// The outcome of [`fetch_cached`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fetched {
+    /// A new version was downloaded
+    Updated,
+    /// The server reported that the cached file is still current
+    NotModified,
+}
+
+/// Download `url` to `path`, unless the server reports that the file did not change
+///
+/// The request is conditional on the ETag and Last-Modified values from the previous
+/// download of the same URL, unless `force` is set. `transform` is applied to the body
+/// before it is written. The `agent` holds the TLS settings, e.g. from `TlsSettings::agent`.
+pub fn fetch_cached(
+    agent: &ureq::Agent,
+    url: &str,
+    path: &Path,
+    force: bool,
+    transform: impl FnOnce(Vec<u8>) -> Vec<u8>,
+) -> Result<Fetched, DownloadError> {
+    let cached = CacheMeta::cached(url, path, force);
+    let mut request = agent.get(url);
+    for (name, value) in cached.iter().flat_map(CacheMeta::conditional_headers) {
+        request = request.set(name, value);
+    }
+    let response = request
+        .call()
+        .map_err(|e| DownloadError::Http(Box::new(e)))?;
+    if response.status() == 304 {
+        info!("'{}' has not changed", url);
+        return Ok(Fetched::NotModified);
+    }
+
+    let meta = CacheMeta {
+        url: url.to_string(),
+        etag: response.header("ETag").map(str::to_string),
+        last_modified: response.header("Last-Modified").map(str::to_string),
+        fetched_at: Utc::now(),
+    };
+    let body = read_body(response, url)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    write_atomic(path, &transform(body))?;
+    write_atomic(
+        &CacheMeta::path_for(path),
+        &serde_json::to_vec_pretty(&meta).map_err(io::Error::from)?,
+    )?;
+    Ok(Fetched::Updated)
+}
+
+/// Download `url` without caching
+pub fn fetch(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>, DownloadError> {
+    let response = agent
+        .get(url)
+        .call()
+        .map_err(|e| DownloadError::Http(Box::new(e)))?;
+    read_body(response, url)
+}
+
+fn read_body(response: ureq::Response, url: &str) -> Result<Vec<u8>, DownloadError> {
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD_SIZE + 1)
+        .read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_DOWNLOAD_SIZE {
+        return Err(DownloadError::TooLarge);
+    }
+    debug!("Downloaded {} bytes from '{}'", body.len(), url);
+    Ok(body)
+}
+
+/// A file that is kept up to date with [`update_all`]
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub url: String,
+    pub path: PathBuf,
+    /// Applied to the body before it is written, e.g. `trust::strip_signature_line`
+    pub transform: fn(Vec<u8>) -> Vec<u8>,
+}
+
+#[cfg(feature = "async")]
+impl Download {
+    pub fn new(url: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            url: url.into(),
+            path: path.into(),
+            transform: |body| body,
+        }
+    }
+}
+
+/// Like [`fetch_cached`], but without blocking the thread of the (tokio) runtime
+#[cfg(feature = "async")]
+pub async fn fetch_cached_async(
+    client: &reqwest::Client,
+    download: &Download,
+    force: bool,
+) -> Result<Fetched, DownloadError> {
+    let (url, path) = (download.url.as_str(), download.path.as_path());
+    let cached = if force || !tokio::fs::try_exists(path).await? {
+        None
+    } else {
+        let meta = tokio::fs::read(CacheMeta::path_for(path)).await.ok();
+        meta.and_then(|meta| serde_json::from_slice::<CacheMeta>(&meta).ok())
+            .filter(|meta| meta.url == url)
+    };
+
+    let mut request = client.get(url);
+    for (name, value) in cached.iter().flat_map(CacheMeta::conditional_headers) {
+        request = request.header(name, value);
+    }
+    let mut response = request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(DownloadError::AsyncHttp)?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        info!("'{}' has not changed", url);
+        return Ok(Fetched::NotModified);
+    }
+    let response_header = |name: &str| {
+        let value = response.headers().get(name)?.to_str().ok()?;
+        Some(value.to_string())
+    };
+    let meta = CacheMeta {
+        url: url.to_string(),
+        etag: response_header("ETag"),
+        last_modified: response_header("Last-Modified"),
+        fetched_at: Utc::now(),
+    };
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(DownloadError::AsyncHttp)? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > MAX_DOWNLOAD_SIZE {
+            return Err(DownloadError::TooLarge);
+        }
+    }
+    debug!("Downloaded {} bytes from '{}'", body.len(), url);
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    write_atomic_async(path, &(download.transform)(body)).await?;
+    write_atomic_async(
+        &CacheMeta::path_for(path),
+        &serde_json::to_vec_pretty(&meta).map_err(io::Error::from)?,
+    )
+    .await?;
+    Ok(Fetched::Updated)
+}
+
+/// Update all `downloads`, e.g. the trust list and value sets of a server
+///
+/// Returns the outcome for each download, in the same order; a failure doesn't stop
+/// the remaining downloads.
+#[cfg(feature = "async")]
+pub async fn update_all(
+    client: &reqwest::Client,
+    downloads: &[Download],
+    force: bool,
+) -> Vec<Result<Fetched, DownloadError>> {
+    let mut results = Vec::with_capacity(downloads.len());
+    for download in downloads {
+        results.push(fetch_cached_async(client, download, force).await);
+    }
+    results
+}
+
+/// Write to a temporary file first, so that readers never see a partial file
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(feature = "async")]
+async fn write_atomic_async(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tokio::fs::write(&tmp, data).await?;
+    tokio::fs::rename(&tmp, path).await
+}
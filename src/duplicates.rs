@@ -0,0 +1,52 @@
+//! Detection of certificates that are scanned more than once in a run
+//!
+//! The same certificate (by UVCI) showing up in several inputs of a batch can be a sign
+//! of a shared certificate, e.g. a screenshot that is passed around. Repeats across runs
+//! are found with the [`crate::db::ResultDb`].
+use std::collections::BTreeMap;
+
+/// The inputs in which each UVCI was seen
+#[derive(Debug, Default, Clone)]
+pub struct UvciIndex {
+    seen: BTreeMap<String, Vec<String>>,
+}
+
+/// A certificate that was seen in more than one input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate<'a> {
+    pub uvci: &'a str,
+    /// The names of the inputs, in the order they were scanned
+    pub inputs: &'a [String],
+}
+
+impl UvciIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `uvci` was seen in `input`
+    ///
+    /// Returns the inputs in which it was seen before, empty for the first scan.
+    pub fn insert(&mut self, uvci: &str, input: &str) -> &[String] {
+        let inputs = self.seen.entry(uvci.to_string()).or_default();
+        inputs.push(input.to_string());
+        &inputs[..inputs.len() - 1]
+    }
+
+    /// The certificates that were seen more than once, by UVCI
+    pub fn duplicates(&self) -> impl Iterator<Item = Duplicate<'_>> {
+        self.seen
+            .iter()
+            .filter(|(_, inputs)| inputs.len() > 1)
+            .map(|(uvci, inputs)| Duplicate { uvci, inputs })
+    }
+
+    /// The number of distinct certificates
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
@@ -0,0 +1,279 @@
+//! Stable, machine-readable codes for the failures of a run
+//!
+//! The messages of errors may change between versions, the codes (e.g. `E_B45_CHAR`)
+//! don't, so that integrators can branch on them. Each code also maps to an exit status.
+use std::{collections::HashSet, error::Error, fmt};
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    b45::Base45Error, dcc::DecodeError, session::SessionResult, shc::ShcError, spec::Rule,
+    vds::VdsError, verify::Verification,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The input could not be read, or contains no QR code
+    Input,
+    /// The input is not valid UTF-8 text
+    Text,
+    /// The `HC1:` prefix is missing
+    Prefix,
+    Base64,
+    Hex,
+    /// A character that is not in the base45 alphabet
+    Base45Char,
+    /// A single character is left over at the end of the base45 input
+    Base45Length,
+    /// Three base45 characters that encode a value above 65535
+    Base45Value,
+    Zlib,
+    /// The decompressed COSE message exceeds the size limit
+    TooLarge,
+    /// The COSE message is malformed
+    Cose,
+    /// The CWT payload is malformed
+    Cbor,
+    SchemaVersion,
+    /// An unknown claim or field, or a field outside of the schema version
+    /// (`--reject-unknown-fields`)
+    UnknownField,
+    /// The CWT payload is not in deterministic CBOR encoding (`--strict`)
+    NotCanonical,
+    /// The SMART Health Card is malformed
+    Shc,
+    /// The VDS-NC is malformed
+    Vds,
+    /// No DSC with a matching kid was found
+    KidUnknown,
+    AlgUnsupported,
+    /// The `alg` header does not match the type of the DSC key
+    AlgMismatch,
+    /// The DSC may not sign this type of certificate
+    DscKeyUsage,
+    /// The DSC was not valid when the certificate was issued
+    DscValidity,
+    /// The DSC is revoked by a CRL of its CSCA
+    DscRevoked,
+    /// The signer certificate is not issued by a trusted CSCA
+    CertPath,
+    /// The signature is invalid
+    CoseSig,
+    /// The ECDSA signature is DER encoded instead of `r || s`
+    SigFormat,
+    /// The kid or certificate identifier is on the blocklist
+    Blocked,
+    /// The certificate is expired (fails the run with `--reject-expired`)
+    Expired,
+    /// Any other failure
+    Other,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Input => "E_INPUT",
+            Self::Text => "E_TEXT",
+            Self::Prefix => "E_PREFIX",
+            Self::Base64 => "E_B64",
+            Self::Hex => "E_HEX",
+            Self::Base45Char => "E_B45_CHAR",
+            Self::Base45Length => "E_B45_LENGTH",
+            Self::Base45Value => "E_B45_VALUE",
+            Self::Zlib => "E_ZLIB",
+            Self::TooLarge => "E_TOO_LARGE",
+            Self::Cose => "E_COSE",
+            Self::Cbor => "E_CBOR",
+            Self::SchemaVersion => "E_SCHEMA_VERSION",
+            Self::UnknownField => "E_UNKNOWN_FIELD",
+            Self::NotCanonical => "E_NOT_CANONICAL",
+            Self::Shc => "E_SHC",
+            Self::Vds => "E_VDS",
+            Self::KidUnknown => "E_KID_UNKNOWN",
+            Self::AlgUnsupported => "E_ALG_UNSUPPORTED",
+            Self::AlgMismatch => "E_ALG_MISMATCH",
+            Self::DscKeyUsage => "E_DSC_KEY_USAGE",
+            Self::DscValidity => "E_DSC_VALIDITY",
+            Self::DscRevoked => "E_DSC_REVOKED",
+            Self::CertPath => "E_CERT_PATH",
+            Self::CoseSig => "E_COSE_SIG",
+            Self::SigFormat => "E_SIG_FORMAT",
+            Self::Blocked => "E_BLOCKED",
+            Self::Expired => "E_EXPIRED",
+            Self::Other => "E_OTHER",
+        }
+    }
+
+    /// The exit status of a run that failed with this code
+    ///
+    /// `1` for input and other errors, `2` for malformed certificates, `3` for certificates
+    /// whose signature was not accepted and `4` for expired ones. An expired certificate
+    /// only fails a run with `--reject-expired`, otherwise [`ErrorCode::Expired`] is only
+    /// listed in [`ErrorCode::of_result`].
+    pub fn exit_status(self) -> i32 {
+        match self {
+            Self::Input | Self::Other => 1,
+            Self::Text
+            | Self::Prefix
+            | Self::Base64
+            | Self::Hex
+            | Self::Base45Char
+            | Self::Base45Length
+            | Self::Base45Value
+            | Self::Zlib
+            | Self::TooLarge
+            | Self::Cose
+            | Self::Cbor
+            | Self::SchemaVersion
+            | Self::UnknownField
+            | Self::NotCanonical
+            | Self::SigFormat
+            | Self::Shc
+            | Self::Vds => 2,
+            Self::KidUnknown
+            | Self::AlgUnsupported
+            | Self::AlgMismatch
+            | Self::DscKeyUsage
+            | Self::DscValidity
+            | Self::DscRevoked
+            | Self::CertPath
+            | Self::CoseSig
+            | Self::Blocked => 3,
+            Self::Expired => 4,
+        }
+    }
+
+    /// The codes of the problems of a run that did not necessarily fail it, e.g. a kid that
+    /// was not found or an expired certificate
+    pub fn of_result(result: &SessionResult, now: DateTime<Utc>) -> Vec<Self> {
+        let verification = result.verification.as_ref();
+        let mut codes: Vec<Self> = verification
+            .and_then(Verification::code)
+            .into_iter()
+            .collect();
+        // The finding of the verification is already covered by its code
+        let finding = verification.and_then(Verification::finding);
+        let findings = result
+            .findings
+            .iter()
+            .filter(|f| Some(*f) != finding.as_ref());
+        codes.extend(findings.map(|f| f.rule.code()));
+        if result.claims.as_ref().is_some_and(|c| c.is_expired(now)) {
+            codes.push(Self::Expired);
+        }
+        let mut seen = HashSet::new();
+        codes.retain(|code| seen.insert(*code));
+        codes
+    }
+
+    /// The code of the first error in the chain of `report` that has one
+    pub fn of_report(report: &color_eyre::Report) -> Self {
+        report
+            .chain()
+            .find_map(Self::of_error)
+            .unwrap_or(Self::Other)
+    }
+
+    fn of_error(e: &(dyn Error + 'static)) -> Option<Self> {
+        if let Some(e) = e.downcast_ref::<CodedError>() {
+            Some(e.code)
+        } else if let Some(e) = e.downcast_ref::<DecodeError>() {
+            Some(e.code())
+        } else if let Some(v) = e.downcast_ref::<Verification>() {
+            v.code()
+        } else if e.is::<ShcError>() {
+            Some(Self::Shc)
+        } else if e.is::<VdsError>() {
+            Some(Self::Vds)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl DecodeError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::MissingPrefix => ErrorCode::Prefix,
+            Self::Text(_) => ErrorCode::Text,
+            Self::Base64(_) => ErrorCode::Base64,
+            Self::Hex(_) => ErrorCode::Hex,
+            Self::Base45(Base45Error::InvalidChar(_), _) => ErrorCode::Base45Char,
+            Self::Base45(Base45Error::InvalidLength, _) => ErrorCode::Base45Length,
+            Self::Base45(Base45Error::InvalidTriple(_), _) => ErrorCode::Base45Value,
+            Self::Zlib(..) => ErrorCode::Zlib,
+            Self::TooLarge(_) => ErrorCode::TooLarge,
+            Self::Cose(..) => ErrorCode::Cose,
+            Self::Payload(..) => ErrorCode::Cbor,
+            Self::SchemaVersion(_) => ErrorCode::SchemaVersion,
+        }
+    }
+}
+
+impl Rule {
+    pub fn code(self) -> ErrorCode {
+        match self {
+            Self::KeyIdentifier => ErrorCode::KidUnknown,
+            Self::SignatureAlgorithm => ErrorCode::AlgUnsupported,
+            Self::Signature => ErrorCode::CoseSig,
+            Self::DscKeyUsage => ErrorCode::DscKeyUsage,
+            Self::DscValidity => ErrorCode::DscValidity,
+            Self::DscRevocation => ErrorCode::DscRevoked,
+            Self::DeterministicEncoding => ErrorCode::NotCanonical,
+            Self::CertificatePath => ErrorCode::CertPath,
+            Self::SignatureFormat => ErrorCode::SigFormat,
+        }
+    }
+}
+
+impl Verification {
+    /// The code of the outcome, if the signature was not verified
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::Verified => None,
+            Self::KeyNotFound => Some(ErrorCode::KidUnknown),
+            Self::UnsupportedAlgorithm(_) => Some(ErrorCode::AlgUnsupported),
+            Self::AlgorithmMismatch(_) => Some(ErrorCode::AlgMismatch),
+            Self::Rejected(finding) => Some(finding.rule.code()),
+            Self::Invalid => Some(ErrorCode::CoseSig),
+            Self::Blocked(_) => Some(ErrorCode::Blocked),
+            Self::Revoked(_) => Some(ErrorCode::DscRevoked),
+        }
+    }
+}
+
+/// An error with a message and an explicit code, for failures without a typed error
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl CodedError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl Error for CodedError {}
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
@@ -0,0 +1,84 @@
+//! Mapping of a DCC to FHIR R4 resources
+//!
+//! This follows the eHN mapping of the vaccination entry to an `Immunization` resource,
+//! which refers to a `Patient` with the name and date of birth of the holder. Test and
+//! recovery entries have no mapping yet.
+use serde_json::{json, Value};
+
+use crate::dcc::{valuesets::ValueSetEntry, CertPayload, DateOfBirth, Vaccination};
+
+const PATIENT_ID: &str = "patient";
+/// The system of the UVCI identifiers
+const UVCI_SYSTEM: &str = "urn:ietf:rfc:3986";
+/// ISO 3166-1 alpha-2 country codes
+const COUNTRY_SYSTEM: &str = "urn:iso:std:iso:3166";
+
+/// A `CodeableConcept` with the system and display name from the value set, if known
+fn concept(entry: &ValueSetEntry) -> Value {
+    let mut coding = json!({ "code": entry.key });
+    if let Some(value) = &entry.value {
+        coding["system"] = json!(value.system);
+        if !value.version.is_empty() {
+            coding["version"] = json!(value.version);
+        }
+        coding["display"] = json!(value.display);
+    }
+    json!({ "coding": [coding] })
+}
+
+fn patient(payload: &CertPayload) -> Value {
+    let cert = &payload.health_claim.cert;
+    let mut patient = json!({
+        "resourceType": "Patient",
+        "id": PATIENT_ID,
+        "name": [{
+            "family": cert.name.first_name,
+            "given": [cert.name.given_name],
+        }],
+    });
+    // FHIR has the same partial dates, but no empty one
+    if cert.date_of_birth != DateOfBirth::Unknown {
+        patient["birthDate"] = json!(cert.date_of_birth.to_string());
+    }
+    patient
+}
+
+fn immunization(index: usize, v: &Vaccination) -> Value {
+    json!({
+        "resourceType": "Immunization",
+        "id": format!("immunization-{}", index),
+        "identifier": [{ "system": UVCI_SYSTEM, "value": v.cert_identifier }],
+        "status": "completed",
+        "vaccineCode": concept(&v.medicinal_product),
+        "patient": { "reference": format!("Patient/{}", PATIENT_ID) },
+        "occurrenceDateTime": v.date.to_string(),
+        "manufacturer": { "display": v.manufacturer.display() },
+        "location": { "identifier": { "system": COUNTRY_SYSTEM, "value": v.country.key } },
+        "protocolApplied": [{
+            "authority": { "display": v.issuer },
+            "targetDisease": [concept(&v.disease_agent_targeted)],
+            "doseNumberPositiveInt": v.dose_number,
+            "seriesDosesPositiveInt": v.series_dose_number,
+        }],
+    })
+}
+
+/// Map a certificate to a FHIR `Bundle` of type `collection`
+pub fn bundle(payload: &CertPayload) -> Value {
+    let cert = &payload.health_claim.cert;
+    let resources = std::iter::once(patient(payload)).chain(
+        cert.vaccine
+            .iter()
+            .enumerate()
+            .map(|(i, v)| immunization(i, v)),
+    );
+    let entries: Vec<Value> = resources
+        .map(|resource| json!({ "resource": resource }))
+        .collect();
+    json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "timestamp": payload.issued_at.to_rfc3339(),
+        "entry": entries,
+    })
+}
@@ -1,7 +1,7 @@
 use std::{borrow::Cow, error::Error, ffi::OsStr, fs::File, io::BufReader, path::Path};
 
-use log::{debug, error};
 use serde::Deserialize;
+use tracing::{debug, error};
 
 pub trait Loadable: for<'de> Deserialize<'de> {
     fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
@@ -0,0 +1,212 @@
+//! Keys and self-signed DSC certificates for local test setups
+//!
+//! The certificate is a minimal X.509 v3 certificate with the DCC extended key usages,
+//! so that it can be used in a trust directory (or trust list) like a real DSC.
+use chrono::{DateTime, Datelike, Duration, Utc};
+use color_eyre::eyre::eyre;
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+
+use crate::trust::jwks::{der_tlv, der_unsigned_integer, P256_SPKI_PREFIX};
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const OCTET_STRING: u8 = 0x04;
+const BIT_STRING: u8 = 0x03;
+const OID: u8 = 0x06;
+const PRINTABLE_STRING: u8 = 0x13;
+const UTF8_STRING: u8 = 0x0c;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+
+/// `ecdsa-with-SHA256` (1.2.840.10045.4.3.2)
+const ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+/// `countryName` (2.5.4.6)
+const COUNTRY_NAME: &[u8] = &[0x55, 0x04, 0x06];
+/// `commonName` (2.5.4.3)
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// `extKeyUsage` (2.5.29.37)
+const EXT_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+/// The DCC key usages `1.3.6.1.4.1.1847.2021.1.x` for tests, vaccinations and recoveries
+const DCC_KEY_USAGES: &[&[u8]] = &[
+    &[
+        0x2b, 0x06, 0x01, 0x04, 0x01, 0x8e, 0x37, 0x8f, 0x65, 0x01, 0x01,
+    ],
+    &[
+        0x2b, 0x06, 0x01, 0x04, 0x01, 0x8e, 0x37, 0x8f, 0x65, 0x01, 0x02,
+    ],
+    &[
+        0x2b, 0x06, 0x01, 0x04, 0x01, 0x8e, 0x37, 0x8f, 0x65, 0x01, 0x03,
+    ],
+];
+
+/// The longest validity of a certificate, about 100 years
+const MAX_DAYS: i64 = 36500;
+
+/// A generated P-256 key with its self-signed certificate
+pub struct TestSigner {
+    /// The private key as PKCS#8 document
+    pub pkcs8: Vec<u8>,
+    /// The DER encoded certificate
+    pub certificate: Vec<u8>,
+}
+
+fn sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    der_tlv(SEQUENCE, &items.concat())
+}
+
+/// A name with a country and a common name
+fn name(country: &str, common_name: &str) -> Vec<u8> {
+    let attribute = |oid: &[u8], tag: u8, value: &str| {
+        der_tlv(
+            SET,
+            &sequence(&[der_tlv(OID, oid), der_tlv(tag, value.as_bytes())]),
+        )
+    };
+    sequence(&[
+        attribute(COUNTRY_NAME, PRINTABLE_STRING, country),
+        attribute(COMMON_NAME, UTF8_STRING, common_name),
+    ])
+}
+
+/// A validity time, as UTCTime until 2049 and as GeneralizedTime from 2050 on (RFC 5280,
+/// section 4.1.2.5)
+fn validity_time(time: DateTime<Utc>) -> Vec<u8> {
+    let (tag, format) = if time.year() < 2050 {
+        (UTC_TIME, "%y%m%d%H%M%SZ")
+    } else {
+        (GENERALIZED_TIME, "%Y%m%d%H%M%SZ")
+    };
+    der_tlv(tag, time.format(format).to_string().as_bytes())
+}
+
+impl TestSigner {
+    /// Generate a key and a certificate for `country` that is valid for `days` from now
+    pub fn generate(country: &str, common_name: &str, days: i64) -> color_eyre::Result<Self> {
+        if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(eyre!(
+                "Expected a two letter country code, got '{}'",
+                country
+            ));
+        }
+        if !(1..=MAX_DAYS).contains(&days) {
+            return Err(eyre!(
+                "Expected a validity of 1 to {} days, got {}",
+                MAX_DAYS,
+                days
+            ));
+        }
+        let now = Utc::now();
+        let not_after = now
+            .checked_add_signed(Duration::days(days))
+            .ok_or_else(|| eyre!("The certificate would be valid beyond the supported dates"))?;
+
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .map_err(|_| eyre!("Failed to generate a key"))?;
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+            .map_err(|e| eyre!("Failed to load the generated key: {}", e))?;
+
+        let mut serial = [0; 16];
+        rng.fill(&mut serial)
+            .map_err(|_| eyre!("Failed to generate a serial number"))?;
+        // Keep the serial number positive and at most 20 bytes
+        serial[0] &= 0x7f;
+
+        let algorithm = sequence(&[der_tlv(OID, ECDSA_WITH_SHA256)]);
+        let name = name(country, common_name);
+        // The prefix ends with the 0x04 of the uncompressed point
+        let mut spki = P256_SPKI_PREFIX[..P256_SPKI_PREFIX.len() - 1].to_vec();
+        spki.extend(key_pair.public_key().as_ref());
+        let usages: Vec<Vec<u8>> = DCC_KEY_USAGES.iter().map(|oid| der_tlv(OID, oid)).collect();
+        let extensions = sequence(&[sequence(&[
+            der_tlv(OID, EXT_KEY_USAGE),
+            der_tlv(OCTET_STRING, &sequence(&usages)),
+        ])]);
+
+        let tbs = sequence(&[
+            // [0] version: v3
+            der_tlv(0xa0, &der_unsigned_integer(&[2])),
+            der_unsigned_integer(&serial),
+            algorithm.clone(),
+            name.clone(),
+            sequence(&[validity_time(now), validity_time(not_after)]),
+            name,
+            spki,
+            // [3] extensions
+            der_tlv(0xa3, &extensions),
+        ]);
+        let signature = key_pair
+            .sign(&rng, &tbs)
+            .map_err(|_| eyre!("Failed to sign the certificate"))?;
+        let mut bit_string = vec![0x00];
+        bit_string.extend(signature.as_ref());
+        let certificate = sequence(&[tbs, algorithm, der_tlv(BIT_STRING, &bit_string)]);
+
+        Ok(Self {
+            pkcs8: pkcs8.as_ref().to_vec(),
+            certificate,
+        })
+    }
+
+    /// The key and the certificate as PEM blocks
+    pub fn to_pem(&self) -> String {
+        pem("PRIVATE KEY", &self.pkcs8) + &pem("CERTIFICATE", &self.certificate)
+    }
+}
+
+/// Encode a PEM block, with the base64 content in lines of 64 characters
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::encode(der);
+    let mut out = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, Utc};
+    use x509_parser::parse_x509_certificate;
+
+    use super::TestSigner;
+
+    /// The years of the notBefore and notAfter of a generated certificate
+    fn validity_years(days: i64) -> (i32, i32) {
+        let signer = TestSigner::generate("DE", "Test", days).unwrap();
+        let (_, cert) = parse_x509_certificate(&signer.certificate).unwrap();
+        let validity = &cert.tbs_certificate.validity;
+        let year = |time: x509_parser::time::ASN1Time| {
+            chrono::DateTime::from_timestamp(time.timestamp(), 0)
+                .unwrap()
+                .year()
+        };
+        (year(validity.not_before), year(validity.not_after))
+    }
+
+    #[test]
+    fn validity() {
+        let year = Utc::now().year();
+        assert_eq!(validity_years(1).0, year);
+        // From 2050 on, GeneralizedTime keeps the century
+        let (not_before, not_after) = validity_years(30000);
+        assert_eq!(not_before, year);
+        assert!(not_after > year + 80);
+    }
+
+    #[test]
+    fn invalid_validity() {
+        for days in [0, -1, 36501, 100_000_000] {
+            assert!(
+                TestSigner::generate("DE", "Test", days).is_err(),
+                "{}",
+                days
+            );
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Decoding (and verification) of EU Digital COVID Certificates
+//!
+//! Without the default `std` feature, only the [`core`] module is built, with `no_std`
+//! and `alloc`, e.g. to run the parser on embedded hardware.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "verify")]
+use x509_parser::{
+    der_parser::{self, oid},
+    oid_registry::OidRegistry,
+};
+
+#[cfg(feature = "std")]
+pub mod b45;
+#[cfg(feature = "verify")]
+pub mod bug_report;
+#[cfg(feature = "verify")]
+pub mod bundle;
+#[cfg(feature = "std")]
+pub mod cbor;
+#[cfg(feature = "verify")]
+pub mod cert;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod context;
+pub mod core;
+#[cfg(feature = "std")]
+pub mod cose;
+#[cfg(feature = "verify")]
+pub mod countersign;
+#[cfg(feature = "verify")]
+pub mod crl;
+#[cfg(feature = "verify")]
+mod crypto;
+#[cfg(feature = "verify")]
+pub mod csv;
+#[cfg(feature = "std")]
+pub mod cwt;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+#[cfg(feature = "db")]
+pub mod db;
+#[cfg(feature = "std")]
+pub mod dcc;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "cli")]
+pub mod dirs;
+#[cfg(feature = "download")]
+pub mod download;
+#[cfg(feature = "std")]
+pub mod duplicates;
+#[cfg(feature = "verify")]
+pub mod error_code;
+#[cfg(feature = "std")]
+pub mod fhir;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(all(feature = "verify", feature = "ring"))]
+pub mod keygen;
+#[cfg(feature = "std")]
+pub mod lang;
+#[cfg(feature = "verify")]
+pub mod minimal;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "qr")]
+pub mod qr;
+#[cfg(feature = "std")]
+pub mod redact;
+#[cfg(feature = "verify")]
+pub mod report;
+#[cfg(all(feature = "download", feature = "verify"))]
+pub mod rules;
+#[cfg(feature = "verify")]
+pub mod session;
+#[cfg(feature = "std")]
+pub mod shc;
+#[cfg(feature = "verify")]
+pub mod sig;
+#[cfg(feature = "std")]
+pub mod spec;
+#[cfg(feature = "verify")]
+pub mod stats;
+#[cfg(feature = "verify")]
+pub mod summary;
+#[cfg(all(feature = "download", feature = "verify"))]
+pub mod tls;
+#[cfg(feature = "verify")]
+pub mod trust;
+#[cfg(feature = "std")]
+pub mod vds;
+#[cfg(feature = "verify")]
+pub mod verify;
+
+/// An OID registry with some additional entries used in DSCs
+#[cfg(feature = "verify")]
+pub fn oid_registry() -> OidRegistry<'static> {
+    let mut oid_registry = OidRegistry::default().with_crypto().with_x509();
+    oid_registry.insert(oid!(2.5.4 .97), ("organizationIdentifier", ""));
+    oid_registry.insert(
+        oid!(2.5.4 .5),
+        ("serialNumber", "Serial number attribute type"),
+    );
+    oid_registry
+}
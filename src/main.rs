@@ -1,188 +1,2474 @@
+use chrono::{DateTime, Duration, Utc};
 use color_eyre::eyre::eyre;
-use log::{debug, info, warn};
-use once_cell::sync::OnceCell;
-use serde_cose::sig::Sig;
-use std::{convert::TryFrom, fmt};
-use structopt::StructOpt;
-use x509_parser::{der_parser::oid, oid_registry::OidRegistry, prelude::*};
-
-use crate::{
-    cert::{Algorithm, Prime, TrustList},
+use serde::{Deserialize, Serialize};
+use serde_cose::Sign1;
+use std::{
+    fmt,
+    io::{BufRead, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use structopt::{clap::ArgMatches, StructOpt};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, EnvFilter};
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+use dcc_decode::{
+    bug_report::BugReport,
+    bundle::Bundle,
+    cbor,
+    cert::{self, CscaStore, TrustList},
+    config::{self, Config},
+    context::DecodeContext,
+    core::cose::Sign1 as RawSign1,
+    cose::CoseSign1,
+    countersign::{self, CountersignatureStatus},
+    dashboard,
+    db::ResultDb,
     dcc::{
-        load_sign1,
+        inflate,
+        input::{decode_transport, decode_transport_strict, DecodedInput, InputFormat},
+        parse_sign1, sign1_kid,
         valuesets::{EhnData, ValueSet},
-        CertPayload,
+        DecodeError, Warning, DEFAULT_MAX_DECOMPRESSED_SIZE,
     },
+    dirs::DataDirs,
+    download::{self, Fetched},
+    duplicates::UvciIndex,
+    error_code::{CodedError, ErrorCode},
+    fhir,
     json::Loadable,
+    keygen::TestSigner,
+    lang::Lang,
+    minimal::Disclosure,
+    profile::Profile,
+    redact,
+    report::{self, ColorChoice, ReportOptions},
+    rules,
+    session::{CwtClaims, Session, SessionResult, TrustSnapshot},
+    shc::{self, HealthCard},
+    spec::{Finding, Rule},
+    stats::BatchStats,
+    summary,
+    tls::{ClientCert, TlsPins, TlsSettings},
+    trust::{
+        self, austria::AustrianTrustList, jwks::JwkSet, Blocklist, CertDirectory, MergedTrust,
+        SignerCert, SignerInfo, TrustListFormat, TrustSource,
+    },
+    vds,
+    verify::{self, Verification, VerifyOptions},
 };
 
-pub mod b45;
-pub mod cert;
-pub mod cwt;
-pub mod dcc;
-pub mod json;
-//pub mod sig;
+type BoxedTrustSource = Box<dyn TrustSource + Send + Sync>;
+
+/// The DSC list of the german verifier backend
+const DEFAULT_TRUSTLIST_URL: &str = "https://de.dscg.ubirch.com/trustList/DSC/";
+
+fn default_trustlist() -> PathBuf {
+    PathBuf::from("trustlist.json")
+}
+
+fn default_trustlists() -> Vec<PathBuf> {
+    vec![default_trustlist()]
+}
+
+fn default_valuesets() -> PathBuf {
+    PathBuf::from("ehn-dcc-valuesets")
+}
+
+/// Store an optional duration as seconds, e.g. the expiry grace in sessions
+fn serialize_seconds<S: serde::Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    d.map(|d| d.num_seconds()).serialize(s)
+}
+
+fn deserialize_seconds<'de, D: serde::Deserializer<'de>>(
+    d: D,
+) -> Result<Option<Duration>, D::Error> {
+    Ok(Option::<i64>::deserialize(d)?.map(Duration::seconds))
+}
 
-static OID_REGISTRY: OnceCell<OidRegistry> = OnceCell::new();
-static EHN_DATA: OnceCell<EhnData> = OnceCell::new();
-static TRUSTLIST: OnceCell<TrustList> = OnceCell::new();
+fn default_max_decompressed_size() -> u64 {
+    DEFAULT_MAX_DECOMPRESSED_SIZE
+}
 
 #[derive(Debug, StructOpt)]
 struct Args {
+    #[structopt(flatten)]
+    options: Options,
+    /// Read default options from this file [default: ~/.config/dcc-decode/config.toml]
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Directory of the cached trust list [default: e.g. ~/.cache/dcc-decode]
+    #[structopt(long, parse(from_os_str))]
+    cache_dir: Option<PathBuf>,
+    /// Directory of the value sets [default: e.g. ~/.local/share/dcc-decode]
+    #[structopt(long, parse(from_os_str))]
+    data_dir: Option<PathBuf>,
+    /// Format of the log messages on stderr (text, json)
+    #[structopt(long, default_value = "text")]
+    log_format: LogFormat,
+    /// Only log warnings and errors, unless RUST_LOG is set
     #[structopt(long)]
-    json: bool,
+    quiet: bool,
+    /// Record the input, options and results to a session file
+    #[structopt(long, parse(from_os_str))]
+    record: Option<PathBuf>,
+    /// Write the intermediate stages with the personal data replaced by placeholders to
+    /// this JSON file, to attach it to a bug report
+    #[structopt(long, parse(from_os_str))]
+    bug_report: Option<PathBuf>,
+    /// Store the verification result (without personal data) in this SQLite database
+    #[structopt(long, parse(from_os_str))]
+    db: Option<PathBuf>,
+    /// Write the statistics of a batch or --pipe run as JSON to this file
+    #[structopt(long, parse(from_os_str))]
+    stats_json: Option<PathBuf>,
+    /// Read the input from the clipboard (e.g. a pasted `HC1:` string) instead of a file
+    #[structopt(long)]
+    clipboard: bool,
+    /// Keep reading one input per line from stdin until it is closed, printing one JSON
+    /// result per line (unless another --output is given)
+    #[structopt(long)]
+    pipe: bool,
+    /// Read the QR codes in the images of this PDF file, each as one input
+    #[structopt(long, parse(from_os_str))]
+    pdf: Option<PathBuf>,
+    /// The input files, `-` for stdin; more than one file runs all of them in a batch
     #[structopt(default_value = "-")]
-    file: String,
+    files: Vec<String>,
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum LogFormat {
+    Text,
+    Json,
 }
 
-struct CertSubject<'a>(&'a X509Name<'a>);
+/// How the result of each input is printed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Output {
+    /// The certificate (or the selected parts of it)
+    #[default]
+    Text,
+    /// One CSV row per input
+    Csv,
+    /// One JSON object with the result per input, on a single line
+    Json,
+    /// A FHIR bundle with the patient and immunizations per input
+    Fhir,
+}
 
-impl fmt::Debug for CertSubject<'_> {
+impl fmt::Display for Output {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let oid_registry = OID_REGISTRY.get().unwrap();
-        let mut debug = f.debug_struct("X509Name");
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Csv => write!(f, "csv"),
+            Self::Json => write!(f, "json"),
+            Self::Fhir => write!(f, "fhir"),
+        }
+    }
+}
 
-        for key in self.0.iter_attributes() {
-            match (oid_registry.get(&key.attr_type), key.attr_value.as_str()) {
-                (Some(name), Ok(value)) => {
-                    debug.field(name.sn(), &value);
-                }
-                (Some(name), Err(_)) => {
-                    debug.field(name.sn(), &"???");
-                }
-                (None, Ok(value)) => {
-                    let name = format!("oid_{}", key.attr_type);
-                    debug.field(&name, &value);
-                }
-                (None, Err(_)) => {
-                    let name = format!("oid_{}", key.attr_type);
-                    debug.field(&name, &"???");
-                }
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "fhir" => Ok(Self::Fhir),
+            _ => Err(format!(
+                "Unknown output '{}' (expected 'text', 'csv', 'json' or 'fhir')",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Unknown log format '{}' (expected 'text' or 'json')",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, StructOpt, Serialize, Deserialize)]
+#[serde(default)]
+struct Options {
+    #[structopt(long)]
+    json: bool,
+    /// Print the full CWT claims (issuer, timestamps and certificate) as JSON
+    #[structopt(long)]
+    json_payload: bool,
+    /// Format of the input (hc1, base45, base64, hex, cose, cbor)
+    #[structopt(long, default_value = "hc1")]
+    input_format: InputFormat,
+    /// Write the intermediate stages (decoded input, COSE message, Sig_structure, payload and
+    /// signature) to files in this directory
+    #[structopt(long, parse(from_os_str))]
+    dump_stages: Option<PathBuf>,
+    /// Print the COSE message and CWT payload in CBOR diagnostic notation
+    #[structopt(long)]
+    diag: bool,
+    /// Reject a CWT payload that is not in deterministic CBOR encoding, and decode the input
+    /// text exactly (without joining wrapped lines or fixing the case of the prefix)
+    #[structopt(long)]
+    strict: bool,
+    /// Print the protected and unprotected COSE header parameters
+    #[structopt(long)]
+    headers: bool,
+    /// Mask the name, date of birth and certificate identifier of the holder in the output
+    #[structopt(long)]
+    redact: bool,
+    /// Print the certificate as text, or one CSV row, JSON result or FHIR bundle per input
+    /// (text, csv, json, fhir)
+    #[structopt(long, default_value = "text")]
+    output: Output,
+    /// Color the certificate report (auto, always, never)
+    #[structopt(long, default_value = "auto")]
+    color: ColorChoice,
+    /// Print a short plain-language summary instead of the full certificate
+    #[structopt(long)]
+    summary: bool,
+    /// Only print the verdict, the kind of certificate, the initials and year of birth of the
+    /// holder and the validity period, without the name, date of birth or UVCI
+    #[structopt(long)]
+    minimal: bool,
+    /// Language of the summary and value set display names (en, de)
+    #[structopt(long, default_value = "en")]
+    lang: Lang,
+    /// Maximum size of the decompressed COSE message in bytes
+    #[structopt(long, default_value = "262144")]
+    #[serde(default = "default_max_decompressed_size")]
+    max_decompressed_size: u64,
+    /// Defaults for the verification options: strict (conformance testing), standard or
+    /// lenient (field verification); the flags below add to the profile
+    #[structopt(long, default_value = "standard")]
+    #[serde(default)]
+    profile: Profile,
+    /// Fail with E_EXPIRED if the certificate is expired
+    #[structopt(long)]
+    #[serde(default)]
+    reject_expired: bool,
+    /// Only treat certificates as expired this long after their expiration time (e.g. 12h, 3d)
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    #[serde(
+        default,
+        serialize_with = "serialize_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    expiry_grace: Option<Duration>,
+    /// Fail with E_UNKNOWN_FIELD on unknown claims or fields, or fields that are not part of
+    /// the schema version of the certificate
+    #[structopt(long)]
+    #[serde(default)]
+    reject_unknown_fields: bool,
+    /// Fail with E_SIG_FORMAT on DER encoded ECDSA signatures instead of accepting them
+    #[structopt(long)]
+    #[serde(default)]
+    reject_der_signature: bool,
+    /// Only warn if the DSC was not valid when the certificate was issued
+    #[structopt(long)]
+    warn_dsc_validity: bool,
+    /// Only warn about trust list entries with a mismatching thumbprint instead of dropping them
+    #[structopt(long)]
+    warn_thumbprint: bool,
+    /// Path of the trust list, repeat to merge several ones [default: trustlist.json in the
+    /// cache directory, unless --trust-dir is given]
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    #[serde(default = "default_trustlists")]
+    trustlist: Vec<PathBuf>,
+    /// Format of the trust list (json, at, jwks), `*.jwks` files are always read as jwks
+    #[structopt(long, default_value = "json")]
+    trustlist_format: TrustListFormat,
+    /// Signature file of an `at` trust list [default: <trustlist>sig]
+    #[structopt(long, parse(from_os_str))]
+    trustlist_sig: Option<PathBuf>,
+    /// Certificate (PEM/DER) to verify the signature of an `at` trust list with
+    #[structopt(long, parse(from_os_str))]
+    trustlist_anchor: Option<PathBuf>,
+    /// Load the DSCs from a directory of PEM/DER certificates, repeat to merge several ones
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    trust_dir: Vec<PathBuf>,
+    /// Verify the signature against this public key (PEM/DER) instead of the trust list
+    #[structopt(long, parse(from_os_str))]
+    pubkey: Option<PathBuf>,
+    /// JSON Web Key Set with the keys of SMART Health Card issuers
+    #[structopt(long, parse(from_os_str))]
+    shc_jwks: Option<PathBuf>,
+    /// CSCA certificates (PEM/DER) that issue the barcode signers of VDS-NC seals
+    #[structopt(long, parse(from_os_str))]
+    vds_csca: Option<PathBuf>,
+    /// Only trust DSCs that chain to a CSCA from this PEM/DER file
+    #[structopt(long, parse(from_os_str))]
+    csca: Option<PathBuf>,
+    /// Reject DSCs that are revoked by the CRLs (PEM/DER) in this file or at this URL,
+    /// repeat for the CRLs of several CSCAs. The CRLs must be signed by a CSCA from --csca,
+    /// if given
+    #[structopt(long, number_of_values = 1)]
+    crl: Vec<String>,
+    /// The pinned certificates of the download servers, from the config
+    #[structopt(skip)]
+    #[serde(skip)]
+    tls_pins: TlsPins,
+    /// Certificates (PEM/DER) to verify the COSE countersignatures of a certificate with,
+    /// e.g. of a gateway or timestamping service
+    #[structopt(long, parse(from_os_str))]
+    countersign_trust: Option<PathBuf>,
+    /// Reject certificates whose kid or UVCI is listed in this file (`kid:<base64>` or
+    /// `uvci:<UVCI>` per line), even if the signature is valid
+    #[structopt(long, parse(from_os_str))]
+    blocklist: Option<PathBuf>,
+    /// Load the trust lists, value sets, rules, blocklist, CSCAs and CRLs from this signed
+    /// bundle (see `export-bundle`) instead of their usual locations
+    #[structopt(long, parse(from_os_str))]
+    bundle: Option<PathBuf>,
+    /// Certificate (PEM/DER) to verify the signature of the bundle with
+    #[structopt(long, parse(from_os_str))]
+    bundle_anchor: Option<PathBuf>,
+    /// Load the --bundle without verifying its signature, if there is no --bundle-anchor
+    #[structopt(long)]
+    #[serde(skip)]
+    insecure_unsigned_bundle: bool,
+    /// Directory of the eHN value sets [default: ehn-dcc-valuesets in the data directory]
+    #[structopt(
+        long,
+        default_value = "ehn-dcc-valuesets",
+        hide_default_value = true,
+        parse(from_os_str)
+    )]
+    #[serde(default = "default_valuesets")]
+    valuesets: PathBuf,
+}
+
+impl Options {
+    /// Use the settings from `config` for all options that were not given on the command line
+    fn apply_config(&mut self, config: Config, matches: &ArgMatches) {
+        let unset = |name| matches.occurrences_of(name) == 0;
+        // A directory replaces the default trust list, but can be merged with given ones
+        if self.trustlist.is_empty() && self.trust_dir.is_empty() {
+            let trustlist = config.trustlist.unwrap_or_else(default_trustlist);
+            self.trustlist.push(trustlist);
+        }
+        if let Some(format) = config
+            .trustlist_format
+            .filter(|_| unset("trustlist-format"))
+        {
+            self.trustlist_format = format;
+        }
+        if self.csca.is_none() {
+            self.csca = config.csca;
+        }
+        if self.crl.is_empty() {
+            self.crl = config.crl.unwrap_or_default();
+        }
+        self.tls_pins = config.tls_pins.unwrap_or_default();
+        if self.blocklist.is_none() {
+            self.blocklist = config.blocklist;
+        }
+        if let Some(valuesets) = config.valuesets.filter(|_| unset("valuesets")) {
+            self.valuesets = valuesets;
+        }
+        if let Some(lang) = config.lang.filter(|_| unset("lang")) {
+            self.lang = lang;
+        }
+        if let Some(strict) = config.strict.filter(|_| unset("strict")) {
+            self.strict = strict;
+        }
+        if let Some(redact) = config.redact.filter(|_| unset("redact")) {
+            self.redact = redact;
+        }
+        if let Some(profile) = config.profile.filter(|_| unset("profile")) {
+            self.profile = profile;
+        }
+    }
+
+    /// Turn on the settings of the profile, and use its grace period unless one was given
+    fn apply_profile(&mut self) {
+        let settings = self.profile.settings();
+        self.strict |= settings.strict;
+        self.reject_expired |= settings.reject_expired;
+        self.reject_unknown_fields |= settings.reject_unknown_fields;
+        self.reject_der_signature |= settings.reject_der_signature;
+        self.warn_dsc_validity |= settings.warn_dsc_validity;
+        self.warn_thumbprint |= settings.warn_thumbprint;
+        if self.expiry_grace.is_none() {
+            self.expiry_grace = Some(settings.expiry_grace);
+        }
+    }
+
+    /// The time to check the expiration against, i.e. now minus the grace period
+    fn expiry_reference(&self) -> DateTime<Utc> {
+        Utc::now() - self.expiry_grace.unwrap_or_else(Duration::zero)
+    }
+
+    fn verify_options(&self) -> VerifyOptions {
+        VerifyOptions {
+            warn_dsc_validity: self.warn_dsc_validity,
+        }
+    }
+
+    fn trustlist_format(&self, trustlist: &Path) -> TrustListFormat {
+        match trustlist.extension() {
+            Some(ext) if ext == "jwks" => TrustListFormat::Jwks,
+            _ => self.trustlist_format,
+        }
+    }
+
+    fn trustlist_sig(&self, trustlist: &Path) -> PathBuf {
+        self.trustlist_sig.clone().unwrap_or_else(|| {
+            let mut path = trustlist.to_path_buf().into_os_string();
+            path.push("sig");
+            PathBuf::from(path)
+        })
+    }
+
+    fn trust_snapshots(&self) -> Vec<TrustSnapshot> {
+        if let Some(pubkey) = &self.pubkey {
+            let files = std::iter::once(pubkey).chain(&self.blocklist);
+            return files.map(TrustSnapshot::of_file).collect();
+        }
+        let mut trust = Vec::new();
+        for trustlist in &self.trustlist {
+            trust.push(TrustSnapshot::of_file(trustlist));
+            if self.trustlist_format(trustlist) == TrustListFormat::At {
+                trust.push(TrustSnapshot::of_file(self.trustlist_sig(trustlist)));
             }
         }
-        debug.finish()
+        trust.extend(self.trust_dir.iter().map(TrustSnapshot::of_dir));
+        for path in self
+            .trustlist_anchor
+            .iter()
+            .chain(&self.csca)
+            .chain(&self.blocklist)
+            .chain(&self.shc_jwks)
+            .chain(&self.countersign_trust)
+        {
+            trust.push(TrustSnapshot::of_file(path));
+        }
+        // CRLs from URLs can't be snapshotted
+        for crl in self.crl.iter().filter(|crl| !is_url(crl)) {
+            trust.push(TrustSnapshot::of_file(Path::new(crl)));
+        }
+        trust
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Reproduce a run from a recorded session file
+    Replay {
+        #[structopt(parse(from_os_str))]
+        session: PathBuf,
+    },
+    /// Print the kid of the certificate(s) in a PEM/DER file
+    Kid {
+        #[structopt(parse(from_os_str))]
+        cert: PathBuf,
+    },
+    /// Inspect the trust list
+    Trustlist(TrustlistCommand),
+    /// Manage the business rules
+    Rules(RulesCommand),
+    /// Generate a key and a self-signed DSC certificate for testing
+    Keygen {
+        /// The key algorithm, only `es256` for now: RSA keys (`ps256`) can't be generated
+        /// yet, create those e.g. with openssl
+        #[structopt(long, default_value = "es256")]
+        alg: KeyAlgorithm,
+        /// Write the private key and the certificate to this PEM file
+        #[structopt(long, parse(from_os_str))]
+        out: PathBuf,
+        /// The country of the certificate subject
+        #[structopt(long, default_value = "XX")]
+        country: String,
+        /// The common name of the certificate subject
+        #[structopt(long, default_value = "DCC Test DSC")]
+        common_name: String,
+        /// The validity of the certificate in days (1 to 36500)
+        #[structopt(long, default_value = "365")]
+        days: i64,
+    },
+    /// Re-issue the content of a certificate in another format
+    Convert {
+        /// The target format
+        #[structopt(long)]
+        to: ConvertTarget,
+        /// The PKCS#8 P-256 private key to sign with (PEM or DER)
+        #[structopt(long, parse(from_os_str))]
+        key: PathBuf,
+        /// The issuer URL of the health card
+        #[structopt(long)]
+        issuer: String,
+        /// Write the JWK set with the public key to this file, to verify the card with
+        #[structopt(long, parse(from_os_str))]
+        jwks: Option<PathBuf>,
+        /// The input file, `-` for stdin
+        #[structopt(default_value = "-")]
+        file: String,
+    },
+    /// Pack the trust lists, trust directories, value sets, rules, blocklist, CSCAs and CRLs
+    /// into one signed file, to load with --bundle on devices without network access
+    ExportBundle {
+        /// The PKCS#8 P-256 private key to sign with (PEM or DER), e.g. from `keygen`
+        #[structopt(long, parse(from_os_str))]
+        key: PathBuf,
+        /// Write the bundle to this file
+        #[structopt(long, parse(from_os_str))]
+        out: PathBuf,
+    },
+    /// Decode two certificates and print the differences in their headers, claims and
+    /// entries (as a JSON array with --output json)
+    Diff {
+        /// The left input file, `-` for stdin
+        left: String,
+        /// The right input file
+        right: String,
+    },
+    /// Show a dashboard of the lines scanned into the terminal (e.g. by a barcode scanner),
+    /// log messages are not shown meanwhile
+    Dashboard,
+    /// Decode every new file (text, image or PDF) in a directory, then move it to `ok/` or
+    /// `failed/` within that directory
+    Watch {
+        /// The directory to watch
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        /// Append a CSV row for each input to this file [default: <dir>/report.csv]
+        #[structopt(long, parse(from_os_str))]
+        report: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TrustlistCommand {
+    /// List all entries of the trust list
+    Show {
+        /// Only show entries of this country
+        #[structopt(long)]
+        country: Option<String>,
+        /// Only show entries that expire within this time (e.g. 30d, 12h, 2w)
+        #[structopt(long, parse(try_from_str = parse_duration))]
+        expiring_within: Option<Duration>,
+    },
+    /// List the DSCs that expire soon (or already did) per country, as text, CSV or JSON
+    /// (with --output)
+    Expiring {
+        /// The time from now within which the DSCs expire (e.g. 60d, 12h, 2w)
+        #[structopt(long, default_value = "60d", parse(try_from_str = parse_duration))]
+        within: Duration,
+        /// Only list DSCs of this country
+        #[structopt(long)]
+        country: Option<String>,
+    },
+    /// Download the trust list, if it changed since the last download
+    Update {
+        /// URL of the trust list [default: the DSC list of the german verifier backend]
+        #[structopt(long)]
+        url: Option<String>,
+        /// Download the trust list even if it did not change
+        #[structopt(long)]
+        force: bool,
+        #[structopt(flatten)]
+        client: ClientCertArgs,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum RulesCommand {
+    /// Download the business rules, next to the value sets
+    Update {
+        /// Base URL of the rules of a gateway
+        #[structopt(long)]
+        url: Option<String>,
+        /// Only download the rules of these countries (e.g. DE)
+        #[structopt(long)]
+        country: Vec<String>,
+        #[structopt(flatten)]
+        client: ClientCertArgs,
+    },
+}
+
+/// The client certificate of the downloads, for gateways that require mutual TLS
+#[derive(Debug, StructOpt)]
+struct ClientCertArgs {
+    /// Client certificate (PEM/DER, optionally followed by its intermediate certificates)
+    /// to authenticate to the server, e.g. of a national gateway
+    #[structopt(long, parse(from_os_str), requires = "client-key")]
+    client_cert: Option<PathBuf>,
+    /// PKCS#8 private key (PEM/DER) of the client certificate
+    #[structopt(long, parse(from_os_str), requires = "client-cert")]
+    client_key: Option<PathBuf>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum KeyAlgorithm {
+    /// ECDSA with P-256 and SHA-256
+    Es256,
+}
+
+impl FromStr for KeyAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "es256" => Ok(Self::Es256),
+            "rsa" | "ps256" => Err("RSA keys can't be generated yet, use e.g. openssl".to_string()),
+            _ => Err(format!("Unknown algorithm '{}' (expected 'es256')", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum ConvertTarget {
+    /// A SMART Health Card
+    Shc,
+}
+
+impl FromStr for ConvertTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shc" => Ok(Self::Shc),
+            _ => Err(format!("Unknown target format '{}' (expected 'shc')", s)),
+        }
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (num, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let num: i64 = num
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}'", s))?;
+    match unit {
+        "h" => Ok(Duration::hours(num)),
+        "d" | "" => Ok(Duration::days(num)),
+        "w" => Ok(Duration::weeks(num)),
+        _ => Err(format!("Unknown unit '{}' (expected h, d or w)", unit)),
+    }
+}
+
+/// Exit with the status of the [`ErrorCode`] of the error, if the run failed
+fn main() {
+    if let Err(e) = try_main() {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(ErrorCode::of_report(&e).exit_status());
     }
 }
 
-fn main() -> color_eyre::Result<()> {
-    // Setup logging and panic hooks
+fn try_main() -> color_eyre::Result<()> {
+    // Setup panic hooks and logging
     color_eyre::install()?;
-    pretty_env_logger::formatted_builder()
-        .filter(Some("dcc_decode"), log::LevelFilter::Debug)
-        .init();
-
-    // Load CLI args
-    let args = Args::from_args();
-
-    // Populate global OID registry
-    let mut oid_registry = OidRegistry::default().with_crypto().with_x509();
-    oid_registry.insert(oid!(2.5.4 .97), ("organizationIdentifier", ""));
-    oid_registry.insert(
-        oid!(2.5.4 .5),
-        ("serialNumber", "Serial number attribute type"),
+    let matches = Args::clap().get_matches();
+    let mut args = Args::from_clap(&matches);
+    init_logging(
+        args.log_format,
+        args.quiet,
+        matches!(args.cmd, Some(Command::Dashboard)),
     );
-    OID_REGISTRY.set(oid_registry).unwrap();
-
-    // Populate eHN value sets
-    let ehn_data = EhnData {
-        vaccine_prophylaxis: ValueSet::load("ehn-dcc-valuesets/vaccine-prophylaxis.json"),
-        disease_agent_targeted: ValueSet::load("ehn-dcc-valuesets/disease-agent-targeted.json"),
-        vaccine_mah_manf: ValueSet::load("ehn-dcc-valuesets/vaccine-mah-manf.json"),
-        vaccine_medicinal_product: ValueSet::load(
-            "ehn-dcc-valuesets/vaccine-medicinal-product.json",
-        ),
-    };
-    EHN_DATA.set(ehn_data).unwrap();
 
-    // Populate cert store
-    if let Some(trustlist) = TrustList::load("trustlist.json") {
-        TRUSTLIST.set(trustlist).unwrap();
+    // Use defaults from the config file for the CLI args
+    // The environment takes precedence over the config file, the command line over both
+    let paths = [
+        (&mut args.config, "CONFIG"),
+        (&mut args.cache_dir, "CACHE_DIR"),
+        (&mut args.data_dir, "DATA_DIR"),
+    ];
+    for (path, name) in paths {
+        if path.is_none() {
+            *path = config::env_var(name).map(PathBuf::from);
+        }
+    }
+    let env = Config::from_env().map_err(|e| eyre!("Invalid environment variable {}", e))?;
+    let mut config = env.or(load_config(args.config.as_deref())?.unwrap_or_default());
+    let dirs = DataDirs::new(args.cache_dir.clone(), args.data_dir.clone());
+    match &dirs {
+        Some(dirs) => config = config.or(dirs.defaults()),
+        None => warn!("Could not determine the cache and data directories"),
+    }
+    let trustlist_url = config.trustlist_url.clone();
+    let rules_url = config.rules_url.clone();
+    let client_cert = (config.client_cert.clone(), config.client_key.clone());
+    args.options.apply_config(config, &matches);
+    if let Some(bundle) = args.options.bundle.clone() {
+        let dirs = dirs
+            .ok_or_else(|| eyre!("No cache directory to extract the bundle to, use --cache-dir"))?;
+        load_bundle(&mut args.options, &bundle, &dirs.cache.join("bundle"))?;
+    }
+    args.options.apply_profile();
+    if args.pipe && matches.occurrences_of("output") == 0 {
+        args.options.output = Output::Json;
     }
 
-    // Load certificate data
-    let mut buf = String::new();
+    match &args.cmd {
+        Some(Command::Replay { session }) => return replay(session),
+        Some(Command::Kid { cert }) => return print_kids(cert),
+        Some(Command::Diff { left, right }) => return diff(left, right, &args.options),
+        Some(Command::Trustlist(TrustlistCommand::Show {
+            country,
+            expiring_within,
+        })) => {
+            let trust = load_trust(&args.options)?;
+            return show_trustlist(trust.as_deref(), country.as_deref(), *expiring_within);
+        }
+        Some(Command::Trustlist(TrustlistCommand::Expiring { within, country })) => {
+            let trust = load_trust(&args.options)?;
+            let trust = trust.ok_or_else(|| eyre!("No trust list was loaded"))?;
+            return print_expiring(
+                trust.as_ref(),
+                *within,
+                country.as_deref(),
+                args.options.output,
+            );
+        }
+        Some(Command::Trustlist(TrustlistCommand::Update { url, force, client })) => {
+            let url = url.as_deref().or(trustlist_url.as_deref());
+            let url = url.unwrap_or(DEFAULT_TRUSTLIST_URL);
+            let agent = download_agent(args.options.tls_pins.clone(), client, client_cert)?;
+            return update_trustlist(&args.options, &agent, url, *force);
+        }
+        Some(Command::Rules(RulesCommand::Update {
+            url,
+            country,
+            client,
+        })) => {
+            let url = url.as_deref().or(rules_url.as_deref()).ok_or_else(|| {
+                eyre!("No URL for the rules, use --url or rules-url in the config")
+            })?;
+            let dir = rules_dir(&args.options.valuesets);
+            info!("Updating '{}' from '{}'", dir.display(), url);
+            let agent = download_agent(args.options.tls_pins.clone(), client, client_cert)?;
+            rules::update(&agent, url, &dir, country)?;
+            return Ok(());
+        }
+        Some(Command::Keygen {
+            alg: KeyAlgorithm::Es256,
+            out,
+            country,
+            common_name,
+            days,
+        }) => return keygen(out, country, common_name, *days),
+        Some(Command::Convert {
+            to,
+            key,
+            issuer,
+            jwks,
+            file,
+        }) => {
+            return match to {
+                ConvertTarget::Shc => {
+                    convert_to_shc(&args.options, file, key, issuer, jwks.as_deref())
+                }
+            }
+        }
+        Some(Command::ExportBundle { key, out }) => return export_bundle(&args.options, key, out),
+        Some(Command::Dashboard) => {
+            let ctx = load_context(&args.options)?;
+            let options = &args.options;
+            let counts = dashboard::run(&ctx, options.redact, options.max_decompressed_size)?;
+            println!(
+                "{} valid, {} expired, {} invalid",
+                counts.valid, counts.expired, counts.invalid
+            );
+            return Ok(());
+        }
+        Some(Command::Watch { .. }) | None => {}
+    }
 
-    if args.file == "-" {
-        std::io::stdin().read_line(&mut buf)?;
+    let options = &args.options;
+    let stored = args.record.is_some() || options.dump_stages.is_some();
+    if options.redact && stored {
+        return Err(eyre!(
+            "--record and --dump-stages store the unredacted certificate, they can't be used with --redact"
+        ));
+    }
+    let personal = options.json || options.json_payload || options.diag || options.headers;
+    if options.minimal && (stored || personal) {
+        return Err(eyre!(
+            "--minimal can't be combined with --json, --json-payload, --diag, --headers, --record or --dump-stages"
+        ));
+    }
+    let watch = match &args.cmd {
+        Some(Command::Watch { dir, report }) => Some((dir, report)),
+        _ => None,
+    };
+    let other_inputs = args.files != ["-"] || args.clipboard || args.pdf.is_some();
+    if watch.is_some() && (other_inputs || args.pipe) {
+        return Err(eyre!(
+            "watch can't be combined with --pipe, --clipboard, --pdf or input files"
+        ));
+    }
+    if args.pipe && other_inputs {
+        return Err(eyre!(
+            "--pipe can't be combined with --clipboard, --pdf or input files"
+        ));
+    }
+    if args.pipe && options.input_format.is_binary() {
+        return Err(eyre!(
+            "--pipe reads lines, the input format '{}' is not text",
+            options.input_format
+        ));
+    }
+    let inputs = if watch.is_some() || args.pipe {
+        Vec::new()
     } else {
-        buf = std::fs::read_to_string(&args.file)?;
+        read_inputs(&args)?
+    };
+    let batch = inputs.len() > 1 || watch.is_some() || args.pipe;
+    if batch && stored {
+        return Err(eyre!(
+            "--record and --dump-stages can only be used with a single input"
+        ));
+    }
+    if batch && args.bug_report.is_some() {
+        return Err(eyre!("--bug-report can only be used with a single input"));
+    }
+    if !batch && args.stats_json.is_some() {
+        return Err(eyre!(
+            "--stats-json can only be used with several inputs, --pipe or watch"
+        ));
+    }
+    let csv = options.output == Output::Csv;
+    if options.output == Output::Fhir && options.redact {
+        return Err(eyre!("--output fhir can't be combined with --redact"));
+    }
+    let printed = [
+        options.json,
+        options.json_payload,
+        options.summary,
+        options.minimal,
+        options.diag,
+        options.headers,
+    ];
+    if options.output != Output::Text && printed.iter().any(|flag| *flag) {
+        return Err(eyre!(
+            "--output {} can't be combined with --json, --json-payload, --summary, --minimal, --diag or --headers",
+            options.output
+        ));
     }
 
-    let sign1 = load_sign1(&buf)?;
-    let b64_kid = base64::encode(sign1.kid());
-    info!("Well-formed COSE certificate (kid='{}')", b64_kid);
-
-    let v = CertPayload::try_from(&sign1)?;
-    info!("Well-formed Digital-Covid-Certificate");
+    // Populate eHN value sets and cert store
+    let ctx = load_context(options)?;
 
-    if args.json {
-        let jout = serde_json::to_string(&v.health_claim.cert)?;
-        println!("{}", jout);
-    } else {
-        println!("{:#?}", v);
+    let db = match &args.db {
+        Some(path) => Some(
+            ResultDb::open(path)
+                .map_err(|e| eyre!("Failed to open '{}': {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    if csv {
+        println!("{}", dcc_decode::csv::header());
+    }
+    if let Some((dir, report)) = watch {
+        let report = report.clone().unwrap_or_else(|| dir.join("report.csv"));
+        return watch_dir(dir, &report, &args, &ctx, db.as_ref());
+    }
+    if args.pipe {
+        return pipe(&args, &ctx, db.as_ref());
+    }
+    let mut failed = Failures::default();
+    let mut tally = RunTally::default();
+    for (file, buf) in &inputs {
+        let res = decode_file(file, buf, &args, &ctx, db.as_ref(), &mut tally, None);
+        if let Err(e) = &res {
+            if !batch {
+                return res;
+            }
+            error!("{}: {}", file, e);
+            failed.add(e);
+        }
+    }
+    if batch {
+        tally.finish(&args)?;
     }
+    failed.check(inputs.len())
+}
 
-    let signature = sign1.signature.clone();
+/// The failed inputs of a batch
+#[derive(Default)]
+struct Failures {
+    count: usize,
+    /// The code with the highest exit status
+    code: Option<ErrorCode>,
+}
 
-    if let Some(cert) = TRUSTLIST
-        .get()
-        .and_then(|t| t.certificates.iter().find(|&c| c.kid == b64_kid))
-    {
-        info!("Found certificate with matching kid in trustlist");
-
-        // Transform COSE_Sign1 into Signature1
-        let sig = Sig::from(sign1);
-        let message = serde_cbor::to_vec(&sig)?;
-        debug!("Signature1 encoding successful");
-
-        // Read the X.509 certificate
-        let sigbytes = base64::decode(&cert.raw_data)?;
-        let (_, sigcert) = parse_x509_certificate(&sigbytes)?;
-        debug!("Loaded issuer X.509 certificate");
-
-        let subject = &sigcert.tbs_certificate.subject;
-        if let Some(name) = subject
-            .iter_common_name()
-            .next()
-            .and_then(|name| name.attr_value.as_str().ok())
+impl Failures {
+    fn add(&mut self, e: &color_eyre::Report) {
+        let code = ErrorCode::of_report(e);
+        self.count += 1;
+        if self
+            .code
+            .is_none_or(|c| c.exit_status() < code.exit_status())
         {
-            info!("subject common name: {:?}", name);
+            self.code = Some(code);
+        }
+    }
+
+    /// Fail if any of the `total` inputs failed, with the code of the worst failure
+    fn check(self, total: usize) -> color_eyre::Result<()> {
+        match self.code {
+            Some(code) => {
+                let message = format!("{} of {} inputs failed", self.count, total);
+                Err(CodedError::new(code, message).into())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Run each line of stdin as an input (`stdin#1`, …) until stdin is closed
+///
+/// Empty lines are skipped. A failed input is logged and reported in its result, without
+/// stopping the loop.
+fn pipe(args: &Args, ctx: &DecodeContext, db: Option<&ResultDb>) -> color_eyre::Result<()> {
+    let mut failed = Failures::default();
+    let mut tally = RunTally::default();
+    let mut count = 0;
+    for (i, line) in std::io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        count += 1;
+        let name = format!("stdin#{}", i + 1);
+        let buf = Ok(line.into_bytes());
+        if let Err(e) = decode_file(&name, &buf, args, ctx, db, &mut tally, None) {
+            error!("{}: {}", name, e);
+            failed.add(&e);
         }
-        // println!("{:#?}", CertSubject(subject));
+    }
+    tally.finish(args)?;
+    failed.check(count)
+}
 
-        // Check the signature algorithm
-        let sigpki = &sigcert.tbs_certificate.subject_pki;
-        let alg = cert::get_pk_sig_algorithm(sigpki)?;
-        debug!("found signature algorithm: {:?}", alg);
+/// The result of an input for `--output json`
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    input: &'a str,
+    #[serde(flatten)]
+    result: SessionResult,
+    /// The code of the error that stopped the run
+    error_code: Option<ErrorCode>,
+    /// The codes of all problems, including those that did not fail the run
+    codes: Vec<ErrorCode>,
+}
 
-        if Algorithm::IdEcPublicKey(Prime::Prime256v1) == alg {
-            let pubkey = ring::signature::UnparsedPublicKey::new(
-                &ring::signature::ECDSA_P256_SHA256_FIXED,
-                &sigpki.subject_public_key.data,
-            );
+/// An input by name (e.g. `cert.pdf#1` for the first QR code of a PDF) and its content
+type Input = (String, color_eyre::Result<Vec<u8>>);
 
-            pubkey
-                .verify(&message, &signature)
-                .map_err(|_e| eyre!("Verification failed"))?;
-            info!("Verified OK");
-        } else {
-            warn!("Unknown signature algorithm");
+/// Read the input files, the clipboard or the QR codes of a PDF file
+fn read_inputs(args: &Args) -> color_eyre::Result<Vec<Input>> {
+    let format = args.options.input_format;
+    let files = args.files != ["-"];
+    if args.clipboard {
+        if files || args.pdf.is_some() {
+            return Err(eyre!(
+                "--clipboard can't be combined with --pdf or input files"
+            ));
+        }
+        let buf = read_clipboard(format).map_err(|e| {
+            let message = format!("Failed to read the clipboard: {}", e);
+            CodedError::new(ErrorCode::Input, message).into()
+        });
+        return Ok(vec![("clipboard".to_string(), buf)]);
+    }
+    if let Some(path) = &args.pdf {
+        if files {
+            return Err(eyre!("--pdf can't be combined with input files"));
+        }
+        if format.is_binary() {
+            return Err(eyre!("the input format '{}' is not text", format));
+        }
+        let data = std::fs::read(path).map_err(|e| read_error(&path.display(), e))?;
+        let codes = dcc_decode::pdf::extract_qr_codes(&data)
+            .map_err(|e| CodedError::new(ErrorCode::Input, e.to_string()))?;
+        return qr_inputs(path, codes);
+    }
+    let inputs = args.files.iter().map(|file| {
+        let buf = read_input(file, format).map_err(|e| read_error(file, e));
+        (file.clone(), buf)
+    });
+    Ok(inputs.collect())
+}
+
+/// Run a single input, recording or storing the result if requested
+fn decode_file(
+    file: &str,
+    buf: &color_eyre::Result<Vec<u8>>,
+    args: &Args,
+    ctx: &DecodeContext,
+    db: Option<&ResultDb>,
+    tally: &mut RunTally,
+    report: Option<&mut std::fs::File>,
+) -> color_eyre::Result<()> {
+    let mut result = SessionResult::default();
+    let res = match buf {
+        Ok(buf) => run(buf, &args.options, ctx, &mut result),
+        Err(e) => Err(CodedError::new(ErrorCode::of_report(e), e.to_string()).into()),
+    };
+    if let Err(e) = &res {
+        result.error = Some(e.to_string());
+    }
+    check_duplicate(file, &mut result, db, &mut tally.seen)?;
+    tally.stats.add(&result, &ctx.valuesets);
+
+    if args.options.output == Output::Csv {
+        println!(
+            "{}",
+            dcc_decode::csv::row(file, &result, args.options.expiry_reference())
+        );
+    }
+    if args.options.output == Output::Json {
+        let mut result = result.clone();
+        if let Some(certificate) = &mut result.certificate {
+            if args.options.redact {
+                redact::redact_json(certificate);
+            }
+        }
+        let error_code = res.as_ref().err().map(ErrorCode::of_report);
+        let mut codes = ErrorCode::of_result(&result, args.options.expiry_reference());
+        if let Some(code) = error_code.filter(|code| !codes.contains(code)) {
+            codes.insert(0, code);
         }
+        let json = JsonResult {
+            input: file,
+            result,
+            error_code,
+            codes,
+        };
+        println!("{}", serde_json::to_string(&json)?);
+    }
+    if let Some(report) = report {
+        writeln!(
+            report,
+            "{}",
+            dcc_decode::csv::row(file, &result, args.options.expiry_reference())
+        )?;
+    }
 
-        // // FIXME: Write out relevant keys as files
-        // let dir = std::env::current_dir()?;
-        // println!("Writing files ({})", dir.display());
-        // std::fs::write("message.bin", &message)?;
-        // std::fs::write("signature.bin", &signature)?;
+    if let (Some(path), Ok(buf)) = (&args.bug_report, buf) {
+        write_bug_report(path, buf, &res, &args.options)?;
+    }
+    if let (Some(path), Ok(buf)) = (&args.record, buf) {
+        let options = serde_json::to_value(&args.options)?;
+        let trust = args.options.trust_snapshots();
+        let session = Session::new(buf, trust, options, result);
+        session.write_to_file(path)?;
+        info!("Recorded session to '{}'", path.display());
+    }
 
-        // // Write out signature as `EcdsaSigValue`
-        // let ecdsa_sig = EcdsaSigValue::new(&signature[..32], &signature[32..]);
-        // let mut buf: Vec<u8> = Vec::new();
-        // ecdsa_sig.encode(&mut buf)?;
-        // std::fs::write("ecdsa-sig-value.bin", &buf)?;
-    } else {
-        warn!("Did not find certificate with matching kid")
+    res
+}
+
+/// Write the anonymized stages of an input and the error of its run
+fn write_bug_report(
+    path: &Path,
+    buf: &[u8],
+    res: &color_eyre::Result<()>,
+    options: &Options,
+) -> color_eyre::Result<()> {
+    let mut report = BugReport::new(&options.input_format.to_string(), res.as_ref().err());
+    let stages = match transport(buf, options) {
+        Ok(DecodedInput::Cose(data)) => inflate(data, options.max_decompressed_size)
+            .map_err(Into::into)
+            .and_then(|(cose, _)| report.add_cose(&cose)),
+        Ok(DecodedInput::Claims(payload)) => report.add_payload(&payload),
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = stages {
+        warn!(
+            "The bug report has no stages, as the COSE message could not be read: {}",
+            e
+        );
     }
+    let json = serde_json::to_string_pretty(&report)?;
+    std::fs::write(path, json + "\n")
+        .map_err(|e| eyre!("Failed to write '{}': {}", path.display(), e))?;
+    info!("Wrote the bug report to '{}'", path.display());
+    Ok(())
+}
 
+/// Store the result, and warn if its certificate was scanned before
+///
+/// The earlier scans are counted in the database if one is given, and within the run
+/// otherwise.
+fn check_duplicate(
+    file: &str,
+    result: &mut SessionResult,
+    db: Option<&ResultDb>,
+    seen: &mut UvciIndex,
+) -> color_eyre::Result<()> {
+    let stored = db.map(|db| db.insert(result, Utc::now())).transpose()?;
+    let uvci = match result.uvci() {
+        Some(uvci) => uvci.to_string(),
+        None => return Ok(()),
+    };
+    let earlier = seen.insert(&uvci, file);
+    let earlier_scans = stored.unwrap_or(earlier.len() as u64);
+    if earlier_scans > 0 {
+        let warning = Warning::Duplicate {
+            earlier_scans,
+            first_input: earlier.first().cloned(),
+        };
+        warn!("{}", warning);
+        result.warnings.push(warning);
+    }
     Ok(())
 }
+
+/// What is collected over all inputs of a batch, `--pipe` or `watch` run
+#[derive(Default)]
+struct RunTally {
+    seen: UvciIndex,
+    stats: BatchStats,
+}
+
+impl RunTally {
+    /// Print the duplicates and statistics (to stderr) at the end of a run
+    fn finish(&self, args: &Args) -> color_eyre::Result<()> {
+        let redact = args.options.redact || args.options.minimal;
+        report_duplicates(&self.seen, redact);
+        eprint!("{}", self.stats.render());
+        self.write_stats(args)
+    }
+
+    /// Write the statistics so far to the `--stats-json` file, if one was given
+    fn write_stats(&self, args: &Args) -> color_eyre::Result<()> {
+        if let Some(path) = &args.stats_json {
+            let json = serde_json::to_string_pretty(&self.stats)?;
+            std::fs::write(path, json + "\n")
+                .map_err(|e| eyre!("Failed to write '{}': {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Log the certificates that were seen in more than one input of the run
+fn report_duplicates(seen: &UvciIndex, redact: bool) {
+    let duplicates: Vec<_> = seen.duplicates().collect();
+    if duplicates.is_empty() {
+        return;
+    }
+    warn!(
+        "{} of {} certificates were scanned more than once",
+        duplicates.len(),
+        seen.len()
+    );
+    for duplicate in duplicates {
+        let uvci = if redact { "***" } else { duplicate.uvci };
+        warn!(
+            "{} ({} times): {}",
+            uvci,
+            duplicate.inputs.len(),
+            duplicate.inputs.join(", ")
+        );
+    }
+}
+
+/// The subdirectories of a watched directory for the processed files
+const OK_DIR: &str = "ok";
+const FAILED_DIR: &str = "failed";
+/// The time without events after which the files of a watched directory are processed
+const WATCH_SETTLE: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Decode the files in `dir` as they appear, appending the results to `report`
+///
+/// A file is moved to `ok/` if all of its inputs (e.g. the QR codes of a PDF file) were
+/// decoded and verified, and to `failed/` otherwise. Files are picked up once no event
+/// was seen for a moment, so they should be moved into the directory once complete.
+fn watch_dir(
+    dir: &Path,
+    report: &Path,
+    args: &Args,
+    ctx: &DecodeContext,
+    db: Option<&ResultDb>,
+) -> color_eyre::Result<()> {
+    use notify::{RecursiveMode, Watcher};
+
+    for sub in [OK_DIR, FAILED_DIR] {
+        std::fs::create_dir_all(dir.join(sub))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(report)
+        .map_err(|e| eyre!("Failed to open '{}': {}", report.display(), e))?;
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{}", dcc_decode::csv::header())?;
+    }
+
+    let mut tally = RunTally::default();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    info!("Watching '{}'", dir.display());
+    loop {
+        // Files that are already there, or that were added since the last event
+        for path in std::fs::read_dir(dir)? {
+            let path = path?.path();
+            let hidden = path.file_name().and_then(|name| name.to_str());
+            let hidden = hidden.is_none_or(|name| name.starts_with('.'));
+            if !path.is_file() || hidden || same_file(&path, report) {
+                continue;
+            }
+            let ok = watch_file(&path, args, ctx, db, &mut tally, &mut file);
+            tally.write_stats(args)?;
+            let target = dir.join(if ok { OK_DIR } else { FAILED_DIR });
+            let target = unique_path(&target, path.file_name().unwrap());
+            std::fs::rename(&path, &target)
+                .map_err(|e| eyre!("Failed to move '{}': {}", path.display(), e))?;
+            info!("Moved '{}' to '{}'", path.display(), target.display());
+        }
+        // Wait for an event, then until the directory settles
+        rx.recv()??;
+        while let Ok(event) = rx.recv_timeout(WATCH_SETTLE) {
+            event?;
+        }
+    }
+}
+
+/// Decode all inputs of a file in a watched directory, `true` if all succeeded
+fn watch_file(
+    path: &Path,
+    args: &Args,
+    ctx: &DecodeContext,
+    db: Option<&ResultDb>,
+    tally: &mut RunTally,
+    report: &mut std::fs::File,
+) -> bool {
+    let name = path.display().to_string();
+    let inputs = match read_file_inputs(path) {
+        Ok(inputs) => inputs,
+        Err(e) => vec![(name, Err(e))],
+    };
+    let mut ok = true;
+    for (file, buf) in &inputs {
+        if let Err(e) = decode_file(file, buf, args, ctx, db, tally, Some(report)) {
+            error!("{}: {}", file, e);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// The inputs of a file by its extension: the QR codes of an image or PDF file, or text
+fn read_file_inputs(path: &Path) -> color_eyre::Result<Vec<Input>> {
+    let data = std::fs::read(path).map_err(|e| read_error(&path.display(), e))?;
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let codes = match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("pdf") => dcc_decode::pdf::extract_qr_codes(&data).map_err(|e| e.to_string()),
+        Some("png") | Some("jpg") | Some("jpeg") => {
+            dcc_decode::qr::decode_image_file(&data).map_err(|e| e.to_string())
+        }
+        _ => return Ok(vec![(path.display().to_string(), Ok(data))]),
+    };
+    let codes = codes.map_err(|e| CodedError::new(ErrorCode::Input, e))?;
+    qr_inputs(path, codes)
+}
+
+/// An error for an input that could not be read
+fn read_error(name: &dyn fmt::Display, e: std::io::Error) -> color_eyre::Report {
+    let message = format!("Failed to read '{}': {}", name, e);
+    CodedError::new(ErrorCode::Input, message).into()
+}
+
+/// The QR codes found in a file, each as one input (e.g. `cert.pdf#1`)
+fn qr_inputs(path: &Path, codes: Vec<String>) -> color_eyre::Result<Vec<Input>> {
+    if codes.is_empty() {
+        let message = format!("No QR codes found in '{}'", path.display());
+        return Err(CodedError::new(ErrorCode::Input, message).into());
+    }
+    info!("Found {} QR codes in '{}'", codes.len(), path.display());
+    let inputs = codes.into_iter().enumerate().map(|(i, code)| {
+        let name = format!("{}#{}", path.display(), i + 1);
+        (name, Ok(code.into_bytes()))
+    });
+    Ok(inputs.collect())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// A path for `name` in `dir` that does not exist yet, e.g. `cert.1.txt`
+fn unique_path(dir: &Path, name: &std::ffi::OsStr) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+    let name = Path::new(name);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = name.extension().map(|ext| ext.to_string_lossy());
+    (1..)
+        .map(|i| match &extension {
+            Some(ext) => dir.join(format!("{}.{}.{}", stem, i, ext)),
+            None => dir.join(format!("{}.{}", stem, i)),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Re-issue the vaccinations of a certificate as a signed SMART Health Card
+fn convert_to_shc(
+    options: &Options,
+    file: &str,
+    key: &Path,
+    issuer: &str,
+    jwks: Option<&Path>,
+) -> color_eyre::Result<()> {
+    let ctx = load_context(options)?;
+    let buf = read_input(file, options.input_format)
+        .map_err(|e| eyre!("Failed to read '{}': {}", file, e))?;
+    let payload = match transport(&buf, options)? {
+        DecodedInput::Cose(data) => {
+            let (bytes, _) = inflate(data, options.max_decompressed_size)?;
+            let sign1 = parse_sign1(&bytes)?;
+            let payload = sign1.payload.clone();
+            let v = ctx.decode_claims(&payload)?;
+            check_verification(ctx.verify(&sign1, &v)?)?;
+            v
+        }
+        DecodedInput::Claims(bytes) => {
+            warn!("Input has no COSE signature, skipping verification");
+            ctx.decode_claims(&bytes)?
+        }
+    };
+    let cert = &payload.health_claim.cert;
+    if cert.vaccine.is_empty() {
+        return Err(eyre!(
+            "Only vaccinations can be converted to a SMART Health Card"
+        ));
+    }
+    if !cert.test.is_empty() || !cert.recovery.is_empty() {
+        warn!("Test and recovery entries are not included in the health card");
+    }
+
+    let pkcs8 = cert::read_private_key(key)?;
+    let signer = shc::ShcSigner::from_pkcs8(&pkcs8)
+        .map_err(|e| eyre!("Unsupported key '{}': {}", key.display(), e))?;
+    let card = shc::ShcPayload::from_certificate(&payload, issuer, Utc::now());
+    let jws = signer.sign(&card)?;
+    info!("Issued SMART Health Card (kid='{}')", signer.kid());
+    if let Some(path) = jwks {
+        std::fs::write(path, signer.jwks().to_string())?;
+        info!("Wrote the issuer key to '{}'", path.display());
+    }
+    println!("{}", shc::encode_numeric(&jws));
+    Ok(())
+}
+
+/// Undo the text encoding of the input, exactly with `--strict`
+fn transport(buf: &[u8], options: &Options) -> Result<DecodedInput, DecodeError> {
+    if options.strict {
+        decode_transport_strict(buf, options.input_format)
+    } else {
+        decode_transport(buf, options.input_format)
+    }
+}
+
+/// Read the input from a file, or a single line from stdin for the text formats
+fn read_input(file: &str, format: InputFormat) -> std::io::Result<Vec<u8>> {
+    if file != "-" {
+        return std::fs::read(file);
+    }
+    if format.is_binary() {
+        let mut buf = Vec::new();
+        std::io::stdin().read_to_end(&mut buf)?;
+        Ok(buf)
+    } else {
+        let mut buf = String::new();
+        std::io::stdin().read_line(&mut buf)?;
+        Ok(buf.into_bytes())
+    }
+}
+
+/// Read the text on the clipboard, for the text formats
+fn read_clipboard(format: InputFormat) -> color_eyre::Result<Vec<u8>> {
+    if format.is_binary() {
+        return Err(eyre!("the input format '{}' is not text", format));
+    }
+    let text = arboard::Clipboard::new()?.get_text()?;
+    Ok(text.trim().as_bytes().to_vec())
+}
+
+/// Log to stderr, at debug level for this crate (warn level with `quiet`) unless `RUST_LOG`
+/// is set
+///
+/// With `discard`, e.g. while the terminal shows the dashboard, log messages are discarded.
+fn init_logging(format: LogFormat, quiet: bool, discard: bool) {
+    let level = if quiet { "warn" } else { "debug" };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("dcc_decode={}", level)));
+    let writer = if discard {
+        BoxMakeWriter::new(std::io::sink)
+    } else {
+        BoxMakeWriter::new(std::io::stderr)
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => builder.without_time().init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+/// Read the given config file, or the one at the default location if it exists
+fn load_config(path: Option<&Path>) -> color_eyre::Result<Option<Config>> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match Config::default_path().filter(|path| path.is_file()) {
+            Some(path) => path,
+            None => return Ok(None),
+        },
+    };
+    let config = Config::read_from_file(&path)
+        .map_err(|e| eyre!("Failed to read config '{}': {}", path.display(), e))?;
+    debug!("Loaded config from '{}'", path.display());
+    Ok(Some(config))
+}
+
+/// Load a value set from `dir`, with its translation to `lang` (from `<dir>/<lang>/`)
+fn load_valueset(dir: &Path, name: &str, lang: Lang) -> Option<ValueSet> {
+    let mut set = ValueSet::load(dir.join(format!("{}.json", name)))?;
+    if lang != Lang::En {
+        let path = dir.join(lang.code()).join(format!("{}.json", name));
+        match ValueSet::read_from_file(&path) {
+            Ok(translation) => set.add_translation(translation),
+            Err(e) => debug!("No translation '{}': {}", path.display(), e),
+        }
+    }
+    Some(set)
+}
+
+fn load_valuesets(dir: &Path, lang: Lang) -> EhnData {
+    EhnData {
+        vaccine_prophylaxis: load_valueset(dir, "vaccine-prophylaxis", lang),
+        disease_agent_targeted: load_valueset(dir, "disease-agent-targeted", lang),
+        vaccine_mah_manf: load_valueset(dir, "vaccine-mah-manf", lang),
+        vaccine_medicinal_product: load_valueset(dir, "vaccine-medicinal-product", lang),
+        test_type: load_valueset(dir, "test-type", lang),
+        test_manf: load_valueset(dir, "test-manf", lang),
+        test_result: load_valueset(dir, "test-result", lang),
+        country_codes: load_valueset(dir, "country-2-codes", lang),
+        lang,
+    }
+}
+
+fn load_context(options: &Options) -> color_eyre::Result<DecodeContext> {
+    let mut ctx = DecodeContext::new(load_valuesets(&options.valuesets, options.lang));
+    ctx.trust = load_trust(options)?;
+    ctx.options = options.verify_options();
+    if let Some(path) = &options.shc_jwks {
+        let jwks = JwkSet::read_from_file(path)
+            .map_err(|e| eyre!("Failed to read '{}': {}", path.display(), e))?;
+        ctx.shc_trust = Some(Box::new(jwks.to_trust()));
+    }
+    if let Some(path) = &options.vds_csca {
+        ctx.vds_csca = Some(CscaStore::read_from_file(path)?);
+    }
+    if let Some(path) = &options.blocklist {
+        ctx.blocklist = Blocklist::read_from_file(path)
+            .map_err(|e| eyre!("Failed to read '{}': {}", path.display(), e))?;
+        info!("Loaded {} blocklist entries", ctx.blocklist.len());
+    }
+    if let Some(path) = &options.countersign_trust {
+        ctx.countersign_trust = cert::read_certificates(path)?;
+        if ctx.countersign_trust.is_empty() {
+            return Err(eyre!("No certificate in '{}'", path.display()));
+        }
+    }
+    if !options.crl.is_empty() {
+        let csca = match &options.csca {
+            Some(path) => Some(CscaStore::read_from_file(path)?),
+            None => None,
+        };
+        for crl in &options.crl {
+            let bytes = read_crl(crl, options)?;
+            ctx.revocations
+                .add(&bytes, csca.as_ref())
+                .map_err(|e| eyre!("Invalid CRL '{}': {}", crl, e))?;
+        }
+        info!("Loaded {} CRLs", ctx.revocations.len());
+    }
+    Ok(ctx)
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
+
+/// Read a CRL from a file or download it, with the pins from the config
+fn read_crl(location: &str, options: &Options) -> color_eyre::Result<Vec<u8>> {
+    if !is_url(location) {
+        return std::fs::read(location).map_err(|e| eyre!("Failed to read '{}': {}", location, e));
+    }
+    let settings = TlsSettings {
+        pins: options.tls_pins.clone(),
+        client_cert: None,
+    };
+    let agent = settings.agent()?;
+    info!("Downloading the CRL '{}'", location);
+    Ok(download::fetch(&agent, location)?)
+}
+
+fn load_trust(options: &Options) -> color_eyre::Result<Option<BoxedTrustSource>> {
+    if options.pubkey.is_some() {
+        return Ok(None);
+    }
+
+    let mut merged = MergedTrust::new();
+    for path in &options.trustlist {
+        if let Some(trustlist) = load_trustlist(options, path)? {
+            merged.add(&path.display().to_string(), trustlist.as_ref());
+        }
+    }
+    for dir in &options.trust_dir {
+        let directory = CertDirectory::read_from_dir(dir)?;
+        merged.add(&dir.display().to_string(), &directory);
+    }
+    if options.trustlist.len() + options.trust_dir.len() > 1 {
+        info!("Merged {} signers from all trust sources", merged.len());
+    }
+    // A single trust list that does not exist is not an error, verification is skipped
+    if merged.is_empty() && options.trust_dir.is_empty() {
+        return Ok(None);
+    }
+
+    if let Some(path) = &options.csca {
+        merged.retain_chained(&CscaStore::read_from_file(path)?);
+    }
+    Ok(Some(Box::new(merged)))
+}
+
+/// Load the trust list at `path`, if it exists (only for the JSON format)
+fn load_trustlist(options: &Options, path: &Path) -> color_eyre::Result<Option<BoxedTrustSource>> {
+    let trust: BoxedTrustSource = match options.trustlist_format(path) {
+        TrustListFormat::Json => match TrustList::load(path) {
+            Some(mut trustlist) => {
+                trustlist.check_kids();
+                trustlist.check_thumbprints(options.warn_thumbprint);
+                Box::new(trustlist)
+            }
+            None => return Ok(None),
+        },
+        TrustListFormat::At => {
+            let anchor = match &options.trustlist_anchor {
+                Some(path) => cert::read_certificates(path)?.into_iter().next(),
+                None => None,
+            };
+            Box::new(AustrianTrustList::read_from_files(
+                path,
+                options.trustlist_sig(path),
+                anchor.as_deref(),
+            )?)
+        }
+        TrustListFormat::Jwks => {
+            let jwks = JwkSet::read_from_file(path)
+                .map_err(|e| eyre!("Failed to read '{}': {}", path.display(), e))?;
+            Box::new(jwks.to_trust())
+        }
+    };
+    Ok(Some(trust))
+}
+
+fn run(
+    buf: &[u8],
+    options: &Options,
+    ctx: &DecodeContext,
+    result: &mut SessionResult,
+) -> color_eyre::Result<()> {
+    if options.input_format == InputFormat::Hc1 {
+        let text = std::str::from_utf8(buf).unwrap_or_default();
+        if shc::is_shc(text) {
+            return run_shc(text, options, ctx, result);
+        }
+        if vds::is_vds(text) {
+            return run_vds(text, options, ctx, result);
+        }
+    }
+    let dump = options
+        .dump_stages
+        .as_deref()
+        .map(StageDump::new)
+        .transpose()?;
+    let mut inflate_warning = None;
+    let input = match transport(buf, options)? {
+        DecodedInput::Cose(data) => {
+            if let Some(dump) = &dump {
+                dump.write("decoded.bin", &data)?;
+            }
+            let (cose, warning) = inflate(data, options.max_decompressed_size)?;
+            inflate_warning = warning;
+            DecodedInput::Cose(cose)
+        }
+        claims => claims,
+    };
+    let (sign1, payload) = match input {
+        DecodedInput::Cose(bytes) => {
+            let sign1 = read_cose(&bytes, options, ctx, result)?;
+            let cose = RawSign1::from_slice(&bytes)?;
+            if let Some(dump) = &dump {
+                let cose = CoseSign1::from_slice(&bytes)?;
+                dump.write("cose.bin", &bytes)?;
+                dump.write("message.bin", &cose.sig_structure())?;
+                dump.write("signature.bin", &cose.signature)?;
+            }
+            let payload = sign1.payload.clone();
+            (Some((sign1, cose)), payload)
+        }
+        DecodedInput::Claims(bytes) => {
+            if options.diag {
+                print_diag(cbor::parse(&bytes)?, options);
+            }
+            (None, bytes)
+        }
+    };
+    if let Some(dump) = &dump {
+        dump.write("payload.cbor", &payload)?;
+        match cbor::canonicalize(&payload) {
+            Ok(canonical) => dump.write("payload.canonical.cbor", &canonical)?,
+            Err(e) => warn!("Could not re-encode the payload deterministically: {}", e),
+        }
+    }
+    if options.strict {
+        check_strict(&payload, result)?;
+    }
+
+    let mut outcome = match &sign1 {
+        Some((_, cose)) => ctx.decode_sign1(cose)?,
+        None => ctx.decode_outcome(&payload)?,
+    };
+    outcome.warnings.splice(0..0, inflate_warning);
+    info!("Well-formed Digital-Covid-Certificate");
+    for warning in &outcome.warnings {
+        warn!("{}", warning);
+    }
+    result.warnings = outcome.warnings;
+    let v = outcome.payload;
+    result.claims = Some(CwtClaims {
+        issuer: v.issuer.clone(),
+        issued_at: v.issued_at,
+        expiration_time: v.expiration_time,
+    });
+    result.certificate = Some(serde_json::to_value(&v.health_claim.cert)?);
+
+    if options.json_payload {
+        println!("{}", to_json(&v, options)?);
+    } else if options.json {
+        println!("{}", to_json(&v.health_claim.cert, options)?);
+    } else if options.output == Output::Fhir {
+        let cert = &v.health_claim.cert;
+        if !cert.test.is_empty() || !cert.recovery.is_empty() {
+            warn!("Test and recovery entries are not included in the FHIR bundle");
+        }
+        println!("{}", fhir::bundle(&v));
+    }
+    // The report replaces the certificate output, unless something else was selected
+    let report = options.output == Output::Text
+        && !(options.json
+            || options.json_payload
+            || options.summary
+            || options.minimal
+            || options.diag);
+    let report_options = ReportOptions {
+        color: options.color.enabled(),
+        redact: options.redact,
+    };
+
+    let sign1 = match sign1 {
+        Some((sign1, _)) => sign1,
+        None => {
+            warn!("Input has no COSE signature, skipping verification");
+            if options.summary {
+                println!("{}", summary::summarize(&v, None, options.lang));
+            }
+            if options.minimal {
+                let disclosure = Disclosure::new(&v, None, options.expiry_reference());
+                println!("{}", disclosure.render(options.lang));
+            }
+            if report {
+                print!(
+                    "{}",
+                    report::render(
+                        &v,
+                        None,
+                        None,
+                        &[],
+                        &result.warnings,
+                        options.expiry_reference(),
+                        report_options,
+                    )
+                );
+            }
+            return check_profile(options, result);
+        }
+    };
+
+    let kid = sign1_kid(&sign1);
+    let (verification, signer) = if let Some(path) = &options.pubkey {
+        let key = cert::read_public_key(path)?;
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&key)?;
+        let verification = verify::verify_with_key(&sign1, &spki)?;
+        (ctx.check_blocklist(&sign1, &v, None, verification), None)
+    } else {
+        ctx.verify_signer(&sign1, &v)?
+    };
+    if kid.is_none() {
+        let matched = signer
+            .as_ref()
+            .filter(|_| verification.is_verified())
+            .map(|signer| signer.kid.clone());
+        if let Some(kid) = &matched {
+            info!("Matched the DSC '{}' without a kid", kid);
+        }
+        for warning in &mut result.warnings {
+            if let Warning::MissingKid { matched_kid, .. } = warning {
+                *matched_kid = matched.clone();
+            }
+        }
+    }
+    let signer_country = signer.as_ref().and_then(|signer| signer.country.as_deref());
+    if let Some(warning) =
+        signer_country.and_then(|country| Warning::country_mismatch("dsc", country, &v.issuer))
+    {
+        warn!("{}", warning);
+        result.warnings.push(warning);
+    }
+    result.verification = Some(verification.clone());
+    result.findings.extend(verification.finding());
+    if let Some(finding) = verify::signature_format(&sign1) {
+        warn!("{}", finding);
+        result.findings.push(finding);
+    }
+
+    if options.summary {
+        println!(
+            "{}",
+            summary::summarize(&v, Some(&verification), options.lang)
+        );
+    }
+    if options.minimal {
+        let disclosure = Disclosure::new(&v, Some(&verification), options.expiry_reference());
+        println!("{}", disclosure.render(options.lang));
+    }
+    if report {
+        let signer = signer
+            .or_else(|| kid.and_then(|kid| ctx.signer(kid)))
+            .unwrap_or_else(|| SignerInfo::unknown(kid.unwrap_or_default()));
+        let text = report::render(
+            &v,
+            Some(&verification),
+            Some(&signer),
+            &result.findings,
+            &result.warnings,
+            options.expiry_reference(),
+            report_options,
+        );
+        print!("{}", text);
+    }
+
+    check_verification(verification)?;
+    check_profile(options, result)
+}
+
+/// Fail on the deviations that the options (or the profile) don't accept
+fn check_profile(options: &Options, result: &SessionResult) -> color_eyre::Result<()> {
+    if options.reject_unknown_fields {
+        let mut unknown = result.warnings.iter().filter(|warning| {
+            matches!(
+                warning,
+                Warning::UnknownField { .. }
+                    | Warning::UnknownClaim { .. }
+                    | Warning::Schema { .. }
+            )
+        });
+        if let Some(warning) = unknown.next() {
+            let message = format!("{} (rejected by --reject-unknown-fields)", warning);
+            return Err(CodedError::new(ErrorCode::UnknownField, message).into());
+        }
+    }
+    if options.reject_der_signature {
+        let der = result
+            .findings
+            .iter()
+            .find(|finding| finding.rule == Rule::SignatureFormat);
+        if let Some(finding) = der {
+            let message = format!("{} (rejected by --reject-der-signature)", finding);
+            return Err(CodedError::new(ErrorCode::SigFormat, message).into());
+        }
+    }
+    if options.reject_expired {
+        let expired = result
+            .claims
+            .as_ref()
+            .filter(|claims| claims.is_expired(options.expiry_reference()));
+        if let Some(claims) = expired {
+            let message = format!("Certificate expired at {}", claims.expiration_time);
+            return Err(CodedError::new(ErrorCode::Expired, message).into());
+        }
+    }
+    Ok(())
+}
+
+/// Log the outcome of the verification, failing if the signature is invalid
+fn check_verification(verification: Verification) -> color_eyre::Result<()> {
+    match verification {
+        Verification::Verified => info!("{}", verification),
+        Verification::KeyNotFound | Verification::UnsupportedAlgorithm(_) => {
+            warn!("{}", verification)
+        }
+        Verification::Rejected(_)
+        | Verification::AlgorithmMismatch(_)
+        | Verification::Invalid
+        | Verification::Blocked(_)
+        | Verification::Revoked(_) => return Err(verification.into()),
+    }
+    Ok(())
+}
+
+/// Decode and verify a SMART Health Card
+fn run_shc(
+    text: &str,
+    options: &Options,
+    ctx: &DecodeContext,
+    result: &mut SessionResult,
+) -> color_eyre::Result<()> {
+    let jws = shc::Jws::parse(&shc::decode_numeric(text)?, options.max_decompressed_size)?;
+    result.kid = jws.header.kid.clone();
+    let payload = shc::ShcPayload::from_slice(&jws.payload)?;
+    let card = shc::HealthCard::from_payload(&payload);
+    info!("Well-formed SMART Health Card from '{}'", card.issuer);
+    result.certificate = Some(serde_json::to_value(&card)?);
+    if options.json || options.json_payload {
+        println!("{}", to_json(&card, options)?);
+    }
+
+    if ctx.shc_trust.is_none() {
+        warn!("No issuer keys for SMART Health Cards were given (--shc-jwks)");
+    }
+    let verification = ctx.verify_shc(&jws)?;
+    result.verification = Some(verification.clone());
+    result.findings.extend(verification.finding());
+
+    let kid = jws.header.kid.as_deref();
+    print_card(&card, "SMART Health Card", kid, &verification, options);
+    check_verification(verification)
+}
+
+/// Decode and verify an ICAO VDS-NC
+fn run_vds(
+    text: &str,
+    options: &Options,
+    ctx: &DecodeContext,
+    result: &mut SessionResult,
+) -> color_eyre::Result<()> {
+    let seal = vds::Seal::parse(text)?;
+    let card = seal.to_card()?;
+    info!("Well-formed VDS-NC from '{}'", card.issuer);
+    result.certificate = Some(serde_json::to_value(&card)?);
+    if options.json || options.json_payload {
+        println!("{}", to_json(&card, options)?);
+    }
+
+    let verification = ctx.verify_vds(&seal)?;
+    result.verification = Some(verification.clone());
+    result.findings.extend(verification.finding());
+
+    let signer = SignerCert::from_der(seal.certificate()?).info().subject;
+    print_card(&card, "VDS-NC", signer.as_deref(), &verification, options);
+    check_verification(verification)
+}
+
+/// Print the report of a health card or VDS-NC, unless another output was selected
+fn print_card(
+    card: &HealthCard,
+    kind: &str,
+    signer: Option<&str>,
+    verification: &Verification,
+    options: &Options,
+) {
+    if options.minimal {
+        warn!("--minimal only applies to DCCs, not to a {}", kind);
+    }
+    let printed =
+        options.json || options.json_payload || options.summary || options.minimal || options.diag;
+    if options.output == Output::Text && !printed {
+        let report_options = ReportOptions {
+            color: options.color.enabled(),
+            redact: options.redact,
+        };
+        let text = report::render_card(card, kind, signer, Some(verification), report_options);
+        print!("{}", text);
+    }
+}
+
+/// Writes the intermediate results of decoding to a directory
+struct StageDump<'a> {
+    dir: &'a Path,
+}
+
+impl<'a> StageDump<'a> {
+    fn new(dir: &'a Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        info!("Writing intermediate stages to '{}'", dir.display());
+        Ok(Self { dir })
+    }
+
+    fn write(&self, name: &str, data: &[u8]) -> std::io::Result<()> {
+        let path = self.dir.join(name);
+        debug!("Writing {} bytes to '{}'", data.len(), path.display());
+        std::fs::write(path, data)
+    }
+}
+
+/// Parse the COSE message and print or record its parts
+fn read_cose(
+    bytes: &[u8],
+    options: &Options,
+    ctx: &DecodeContext,
+    result: &mut SessionResult,
+) -> color_eyre::Result<Sign1> {
+    if options.diag {
+        print_diag(cbor::parse_cose_sign1(bytes)?, options);
+    }
+    let sign1 = parse_sign1(bytes)?;
+    let cose = CoseSign1::from_slice(bytes)?;
+    let headers = cose.headers();
+    let text = !(options.json || options.json_payload);
+    if options.headers {
+        if text {
+            print!("{}", headers);
+        } else {
+            println!("{}", serde_json::to_string(&headers)?);
+        }
+    }
+    result.headers = Some(headers);
+    match countersign::countersignatures(&cose) {
+        Ok(countersignatures) => {
+            for countersignature in countersignatures {
+                let checked = countersignature.check(&cose, &ctx.countersign_trust);
+                match checked.status {
+                    CountersignatureStatus::Verified | CountersignatureStatus::NotChecked => {
+                        info!("{}", checked)
+                    }
+                    _ => warn!("{}", checked),
+                }
+                if options.headers && text {
+                    println!("{}", checked);
+                    for line in checked.headers.to_string().lines() {
+                        println!("  {}", line);
+                    }
+                }
+                result.countersignatures.push(checked);
+            }
+        }
+        Err(e) => warn!("Could not read the countersignatures: {}", e),
+    }
+    match sign1_kid(&sign1) {
+        Some(kid) => {
+            let b64_kid = base64::encode(kid);
+            info!("Well-formed COSE certificate (kid='{}')", b64_kid);
+            result.kid = Some(b64_kid);
+        }
+        None => info!("Well-formed COSE certificate (without kid)"),
+    }
+    Ok(sign1)
+}
+
+/// Serialize `value` to JSON, masking the personal data with `--redact`
+fn to_json<T: Serialize>(value: &T, options: &Options) -> serde_json::Result<String> {
+    if options.redact {
+        let mut value = serde_json::to_value(value)?;
+        redact::redact_json(&mut value);
+        serde_json::to_string(&value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+/// Print a CBOR item in diagnostic notation, masking the personal data with `--redact`
+fn print_diag(mut item: cbor::Item, options: &Options) {
+    if options.redact {
+        redact::redact_cbor(&mut item);
+    }
+    println!("{}", item.to_diag());
+}
+
+/// Check that the CWT payload uses the deterministic CBOR encoding
+fn check_strict(payload: &[u8], result: &mut SessionResult) -> color_eyre::Result<()> {
+    let violations = cbor::check_deterministic(payload)?;
+    if violations.is_empty() {
+        debug!("CWT payload is in deterministic CBOR encoding");
+        return Ok(());
+    }
+    for violation in &violations {
+        let finding = Finding::new(Rule::DeterministicEncoding, violation.to_string());
+        warn!("{}", finding);
+        result.findings.push(finding);
+    }
+    let message = format!(
+        "CWT payload is not in deterministic CBOR encoding ({} violations)",
+        violations.len()
+    );
+    Err(CodedError::new(ErrorCode::NotCanonical, message).into())
+}
+
+/// The agent for the downloads, with the client certificate from the command line or else
+/// from the config
+fn download_agent(
+    pins: TlsPins,
+    args: &ClientCertArgs,
+    configured: (Option<PathBuf>, Option<PathBuf>),
+) -> color_eyre::Result<ureq::Agent> {
+    let paths = match (&args.client_cert, &args.client_key, configured) {
+        (Some(cert), Some(key), _) => Some((cert.clone(), key.clone())),
+        (_, _, (Some(cert), Some(key))) => Some((cert, key)),
+        (_, _, (None, None)) => None,
+        _ => {
+            return Err(eyre!(
+                "client-cert and client-key must be configured together"
+            ))
+        }
+    };
+    let client_cert = match paths {
+        Some((cert, key)) => {
+            let client_cert = ClientCert::read(&cert, &key).map_err(|e| {
+                eyre!(
+                    "Failed to read the client certificate '{}': {}",
+                    cert.display(),
+                    e
+                )
+            })?;
+            info!("Using the client certificate '{}'", cert.display());
+            Some(client_cert)
+        }
+        None => None,
+    };
+    let settings = TlsSettings { pins, client_cert };
+    settings
+        .agent()
+        .map_err(|e| eyre!("Invalid client certificate or key: {}", e))
+}
+
+/// Download the trust list to the configured path (the first one, if there are several)
+fn update_trustlist(
+    options: &Options,
+    agent: &ureq::Agent,
+    url: &str,
+    force: bool,
+) -> color_eyre::Result<()> {
+    let path = options
+        .trustlist
+        .first()
+        .ok_or_else(|| eyre!("No trust list path to update, use --trustlist"))?;
+    let format = options.trustlist_format(path);
+    if format == TrustListFormat::At {
+        return Err(eyre!(
+            "Updating a trust list in the '{}' format is not supported",
+            format
+        ));
+    }
+    info!("Updating '{}' from '{}'", path.display(), url);
+    let fetched = download::fetch_cached(agent, url, path, force, |body| match format {
+        TrustListFormat::Json => trust::strip_signature_line(body),
+        _ => body,
+    })?;
+    match fetched {
+        Fetched::Updated => info!("Trust list updated"),
+        Fetched::NotModified => info!("Trust list is up to date"),
+    }
+    Ok(())
+}
+
+/// The business rules are kept next to the value sets
+fn rules_dir(valuesets: &Path) -> PathBuf {
+    valuesets.with_file_name("rules")
+}
+
+/// Pack the trust data of `options` into a bundle signed with `key`
+fn export_bundle(options: &Options, key: &Path, out: &Path) -> color_eyre::Result<()> {
+    let mut bundle = Bundle::new();
+    for (i, path) in options.trustlist.iter().enumerate() {
+        if options.trustlist_format(path) == TrustListFormat::At {
+            return Err(eyre!(
+                "Trust lists in the '{}' format can't be bundled",
+                TrustListFormat::At
+            ));
+        }
+        if !path.exists() {
+            warn!(
+                "Skipping the trust list '{}', it does not exist",
+                path.display()
+            );
+            continue;
+        }
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| eyre!("Invalid trust list path '{}'", path.display()))?;
+        bundle.add_file(&format!("trustlist/{}/{}", i, file_name), path)?;
+    }
+    for (i, dir) in options.trust_dir.iter().enumerate() {
+        bundle.add_dir(&format!("trust-dir/{}", i), dir)?;
+    }
+    let rules = rules_dir(&options.valuesets);
+    for (name, dir) in [("ehn-dcc-valuesets", &options.valuesets), ("rules", &rules)] {
+        if dir.is_dir() {
+            bundle.add_dir(name, dir)?;
+        } else {
+            warn!("Skipping '{}', it does not exist", dir.display());
+        }
+    }
+    if let Some(path) = &options.blocklist {
+        bundle.add_file("blocklist.txt", path)?;
+    }
+    if let Some(path) = &options.csca {
+        bundle.add_file("csca.pem", path)?;
+    }
+    // CRLs at a URL are downloaded, so that the device can check revocations offline
+    for (i, crl) in options.crl.iter().enumerate() {
+        bundle.insert(&format!("crl/{}", i), read_crl(crl, options)?)?;
+    }
+
+    let pkcs8 = cert::read_private_key(key)?;
+    // `keygen` writes the certificate next to the key, its kid identifies the signer
+    let kid = cert::read_certificates(key)
+        .ok()
+        .and_then(|certificates| certificates.into_iter().next())
+        .map(|der| cert::compute_kid(&der));
+    let signed = bundle
+        .sign(&pkcs8, kid.as_deref())
+        .map_err(|e| eyre!("Failed to sign with '{}': {}", key.display(), e))?;
+    std::fs::write(out, signed)?;
+    info!("Wrote {} files to '{}'", bundle.len(), out.display());
+    Ok(())
+}
+
+/// Extract the bundle at `path` to `dir` and use the files in it instead of the other paths
+fn load_bundle(options: &mut Options, path: &Path, dir: &Path) -> color_eyre::Result<()> {
+    let bundle = match &options.bundle_anchor {
+        Some(anchor) => {
+            let anchor = cert::read_certificates(anchor)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| eyre!("No certificate in '{}'", anchor.display()))?;
+            Bundle::read_from_file(path, &anchor)
+        }
+        None if options.insecure_unsigned_bundle => std::fs::read(path)
+            .map_err(Into::into)
+            .and_then(|bytes| Bundle::from_bytes_unverified(&bytes)),
+        None => {
+            return Err(eyre!(
+                "No --bundle-anchor to verify the bundle '{}' with (use \
+                 --insecure-unsigned-bundle to load it without verifying the signature)",
+                path.display()
+            ))
+        }
+    }
+    .map_err(|e| eyre!("Failed to read the bundle '{}': {}", path.display(), e))?;
+    info!(
+        "Loaded {} files from the bundle created {}",
+        bundle.len(),
+        bundle.created
+    );
+    // Start from scratch, so that no files of a previous bundle are left over
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    bundle.extract(dir)?;
+
+    let mut trust_dirs = Vec::new();
+    options.trustlist.clear();
+    options.crl.clear();
+    for (name, _) in bundle.files() {
+        if name.starts_with("crl/") {
+            let path = Bundle::path_in(dir, name);
+            options.crl.push(path.display().to_string());
+        } else if name.starts_with("trustlist/") {
+            options.trustlist.push(Bundle::path_in(dir, name));
+        } else if name.starts_with("trust-dir/") {
+            // The directory is the first two components, e.g. `trust-dir/0`
+            let parts: Vec<&str> = name.splitn(3, '/').take(2).collect();
+            let trust_dir = Bundle::path_in(dir, &parts.join("/"));
+            if !trust_dirs.contains(&trust_dir) {
+                trust_dirs.push(trust_dir);
+            }
+        }
+    }
+    options.trust_dir = trust_dirs;
+    options.valuesets = Bundle::path_in(dir, "ehn-dcc-valuesets");
+    options.blocklist =
+        Some(Bundle::path_in(dir, "blocklist.txt")).filter(|_| bundle.contains("blocklist.txt"));
+    options.csca = Some(Bundle::path_in(dir, "csca.pem")).filter(|_| bundle.contains("csca.pem"));
+    Ok(())
+}
+
+/// Print the differences between two certificates
+fn diff(left: &str, right: &str, options: &Options) -> color_eyre::Result<()> {
+    let left = comparable(left, options)?;
+    let right = comparable(right, options)?;
+    let differences = dcc_decode::diff::diff(&left, &right);
+    if options.output == Output::Json {
+        println!("{}", serde_json::to_string(&differences)?);
+    } else if differences.is_empty() {
+        println!("No differences");
+    } else {
+        for difference in &differences {
+            println!("{}", difference);
+        }
+    }
+    Ok(())
+}
+
+/// Decode an input (without verifying it) into the form that `diff` compares
+fn comparable(file: &str, options: &Options) -> color_eyre::Result<serde_json::Value> {
+    let buf = read_input(file, options.input_format).map_err(|e| read_error(&file, e))?;
+    let (headers, payload) = match transport(&buf, options)? {
+        DecodedInput::Cose(data) => {
+            let (cose, _) = inflate(data, options.max_decompressed_size)?;
+            let sign1 = CoseSign1::from_slice(&cose)?;
+            (Some(sign1.headers()), sign1.payload)
+        }
+        DecodedInput::Claims(payload) => (None, payload),
+    };
+    let ctx = DecodeContext::new(EhnData::default());
+    let payload = ctx
+        .decode_claims(&payload)
+        .map_err(|e| CodedError::new(e.code(), format!("{}: {}", file, e)))?;
+    let mut value = dcc_decode::diff::comparable(headers.as_ref(), &payload)?;
+    if options.redact {
+        redact::redact_json(&mut value);
+    }
+    Ok(value)
+}
+
+fn print_kids(path: &Path) -> color_eyre::Result<()> {
+    for der in cert::read_certificates(path)? {
+        println!("{}", base64::encode(cert::compute_kid(&der)));
+    }
+    Ok(())
+}
+
+/// Write a new key with its certificate and print the kid
+fn keygen(out: &Path, country: &str, common_name: &str, days: i64) -> color_eyre::Result<()> {
+    if out.exists() {
+        return Err(eyre!("'{}' already exists", out.display()));
+    }
+    let signer = TestSigner::generate(country, common_name, days)?;
+    std::fs::write(out, signer.to_pem())?;
+    info!("Wrote the key and certificate to '{}'", out.display());
+    println!("{}", base64::encode(cert::compute_kid(&signer.certificate)));
+    Ok(())
+}
+
+fn show_trustlist(
+    trust: Option<&(dyn TrustSource + Send + Sync)>,
+    country: Option<&str>,
+    expiring_within: Option<Duration>,
+) -> color_eyre::Result<()> {
+    let trust = trust.ok_or_else(|| eyre!("No trust list was loaded"))?;
+    let deadline = expiring_within.map(|d| Utc::now() + d);
+    let date = |d: Option<DateTime<Utc>>| d.map_or("-".to_string(), |d| d.date_naive().to_string());
+
+    println!(
+        "{:<14} {:<7} {:<10} {:<10} {:<10} SUBJECT",
+        "KID", "COUNTRY", "ALGORITHM", "NOT BEFORE", "NOT AFTER"
+    );
+    for signer in trust.signers() {
+        let info = signer.info();
+        if let Some(country) = country {
+            if !info
+                .country
+                .as_deref()
+                .is_some_and(|c| c.eq_ignore_ascii_case(country))
+            {
+                continue;
+            }
+        }
+        if let Some(deadline) = deadline {
+            if info.not_after.is_none_or(|t| t > deadline) {
+                continue;
+            }
+        }
+        println!(
+            "{:<14} {:<7} {:<10} {:<10} {:<10} {}",
+            info.kid,
+            info.country.as_deref().unwrap_or("-"),
+            info.algorithm.as_deref().unwrap_or("-"),
+            date(info.not_before),
+            date(info.not_after),
+            info.subject.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
+
+/// A DSC in the expiry report
+#[derive(Serialize)]
+struct ExpiringSigner<'a> {
+    #[serde(flatten)]
+    info: &'a SignerInfo,
+    /// Negative if the DSC already expired
+    days_left: i64,
+}
+
+fn print_expiring(
+    trust: &(dyn TrustSource + Send + Sync),
+    within: Duration,
+    country: Option<&str>,
+    output: Output,
+) -> color_eyre::Result<()> {
+    let now = Utc::now();
+    let signers: Vec<SignerInfo> = trust::expiring(trust, now + within)
+        .into_iter()
+        .filter(|info| {
+            country.is_none_or(|country| {
+                info.country
+                    .as_deref()
+                    .is_some_and(|c| c.eq_ignore_ascii_case(country))
+            })
+        })
+        .collect();
+    let days_left = |info: &SignerInfo| info.not_after.map_or(0, |t| (t - now).num_days());
+
+    match output {
+        Output::Text => {
+            let mut countries: Vec<Option<&str>> =
+                signers.iter().map(|info| info.country.as_deref()).collect();
+            countries.dedup();
+            for country in countries {
+                let group: Vec<&SignerInfo> = signers
+                    .iter()
+                    .filter(|info| info.country.as_deref() == country)
+                    .collect();
+                println!("{} ({})", country.unwrap_or("-"), group.len());
+                for info in group {
+                    let not_after = info.not_after.map(|t| t.date_naive().to_string());
+                    let days = days_left(info);
+                    let left = if days < 0 {
+                        format!("expired {} days ago", -days)
+                    } else {
+                        format!("{} days left", days)
+                    };
+                    println!(
+                        "  {:<10} {:<20} {:<14} {}",
+                        not_after.as_deref().unwrap_or("-"),
+                        left,
+                        info.kid,
+                        info.subject.as_deref().unwrap_or("-"),
+                    );
+                }
+            }
+            if signers.is_empty() {
+                info!("No DSCs expire within {} days", within.num_days());
+            }
+        }
+        Output::Csv => {
+            println!("country,kid,subject,algorithm,not_after,days_left");
+            for info in &signers {
+                let fields = [
+                    info.country.clone().unwrap_or_default(),
+                    info.kid.clone(),
+                    info.subject.clone().unwrap_or_default(),
+                    info.algorithm.clone().unwrap_or_default(),
+                    info.not_after.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                    days_left(info).to_string(),
+                ];
+                println!("{}", dcc_decode::csv::format_row(&fields));
+            }
+        }
+        Output::Json => {
+            for info in &signers {
+                let signer = ExpiringSigner {
+                    info,
+                    days_left: days_left(info),
+                };
+                println!("{}", serde_json::to_string(&signer)?);
+            }
+        }
+        Output::Fhir => return Err(eyre!("The expiry report can't be printed as FHIR")),
+    }
+    Ok(())
+}
+
+fn replay(path: &Path) -> color_eyre::Result<()> {
+    let session = Session::read_from_file(path)
+        .map_err(|e| eyre!("Failed to read session '{}': {}", path.display(), e))?;
+    info!("Replaying session recorded at {}", session.recorded_at);
+
+    let options: Options = serde_json::from_value(session.options.clone())?;
+    let ctx = load_context(&options)?;
+    if !session.check_trust() {
+        warn!("Trust data differs from the recorded run");
+    }
+
+    let buf = session.input_bytes()?;
+    let mut result = SessionResult::default();
+    let res = run(&buf, &options, &ctx, &mut result);
+    if let Err(e) = &res {
+        result.error = Some(e.to_string());
+    }
+
+    let recorded = &session.result;
+    if &result == recorded {
+        info!("Replay matches the recorded result");
+    } else {
+        warn!("Replay differs from the recorded result");
+        if result.kid != recorded.kid {
+            warn!("kid: recorded {:?}, now {:?}", recorded.kid, result.kid);
+        }
+        if result.headers != recorded.headers {
+            warn!(
+                "headers: recorded {:?}, now {:?}",
+                recorded.headers, result.headers
+            );
+        }
+        if result.claims != recorded.claims {
+            warn!(
+                "claims: recorded {:?}, now {:?}",
+                recorded.claims, result.claims
+            );
+        }
+        if result.certificate != recorded.certificate {
+            warn!("certificate: recorded and replayed payloads differ");
+        }
+        if result.verification != recorded.verification {
+            warn!(
+                "verification: recorded {:?}, now {:?}",
+                recorded.verification, result.verification
+            );
+        }
+        if result.findings != recorded.findings {
+            warn!(
+                "findings: recorded {:?}, now {:?}",
+                recorded.findings, result.findings
+            );
+        }
+        if result.error != recorded.error {
+            warn!(
+                "error: recorded {:?}, now {:?}",
+                recorded.error, result.error
+            );
+        }
+    }
+
+    res
+}
@@ -0,0 +1,139 @@
+//! The minimal disclosure of a certificate, for door checks under data minimization rules
+//!
+//! Only what is needed to admit the holder is shown: the verdict, the kind of the
+//! certificate, the initials and year of birth of the holder (to compare with an ID
+//! document) and the validity period of the certificate. The full name, the date of birth
+//! and the UVCI are left out.
+use chrono::{DateTime, Utc};
+
+use crate::{
+    dcc::{CertPayload, EntryKind},
+    lang::Lang,
+    verify::Verification,
+};
+
+/// Whether the holder can be admitted with the certificate
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The signature is verified and the certificate is not expired
+    Valid,
+    /// The signature is verified, but the certificate is expired
+    Expired,
+    /// The signature is invalid, or the signer or certificate is blocked or revoked
+    Invalid,
+    /// The signature could not be checked, e.g. without a trust list
+    NotChecked,
+}
+
+impl Verdict {
+    pub fn new(verification: Option<&Verification>, expired: bool) -> Self {
+        match verification {
+            Some(Verification::Verified) if expired => Self::Expired,
+            Some(Verification::Verified) => Self::Valid,
+            Some(Verification::Rejected(_))
+            | Some(Verification::AlgorithmMismatch(_))
+            | Some(Verification::Invalid)
+            | Some(Verification::Blocked(_))
+            | Some(Verification::Revoked(_)) => Self::Invalid,
+            Some(Verification::KeyNotFound)
+            | Some(Verification::UnsupportedAlgorithm(_))
+            | None => Self::NotChecked,
+        }
+    }
+
+    fn text(self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (Self::Valid, Lang::En) => "VALID",
+            (Self::Valid, Lang::De) => "GÜLTIG",
+            (Self::Expired, Lang::En) => "EXPIRED",
+            (Self::Expired, Lang::De) => "ABGELAUFEN",
+            (Self::Invalid, Lang::En) => "INVALID",
+            (Self::Invalid, Lang::De) => "UNGÜLTIG",
+            (Self::NotChecked, Lang::En) => "NOT CHECKED",
+            (Self::NotChecked, Lang::De) => "NICHT GEPRÜFT",
+        }
+    }
+}
+
+/// The parts of a certificate that a door check needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disclosure {
+    pub verdict: Verdict,
+    /// The kinds of the entries, usually just one
+    pub kinds: Vec<EntryKind>,
+    /// The initials of the given name and the surname, e.g. `E. M.`
+    pub initials: String,
+    pub birth_year: Option<i32>,
+    pub valid_from: DateTime<Utc>,
+    pub valid_until: DateTime<Utc>,
+}
+
+/// The first letter of a name, followed by a dot
+fn initial(name: &str) -> Option<String> {
+    let first = name.trim().chars().next()?;
+    Some(format!("{}.", first.to_uppercase()))
+}
+
+impl Disclosure {
+    /// Select the minimal data of `payload`, treating it as expired at `now`
+    pub fn new(
+        payload: &CertPayload,
+        verification: Option<&Verification>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let cert = &payload.health_claim.cert;
+        let expired = payload.expiration_time <= now;
+        let names = [&cert.name.given_name, &cert.name.first_name];
+        Self {
+            verdict: Verdict::new(verification, expired),
+            kinds: cert.entry_kinds(),
+            initials: names
+                .iter()
+                .filter_map(|name| initial(name))
+                .collect::<Vec<_>>()
+                .join(" "),
+            birth_year: cert.date_of_birth.year(),
+            valid_from: payload.issued_at,
+            valid_until: payload.expiration_time,
+        }
+    }
+
+    /// Render the disclosure as a single line
+    pub fn render(&self, lang: Lang) -> String {
+        let kinds = match (self.kinds.is_empty(), lang) {
+            (true, Lang::En) => "no entries".to_string(),
+            (true, Lang::De) => "keine Einträge".to_string(),
+            (false, _) => (self.kinds.iter())
+                .map(|kind| kind_name(*kind, lang))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let holder = match self.birth_year {
+            Some(year) => format!("{} ({})", self.initials, year),
+            None => self.initials.clone(),
+        };
+        let (from, until) = (self.valid_from.date_naive(), self.valid_until.date_naive());
+        let validity = match lang {
+            Lang::En => format!("valid {} to {}", from, until),
+            Lang::De => format!("gültig {} bis {}", from, until),
+        };
+        format!(
+            "{} · {} · {} · {}",
+            self.verdict.text(lang),
+            kinds,
+            holder,
+            validity
+        )
+    }
+}
+
+fn kind_name(kind: EntryKind, lang: Lang) -> &'static str {
+    match (kind, lang) {
+        (EntryKind::Vaccination, Lang::En) => "vaccination",
+        (EntryKind::Vaccination, Lang::De) => "Impfung",
+        (EntryKind::Test, Lang::En) => "test",
+        (EntryKind::Test, Lang::De) => "Test",
+        (EntryKind::Recovery, Lang::En) => "recovery",
+        (EntryKind::Recovery, Lang::De) => "Genesung",
+    }
+}
@@ -0,0 +1,271 @@
+//! Finding QR codes in PDF files
+//!
+//! Certificates are often distributed as PDF files, with the QR code as an embedded image.
+//! All image XObjects of the document are decoded and searched for QR codes; pages are not
+//! rendered, so a QR code that is drawn with vector graphics is not found.
+use std::{convert::TryFrom, error::Error, fmt, io::Read};
+
+use flate2::read::ZlibDecoder;
+use image::{GrayImage, ImageFormat};
+use lopdf::{Dictionary, Document, Object, Stream};
+use tracing::debug;
+
+use crate::qr;
+
+#[derive(Debug)]
+pub enum PdfError {
+    Parse(lopdf::Error),
+}
+
+impl Error for PdfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl fmt::Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "Invalid PDF: {}", e),
+        }
+    }
+}
+
+/// The text of all QR codes in the images of a PDF file, in the order of the objects
+///
+/// The parts of a structured append sequence are joined, even across images.
+pub fn extract_qr_codes(data: &[u8]) -> Result<Vec<String>, PdfError> {
+    let doc = Document::load_mem(data).map_err(PdfError::Parse)?;
+    let mut symbols = Vec::new();
+    for (id, object) in &doc.objects {
+        let stream = match object {
+            Object::Stream(stream) if name(&stream.dict, b"Subtype") == Some(b"Image") => stream,
+            _ => continue,
+        };
+        match decode_image(&doc, stream) {
+            Some(image) => symbols.extend(qr::decode_symbols(&image)),
+            None => debug!("Skipping image {:?} with an unsupported encoding", id),
+        }
+    }
+    Ok(qr::join(symbols))
+}
+
+fn name<'a>(dict: &'a Dictionary, key: &[u8]) -> Option<&'a [u8]> {
+    dict.get(key).and_then(Object::as_name).ok()
+}
+
+fn integer(doc: &Document, dict: &Dictionary, key: &[u8]) -> Option<i64> {
+    let (_, value) = doc.dereference(dict.get(key).ok()?).ok()?;
+    value.as_i64().ok()
+}
+
+/// The filters of a stream, in decoding order
+fn filters(stream: &Stream) -> Vec<&[u8]> {
+    match stream.dict.get(b"Filter") {
+        Ok(Object::Name(name)) => vec![name.as_slice()],
+        Ok(Object::Array(names)) => names.iter().filter_map(|n| n.as_name().ok()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The color space of an image, as a function from a sample to a gray value
+enum ColorSpace {
+    /// A number of components that are averaged, `true` for CMYK
+    Device(usize, bool),
+    /// A palette with gray values
+    Indexed(Vec<u8>),
+}
+
+impl ColorSpace {
+    fn components(&self) -> usize {
+        match self {
+            Self::Device(n, _) => *n,
+            Self::Indexed(_) => 1,
+        }
+    }
+
+    fn from_object(doc: &Document, object: &Object) -> Option<Self> {
+        let (_, object) = doc.dereference(object).ok()?;
+        match object {
+            Object::Name(name) => match name.as_slice() {
+                b"DeviceGray" | b"CalGray" | b"G" => Some(Self::Device(1, false)),
+                b"DeviceRGB" | b"CalRGB" | b"RGB" => Some(Self::Device(3, false)),
+                b"DeviceCMYK" | b"CMYK" => Some(Self::Device(4, true)),
+                _ => None,
+            },
+            Object::Array(array) => match array.first()?.as_name().ok()? {
+                b"ICCBased" => {
+                    let (_, profile) = doc.dereference(array.get(1)?).ok()?;
+                    let n = integer(doc, &profile.as_stream().ok()?.dict, b"N")?;
+                    Some(Self::Device(n as usize, n == 4))
+                }
+                b"CalGray" => Some(Self::Device(1, false)),
+                b"CalRGB" => Some(Self::Device(3, false)),
+                b"Indexed" | b"I" => {
+                    let base = Self::from_object(doc, array.get(1)?)?;
+                    let (_, lookup) = doc.dereference(array.get(3)?).ok()?;
+                    let lookup = match lookup {
+                        Object::String(bytes, _) => bytes.clone(),
+                        Object::Stream(stream) => decode_stream(stream)?,
+                        _ => return None,
+                    };
+                    let palette = lookup
+                        .chunks_exact(base.components())
+                        .map(|color| base.gray(color))
+                        .collect();
+                    Some(Self::Indexed(palette))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The gray value of a color with 8-bit components
+    fn gray(&self, color: &[u8]) -> u8 {
+        match self {
+            Self::Indexed(palette) => palette.get(usize::from(color[0])).copied().unwrap_or(255),
+            Self::Device(_, true) => {
+                let ink = color[..3].iter().map(|c| u32::from(*c)).max().unwrap_or(0);
+                let black = u32::from(color[3]);
+                ((255 - ink) * (255 - black) / 255) as u8
+            }
+            Self::Device(_, false) => {
+                let sum: u32 = color.iter().map(|c| u32::from(*c)).sum();
+                (sum / color.len() as u32) as u8
+            }
+        }
+    }
+}
+
+/// Decode the `FlateDecode` filter of a stream (with the PNG predictors)
+fn decode_stream(stream: &Stream) -> Option<Vec<u8>> {
+    let mut data = stream.content.clone();
+    for filter in filters(stream) {
+        match filter {
+            b"FlateDecode" | b"Fl" => {
+                let mut out = Vec::new();
+                ZlibDecoder::new(data.as_slice())
+                    .read_to_end(&mut out)
+                    .ok()?;
+                data = out;
+            }
+            _ => return None,
+        }
+    }
+    Some(data)
+}
+
+/// Undo the PNG predictors, with one predictor byte in front of each row
+fn unpredict(data: &[u8], row_len: usize, bytes_per_pixel: usize) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len());
+    let mut previous = vec![0; row_len];
+    for row in data.chunks(row_len + 1) {
+        let (&predictor, row) = row.split_first()?;
+        let start = out.len();
+        for (i, &byte) in row.iter().enumerate() {
+            let left = if i >= bytes_per_pixel {
+                out[start + i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up = previous[i];
+            let up_left = if i >= bytes_per_pixel {
+                previous[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let prediction = match predictor {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return None,
+            };
+            out.push(byte.wrapping_add(prediction));
+        }
+        previous[..row.len()].copy_from_slice(&out[start..]);
+    }
+    Some(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Decode an image XObject into a gray image
+fn decode_image(doc: &Document, stream: &Stream) -> Option<GrayImage> {
+    let dict = &stream.dict;
+    let filters = filters(stream);
+    if filters.last() == Some(&b"DCTDecode".as_slice()) && filters.len() == 1 {
+        let image = image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg);
+        return image.ok().map(|image| image.to_luma8());
+    }
+
+    let width = usize::try_from(integer(doc, dict, b"Width")?).ok()?;
+    let height = usize::try_from(integer(doc, dict, b"Height")?).ok()?;
+    let mask = matches!(dict.get(b"ImageMask"), Ok(Object::Boolean(true)));
+    let (space, bits) = if mask {
+        (ColorSpace::Device(1, false), 1)
+    } else {
+        let space = ColorSpace::from_object(doc, dict.get(b"ColorSpace").ok()?)?;
+        (space, integer(doc, dict, b"BitsPerComponent")? as usize)
+    };
+    if ![1, 2, 4, 8].contains(&bits) {
+        return None;
+    }
+    let components = space.components();
+    let row_len = (width * components * bits).div_ceil(8);
+
+    let mut data = decode_stream(stream)?;
+    let params = dict.get(b"DecodeParms").and_then(Object::as_dict).ok();
+    if params
+        .and_then(|p| integer(doc, p, b"Predictor"))
+        .unwrap_or(1)
+        >= 10
+    {
+        data = unpredict(&data, row_len, (components * bits).div_ceil(8))?;
+    }
+    if data.len() < row_len * height {
+        return None;
+    }
+
+    // A `Decode` array of `[1 0]` inverts a gray image or mask (1 is painted)
+    let inverted = match dict.get(b"Decode") {
+        Ok(Object::Array(decode)) => decode.first().and_then(|d| d.as_float().ok()) == Some(1.0),
+        _ => false,
+    };
+    let max = (1u16 << bits) - 1;
+    let mut color = vec![0; components];
+    let image = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        let row = &data[y as usize * row_len..][..row_len];
+        for (c, value) in color.iter_mut().enumerate() {
+            let bit = (x as usize * components + c) * bits;
+            let sample = u16::from(row[bit / 8] >> (8 - bits - bit % 8)) & max;
+            *value = match space {
+                ColorSpace::Indexed(_) => sample as u8,
+                _ => (sample * 255 / max) as u8,
+            };
+        }
+        let gray = space.gray(&color);
+        // In a mask, samples of 0 are painted (black), just like in a gray image
+        let gray = if inverted { 255 - gray } else { gray };
+        image::Luma([gray])
+    });
+    Some(image)
+}
@@ -0,0 +1,116 @@
+//! Named bundles of the verification options
+//!
+//! A profile sets the defaults of the options that decide how much of a deviation from
+//! the specifications is tolerated, from conformance testing (`strict`) to verifying
+//! certificates of all kinds of issuers in the field (`lenient`).
+use std::{fmt, str::FromStr};
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Reject everything that does not conform to the specifications
+    Strict,
+    /// Report deviations, but only reject invalid signatures and signers
+    #[default]
+    Standard,
+    /// Like `standard`, but also accept signers outside of their validity, and only treat
+    /// certificates as expired after a grace period
+    Lenient,
+}
+
+/// The options that a [`Profile`] stands for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileSettings {
+    /// Require the deterministic CBOR encoding and the exact input text
+    pub strict: bool,
+    /// Fail on expired certificates
+    pub reject_expired: bool,
+    /// How long after its expiration time a certificate is not yet treated as expired
+    pub expiry_grace: Duration,
+    /// Fail on unknown claims and fields, or fields that are not in the schema version
+    pub reject_unknown_fields: bool,
+    /// Fail on DER encoded ECDSA signatures
+    pub reject_der_signature: bool,
+    /// Only warn if the DSC was not valid when the certificate was issued
+    pub warn_dsc_validity: bool,
+    /// Only warn about trust list entries with a mismatching thumbprint
+    pub warn_thumbprint: bool,
+}
+
+impl Profile {
+    pub fn settings(self) -> ProfileSettings {
+        match self {
+            Self::Strict => ProfileSettings {
+                strict: true,
+                reject_expired: true,
+                expiry_grace: Duration::zero(),
+                reject_unknown_fields: true,
+                reject_der_signature: true,
+                warn_dsc_validity: false,
+                warn_thumbprint: false,
+            },
+            Self::Standard => ProfileSettings {
+                strict: false,
+                reject_expired: false,
+                expiry_grace: Duration::zero(),
+                reject_unknown_fields: false,
+                reject_der_signature: false,
+                warn_dsc_validity: false,
+                warn_thumbprint: false,
+            },
+            Self::Lenient => ProfileSettings {
+                strict: false,
+                reject_expired: false,
+                expiry_grace: Duration::days(3),
+                reject_unknown_fields: false,
+                reject_der_signature: false,
+                warn_dsc_validity: true,
+                warn_thumbprint: true,
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Standard => "standard",
+            Self::Lenient => "lenient",
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownProfile(String);
+
+impl std::error::Error for UnknownProfile {}
+impl fmt::Display for UnknownProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown profile '{}' (expected 'strict', 'standard' or 'lenient')",
+            self.0
+        )
+    }
+}
+
+impl FromStr for Profile {
+    type Err = UnknownProfile;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "standard" => Ok(Self::Standard),
+            "lenient" => Ok(Self::Lenient),
+            _ => Err(UnknownProfile(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! Structured append: one text split across up to 16 QR codes
+//!
+//! Each symbol starts with a header (mode `0011`) with its position in the sequence, the
+//! number of symbols and a parity byte (the XOR of all bytes of the complete text), which
+//! identifies the sequence. rqrr does not know this mode, so the data codewords of such a
+//! symbol are read and error corrected here, and its segments are decoded afterwards.
+use std::collections::BTreeMap;
+
+use reed_solomon::Decoder;
+use rqrr::{BitGrid, DeQRError, Grid, MetaData};
+use tracing::{debug, warn};
+
+/// A symbol of a structured append sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// The position in the sequence, starting at 0
+    pub index: u8,
+    /// The number of symbols in the sequence
+    pub total: u8,
+    /// The parity byte of the complete text
+    pub parity: u8,
+    pub text: String,
+}
+
+/// Mode indicator of the structured append header
+const MODE_STRUCTURED_APPEND: usize = 0b0011;
+
+/// Error correction codewords per block, by version (1-40) and level (L, M, Q, H)
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 40]; 4] = [
+    [
+        7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30,
+        30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ],
+    [
+        13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30,
+        30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+];
+
+/// Error correction blocks, by version (1-40) and level (L, M, Q, H)
+const BLOCKS: [[u8; 40]; 4] = [
+    [
+        1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ],
+    [
+        1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23,
+        25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ],
+    [
+        1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ],
+    [
+        1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35,
+        37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ],
+];
+
+/// The characters of the alphanumeric mode, in the order of their values
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Decode a symbol that starts with a structured append header
+pub fn decode<G: BitGrid>(grid: &Grid<G>) -> Result<Part, DeQRError> {
+    let (meta, _) = grid.get_raw_data()?;
+    let unmasked = Grid::new(Unmasked {
+        grid: &grid.grid,
+        mask: meta.mask,
+    });
+    let (_, raw) = unmasked.get_raw_data()?;
+    let data = correct(&meta, &raw.data)?;
+
+    let mut bits = BitReader::new(&data);
+    if bits.take(4)? != MODE_STRUCTURED_APPEND {
+        return Err(DeQRError::UnknownDataType);
+    }
+    let index = bits.take(4)? as u8;
+    let total = bits.take(4)? as u8 + 1;
+    let parity = bits.take(8)? as u8;
+    let text = String::from_utf8(segments(&meta, &mut bits)?)?;
+    debug!(
+        "Read part {} of {} of a structured append sequence",
+        index + 1,
+        total
+    );
+    Ok(Part {
+        index,
+        total,
+        parity,
+        text,
+    })
+}
+
+/// A grid with the mask removed from all cells but the format information
+///
+/// rqrr reads the format (and with it the mask) before the data, so those cells must
+/// stay as they are; all other reserved cells are not read as data.
+struct Unmasked<'a, G> {
+    grid: &'a G,
+    mask: u16,
+}
+
+impl<G: BitGrid> BitGrid for Unmasked<'_, G> {
+    fn size(&self) -> usize {
+        self.grid.size()
+    }
+
+    fn bit(&self, y: usize, x: usize) -> bool {
+        let size = self.size();
+        let format = (y < 9 || y + 8 >= size) && x < 9 || y < 9 && x + 8 >= size;
+        self.grid.bit(y, x) ^ (!format && mask_bit(self.mask, y, x))
+    }
+}
+
+fn mask_bit(mask: u16, y: usize, x: usize) -> bool {
+    match mask {
+        0 => (y + x).is_multiple_of(2),
+        1 => y.is_multiple_of(2),
+        2 => x.is_multiple_of(3),
+        3 => (y + x).is_multiple_of(3),
+        4 => (y / 2 + x / 3).is_multiple_of(2),
+        5 => (y * x) % 2 + (y * x) % 3 == 0,
+        6 => ((y * x) % 2 + (y * x) % 3).is_multiple_of(2),
+        _ => ((y * x) % 3 + (y + x) % 2).is_multiple_of(2),
+    }
+}
+
+/// The number of codewords of a symbol
+fn codewords(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignment = version / 7 + 2;
+        modules -= (25 * alignment - 10) * alignment - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules / 8
+}
+
+/// De-interleave the codewords into their blocks and return the corrected data codewords
+fn correct(meta: &MetaData, raw: &[u8]) -> Result<Vec<u8>, DeQRError> {
+    let version = meta.version.0;
+    if !(1..=40).contains(&version) {
+        return Err(DeQRError::InvalidVersion);
+    }
+    // The format bits of the levels are M, L, H, Q
+    let level = [1, 0, 3, 2][usize::from(meta.ecc_level & 3)];
+    let blocks = usize::from(BLOCKS[level][version - 1]);
+    let ecc = usize::from(ECC_CODEWORDS_PER_BLOCK[level][version - 1]);
+    let total = codewords(version);
+    // The last blocks have one data codeword more than the first ones
+    let short = blocks - total % blocks;
+    let short_data = total / blocks - ecc;
+    let data_len = |block| short_data + usize::from(block >= short);
+    let data_total = total - blocks * ecc;
+
+    let decoder = Decoder::new(ecc);
+    let mut data = Vec::with_capacity(data_total);
+    for block in 0..blocks {
+        let len = data_len(block);
+        let mut codewords = Vec::with_capacity(len + ecc);
+        // Data codewords are interleaved, with the extra ones of the long blocks at the end
+        codewords.extend((0..len).map(|i| {
+            if i < short_data {
+                raw[i * blocks + block]
+            } else {
+                raw[short_data * blocks + block - short]
+            }
+        }));
+        codewords.extend((0..ecc).map(|i| raw[data_total + i * blocks + block]));
+        let corrected = decoder
+            .correct(&codewords, None)
+            .map_err(|_| DeQRError::DataEcc)?;
+        data.extend_from_slice(corrected.data());
+    }
+    Ok(data)
+}
+
+/// Decode the data segments after the header
+fn segments(meta: &MetaData, bits: &mut BitReader) -> Result<Vec<u8>, DeQRError> {
+    // Size of the character count, by mode, for versions 1-9, 10-26 and 27-40
+    let group = match meta.version.0 {
+        0..=9 => 0,
+        10..=26 => 1,
+        _ => 2,
+    };
+    let mut out = Vec::new();
+    while bits.remaining() >= 4 {
+        match bits.take(4)? {
+            0 => break,
+            // Numeric
+            1 => {
+                let mut count = bits.take([10, 12, 14][group])?;
+                while count > 0 {
+                    let digits = count.min(3);
+                    let value = bits.take([0, 4, 7, 10][digits])?;
+                    out.extend(format!("{:0width$}", value, width = digits).bytes());
+                    count -= digits;
+                }
+            }
+            // Alphanumeric
+            2 => {
+                let mut count = bits.take([9, 11, 13][group])?;
+                while count > 0 {
+                    let chars = count.min(2);
+                    let value = bits.take([0, 6, 11][chars])?;
+                    let char = |v: usize| ALPHANUMERIC.get(v).ok_or(DeQRError::EncodingError);
+                    if chars == 2 {
+                        out.push(*char(value / 45)?);
+                    }
+                    out.push(*char(value % 45)?);
+                    count -= chars;
+                }
+            }
+            // Byte
+            4 => {
+                let count = bits.take([8, 16, 16][group])?;
+                for _ in 0..count {
+                    out.push(bits.take(8)? as u8);
+                }
+            }
+            // ECI, the designator is skipped and the text is read as UTF-8
+            7 => {
+                let first = bits.take(8)?;
+                match first >> 5 {
+                    0b110 => bits.take(16)?,
+                    0b100 | 0b101 => bits.take(8)?,
+                    _ => 0,
+                };
+            }
+            mode => {
+                warn!("Unsupported QR code segment mode {:#06b}", mode);
+                return Err(DeQRError::UnknownDataType);
+            }
+        }
+    }
+    Ok(out)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    fn take(&mut self, n: usize) -> Result<usize, DeQRError> {
+        if n > self.remaining() {
+            return Err(DeQRError::DataUnderflow);
+        }
+        let value = (self.pos..self.pos + n).fold(0, |value, bit| {
+            value << 1 | usize::from(self.data[bit / 8] >> (7 - bit % 8) & 1)
+        });
+        self.pos += n;
+        Ok(value)
+    }
+}
+
+/// Join the parts of each complete sequence, in the order of their first part
+///
+/// Returns the texts and the position of each in `parts`. Incomplete sequences are
+/// skipped with a warning.
+pub fn assemble(parts: &[Part]) -> Vec<(usize, String)> {
+    let mut sequences: BTreeMap<(u8, u8), (usize, Vec<&Part>)> = BTreeMap::new();
+    for (position, part) in parts.iter().enumerate() {
+        let (_, sequence) = sequences
+            .entry((part.parity, part.total))
+            .or_insert_with(|| (position, Vec::new()));
+        sequence.push(part);
+    }
+
+    let mut texts = Vec::new();
+    for ((parity, total), (position, mut sequence)) in sequences {
+        sequence.sort_by_key(|part| part.index);
+        sequence.dedup_by_key(|part| part.index);
+        let complete = sequence.len() == usize::from(total)
+            && sequence
+                .iter()
+                .enumerate()
+                .all(|(i, p)| usize::from(p.index) == i);
+        if !complete {
+            let found: Vec<String> = sequence.iter().map(|p| (p.index + 1).to_string()).collect();
+            warn!(
+                "Skipping an incomplete structured append sequence: found part {} of {}",
+                found.join(", "),
+                total
+            );
+            continue;
+        }
+        let text: String = sequence.iter().map(|part| part.text.as_str()).collect();
+        if text.bytes().fold(0, |a, b| a ^ b) != parity {
+            warn!("The parity of a structured append sequence does not match its text");
+        }
+        debug!("Joined a structured append sequence of {} QR codes", total);
+        texts.push((position, text));
+    }
+    texts.sort_by_key(|(position, _)| *position);
+    texts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, codewords, BitReader, Part};
+
+    /// The parts of `text`, split into `chunks` with the parity of the complete text
+    fn split(text: &str, chunks: &[&str]) -> Vec<Part> {
+        let parity = text.bytes().fold(0, |a, b| a ^ b);
+        let total = chunks.len() as u8;
+        (chunks.iter().enumerate())
+            .map(|(index, chunk)| Part {
+                index: index as u8,
+                total,
+                parity,
+                text: chunk.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn in_order() {
+        let parts = split("HC1:NCFOXN%TS3DH", &["HC1:NC", "FOXN%T", "S3DH"]);
+        assert_eq!(assemble(&parts), [(0, "HC1:NCFOXN%TS3DH".to_string())]);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut parts = split("HC1:NCFOXN%TS3DH", &["HC1:NC", "FOXN%T", "S3DH"]);
+        parts.rotate_left(1);
+        assert_eq!(assemble(&parts), [(0, "HC1:NCFOXN%TS3DH".to_string())]);
+
+        // A part that was scanned twice is only used once
+        parts.push(parts[1].clone());
+        assert_eq!(assemble(&parts), [(0, "HC1:NCFOXN%TS3DH".to_string())]);
+    }
+
+    #[test]
+    fn interleaved_sequences() {
+        let first = split("HC1:AAABBB", &["HC1:AAA", "BBB"]);
+        let second = split("HC1:CCCDD", &["HC1:CCC", "DD"]);
+        let parts = vec![
+            second[1].clone(),
+            first[0].clone(),
+            second[0].clone(),
+            first[1].clone(),
+        ];
+        assert_eq!(
+            assemble(&parts),
+            [(0, "HC1:CCCDD".to_string()), (1, "HC1:AAABBB".to_string())]
+        );
+    }
+
+    #[test]
+    fn missing_index() {
+        let parts = split("HC1:NCFOXN%TS3DH", &["HC1:NC", "FOXN%T", "S3DH"]);
+        assert!(assemble(&[parts[0].clone(), parts[2].clone()]).is_empty());
+        assert!(assemble(&parts[1..]).is_empty());
+        assert!(assemble(&parts[..2]).is_empty());
+    }
+
+    #[test]
+    fn parity_mismatch() {
+        // A part with another parity belongs to another sequence
+        let mut parts = split("HC1:NCFOXN%TS3DH", &["HC1:NC", "FOXN%T", "S3DH"]);
+        parts[1].parity ^= 0x01;
+        assert!(assemble(&parts).is_empty());
+
+        // A complete sequence is joined even if its text does not match the parity
+        for part in &mut parts {
+            part.parity = 0x42;
+        }
+        assert_eq!(assemble(&parts), [(0, "HC1:NCFOXN%TS3DH".to_string())]);
+    }
+
+    #[test]
+    fn symbol_sizes() {
+        assert_eq!(codewords(1), 26);
+        assert_eq!(codewords(7), 196);
+        assert_eq!(codewords(40), 3706);
+    }
+
+    #[test]
+    fn bit_reader() {
+        let mut bits = BitReader::new(&[0b0011_0001, 0b0010_1010]);
+        assert_eq!(bits.take(4).unwrap(), 0b0011);
+        assert_eq!(bits.take(4).unwrap(), 0b0001);
+        assert_eq!(bits.take(8).unwrap(), 0b0010_1010);
+        assert_eq!(bits.remaining(), 0);
+        assert!(bits.take(1).is_err());
+    }
+}
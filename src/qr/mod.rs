@@ -0,0 +1,106 @@
+//! Reading the text of QR codes from images
+use image::{imageops, GrayImage, ImageError, Luma};
+use rqrr::DeQRError;
+use tracing::{debug, warn};
+
+mod append;
+
+pub use self::append::Part;
+
+/// The smallest width at which the modules of a QR code are detected reliably
+const MIN_WIDTH: u32 = 400;
+/// The white border (in pixels of the original image) that is added around the image
+const QUIET_ZONE: u32 = 4;
+
+/// Scale up small images (e.g. one pixel per module) and add a quiet zone
+///
+/// Embedded QR code images often have no border and very few pixels, which the detector
+/// can't find the finder patterns in.
+fn normalize(image: &GrayImage) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let mut padded =
+        GrayImage::from_pixel(width + 2 * QUIET_ZONE, height + 2 * QUIET_ZONE, Luma([255]));
+    imageops::replace(&mut padded, image, QUIET_ZONE.into(), QUIET_ZONE.into());
+    let scale = (MIN_WIDTH / padded.width().max(1)).max(1);
+    if scale == 1 {
+        return padded;
+    }
+    imageops::resize(
+        &padded,
+        padded.width() * scale,
+        padded.height() * scale,
+        imageops::FilterType::Nearest,
+    )
+}
+
+/// The content of a QR code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Symbol {
+    Text(String),
+    /// A part of a text that is split across several QR codes
+    Part(Part),
+}
+
+/// The content of all QR codes that are found in `image`
+pub fn decode_symbols(image: &GrayImage) -> Vec<Symbol> {
+    let mut prepared = rqrr::PreparedImage::prepare(normalize(image));
+    let grids = prepared.detect_grids();
+    debug!(
+        "Found {} QR codes in a {:?} image",
+        grids.len(),
+        image.dimensions()
+    );
+    grids
+        .iter()
+        .filter_map(|grid| match grid.decode() {
+            Ok((_, text)) => Some(Symbol::Text(text)),
+            // rqrr stops at the structured append header
+            Err(DeQRError::UnknownDataType) => match append::decode(grid) {
+                Ok(part) => Some(Symbol::Part(part)),
+                Err(e) => {
+                    warn!("Failed to read a QR code: {}", e);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("Failed to read a QR code: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The texts of `symbols`, with the parts of each structured append sequence joined
+///
+/// A joined text takes the place of the first part of its sequence that was found, so
+/// the parts may be in any order (and in different images). Incomplete sequences are
+/// skipped with a warning.
+pub fn join(symbols: Vec<Symbol>) -> Vec<String> {
+    let mut texts = Vec::new();
+    let mut parts = Vec::new();
+    let mut positions = Vec::new();
+    for (position, symbol) in symbols.into_iter().enumerate() {
+        match symbol {
+            Symbol::Text(text) => texts.push((position, text)),
+            Symbol::Part(part) => {
+                positions.push(position);
+                parts.push(part);
+            }
+        }
+    }
+    let joined = append::assemble(&parts).into_iter();
+    texts.extend(joined.map(|(i, text)| (positions[i], text)));
+    texts.sort_by_key(|(position, _)| *position);
+    texts.into_iter().map(|(_, text)| text).collect()
+}
+
+/// The text of all QR codes that are found in `image`
+pub fn decode(image: &GrayImage) -> Vec<String> {
+    join(decode_symbols(image))
+}
+
+/// The text of all QR codes in an image file (PNG or JPEG)
+pub fn decode_image_file(data: &[u8]) -> Result<Vec<String>, ImageError> {
+    let image = image::load_from_memory(data)?;
+    Ok(decode(&image.to_luma8()))
+}
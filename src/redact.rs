@@ -0,0 +1,162 @@
+//! Masking of the personal data in a certificate
+//!
+//! The name, date of birth and certificate identifier (UVCI) of the holder are replaced
+//! with [`MASK`], while the technical details (issuer, timestamps, value set entries)
+//! are kept as they are.
+use serde_json::Value;
+
+use crate::cbor::{Item, ItemKind};
+
+/// The text that replaces personal data
+pub const MASK: &str = "***";
+
+/// Keys of the DCC schema (and of a [`HealthCard`](crate::shc::HealthCard)) whose values
+/// identify the holder
+const PERSONAL_KEYS: &[&str] = &["nam", "dob", "ci", "name", "birth_date"];
+
+fn is_personal(key: &str) -> bool {
+    PERSONAL_KEYS.contains(&key)
+}
+
+/// Mask the personal data in the JSON form of a certificate or CWT payload
+pub fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_personal(key) {
+                    mask_json(value);
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// Replace all values within `value`, keeping the structure of objects and arrays
+fn mask_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => map.values_mut().for_each(mask_json),
+        Value::Array(items) => items.iter_mut().for_each(mask_json),
+        _ => *value = Value::String(MASK.to_string()),
+    }
+}
+
+/// Mask the personal data in a parsed CBOR item, including embedded items
+pub fn redact_cbor(item: &mut Item) {
+    match &mut item.kind {
+        ItemKind::Map(entries) => {
+            for (key, value) in entries.iter_mut() {
+                match &key.kind {
+                    ItemKind::Text(key) if is_personal(key) => mask_cbor(value),
+                    _ => redact_cbor(value),
+                }
+            }
+        }
+        ItemKind::Array(items) => items.iter_mut().for_each(redact_cbor),
+        ItemKind::Tag(_, inner) | ItemKind::Embedded(inner) => redact_cbor(inner),
+        _ => {}
+    }
+}
+
+fn mask_cbor(item: &mut Item) {
+    match &mut item.kind {
+        ItemKind::Map(entries) => entries.iter_mut().for_each(|(_, value)| mask_cbor(value)),
+        ItemKind::Array(items) => items.iter_mut().for_each(mask_cbor),
+        ItemKind::Tag(_, inner) | ItemKind::Embedded(inner) => mask_cbor(inner),
+        kind => {
+            *kind = ItemKind::Text(MASK.to_string());
+            item.indefinite = false;
+        }
+    }
+}
+
+/// Replace the personal data in the encoded CBOR `data` in place, as parsed into `item`
+///
+/// Unlike [`redact_cbor`], the texts keep their length in bytes, so the encoding and its
+/// offsets stay the same: digits become `1` (which keeps dates valid), letters `X` or `x`
+/// and other characters keep their place. Returns the paths (e.g. `-260.1.nam.fn`) and
+/// original texts of the replaced values.
+pub fn anonymize_cbor(data: &mut [u8], item: &Item) -> Vec<(String, String)> {
+    let mut paths = Vec::new();
+    anonymize_item(data, 0, item, "", false, &mut paths);
+    paths
+}
+
+fn anonymize_item(
+    data: &mut [u8],
+    base: usize,
+    item: &Item,
+    path: &str,
+    personal: bool,
+    paths: &mut Vec<(String, String)>,
+) {
+    match &item.kind {
+        ItemKind::Map(entries) => {
+            for (key, value) in entries {
+                let name = match &key.kind {
+                    ItemKind::Text(key) => key.clone(),
+                    ItemKind::Unsigned(n) => n.to_string(),
+                    ItemKind::Negative(n) => format!("-{}", u128::from(*n) + 1),
+                    _ => "?".to_string(),
+                };
+                let personal = personal || is_personal(&name);
+                let path = match path {
+                    "" => name,
+                    path => format!("{}.{}", path, name),
+                };
+                anonymize_item(data, base, value, &path, personal, paths);
+            }
+        }
+        ItemKind::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                anonymize_item(data, base, item, &path, personal, paths);
+            }
+        }
+        ItemKind::Tag(_, inner) => anonymize_item(data, base, inner, path, personal, paths),
+        ItemKind::Embedded(inner) => {
+            if let Some(start) = content_start(data, base + item.offset) {
+                anonymize_item(data, start, inner, path, personal, paths);
+            }
+        }
+        // The chunks of indefinite-length texts are not tracked
+        ItemKind::Text(text) if personal && !item.indefinite => {
+            let range = content_start(data, base + item.offset)
+                .map(|start| start..start + text.len())
+                .and_then(|range| data.get_mut(range));
+            if let Some(bytes) = range {
+                bytes.copy_from_slice(placeholder(text).as_bytes());
+                paths.push((path.to_string(), text.clone()));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The offset of the content of the string whose head is at `offset`
+fn content_start(data: &[u8], offset: usize) -> Option<usize> {
+    let head = match data.get(offset)? & 0x1f {
+        0..=23 => 1,
+        24 => 2,
+        25 => 3,
+        26 => 5,
+        27 => 9,
+        _ => return None,
+    };
+    Some(offset + head)
+}
+
+/// A text of the same length in bytes, without the letters and digits of `text`
+fn placeholder(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '0'..='9' => "1".to_string(),
+            'A'..='Z' => "X".to_string(),
+            c if c.is_ascii() && !c.is_ascii_alphabetic() => c.to_string(),
+            c => "x".repeat(c.len_utf8()),
+        })
+        .collect()
+}
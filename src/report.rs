@@ -0,0 +1,407 @@
+//! A human-friendly report of a certificate (or health card) for the terminal
+use std::{fmt, io::IsTerminal, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dcc::{
+        valuesets::ValueSetEntry, CertPayload, Entry, EntryKind, Recovery, Test, Vaccination,
+        Warning,
+    },
+    redact::MASK,
+    shc::HealthCard,
+    spec::Finding,
+    trust::SignerInfo,
+    verify::Verification,
+};
+
+const BOLD: &str = "1";
+const DIM: &str = "2";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+
+/// When to color the output
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// If stdout is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Auto => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Always => write!(f, "always"),
+            Self::Never => write!(f, "never"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownColorChoice(String);
+
+impl std::error::Error for UnknownColorChoice {}
+impl fmt::Display for UnknownColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown color choice '{}' (expected 'auto', 'always' or 'never')",
+            self.0
+        )
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = UnknownColorChoice;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(UnknownColorChoice(s.to_string())),
+        }
+    }
+}
+
+/// How the report is rendered
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ReportOptions {
+    pub color: bool,
+    /// Mask the name, date of birth and certificate identifiers
+    pub redact: bool,
+}
+
+/// A titled list of key/value pairs
+struct Table {
+    title: String,
+    rows: Vec<(&'static str, String)>,
+}
+
+impl Table {
+    fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            rows: Vec::new(),
+        }
+    }
+
+    fn row(&mut self, key: &'static str, value: impl ToString) -> &mut Self {
+        self.rows.push((key, value.to_string()));
+        self
+    }
+
+    fn optional(&mut self, key: &'static str, value: Option<impl ToString>) -> &mut Self {
+        if let Some(value) = value {
+            self.row(key, value);
+        }
+        self
+    }
+}
+
+fn entry(entry: &ValueSetEntry) -> String {
+    match &entry.value {
+        Some(_) => format!("{} ({})", entry.display(), entry.key),
+        None => entry.key.clone(),
+    }
+}
+
+/// The verdict symbol, its style and the description
+fn verdict(verification: Option<&Verification>) -> (&'static str, &'static str, String) {
+    match verification {
+        Some(Verification::Verified) => ("✓", GREEN, "Signature verified".to_string()),
+        Some(v @ Verification::Rejected(_))
+        | Some(v @ Verification::AlgorithmMismatch(_))
+        | Some(v @ Verification::Invalid)
+        | Some(v @ Verification::Blocked(_))
+        | Some(v @ Verification::Revoked(_)) => ("✗", RED, v.to_string()),
+        Some(v) => ("?", YELLOW, format!("Not verified: {}", v)),
+        None => ("?", YELLOW, "Not verified: no signature".to_string()),
+    }
+}
+
+struct Renderer {
+    options: ReportOptions,
+    out: String,
+}
+
+impl Renderer {
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.options.color {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Mask personal data with `--redact`
+    fn personal(&self, value: impl ToString) -> String {
+        if self.options.redact {
+            MASK.to_string()
+        } else {
+            value.to_string()
+        }
+    }
+
+    fn table(&mut self, table: &Table) {
+        let width = table.rows.iter().map(|(key, _)| key.len()).max();
+        let title = self.paint(BOLD, &table.title);
+        self.out.push('\n');
+        self.out.push_str(&title);
+        self.out.push('\n');
+        for (key, value) in &table.rows {
+            let key = format!("{:<1$}", key, width.unwrap_or(0));
+            let key = self.paint(DIM, &key);
+            self.out.push_str(&format!("  {}  {}\n", key, value));
+        }
+    }
+
+    /// The header line with the verdict
+    fn verdict(&mut self, verification: Option<&Verification>, expired: bool) {
+        let (symbol, style, text) = verdict(verification);
+        let header = self.paint(
+            &format!("{};{}", BOLD, style),
+            &format!("{} {}", symbol, text),
+        );
+        self.out.push_str(&header);
+        if expired {
+            let expired = self.paint(YELLOW, " (expired)");
+            self.out.push_str(&expired);
+        }
+        self.out.push('\n');
+    }
+
+    fn vaccination(&mut self, v: &Vaccination, title: &str) {
+        let mut table = Table::new(title);
+        table
+            .row("Disease", entry(&v.disease_agent_targeted))
+            .row("Vaccine", entry(&v.vaccine_or_prophylaxis))
+            .row("Product", entry(&v.medicinal_product))
+            .row("Manufacturer", entry(&v.manufacturer))
+            .row(
+                "Dose",
+                format!(
+                    "{} of {} ({})",
+                    v.dose_number,
+                    v.series_dose_number,
+                    v.series()
+                ),
+            )
+            .row("Date", v.date)
+            .row("Country", entry(&v.country))
+            .row("Issuer", &v.issuer)
+            .row("UVCI", self.personal(&v.cert_identifier));
+        self.table(&table);
+    }
+
+    fn test(&mut self, t: &Test, title: &str) {
+        let mut table = Table::new(title);
+        table
+            .row("Disease", entry(&t.disease_agent_targeted))
+            .row("Type", entry(&t.test_type))
+            .optional("Name", t.name.as_ref())
+            .optional("Manufacturer", t.manufacturer.as_ref().map(entry))
+            .row("Sampled", t.sample_collection)
+            .optional("Result date", t.result_date)
+            .row("Result", entry(&t.result))
+            .optional("Centre", t.testing_centre.as_ref())
+            .row("Country", entry(&t.country))
+            .row("Issuer", &t.issuer)
+            .row("UVCI", self.personal(&t.cert_identifier));
+        self.table(&table);
+    }
+
+    fn recovery(&mut self, r: &Recovery, title: &str) {
+        let mut table = Table::new(title);
+        table
+            .row("Disease", entry(&r.disease_agent_targeted))
+            .row("First positive", r.first_positive)
+            .row("Valid from", r.valid_from)
+            .row("Valid until", r.valid_until)
+            .row("Country", entry(&r.country))
+            .row("Issuer", &r.issuer)
+            .row("UVCI", self.personal(&r.cert_identifier));
+        self.table(&table);
+    }
+}
+
+/// Render a report of a certificate, with the verification result and the signer
+///
+/// The `findings` are listed below the verdict, e.g. a DER encoded signature that was
+/// accepted, followed by the `warnings` of the decoding. `now` decides whether the
+/// certificate is shown as expired.
+#[allow(clippy::too_many_arguments)]
+pub fn render(
+    payload: &CertPayload,
+    verification: Option<&Verification>,
+    signer: Option<&SignerInfo>,
+    findings: &[Finding],
+    warnings: &[Warning],
+    now: DateTime<Utc>,
+    options: ReportOptions,
+) -> String {
+    let mut r = Renderer {
+        options,
+        out: String::new(),
+    };
+    let cert = &payload.health_claim.cert;
+
+    r.verdict(verification, payload.expiration_time <= now);
+    // The finding of a failed verification is already in the verdict
+    let own = verification.and_then(Verification::finding);
+    for finding in findings.iter().filter(|f| Some(*f) != own.as_ref()) {
+        let line = r.paint(YELLOW, &format!("! {}", finding));
+        r.out.push_str(&line);
+        r.out.push('\n');
+    }
+    for warning in warnings {
+        let line = r.paint(YELLOW, &format!("! {}", warning));
+        r.out.push_str(&line);
+        r.out.push('\n');
+    }
+
+    let name = &cert.name;
+    let mut holder = Table::new("Holder");
+    holder
+        .row(
+            "Name",
+            r.personal(format!("{}, {}", name.first_name, name.given_name)),
+        )
+        .row(
+            "Transliterated",
+            r.personal(format!(
+                "{}<<{}",
+                name.first_name_transliterated, name.given_name_transliterated
+            )),
+        )
+        .row("Date of birth", r.personal(cert.date_of_birth))
+        .optional(
+            "Age",
+            cert.age_at(now.date_naive()).map(|age| age.bracket()),
+        );
+    r.table(&holder);
+
+    // With several entries, mark the one that the validity is based on
+    let relevant = match cert.entries().count() {
+        0 | 1 => None,
+        _ => cert.relevant_entry(),
+    };
+    for entry in cert.entries() {
+        let title = match entry.kind() {
+            EntryKind::Vaccination => "Vaccination",
+            EntryKind::Test => "Test",
+            EntryKind::Recovery => "Recovery",
+        };
+        let title = if relevant == Some(entry) {
+            format!("{} (most recent, used for validity)", title)
+        } else {
+            title.to_string()
+        };
+        match entry {
+            Entry::Vaccination(v) => r.vaccination(v, &title),
+            Entry::Test(t) => r.test(t, &title),
+            Entry::Recovery(rec) => r.recovery(rec, &title),
+        }
+    }
+
+    let expires = payload.expiration_time.to_string();
+    let expires = if payload.expiration_time <= now {
+        r.paint(RED, &expires)
+    } else {
+        expires
+    };
+    let mut certificate = Table::new("Certificate");
+    certificate
+        .row("Issuer", &payload.issuer)
+        .row("Issued at", payload.issued_at)
+        .row("Expires", expires)
+        .row("Schema version", &cert.version);
+    r.table(&certificate);
+
+    if let Some(signer) = signer {
+        let mut table = Table::new("Signer");
+        table
+            .row("KID", &signer.kid)
+            .optional("Subject", signer.subject.as_ref())
+            .optional("Country", signer.country.as_ref())
+            .optional("Algorithm", signer.algorithm.as_ref())
+            .optional("Not before", signer.not_before)
+            .optional("Not after", signer.not_after)
+            .optional(
+                "Source",
+                Some(signer.sources.join(", ")).filter(|s| !s.is_empty()),
+            );
+        r.table(&table);
+    }
+    r.out
+}
+
+/// Render a report of a SMART Health Card (or VDS-NC), with the verification result
+///
+/// `kind` is the title of the table with the issuer and `signer` identifies the key
+/// that was used, e.g. the kid.
+pub fn render_card(
+    card: &HealthCard,
+    kind: &str,
+    signer: Option<&str>,
+    verification: Option<&Verification>,
+    options: ReportOptions,
+) -> String {
+    let mut r = Renderer {
+        options,
+        out: String::new(),
+    };
+    r.verdict(verification, false);
+
+    let mut holder = Table::new("Holder");
+    holder
+        .optional("Name", card.name.as_ref().map(|name| r.personal(name)))
+        .optional(
+            "Date of birth",
+            card.birth_date.as_ref().map(|date| r.personal(date)),
+        );
+    r.table(&holder);
+
+    for immunization in &card.immunizations {
+        let vaccine = match (&immunization.vaccine_system, &immunization.vaccine_code) {
+            (Some(system), Some(code)) => Some(format!("{} ({})", code, system)),
+            (None, code) => code.clone(),
+            (system, None) => system.clone(),
+        };
+        let mut table = Table::new("Immunization");
+        table
+            .optional("Vaccine", vaccine)
+            .optional("Product", immunization.vaccine_name.as_ref())
+            .optional("Dose", immunization.dose)
+            .optional("Date", immunization.date.as_ref())
+            .optional("Status", immunization.status.as_ref())
+            .optional("Lot", immunization.lot_number.as_ref())
+            .optional("Performer", immunization.performer.as_ref())
+            .optional("Country", immunization.country.as_ref());
+        r.table(&table);
+    }
+
+    let mut issuer = Table::new(kind);
+    issuer
+        .row("Issuer", &card.issuer)
+        .optional("Issued at", card.issued_at)
+        .optional("Signer", signer);
+    r.table(&issuer);
+    r.out
+}
@@ -0,0 +1,192 @@
+//! CertLogic business rules, as distributed by a gateway
+//!
+//! The gateway lists all rules at its base URL as `[{identifier, version, country, hash}]`
+//! and serves each rule (as JSON) at `<url>/<country>/<hash>`, where `hash` is the hex
+//! encoded SHA-256 digest of the rule. The rules are cached in a directory with the list
+//! as `index.json` and each rule as `<country>/<hash>.json`.
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::download::{self, DownloadError};
+
+/// The name of the cached list of rules
+const INDEX: &str = "index.json";
+
+/// An entry of the list of rules of a gateway
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleIdentifier {
+    /// e.g. `VR-DE-0001`
+    pub identifier: String,
+    pub version: String,
+    /// The ISO 3166-1 alpha-2 code of the country that defines the rule
+    pub country: String,
+    /// The hex encoded SHA-256 digest of the rule
+    pub hash: String,
+}
+
+impl RuleIdentifier {
+    /// The path of the cached rule within `dir`
+    fn path(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.country)
+            .join(format!("{}.json", self.hash.to_lowercase()))
+    }
+
+    /// Check that `data` is the rule with this hash
+    fn check(&self, data: &[u8]) -> Result<(), RulesError> {
+        if crate::crypto::sha256_hex(data).eq_ignore_ascii_case(&self.hash) {
+            Ok(())
+        } else {
+            Err(RulesError::HashMismatch(self.identifier.clone()))
+        }
+    }
+
+    /// A country code and hash can be used as path components
+    fn is_safe(&self) -> bool {
+        let alphanumeric = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric());
+        alphanumeric(&self.country) && alphanumeric(&self.hash)
+    }
+}
+
+#[derive(Debug)]
+pub enum RulesError {
+    Download(DownloadError),
+    Json(serde_json::Error),
+    Io(io::Error),
+    /// The rule with this identifier does not match its hash
+    HashMismatch(String),
+    /// The list contains a country or hash that can't be used as a file name
+    InvalidEntry(String),
+}
+
+impl Error for RulesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Download(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::HashMismatch(_) | Self::InvalidEntry(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Download(e) => write!(f, "{}", e),
+            Self::Json(e) => write!(f, "Invalid rule list: {}", e),
+            Self::Io(e) => write!(f, "Failed to store rules: {}", e),
+            Self::HashMismatch(id) => write!(f, "Rule '{}' does not match its hash", id),
+            Self::InvalidEntry(id) => write!(f, "Invalid entry '{}' in the rule list", id),
+        }
+    }
+}
+
+impl From<DownloadError> for RulesError {
+    fn from(e: DownloadError) -> Self {
+        Self::Download(e)
+    }
+}
+
+impl From<io::Error> for RulesError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// The outcome of [`update`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RulesUpdate {
+    /// Rules that were downloaded
+    pub downloaded: usize,
+    /// Rules that were already cached
+    pub unchanged: usize,
+    /// Cached rules that are no longer listed
+    pub removed: usize,
+}
+
+/// Download the rules of `countries` (or all countries, if empty) from `url` to `dir`
+///
+/// Rules that are already cached with the same hash are not downloaded again. The cached
+/// list is only replaced once all rules were downloaded and checked against their hash,
+/// so a failed update keeps the previous rules.
+pub fn update(
+    agent: &ureq::Agent,
+    url: &str,
+    dir: &Path,
+    countries: &[String],
+) -> Result<RulesUpdate, RulesError> {
+    let url = url.trim_end_matches('/');
+    let list = download::fetch(agent, url)?;
+    let mut rules: Vec<RuleIdentifier> = serde_json::from_slice(&list).map_err(RulesError::Json)?;
+    rules.retain(|rule| {
+        countries.is_empty()
+            || countries
+                .iter()
+                .any(|country| country.eq_ignore_ascii_case(&rule.country))
+    });
+
+    let mut result = RulesUpdate::default();
+    for rule in &rules {
+        if !rule.is_safe() {
+            return Err(RulesError::InvalidEntry(rule.identifier.clone()));
+        }
+        let path = rule.path(dir);
+        let cached = std::fs::read(&path).ok();
+        if cached.is_some_and(|data| rule.check(&data).is_ok()) {
+            result.unchanged += 1;
+            continue;
+        }
+        debug!("Downloading rule '{}' ({})", rule.identifier, rule.version);
+        let data = download::fetch(agent, &format!("{}/{}/{}", url, rule.country, rule.hash))?;
+        rule.check(&data)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        download::write_atomic(&path, &data)?;
+        result.downloaded += 1;
+    }
+
+    let previous = read_index(dir).unwrap_or_default();
+    let current: BTreeSet<PathBuf> = rules.iter().map(|rule| rule.path(dir)).collect();
+    let index = serde_json::to_vec_pretty(&rules).map_err(RulesError::Json)?;
+    std::fs::create_dir_all(dir)?;
+    download::write_atomic(&dir.join(INDEX), &index)?;
+    for rule in previous.iter().filter(|rule| rule.is_safe()) {
+        let path = rule.path(dir);
+        if !current.contains(&path) && std::fs::remove_file(&path).is_ok() {
+            result.removed += 1;
+        }
+    }
+    info!(
+        "{} rules downloaded, {} unchanged, {} removed",
+        result.downloaded, result.unchanged, result.removed
+    );
+    Ok(result)
+}
+
+fn read_index(dir: &Path) -> Result<Vec<RuleIdentifier>, RulesError> {
+    let index = std::fs::read(dir.join(INDEX))?;
+    serde_json::from_slice(&index).map_err(RulesError::Json)
+}
+
+/// Load the cached rules of a country, each checked against its hash
+pub fn load(dir: &Path, country: &str) -> Result<Vec<(RuleIdentifier, Value)>, RulesError> {
+    let rules = read_index(dir)?;
+    let rules = rules
+        .into_iter()
+        .filter(|rule| rule.country.eq_ignore_ascii_case(country) && rule.is_safe());
+    let mut out = Vec::new();
+    for rule in rules {
+        let data = std::fs::read(rule.path(dir))?;
+        rule.check(&data)?;
+        let value = serde_json::from_slice(&data).map_err(RulesError::Json)?;
+        out.push((rule, value));
+    }
+    Ok(out)
+}
@@ -0,0 +1,217 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    cose::Headers, countersign::CountersignatureInfo, crypto::sha256_hex, dcc::Warning,
+    spec::Finding, trust::cert_files, verify::Verification,
+};
+
+/// Current version of the session file format
+///
+/// Bump this whenever the recorded options or results change their shape, sessions of
+/// other versions are rejected instead of being compared field by field.
+pub const SESSION_VERSION: u32 = 2;
+
+/// A recorded run of the decoder, which can be replayed later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Version of the session file format
+    pub version: u32,
+    /// When the session was recorded
+    pub recorded_at: DateTime<Utc>,
+    /// The exact input bytes (base64)
+    pub input: String,
+    /// Identifiers of the trust data that was in use
+    pub trust: Vec<TrustSnapshot>,
+    /// The options of the run
+    pub options: serde_json::Value,
+    /// The results of the run
+    pub result: SessionResult,
+}
+
+impl Session {
+    pub fn new(
+        input: &[u8],
+        trust: Vec<TrustSnapshot>,
+        options: serde_json::Value,
+        result: SessionResult,
+    ) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            recorded_at: Utc::now(),
+            input: base64::encode(input),
+            trust,
+            options,
+            result,
+        }
+    }
+
+    /// Read a session file, rejecting other versions of the format before the rest is read
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> color_eyre::Result<Self> {
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+
+        let text = std::fs::read_to_string(path)?;
+        let Version { version } = serde_json::from_str(&text)?;
+        if version != SESSION_VERSION {
+            return Err(eyre!(
+                "Unsupported session file version {} (expected {})",
+                version,
+                SESSION_VERSION
+            ));
+        }
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn input_bytes(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        base64::decode(&self.input)
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> color_eyre::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Check whether the trust data on disk is still the same as when recording
+    pub fn check_trust(&self) -> bool {
+        let mut same = true;
+        for recorded in &self.trust {
+            let current = TrustSnapshot::of_path(&recorded.path);
+            if current.sha256 != recorded.sha256 {
+                warn!(
+                    "Trust data '{}' changed since recording (was {:?}, is {:?})",
+                    recorded.path, recorded.sha256, current.sha256
+                );
+                same = false;
+            }
+        }
+        same
+    }
+}
+
+/// Identifies the state of a trust data file (e.g. the trustlist) or directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustSnapshot {
+    pub path: String,
+    /// Hex-encoded SHA-256 of the file contents, if it exists
+    ///
+    /// For a directory, this is the hash of the names and contents of all certificate files.
+    pub sha256: Option<String>,
+}
+
+impl TrustSnapshot {
+    /// Snapshot a file or a directory of certificates
+    pub fn of_path<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Self::of_dir(path)
+        } else {
+            Self::of_file(path)
+        }
+    }
+
+    pub fn of_file<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        let sha256 = std::fs::read(path).ok().map(|bytes| sha256_hex(&bytes));
+        Self {
+            path: path.display().to_string(),
+            sha256,
+        }
+    }
+
+    pub fn of_dir<P: AsRef<Path>>(dir: P) -> Self {
+        let dir = dir.as_ref();
+        let sha256 = cert_files(dir).ok().and_then(|files| {
+            let mut data = Vec::new();
+            for file in files {
+                let name = file.strip_prefix(dir).unwrap_or(&file);
+                data.extend(name.to_string_lossy().as_bytes());
+                data.extend(std::fs::read(&file).ok()?);
+            }
+            Some(sha256_hex(&data))
+        });
+        Self {
+            path: dir.display().to_string(),
+            sha256,
+        }
+    }
+}
+
+/// The results of a single run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionResult {
+    /// The base64 encoded kid of the COSE message
+    pub kid: Option<String>,
+    /// The header parameters of the COSE message
+    pub headers: Option<Headers>,
+    /// The issuer and validity period of the CWT
+    pub claims: Option<CwtClaims>,
+    /// The JSON version of the DCC
+    pub certificate: Option<serde_json::Value>,
+    /// The outcome of the signature verification
+    pub verification: Option<Verification>,
+    /// The violated rules, with references to the specification
+    pub findings: Vec<Finding>,
+    /// The non-fatal issues found while decoding
+    pub warnings: Vec<Warning>,
+    /// The countersignatures of the COSE message, with the outcome of their check
+    pub countersignatures: Vec<CountersignatureInfo>,
+    /// The error that stopped the run
+    pub error: Option<String>,
+}
+
+impl SessionResult {
+    /// The first entry of each type in the certificate, with its key (`v`, `t` or `r`)
+    pub fn entries(&self) -> Vec<(&'static str, &serde_json::Value)> {
+        let certificate = match &self.certificate {
+            Some(certificate) => certificate,
+            None => return Vec::new(),
+        };
+        ["v", "t", "r"]
+            .iter()
+            .filter_map(|key| {
+                let entry = certificate.get(*key)?.as_array()?.first()?;
+                Some((*key, entry))
+            })
+            .collect()
+    }
+
+    /// The outcome of the signature check, `error` if the run stopped before it and
+    /// `unsigned` for a bare CWT payload
+    pub fn signature_status(&self) -> &'static str {
+        match (&self.verification, &self.error) {
+            (Some(verification), _) => verification.status(),
+            (None, Some(_)) => "error",
+            (None, None) => "unsigned",
+        }
+    }
+
+    /// The unique certificate identifier (UVCI) of the first entry
+    pub fn uvci(&self) -> Option<&str> {
+        self.entries()
+            .into_iter()
+            .find_map(|(_, entry)| entry.get("ci")?.as_str())
+    }
+}
+
+/// The claims of the CWT around the DCC
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CwtClaims {
+    /// The issuing country (`iss`)
+    pub issuer: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: DateTime<Utc>,
+}
+
+impl CwtClaims {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expiration_time <= now
+    }
+}
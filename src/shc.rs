@@ -0,0 +1,447 @@
+//! SMART Health Cards (`shc:/` QR code payloads)
+//!
+//! The numeric QR code content encodes a compact JWS, whose payload is a raw DEFLATE
+//! compressed JSON Web Token with a FHIR bundle inside.
+use std::{error::Error, fmt, io::Read};
+
+use chrono::{DateTime, TimeZone, Utc};
+use flate2::read::DeflateDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, instrument};
+
+/// Prefix of a SMART Health Card in a QR code
+pub const SHC_PREFIX: &str = "shc:/";
+
+#[derive(Debug)]
+pub enum ShcError {
+    MissingPrefix,
+    /// An invalid pair of digits at the given offset in the numeric content
+    Numeric(usize),
+    /// A QR code that is one chunk of several (`shc:/1/2/...`)
+    Chunked,
+    Jws(&'static str),
+    Base64(base64::DecodeError),
+    Inflate(std::io::Error),
+    TooLarge(u64),
+    Json(serde_json::Error),
+}
+
+impl Error for ShcError {}
+impl fmt::Display for ShcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingPrefix => write!(f, "Missing '{}' prefix", SHC_PREFIX),
+            Self::Numeric(offset) => write!(f, "Invalid numeric content at position {}", offset),
+            Self::Chunked => write!(
+                f,
+                "Health cards split into several QR codes are not supported"
+            ),
+            Self::Jws(msg) => write!(f, "Invalid JWS: {}", msg),
+            Self::Base64(e) => write!(f, "Base64 decoding failed: {}", e),
+            Self::Inflate(e) => write!(f, "DEFLATE decoding failed: {}", e),
+            Self::TooLarge(max) => write!(f, "Decompressed payload exceeds {} bytes", max),
+            Self::Json(e) => write!(f, "Invalid JSON: {}", e),
+        }
+    }
+}
+
+/// Check whether the text is a SMART Health Card
+pub fn is_shc(text: &str) -> bool {
+    text.trim_start().starts_with(SHC_PREFIX)
+}
+
+/// Decode the numeric content of a `shc:/` QR code into the compact JWS
+///
+/// Each pair of digits is a character of the JWS, offset by 45 (`-`).
+pub fn decode_numeric(text: &str) -> Result<String, ShcError> {
+    let digits = text
+        .trim()
+        .strip_prefix(SHC_PREFIX)
+        .ok_or(ShcError::MissingPrefix)?;
+    if digits.contains('/') {
+        return Err(ShcError::Chunked);
+    }
+    let start = SHC_PREFIX.len();
+    digits
+        .as_bytes()
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let offset = start + 2 * i;
+            match pair {
+                [a, b] if a.is_ascii_digit() && b.is_ascii_digit() => {
+                    let n = (a - b'0') * 10 + (b - b'0');
+                    if n > b'z' - b'-' {
+                        return Err(ShcError::Numeric(offset));
+                    }
+                    Ok(char::from(n + b'-'))
+                }
+                _ => Err(ShcError::Numeric(offset)),
+            }
+        })
+        .collect()
+}
+
+/// Encode a compact JWS as the numeric content of a `shc:/` QR code
+pub fn encode_numeric(jws: &str) -> String {
+    let mut out = String::with_capacity(SHC_PREFIX.len() + 2 * jws.len());
+    out.push_str(SHC_PREFIX);
+    for b in jws.bytes() {
+        out.push_str(&format!("{:02}", b - b'-'));
+    }
+    out
+}
+
+/// The protected header of the JWS
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JwsHeader {
+    pub alg: String,
+    pub kid: Option<String>,
+    /// `DEF` if the payload is compressed
+    pub zip: Option<String>,
+}
+
+/// A compact JWS with its payload inflated
+#[derive(Debug, Clone)]
+pub struct Jws {
+    pub header: JwsHeader,
+    /// The (decompressed) payload
+    pub payload: Vec<u8>,
+    /// The signed bytes, `<header>.<payload>` as in the compact serialization
+    pub signing_input: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn base64url(text: &str) -> Result<Vec<u8>, ShcError> {
+    base64::decode_config(text, base64::URL_SAFE_NO_PAD).map_err(ShcError::Base64)
+}
+
+#[cfg(all(feature = "verify", feature = "ring"))]
+fn to_base64url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+impl Jws {
+    /// Parse a compact JWS, inflating the payload up to `max_size` bytes
+    #[instrument(name = "jws", level = "debug", skip_all)]
+    pub fn parse(text: &str, max_size: u64) -> Result<Self, ShcError> {
+        let mut parts = text.split('.');
+        let (header, payload, signature) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(h), Some(p), Some(s)) if parts.next().is_none() => (h, p, s),
+            _ => return Err(ShcError::Jws("expected three parts")),
+        };
+        let header_json: JwsHeader =
+            serde_json::from_slice(&base64url(header)?).map_err(ShcError::Json)?;
+        let mut body = base64url(payload)?;
+        if header_json.zip.as_deref() == Some("DEF") {
+            let mut inflated = Vec::new();
+            DeflateDecoder::new(&body[..])
+                .take(max_size + 1)
+                .read_to_end(&mut inflated)
+                .map_err(ShcError::Inflate)?;
+            if inflated.len() as u64 > max_size {
+                return Err(ShcError::TooLarge(max_size));
+            }
+            debug!("Inflated {} to {} bytes", body.len(), inflated.len());
+            body = inflated;
+        }
+        Ok(Self {
+            header: header_json,
+            payload: body,
+            signing_input: text.as_bytes()[..header.len() + 1 + payload.len()].to_vec(),
+            signature: base64url(signature)?,
+        })
+    }
+
+    /// The kid from the header, decoded from base64url
+    pub fn kid(&self) -> Option<Vec<u8>> {
+        base64url(self.header.kid.as_deref()?).ok()
+    }
+}
+
+/// The JWT claims of a health card
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShcPayload {
+    /// The URL of the issuer, whose keys are at `<iss>/.well-known/jwks.json`
+    pub iss: String,
+    /// The issuance time, in seconds since the epoch
+    pub nbf: Option<f64>,
+    pub vc: VerifiableCredential,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiableCredential {
+    #[serde(rename = "type", default)]
+    pub types: Vec<String>,
+    #[serde(rename = "credentialSubject")]
+    pub credential_subject: CredentialSubject,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialSubject {
+    #[serde(rename = "fhirVersion")]
+    pub fhir_version: Option<String>,
+    #[serde(rename = "fhirBundle")]
+    pub fhir_bundle: Value,
+}
+
+/// The credential types of a COVID-19 vaccination card
+const VACCINATION_TYPES: &[&str] = &[
+    "https://smarthealth.cards#health-card",
+    "https://smarthealth.cards#immunization",
+    "https://smarthealth.cards#covid19",
+];
+
+impl ShcPayload {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ShcError> {
+        serde_json::from_slice(bytes).map_err(ShcError::Json)
+    }
+
+    /// Re-issue the vaccinations of a DCC as a health card from `issuer`
+    ///
+    /// The resources are those of the [FHIR mapping](crate::fhir), without the ids and
+    /// with `resource:N` references, as the health card format requires.
+    pub fn from_certificate(
+        payload: &crate::dcc::CertPayload,
+        issuer: &str,
+        issued_at: DateTime<Utc>,
+    ) -> Self {
+        let mut bundle = crate::fhir::bundle(payload);
+        if let Some(bundle) = bundle.as_object_mut() {
+            bundle.remove("timestamp");
+        }
+        let entries = bundle["entry"].as_array_mut().into_iter().flatten();
+        for (i, entry) in entries.enumerate() {
+            entry["fullUrl"] = json!(format!("resource:{}", i));
+            let resource = &mut entry["resource"];
+            if let Some(resource) = resource.as_object_mut() {
+                resource.remove("id");
+            }
+            if resource.get("patient").is_some() {
+                resource["patient"] = json!({ "reference": "resource:0" });
+            }
+        }
+        Self {
+            iss: issuer.to_string(),
+            nbf: Some(issued_at.timestamp() as f64),
+            vc: VerifiableCredential {
+                types: VACCINATION_TYPES.iter().map(|t| t.to_string()).collect(),
+                credential_subject: CredentialSubject {
+                    fhir_version: Some("4.0.1".to_string()),
+                    fhir_bundle: bundle,
+                },
+            },
+        }
+    }
+}
+
+/// The contents of a health card, in the shape used for the output
+///
+/// This is also used for other formats with vaccination data, e.g. a
+/// [VDS-NC](crate::vds).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthCard {
+    /// The issuer URL, or the issuing country of a VDS-NC
+    pub issuer: String,
+    pub issued_at: Option<DateTime<Utc>>,
+    pub name: Option<String>,
+    pub birth_date: Option<String>,
+    pub immunizations: Vec<Immunization>,
+}
+
+/// An `Immunization` resource of the FHIR bundle
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Immunization {
+    pub status: Option<String>,
+    /// The system and code of the vaccine, e.g. a CVX code
+    pub vaccine_system: Option<String>,
+    pub vaccine_code: Option<String>,
+    pub vaccine_name: Option<String>,
+    pub date: Option<String>,
+    /// The number of the dose in the series
+    pub dose: Option<u32>,
+    pub lot_number: Option<String>,
+    pub performer: Option<String>,
+    pub country: Option<String>,
+}
+
+fn text(value: &Value, pointer: &str) -> Option<String> {
+    value.pointer(pointer)?.as_str().map(str::to_string)
+}
+
+impl HealthCard {
+    /// Collect the patient and immunizations from the FHIR bundle
+    pub fn from_payload(payload: &ShcPayload) -> Self {
+        let mut card = HealthCard {
+            issuer: payload.iss.clone(),
+            issued_at: payload
+                .nbf
+                .and_then(|nbf| Utc.timestamp_opt(nbf as i64, 0).single()),
+            ..Self::default()
+        };
+        let bundle = &payload.vc.credential_subject.fhir_bundle;
+        let resources = bundle["entry"].as_array().into_iter().flatten();
+        for resource in resources.map(|entry| &entry["resource"]) {
+            match resource["resourceType"].as_str() {
+                Some("Patient") => {
+                    let name = &resource["name"][0];
+                    let given = name["given"].as_array().into_iter().flatten();
+                    let mut parts: Vec<&str> = given.filter_map(Value::as_str).collect();
+                    parts.extend(name["family"].as_str());
+                    if !parts.is_empty() {
+                        card.name = Some(parts.join(" "));
+                    }
+                    card.birth_date = text(resource, "/birthDate");
+                }
+                Some("Immunization") => card.immunizations.push(Immunization {
+                    status: text(resource, "/status"),
+                    vaccine_system: text(resource, "/vaccineCode/coding/0/system"),
+                    vaccine_code: text(resource, "/vaccineCode/coding/0/code"),
+                    date: text(resource, "/occurrenceDateTime"),
+                    lot_number: text(resource, "/lotNumber"),
+                    performer: text(resource, "/performer/0/actor/display"),
+                    ..Immunization::default()
+                }),
+                other => debug!("Skipping FHIR resource {:?}", other),
+            }
+        }
+        card
+    }
+}
+
+/// Verify the ES256 signature of a health card against the issuer keys from `trust`
+#[cfg(feature = "verify")]
+#[instrument(name = "verify", level = "debug", skip_all)]
+pub fn verify(
+    jws: &Jws,
+    trust: &dyn crate::trust::TrustSource,
+) -> color_eyre::Result<crate::verify::Verification> {
+    use crate::{trust::SignerKey, verify::Verification};
+    use x509_parser::{parse_x509_certificate, x509::SubjectPublicKeyInfo};
+
+    if jws.header.alg != "ES256" {
+        return Ok(Verification::UnsupportedAlgorithm(jws.header.alg.clone()));
+    }
+    let kid = match jws.kid() {
+        Some(kid) => kid,
+        None => return Ok(Verification::KeyNotFound),
+    };
+    let mut verification = Verification::KeyNotFound;
+    for signer in trust.lookup(&kid) {
+        let point = match &signer.key {
+            SignerKey::Certificate(der) => {
+                let (_, cert) = parse_x509_certificate(der)?;
+                cert.tbs_certificate
+                    .subject_pki
+                    .subject_public_key
+                    .data
+                    .to_vec()
+            }
+            SignerKey::PublicKey(spki) => {
+                let (_, spki) = SubjectPublicKeyInfo::from_der(spki)?;
+                spki.subject_public_key.data.to_vec()
+            }
+        };
+        let alg = crate::crypto::SignatureAlgorithm::EcdsaP256Sha256Fixed;
+        verification = match crate::crypto::verify(alg, &point, &jws.signing_input, &jws.signature)
+        {
+            Ok(()) => return Ok(Verification::Verified),
+            Err(_) => Verification::Invalid,
+        };
+    }
+    Ok(verification)
+}
+
+/// An ES256 key to issue health cards with
+#[cfg(all(feature = "verify", feature = "ring"))]
+pub struct ShcSigner {
+    key_pair: ring::signature::EcdsaKeyPair,
+    kid: String,
+}
+
+#[cfg(all(feature = "verify", feature = "ring"))]
+impl ShcSigner {
+    /// Load a P-256 key from a PKCS#8 document
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self, ring::error::KeyRejected> {
+        let key_pair = ring::signature::EcdsaKeyPair::from_pkcs8(
+            &ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            pkcs8,
+        )?;
+        let mut signer = Self {
+            key_pair,
+            kid: String::new(),
+        };
+        signer.kid = signer.thumbprint();
+        Ok(signer)
+    }
+
+    /// The x and y coordinates of the public key
+    fn coordinates(&self) -> (&[u8], &[u8]) {
+        use ring::signature::KeyPair;
+        // Uncompressed point: 0x04 || x || y
+        let point = &self.key_pair.public_key().as_ref()[1..];
+        point.split_at(32)
+    }
+
+    /// The JWK thumbprint (RFC 7638), which the health card framework uses as the kid
+    fn thumbprint(&self) -> String {
+        let (x, y) = self.coordinates();
+        let members = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            to_base64url(x),
+            to_base64url(y)
+        );
+        to_base64url(&crate::crypto::sha256(members.as_bytes()))
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    /// The JWK set with the public key, to verify the issued cards with
+    pub fn jwks(&self) -> Value {
+        let (x, y) = self.coordinates();
+        json!({
+            "keys": [{
+                "kty": "EC",
+                "kid": self.kid,
+                "use": "sig",
+                "alg": "ES256",
+                "crv": "P-256",
+                "x": to_base64url(x),
+                "y": to_base64url(y),
+            }]
+        })
+    }
+
+    /// Sign the payload as a compact JWS, with the payload compressed
+    pub fn sign(&self, payload: &ShcPayload) -> color_eyre::Result<String> {
+        use flate2::{write::DeflateEncoder, Compression};
+        use std::io::Write;
+
+        let header = JwsHeader {
+            alg: "ES256".to_string(),
+            kid: Some(self.kid.clone()),
+            zip: Some("DEF".to_string()),
+        };
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&serde_json::to_vec(payload)?)?;
+        let body = encoder.finish()?;
+        let signing_input = format!(
+            "{}.{}",
+            to_base64url(&serde_json::to_vec(&header)?),
+            to_base64url(&body)
+        );
+        let rng = ring::rand::SystemRandom::new();
+        let signature = self
+            .key_pair
+            .sign(&rng, signing_input.as_bytes())
+            .map_err(|_| color_eyre::eyre::eyre!("Failed to sign the health card"))?;
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            to_base64url(signature.as_ref())
+        ))
+    }
+}
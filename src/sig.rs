@@ -1,7 +1,18 @@
-use asn1_der::{Asn1DerError, Asn1DerErrorVariant, typed::{DerDecodable, DerEncodable, Integer, Sequence}};
+//! Minimal DER types for public keys and ECDSA signatures
+use std::fmt;
+
+use asn1_der::{
+    typed::{DerDecodable, DerEncodable, DerTypeView, Integer, Sequence},
+    Asn1DerError, Asn1DerErrorVariant, DerObject,
+};
 
 use crate::cert::{Algorithm, Prime};
 
+/// DER tag of an OBJECT IDENTIFIER
+const TAG_OID: u8 = 0x06;
+/// DER tag of a BIT STRING
+const TAG_BIT_STRING: u8 = 0x03;
+
 pub struct EcdsaSigValue<'a> {
     r: Int<'a>,
     s: Int<'a>,
@@ -18,65 +29,403 @@ impl DerEncodable for Int<'_> {
 
 impl<'a> EcdsaSigValue<'a> {
     pub fn new(r: &'a [u8], s: &'a [u8]) -> Self {
-        Self { r: Int(r), s: Int(s) }
+        Self {
+            r: Int(r),
+            s: Int(s),
+        }
+    }
+
+    /// Split a fixed size `r || s` signature (as in COSE or JWS)
+    pub fn from_fixed(sig: &'a [u8]) -> Result<Self, Asn1DerError> {
+        if sig.is_empty() || !sig.len().is_multiple_of(2) {
+            return Err(invalid("fixed size signature of odd length"));
+        }
+        let (r, s) = sig.split_at(sig.len() / 2);
+        Ok(Self::new(r, s))
+    }
+
+    /// The fixed size `r || s` form, with both padded to the size of `prime`
+    pub fn to_fixed(&self, prime: Prime) -> Result<Vec<u8>, Asn1DerError> {
+        let len = prime.scalar_len();
+        let mut fixed = Vec::with_capacity(2 * len);
+        for int in [self.r, self.s] {
+            let value = int.value();
+            if value.len() > len {
+                return Err(invalid("signature value too large for the curve"));
+            }
+            fixed.resize(fixed.len() + len - value.len(), 0);
+            fixed.extend_from_slice(value);
+        }
+        Ok(fixed)
+    }
+
+    /// The DER encoded `ECDSA-Sig-Value` (as in X.509 or from openssl)
+    pub fn to_der(&self) -> Result<Vec<u8>, Asn1DerError> {
+        let mut der = Vec::new();
+        self.encode(&mut der)?;
+        Ok(der)
+    }
+}
+
+impl<'a> Int<'a> {
+    /// The big-endian value without leading zeros
+    fn value(self) -> &'a [u8] {
+        let zeros = self.0.iter().take_while(|&&b| b == 0).count();
+        &self.0[zeros..]
     }
 }
 
-impl<'a> DerEncodable for EcdsaSigValue<'a> {
+impl DerEncodable for EcdsaSigValue<'_> {
     fn encode<S: asn1_der::Sink>(&self, sink: &mut S) -> Result<(), Asn1DerError> {
         Sequence::write(&[self.r, self.s], sink)
     }
 }
 
+impl<'a> DerDecodable<'a> for EcdsaSigValue<'a> {
+    fn load(object: DerObject<'a>) -> Result<Self, Asn1DerError> {
+        let seq = Sequence::load(object)?;
+        if seq.len() != 2 {
+            return Err(invalid("expected a sequence of r and s"));
+        }
+        let int = |n| -> Result<&'a [u8], Asn1DerError> {
+            let int = seq.get_as::<Integer>(n)?;
+            if int.is_negative() {
+                return Err(invalid("negative signature value"));
+            }
+            // The leading zero of positive values is skipped by `Int::value`
+            Ok(int.object().value())
+        };
+        Ok(Self::new(int(0)?, int(1)?))
+    }
+}
+
+/// Convert a DER encoded ECDSA signature to the fixed size `r || s` form for `prime`
+pub fn der_to_fixed(der: &[u8], prime: Prime) -> Result<Vec<u8>, Asn1DerError> {
+    EcdsaSigValue::decode(der)?.to_fixed(prime)
+}
+
+/// The fixed size form of `sig` if it is a DER encoded ECDSA signature for `prime`
+///
+/// Returns `None` for signatures that already have the fixed size or are not DER.
+pub fn der_signature_to_fixed(sig: &[u8], prime: Prime) -> Option<Vec<u8>> {
+    if sig.len() == 2 * prime.scalar_len() || sig.first() != Some(&0x30) {
+        return None;
+    }
+    der_to_fixed(sig, prime).ok()
+}
+
+/// Convert a fixed size `r || s` ECDSA signature to DER
+pub fn fixed_to_der(sig: &[u8]) -> Result<Vec<u8>, Asn1DerError> {
+    EcdsaSigValue::from_fixed(sig)?.to_der()
+}
+
+fn invalid(msg: &'static str) -> Asn1DerError {
+    Asn1DerError::new(Asn1DerErrorVariant::InvalidData(msg))
+}
+
+fn unsupported(msg: &'static str) -> Asn1DerError {
+    Asn1DerError::new(Asn1DerErrorVariant::Unsupported(msg))
+}
+
+/// An object identifier of an algorithm or curve
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ObjectIdentifier {
     /// `id-ecPublicKey`
     IdEcPublicKey,
-    /// `prime256v1`
-    Prime256v1
+    /// `rsaEncryption`
+    RsaEncryption,
+    /// A named elliptic curve, e.g. `prime256v1`
+    Curve(Prime),
+    /// Any other OID, as its arcs
+    Other(Vec<u64>),
+}
+
+impl ObjectIdentifier {
+    /// The known OIDs, with their arcs
+    const KNOWN: &'static [(&'static [u64], Self)] = &[
+        (&[1, 2, 840, 10045, 2, 1], Self::IdEcPublicKey),
+        (&[1, 2, 840, 113549, 1, 1, 1], Self::RsaEncryption),
+        (&[1, 2, 840, 10045, 3, 1, 7], Self::Curve(Prime::Prime256v1)),
+        (&[1, 3, 132, 0, 34], Self::Curve(Prime::Secp384r1)),
+        (&[1, 3, 132, 0, 35], Self::Curve(Prime::Secp521r1)),
+        (
+            &[1, 3, 36, 3, 3, 2, 8, 1, 1, 7],
+            Self::Curve(Prime::BrainpoolP256r1),
+        ),
+        (
+            &[1, 3, 36, 3, 3, 2, 8, 1, 1, 11],
+            Self::Curve(Prime::BrainpoolP384r1),
+        ),
+    ];
+
+    /// Match the arcs of an OID against the known ones
+    pub fn from_arcs(arcs: Vec<u64>) -> Self {
+        Self::KNOWN
+            .iter()
+            .find(|(known, _)| *known == arcs.as_slice())
+            .map(|(_, oid)| oid.clone())
+            .unwrap_or(Self::Other(arcs))
+    }
+
+    /// The arcs of this OID
+    pub fn arcs(&self) -> &[u64] {
+        match self {
+            Self::Other(arcs) => arcs,
+            known => Self::KNOWN
+                .iter()
+                .find(|(_, oid)| oid == known)
+                .map(|(arcs, _)| *arcs)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for ObjectIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arcs: Vec<String> = self.arcs().iter().map(u64::to_string).collect();
+        f.write_str(&arcs.join("."))
+    }
+}
+
+/// Decode the contents of an OBJECT IDENTIFIER into its arcs
+///
+/// Each component is base-128 with the high bit set on all but its last byte. The first
+/// one combines the first two arcs as `40 * x + y`.
+pub fn decode_oid_arcs(value: &[u8]) -> Result<Vec<u64>, Asn1DerError> {
+    if value.is_empty() {
+        return Err(invalid("empty object id"));
+    }
+    let mut components = Vec::new();
+    let mut current: u64 = 0;
+    let mut start = true;
+    for &byte in value {
+        if start && byte == 0x80 {
+            return Err(invalid("non-minimal object id component"));
+        }
+        if current > u64::MAX >> 7 {
+            return Err(unsupported("object id component too large"));
+        }
+        current = current << 7 | u64::from(byte & 0x7f);
+        start = byte & 0x80 == 0;
+        if start {
+            components.push(current);
+            current = 0;
+        }
+    }
+    if !start {
+        return Err(invalid("truncated object id component"));
+    }
+
+    let first = components[0];
+    let (x, y) = match first {
+        0..=39 => (0, first),
+        40..=79 => (1, first - 40),
+        _ => (2, first - 80),
+    };
+    let mut arcs = vec![x, y];
+    arcs.extend_from_slice(&components[1..]);
+    Ok(arcs)
 }
 
 impl<'a> DerDecodable<'a> for ObjectIdentifier {
-    fn load(object: asn1_der::DerObject<'a>) -> Result<Self, Asn1DerError> {
-        if object.tag() == 0x06 {
-            todo!();
+    fn load(object: DerObject<'a>) -> Result<Self, Asn1DerError> {
+        if object.tag() == TAG_OID {
+            decode_oid_arcs(object.value()).map(Self::from_arcs)
         } else {
-            Err(Asn1DerError::new(Asn1DerErrorVariant::InvalidData("expected object id tag 0x06")))
+            Err(invalid("expected object id tag 0x06"))
         }
     }
 }
 
 impl<'a> DerDecodable<'a> for Algorithm {
-    fn load(object: asn1_der::DerObject<'a>) -> Result<Self, Asn1DerError> {
+    fn load(object: DerObject<'a>) -> Result<Self, Asn1DerError> {
         let seq = Sequence::load(object)?;
         let alg_obj_id = seq.get_as::<ObjectIdentifier>(0)?;
         match alg_obj_id {
-            ObjectIdentifier::IdEcPublicKey => {
-                let prime_obj_id = seq.get_as::<ObjectIdentifier>(1)?;
-                if let ObjectIdentifier::Prime256v1 = prime_obj_id {
-                    Ok(Algorithm::IdEcPublicKey(Prime::Prime256v1))
-                } else {
-                    Err(Asn1DerError::new(Asn1DerErrorVariant::Unsupported("prime object id")))
-                }
-                
-            }
-            _ => Err(Asn1DerError::new(Asn1DerErrorVariant::Unsupported("algorithm object id"))),
+            ObjectIdentifier::IdEcPublicKey => match seq.get_as::<ObjectIdentifier>(1)? {
+                ObjectIdentifier::Curve(prime) => Ok(Algorithm::IdEcPublicKey(prime)),
+                _ => Err(unsupported("prime object id")),
+            },
+            ObjectIdentifier::RsaEncryption => Ok(Algorithm::RsaEncryption),
+            _ => Err(unsupported("algorithm object id")),
         }
     }
 }
 
+/// A `SubjectPublicKeyInfo`
 pub struct PublicKey {
     pub algorithm: Algorithm,
+    /// The content of the `subjectPublicKey` bit string
     pub data: Vec<u8>,
 }
 
 impl<'a> DerDecodable<'a> for PublicKey {
-    fn load(object: asn1_der::DerObject<'a>) -> Result<Self, Asn1DerError> {
+    fn load(object: DerObject<'a>) -> Result<Self, Asn1DerError> {
         let seq = Sequence::load(object)?;
         let algorithm = seq.get_as(0)?;
+        let key = seq.get(1)?;
+        if key.tag() != TAG_BIT_STRING {
+            return Err(invalid("expected bit string tag 0x03"));
+        }
+        let data = match key.value().split_first() {
+            Some((0, data)) => data.to_vec(),
+            Some(_) => return Err(unsupported("bit string with unused bits")),
+            None => return Err(invalid("empty bit string")),
+        };
+
+        Ok(PublicKey { algorithm, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asn1_der::typed::DerDecodable;
+
+    use super::{
+        decode_oid_arcs, der_signature_to_fixed, der_to_fixed, fixed_to_der, EcdsaSigValue,
+        ObjectIdentifier,
+    };
+    use crate::cert::Prime;
+
+    /// A P-256 scalar of `len` bytes, starting with `first` followed by `0x11`
+    fn scalar(first: u8, len: usize) -> Vec<u8> {
+        let mut scalar = vec![0x11; len];
+        scalar[0] = first;
+        scalar
+    }
+
+    /// A DER `ECDSA-Sig-Value` from the contents of the integers `r` and `s`
+    fn der(r: &[u8], s: &[u8]) -> Vec<u8> {
+        let mut der = vec![0x30, (4 + r.len() + s.len()) as u8];
+        for int in [r, s] {
+            der.extend_from_slice(&[0x02, int.len() as u8]);
+            der.extend_from_slice(int);
+        }
+        der
+    }
+
+    #[test]
+    fn leading_zero() {
+        // r has a leading zero byte in the fixed form, and is shorter in DER
+        let (r, s) = (scalar(0x00, 32), scalar(0x7f, 32));
+        let fixed = [r.as_slice(), &s].concat();
+        let der = fixed_to_der(&fixed).unwrap();
+        assert_eq!(der, self::der(&r[1..], &s));
+        assert_eq!(der_to_fixed(&der, Prime::Prime256v1).unwrap(), fixed);
+        assert_eq!(der_signature_to_fixed(&der, Prime::Prime256v1), Some(fixed));
+    }
+
+    #[test]
+    fn high_bit() {
+        // Positive integers with the high bit set are padded with a zero byte in DER
+        let (r, s) = (scalar(0x80, 32), scalar(0xff, 32));
+        let fixed = [r.as_slice(), &s].concat();
+        let der = fixed_to_der(&fixed).unwrap();
+        let padded = |int: &[u8]| [&[0x00], int].concat();
+        assert_eq!(der, self::der(&padded(&r), &padded(&s)));
+        assert_eq!(der_to_fixed(&der, Prime::Prime256v1).unwrap(), fixed);
+        assert_eq!(der_signature_to_fixed(&der, Prime::Prime256v1), Some(fixed));
+    }
 
-        Ok(PublicKey {
-            algorithm,
-            data: vec![],
-        })
+    #[test]
+    fn short_values() {
+        // Values shorter than the scalar size are padded in the fixed form
+        let (r, s) = (scalar(0x42, 30), scalar(0x01, 1));
+        let fixed = der_to_fixed(&der(&r, &s), Prime::Prime256v1).unwrap();
+        assert_eq!(fixed.len(), 64);
+        assert_eq!(&fixed[..2], [0, 0]);
+        assert_eq!(&fixed[2..32], r.as_slice());
+        assert_eq!(&fixed[32..63], [0; 31]);
+        assert_eq!(fixed[63], 0x01);
+
+        let sig = EcdsaSigValue::from_fixed(&fixed).unwrap();
+        assert_eq!(sig.to_fixed(Prime::Prime256v1).unwrap(), fixed);
+        assert_eq!(sig.to_der().unwrap(), der(&r, &s));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn oversized_values() {
+        // 33 bytes without a leading zero do not fit into a P-256 scalar
+        let der = der(&scalar(0x42, 33), &scalar(0x42, 32));
+        assert!(der_to_fixed(&der, Prime::Prime256v1).is_err());
+        assert_eq!(der_signature_to_fixed(&der, Prime::Prime256v1), None);
+        assert_eq!(der_to_fixed(&der, Prime::Secp384r1).unwrap().len(), 96);
+
+        let fixed = [scalar(0x42, 48), scalar(0x42, 48)].concat();
+        let sig = EcdsaSigValue::from_fixed(&fixed).unwrap();
+        assert!(sig.to_fixed(Prime::Prime256v1).is_err());
+        assert_eq!(sig.to_fixed(Prime::Secp384r1).unwrap(), fixed);
+    }
+
+    #[test]
+    fn invalid_signatures() {
+        assert!(EcdsaSigValue::from_fixed(&[]).is_err());
+        assert!(EcdsaSigValue::from_fixed(&[0x42; 63]).is_err());
+        // Negative integers, a missing s and a truncated sequence
+        assert!(der_to_fixed(&der(&[0x80], &[0x01]), Prime::Prime256v1).is_err());
+        assert!(der_to_fixed(&[0x30, 0x03, 0x02, 0x01, 0x01], Prime::Prime256v1).is_err());
+        assert!(der_to_fixed(&[0x30, 0x06, 0x02, 0x01, 0x01], Prime::Prime256v1).is_err());
+        assert_eq!(
+            der_signature_to_fixed(&[0x30, 0x06], Prime::Prime256v1),
+            None
+        );
+        // Fixed size signatures are not converted
+        assert_eq!(der_signature_to_fixed(&[0x30; 64], Prime::Prime256v1), None);
+    }
+
+    #[test]
+    fn multi_byte_arcs() {
+        // 1.2.840.113549.1.1.1 (rsaEncryption)
+        let value = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+        let arcs = decode_oid_arcs(&value).unwrap();
+        assert_eq!(arcs, [1, 2, 840, 113549, 1, 1, 1]);
+
+        let der = [&[0x06, 0x09][..], &value].concat();
+        let oid = ObjectIdentifier::decode(&der).unwrap();
+        assert_eq!(oid, ObjectIdentifier::RsaEncryption);
+        assert_eq!(oid.to_string(), "1.2.840.113549.1.1.1");
+    }
+
+    #[test]
+    fn first_arcs() {
+        assert_eq!(decode_oid_arcs(&[0x27]).unwrap(), [0, 39]);
+        assert_eq!(decode_oid_arcs(&[0x28]).unwrap(), [1, 0]);
+        assert_eq!(decode_oid_arcs(&[0x4f]).unwrap(), [1, 39]);
+        assert_eq!(decode_oid_arcs(&[0x50]).unwrap(), [2, 0]);
+        // 2.999.3, the first component 1079 spans two bytes
+        assert_eq!(decode_oid_arcs(&[0x88, 0x37, 0x03]).unwrap(), [2, 999, 3]);
+
+        let oid = ObjectIdentifier::decode(&[0x06, 0x03, 0x88, 0x37, 0x03]).unwrap();
+        assert_eq!(oid, ObjectIdentifier::Other(vec![2, 999, 3]));
+        assert_eq!(oid.to_string(), "2.999.3");
+    }
+
+    #[test]
+    fn known_oids() {
+        let der = [0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
+        let oid = ObjectIdentifier::decode(&der).unwrap();
+        assert_eq!(oid, ObjectIdentifier::Curve(Prime::Secp384r1));
+        assert_eq!(ObjectIdentifier::from_arcs(oid.arcs().to_vec()), oid);
+    }
+
+    #[test]
+    fn invalid_oids() {
+        assert!(decode_oid_arcs(&[]).is_err());
+        // The last component has the continuation bit set
+        assert!(decode_oid_arcs(&[0x2a, 0x86]).is_err());
+        assert!(decode_oid_arcs(&[0x88]).is_err());
+        // Leading 0x80 bytes are not minimal
+        assert!(decode_oid_arcs(&[0x2a, 0x80, 0x01]).is_err());
+        // A component larger than 64 bits
+        let mut overlong = vec![0x2a];
+        overlong.extend_from_slice(&[0xff; 10]);
+        overlong.push(0x7f);
+        assert!(decode_oid_arcs(&overlong).is_err());
+
+        // The length is longer than the contents
+        assert!(ObjectIdentifier::decode(&[0x06, 0x05, 0x2a, 0x86, 0x48]).is_err());
+        assert!(ObjectIdentifier::decode(&[0x06, 0x02, 0x2a, 0x86]).is_err());
+        // Not an OBJECT IDENTIFIER
+        assert!(ObjectIdentifier::decode(&[0x04, 0x01, 0x2a]).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::{borrow::Cow, fmt};
+
+use serde::{Deserialize, Serialize};
+
+const HCERT: &str = "eHN HCERT spec";
+const DCC_VOL1: &str = "eHN DCC Technical Specifications Volume 1";
+const COSE: &str = "RFC 8152";
+const PKIX: &str = "RFC 5280";
+const CBOR: &str = "RFC 8949";
+
+/// A reference to a section of a normative document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecReference {
+    pub document: Cow<'static, str>,
+    pub section: Cow<'static, str>,
+}
+
+impl SpecReference {
+    const fn new(document: &'static str, section: &'static str) -> Self {
+        Self {
+            document: Cow::Borrowed(document),
+            section: Cow::Borrowed(section),
+        }
+    }
+}
+
+impl fmt::Display for SpecReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.document, self.section)
+    }
+}
+
+/// A requirement that is checked when validating a certificate
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// The `kid` must identify a DSC from the trust list
+    KeyIdentifier,
+    /// The signature algorithm must be one of the allowed ones
+    SignatureAlgorithm,
+    /// The signature must be valid for the `Sig_structure`
+    Signature,
+    /// The DSC must be allowed to sign the contained entry types (extended key usage)
+    DscKeyUsage,
+    /// The DSC must be valid when the certificate is issued
+    DscValidity,
+    /// The DSC must not be revoked by its CSCA (only checked with CRLs)
+    DscRevocation,
+    /// The CWT payload must use the deterministic CBOR encoding (only checked with `--strict`)
+    DeterministicEncoding,
+    /// The signer certificate of a VDS-NC must be issued by a trusted CSCA
+    CertificatePath,
+    /// An ECDSA signature must be in the fixed size `r || s` form, not DER encoded
+    SignatureFormat,
+}
+
+impl Rule {
+    /// The normative source of this rule
+    pub fn reference(self) -> SpecReference {
+        match self {
+            Self::KeyIdentifier => SpecReference::new(HCERT, "§3.3.2"),
+            Self::SignatureAlgorithm => SpecReference::new(HCERT, "§3.3.1"),
+            Self::Signature => SpecReference::new(COSE, "§4.4"),
+            Self::DscKeyUsage => SpecReference::new(DCC_VOL1, "Annex 2"),
+            Self::DscValidity => SpecReference::new(PKIX, "§4.1.2.5"),
+            Self::DscRevocation => SpecReference::new(PKIX, "§6.3"),
+            Self::DeterministicEncoding => SpecReference::new(CBOR, "§4.2.1"),
+            Self::CertificatePath => SpecReference::new(PKIX, "§6"),
+            Self::SignatureFormat => SpecReference::new(COSE, "§8.1"),
+        }
+    }
+}
+
+/// A violation of a [`Rule`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finding {
+    pub rule: Rule,
+    pub message: String,
+    pub reference: SpecReference,
+}
+
+impl Finding {
+    pub fn new(rule: Rule, message: impl Into<String>) -> Self {
+        Self {
+            rule,
+            message: message.into(),
+            reference: rule.reference(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.reference)
+    }
+}
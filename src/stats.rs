@@ -0,0 +1,95 @@
+//! Aggregate statistics over the results of a batch
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::{dcc::valuesets::EhnData, session::SessionResult};
+
+/// The width of the longest bar of the dose histogram
+const BAR_WIDTH: u64 = 40;
+
+/// Counts over the results of all inputs of a batch
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchStats {
+    pub inputs: u64,
+    /// By the outcome of the signature check (`verified`, `rejected`, `error`, …)
+    pub verdicts: BTreeMap<String, u64>,
+    /// By the issuing country of the CWT (`iss`)
+    pub countries: BTreeMap<String, u64>,
+    /// Vaccinations by the display name of the medicinal product, or its code if it is
+    /// not in the value set
+    pub products: BTreeMap<String, u64>,
+    /// Vaccinations by their dose number (`dn`)
+    pub doses: BTreeMap<u64, u64>,
+}
+
+impl BatchStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the result of one input, resolving the products against `data`
+    pub fn add(&mut self, result: &SessionResult, data: &EhnData) {
+        self.inputs += 1;
+        *self
+            .verdicts
+            .entry(result.signature_status().to_string())
+            .or_default() += 1;
+        if let Some(claims) = &result.claims {
+            *self.countries.entry(claims.issuer.clone()).or_default() += 1;
+        }
+        let vaccinations = result
+            .certificate
+            .as_ref()
+            .and_then(|cert| cert.get("v")?.as_array());
+        for vaccination in vaccinations.into_iter().flatten() {
+            if let Some(code) = vaccination.get("mp").and_then(|mp| mp.as_str()) {
+                let product = data
+                    .vaccine_medicinal_product
+                    .as_ref()
+                    .and_then(|set| set.get(code, data.lang))
+                    .map_or(code, |value| value.display.as_str());
+                *self.products.entry(product.to_string()).or_default() += 1;
+            }
+            if let Some(dn) = vaccination.get("dn").and_then(|dn| dn.as_u64()) {
+                *self.doses.entry(dn).or_default() += 1;
+            }
+        }
+    }
+
+    /// The statistics as text, with a histogram of the dose numbers
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Statistics of {} inputs", self.inputs);
+        let sections = [
+            ("Verdicts", &self.verdicts),
+            ("Issuing countries", &self.countries),
+            ("Vaccine products", &self.products),
+        ];
+        for (title, counts) in sections {
+            if counts.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "{}:", title);
+            let width = counts.keys().map(|key| key.chars().count()).max();
+            for (key, count) in counts {
+                let _ = writeln!(out, "  {:w$}  {}", key, count, w = width.unwrap_or(0));
+            }
+        }
+        if let Some(max) = self.doses.values().max() {
+            let _ = writeln!(out, "Dose numbers:");
+            for (dn, count) in &self.doses {
+                let bar = "#".repeat((count * BAR_WIDTH).div_ceil(*max) as usize);
+                let _ = writeln!(
+                    out,
+                    "  {:>2}  {:w$}  {}",
+                    dn,
+                    bar,
+                    count,
+                    w = BAR_WIDTH as usize
+                );
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    dcc::{CertPayload, Recovery, Test, Vaccination},
+    lang::Lang,
+    verify::Verification,
+};
+
+fn signature_status(verification: Option<&Verification>, lang: Lang) -> &'static str {
+    match (verification, lang) {
+        (Some(Verification::Verified), Lang::En) => "signature valid",
+        (Some(Verification::Verified), Lang::De) => "Signatur gültig",
+        (Some(Verification::Rejected(_)), Lang::En)
+        | (Some(Verification::AlgorithmMismatch(_)), Lang::En)
+        | (Some(Verification::Invalid), Lang::En) => "signature INVALID",
+        (Some(Verification::Rejected(_)), Lang::De)
+        | (Some(Verification::AlgorithmMismatch(_)), Lang::De)
+        | (Some(Verification::Invalid), Lang::De) => "Signatur UNGÜLTIG",
+        (Some(Verification::Blocked(_)), Lang::En) => "certificate BLOCKED",
+        (Some(Verification::Blocked(_)), Lang::De) => "Zertifikat GESPERRT",
+        (Some(Verification::Revoked(_)), Lang::En) => "signer REVOKED",
+        (Some(Verification::Revoked(_)), Lang::De) => "Signaturzertifikat WIDERRUFEN",
+        (_, Lang::En) => "signature could not be checked",
+        (_, Lang::De) => "Signatur konnte nicht geprüft werden",
+    }
+}
+
+fn vaccination(v: &Vaccination, lang: Lang) -> String {
+    match lang {
+        Lang::En => format!(
+            "dose {} of {} of {} administered on {}",
+            v.dose_number,
+            v.series_dose_number,
+            v.medicinal_product.display(),
+            v.date
+        ),
+        Lang::De => format!(
+            "Dosis {} von {} mit {}, verabreicht am {}",
+            v.dose_number,
+            v.series_dose_number,
+            v.medicinal_product.display(),
+            v.date
+        ),
+    }
+}
+
+fn test(t: &Test, lang: Lang) -> String {
+    let date = t.sample_collection.date_naive();
+    match lang {
+        Lang::En => format!(
+            "a {} ({}) sampled on {}",
+            t.test_type.display(),
+            t.result.display(),
+            date
+        ),
+        Lang::De => format!(
+            "einen {} ({}), Probenahme am {}",
+            t.test_type.display(),
+            t.result.display(),
+            date
+        ),
+    }
+}
+
+fn recovery(r: &Recovery, lang: Lang) -> String {
+    match lang {
+        Lang::En => format!(
+            "recovery from {} (first positive test on {}), valid from {} until {}",
+            r.disease_agent_targeted.display(),
+            r.first_positive,
+            r.valid_from,
+            r.valid_until
+        ),
+        Lang::De => format!(
+            "die Genesung von {} (erster positiver Test am {}), gültig vom {} bis {}",
+            r.disease_agent_targeted.display(),
+            r.first_positive,
+            r.valid_from,
+            r.valid_until
+        ),
+    }
+}
+
+/// Render a short plain-language statement about a certificate, e.g. for help-desk staff
+pub fn summarize(payload: &CertPayload, verification: Option<&Verification>, lang: Lang) -> String {
+    let cert = &payload.health_claim.cert;
+    let entries: Vec<String> = (cert.vaccine.iter().map(|v| vaccination(v, lang)))
+        .chain(cert.test.iter().map(|t| test(t, lang)))
+        .chain(cert.recovery.iter().map(|r| recovery(r, lang)))
+        .collect();
+    let signature = signature_status(verification, lang);
+    let expires = payload.expiration_time.date_naive();
+
+    match lang {
+        Lang::En => {
+            let shows = if entries.is_empty() {
+                "no entries".to_string()
+            } else {
+                entries.join(" and ")
+            };
+            format!(
+                "This certificate shows {}; {}; expires {}.",
+                shows, signature, expires
+            )
+        }
+        Lang::De => {
+            let shows = if entries.is_empty() {
+                "keine Einträge".to_string()
+            } else {
+                entries.join(" und ")
+            };
+            format!(
+                "Dieses Zertifikat bescheinigt {}; {}; läuft ab am {}.",
+                shows, signature, expires
+            )
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! TLS settings of the downloads
+//!
+//! The verifier is only as trustworthy as the trust lists and rules it downloads, so the
+//! certificates of the servers can be pinned per host. A pin is the SHA-256 hash of a
+//! `SubjectPublicKeyInfo` (`sha256/<base64>`, as in HPKP and `curl --pinnedpubkey`) or of
+//! a whole certificate (`cert-sha256/<base64>`). A connection to a host with pins fails
+//! unless one of them matches the server certificate or an intermediate certificate that
+//! the server sent. The usual checks against the web PKI roots still apply.
+//!
+//! Gateways like the DGCG also require a client certificate (mutual TLS), which is sent
+//! to every server that asks for one.
+//!
+//! The async downloads take a `reqwest::Client`, which is configured by the caller.
+use std::{
+    collections::BTreeMap, convert::TryFrom, error::Error, fmt, path::Path, str::FromStr,
+    sync::Arc, time::SystemTime,
+};
+
+use color_eyre::eyre::eyre;
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{cert, crypto};
+
+/// The pins of the servers, by host name
+pub type TlsPins = BTreeMap<String, Vec<TlsPin>>;
+
+/// A pinned certificate of a server
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TlsPin {
+    /// The SHA-256 hash of the DER encoded `SubjectPublicKeyInfo`
+    PublicKey([u8; 32]),
+    /// The SHA-256 hash of the DER encoded certificate
+    Certificate([u8; 32]),
+}
+
+impl TlsPin {
+    /// Whether the DER encoded certificate `der` matches this pin
+    pub fn matches(&self, der: &[u8]) -> bool {
+        match self {
+            Self::PublicKey(hash) => {
+                cert::spki_der(der).is_some_and(|spki| crypto::sha256(spki) == *hash)
+            }
+            Self::Certificate(hash) => crypto::sha256(der) == *hash,
+        }
+    }
+}
+
+impl fmt::Display for TlsPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PublicKey(hash) => write!(f, "sha256/{}", base64::encode(hash)),
+            Self::Certificate(hash) => write!(f, "cert-sha256/{}", base64::encode(hash)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPin(String);
+
+impl Error for InvalidPin {}
+impl fmt::Display for InvalidPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid pin '{}' (expected sha256/<base64> or cert-sha256/<base64>)",
+            self.0
+        )
+    }
+}
+
+impl FromStr for TlsPin {
+    type Err = InvalidPin;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hash = |hash: &str| {
+            let hash = base64::decode(hash).map_err(|_| InvalidPin(s.to_string()))?;
+            <[u8; 32]>::try_from(hash.as_slice()).map_err(|_| InvalidPin(s.to_string()))
+        };
+        if let Some(rest) = s.strip_prefix("sha256/") {
+            hash(rest).map(Self::PublicKey)
+        } else if let Some(rest) = s.strip_prefix("cert-sha256/") {
+            hash(rest).map(Self::Certificate)
+        } else {
+            Err(InvalidPin(s.to_string()))
+        }
+    }
+}
+
+impl TryFrom<String> for TlsPin {
+    type Error = InvalidPin;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A client certificate for mutual TLS
+#[derive(Clone)]
+pub struct ClientCert {
+    /// The DER encoded certificate, followed by its intermediate certificates
+    pub chain: Vec<Vec<u8>>,
+    /// The DER encoded PKCS#8 private key of the certificate
+    pub key: Vec<u8>,
+}
+
+impl fmt::Debug for ClientCert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCert")
+            .field("chain", &self.chain.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClientCert {
+    /// Read the certificate (chain) and the PKCS#8 key from PEM or DER files
+    pub fn read(cert: &Path, key: &Path) -> color_eyre::Result<Self> {
+        let chain = cert::read_certificates(cert)?;
+        if chain.is_empty() {
+            return Err(eyre!("No certificate in '{}'", cert.display()));
+        }
+        let key = cert::read_private_key(key)?;
+        Ok(Self { chain, key })
+    }
+}
+
+/// The TLS settings of the downloads
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    pub pins: TlsPins,
+    pub client_cert: Option<ClientCert>,
+}
+
+impl TlsSettings {
+    /// The agent for the downloads with these settings
+    ///
+    /// Fails if the key of the client certificate can't be used.
+    pub fn agent(&self) -> Result<ureq::Agent, rustls::Error> {
+        let pinned = !self.pins.values().all(Vec::is_empty);
+        if !pinned && self.client_cert.is_none() {
+            return Ok(ureq::Agent::new());
+        }
+        let roots = web_pki_roots();
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots.clone());
+        let mut config = match &self.client_cert {
+            Some(client_cert) => {
+                let chain = client_cert.chain.iter().cloned().map(Certificate).collect();
+                builder.with_client_auth_cert(chain, PrivateKey(client_cert.key.clone()))?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        if pinned {
+            let verifier = PinnedVerifier {
+                inner: WebPkiVerifier::new(roots, None),
+                pins: self.pins.clone(),
+            };
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(verifier));
+        }
+        Ok(ureq::AgentBuilder::new()
+            .tls_config(Arc::new(config))
+            .build())
+    }
+}
+
+/// The roots that `ureq` uses by default
+fn web_pki_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    roots
+}
+
+/// Checks the pins of a host after the usual checks of the certificate chain
+struct PinnedVerifier {
+    inner: WebPkiVerifier,
+    pins: TlsPins,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => return Ok(verified),
+        };
+        let pins = match self.pins.get(&host) {
+            Some(pins) if !pins.is_empty() => pins,
+            _ => return Ok(verified),
+        };
+        let mut chain = std::iter::once(end_entity).chain(intermediates);
+        if chain.any(|cert| pins.iter().any(|pin| pin.matches(&cert.0))) {
+            debug!("Pinned certificate of '{}' matched", host);
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(format!(
+                "The certificate of '{}' matches none of its pins",
+                host
+            )))
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{TimeZone, Utc};
+use color_eyre::eyre::eyre;
+use serde_cbor::Value;
+use tracing::{debug, warn};
+use x509_parser::parse_x509_certificate;
+
+use super::{subject_country, SignerCert, SignerKey, TrustSource};
+use crate::{
+    cert::CscaStore,
+    cose::{CoseSign1, ALG_ES256},
+    crypto::{self, SignatureAlgorithm},
+};
+
+/// CWT claim keys used in the trust list signature
+const CLAIM_CONTENT_HASH: i128 = 2;
+const CLAIM_EXPIRATION: i128 = 4;
+const CLAIM_NOT_BEFORE: i128 = 5;
+
+/// The trust list published by Austria
+///
+/// It consists of a CBOR content file (`{"c": [{"i": kid, "c": cert}, ...]}`) and a
+/// COSE_Sign1 signature file, whose payload contains the SHA-256 hash of the content.
+#[derive(Debug, Clone, Default)]
+pub struct AustrianTrustList {
+    pub certificates: Vec<SignerCert>,
+}
+
+impl AustrianTrustList {
+    /// Parse the content after checking it against the signature
+    ///
+    /// If `anchor` (a DER encoded certificate) is given, the signature itself is verified too.
+    pub fn from_bytes(
+        content: &[u8],
+        signature: &[u8],
+        anchor: Option<&[u8]>,
+    ) -> color_eyre::Result<Self> {
+        check_signature(content, signature, anchor)?;
+        parse_content(content)
+    }
+
+    pub fn read_from_files<P: AsRef<Path>, Q: AsRef<Path>>(
+        content: P,
+        signature: Q,
+        anchor: Option<&[u8]>,
+    ) -> color_eyre::Result<Self> {
+        let content = std::fs::read(content)?;
+        let signature = std::fs::read(signature)?;
+        Self::from_bytes(&content, &signature, anchor)
+    }
+
+    /// Remove all DSCs that do not chain to a CSCA in `store`
+    pub fn retain_chained(&mut self, store: &CscaStore) {
+        super::retain_chained(&mut self.certificates, store);
+    }
+}
+
+impl TrustSource for AustrianTrustList {
+    fn lookup(&self, kid: &[u8]) -> Vec<SignerCert> {
+        self.certificates
+            .iter()
+            .filter(|c| c.kid == kid)
+            .cloned()
+            .collect()
+    }
+
+    fn signers(&self) -> Vec<SignerCert> {
+        self.certificates.clone()
+    }
+}
+
+fn check_signature(
+    content: &[u8],
+    signature: &[u8],
+    anchor: Option<&[u8]>,
+) -> color_eyre::Result<()> {
+    let sign1 = CoseSign1::from_slice(signature)?;
+    let claims: BTreeMap<Value, Value> = serde_cbor::from_slice(&sign1.payload)?;
+
+    let hash = match claims.get(&Value::Integer(CLAIM_CONTENT_HASH)) {
+        Some(Value::Bytes(hash)) => hash,
+        _ => {
+            return Err(eyre!(
+                "Trust list signature does not contain a content hash"
+            ))
+        }
+    };
+    if crypto::sha256(content)[..] != hash[..] {
+        return Err(eyre!("Trust list content does not match the signed hash"));
+    }
+    debug!("Trust list content hash matches");
+
+    let now = Utc::now().timestamp();
+    let timestamp = |key| match claims.get(&Value::Integer(key)) {
+        Some(Value::Integer(t)) => Some(*t as i64),
+        _ => None,
+    };
+    if let Some(nbf) = timestamp(CLAIM_NOT_BEFORE) {
+        if now < nbf {
+            warn!(
+                "Trust list is not valid before {}",
+                Utc.timestamp_opt(nbf, 0).unwrap()
+            );
+        }
+    }
+    if let Some(exp) = timestamp(CLAIM_EXPIRATION) {
+        if now >= exp {
+            warn!(
+                "Trust list expired at {}",
+                Utc.timestamp_opt(exp, 0).unwrap()
+            );
+        }
+    }
+
+    match anchor {
+        Some(anchor) => {
+            let (_, cert) = parse_x509_certificate(anchor)?;
+            if sign1.alg() != Some(ALG_ES256) {
+                return Err(eyre!(
+                    "Unsupported trust list signature algorithm {:?}",
+                    sign1.alg()
+                ));
+            }
+            crypto::verify(
+                SignatureAlgorithm::EcdsaP256Sha256Fixed,
+                cert.tbs_certificate.subject_pki.subject_public_key.data,
+                &sign1.sig_structure(),
+                &sign1.signature,
+            )
+            .map_err(|_| eyre!("Trust list signature verification failed"))?;
+            debug!("Trust list signature verified");
+        }
+        None => warn!("Trust list signature not verified (no anchor certificate given)"),
+    }
+    Ok(())
+}
+
+fn parse_content(content: &[u8]) -> color_eyre::Result<AustrianTrustList> {
+    let value: Value = serde_cbor::from_slice(content)?;
+    let entries = match value {
+        Value::Map(mut map) => map.remove(&Value::Text("c".to_string())),
+        _ => None,
+    };
+    let entries = match entries {
+        Some(Value::Array(entries)) => entries,
+        _ => return Err(eyre!("Expected a map with a 'c' array in the trust list")),
+    };
+
+    let mut certificates = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let mut entry = match entry {
+            Value::Map(map) => map,
+            _ => return Err(eyre!("Expected a map for each trust list entry")),
+        };
+        let kid = entry.remove(&Value::Text("i".to_string()));
+        let der = entry.remove(&Value::Text("c".to_string()));
+        match (kid, der) {
+            (Some(Value::Bytes(kid)), Some(Value::Bytes(der))) => {
+                let country = subject_country(&der);
+                certificates.push(SignerCert {
+                    kid,
+                    country,
+                    key: SignerKey::Certificate(der),
+                    sources: Vec::new(),
+                });
+            }
+            _ => {
+                return Err(eyre!(
+                    "Expected 'i' and 'c' byte strings in trust list entry"
+                ))
+            }
+        }
+    }
+    debug!(
+        "Loaded {} certificates from the austrian trust list",
+        certificates.len()
+    );
+    Ok(AustrianTrustList { certificates })
+}
@@ -0,0 +1,113 @@
+//! Blocked key identifiers and certificate identifiers
+//!
+//! Some DSC keys leaked in 2021 and were used to sign fake certificates, and single
+//! certificates are revoked by their UVCI. A certificate that matches either is blocked,
+//! even if its signature is valid.
+use std::{collections::HashSet, path::Path};
+
+use color_eyre::eyre::eyre;
+use tracing::debug;
+
+use crate::dcc::CertPayload;
+
+/// A list of blocked kids and UVCIs
+///
+/// The text format has one entry per line, `kid:` followed by the base64 encoded kid or
+/// `uvci:` followed by the certificate identifier. Empty lines and lines starting with `#`
+/// are skipped.
+///
+/// ```text
+/// # Leaked DSC key
+/// kid:1N3OpCzZjAM=
+/// uvci:URN:UVCI:01:FR:W7V2BE46QSBJ#L
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    kids: HashSet<Vec<u8>>,
+    uvcis: HashSet<String>,
+}
+
+impl Blocklist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> color_eyre::Result<Self> {
+        let mut blocklist = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(kid) = line.strip_prefix("kid:") {
+                let kid = base64::decode(kid.trim())
+                    .map_err(|e| eyre!("Invalid kid in line {}: {}", i + 1, e))?;
+                blocklist.insert_kid(kid);
+            } else if let Some(uvci) = line.strip_prefix("uvci:") {
+                blocklist.insert_uvci(uvci.trim());
+            } else {
+                return Err(eyre!(
+                    "Invalid entry in line {}, expected 'kid:' or 'uvci:'",
+                    i + 1
+                ));
+            }
+        }
+        Ok(blocklist)
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> color_eyre::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let blocklist = Self::parse(&text)?;
+        debug!(
+            "Loaded a blocklist with {} kids and {} UVCIs",
+            blocklist.kids.len(),
+            blocklist.uvcis.len()
+        );
+        Ok(blocklist)
+    }
+
+    pub fn insert_kid(&mut self, kid: Vec<u8>) {
+        self.kids.insert(kid);
+    }
+
+    pub fn insert_uvci(&mut self, uvci: &str) {
+        self.uvcis.insert(normalize_uvci(uvci).to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.kids.len() + self.uvcis.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kids.is_empty() && self.uvcis.is_empty()
+    }
+
+    pub fn is_kid_blocked(&self, kid: &[u8]) -> bool {
+        self.kids.contains(kid)
+    }
+
+    /// Whether `uvci` is blocked, with or without the `URN:UVCI:` prefix
+    pub fn is_uvci_blocked(&self, uvci: &str) -> bool {
+        self.uvcis.contains(normalize_uvci(uvci))
+    }
+
+    /// The reason why a certificate with this kid and payload is blocked, if it is
+    pub fn check(&self, kid: &[u8], payload: &CertPayload) -> Option<String> {
+        if self.is_kid_blocked(kid) {
+            return Some(format!("the kid {} is blocked", base64::encode(kid)));
+        }
+        let cert = &payload.health_claim.cert;
+        cert.entries()
+            .map(|entry| entry.cert_identifier())
+            .find(|uvci| self.is_uvci_blocked(uvci))
+            .map(|uvci| format!("the certificate identifier {} is blocked", uvci))
+    }
+}
+
+/// Strip the optional `URN:UVCI:` prefix
+fn normalize_uvci(uvci: &str) -> &str {
+    match uvci.get(..9) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("URN:UVCI:") => &uvci[9..],
+        _ => uvci,
+    }
+}
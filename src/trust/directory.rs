@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use tracing::{debug, warn};
+
+use super::{MemoryTrust, SignerCert, TrustSource};
+use crate::cert::{read_certificates, CscaStore};
+
+/// File extensions of the certificates in a directory
+const EXTENSIONS: &[&str] = &["pem", "der", "crt", "cer"];
+
+/// Signer certificates from a directory of PEM or DER files
+///
+/// The kid of each certificate is computed from its DER encoding.
+#[derive(Debug, Clone, Default)]
+pub struct CertDirectory {
+    store: MemoryTrust,
+}
+
+impl CertDirectory {
+    /// Load all certificates from `dir` and its subdirectories
+    pub fn read_from_dir<P: AsRef<Path>>(dir: P) -> color_eyre::Result<Self> {
+        let mut store = MemoryTrust::new();
+        for path in cert_files(dir.as_ref())? {
+            match read_certificates(&path) {
+                Ok(certificates) => {
+                    for der in certificates {
+                        let cert = SignerCert::from_der(der);
+                        debug!(
+                            "Loaded '{}' (kid='{}')",
+                            path.display(),
+                            base64::encode(&cert.kid)
+                        );
+                        store.insert(cert);
+                    }
+                }
+                Err(e) => warn!("Skipping '{}': {}", path.display(), e),
+            }
+        }
+        debug!("Loaded {} certificates from the directory", store.len());
+        Ok(Self { store })
+    }
+
+    /// Remove all DSCs that do not chain to a CSCA in `store`
+    pub fn retain_chained(&mut self, store: &CscaStore) {
+        self.store.retain_chained(store);
+    }
+}
+
+impl TrustSource for CertDirectory {
+    fn lookup(&self, kid: &[u8]) -> Vec<SignerCert> {
+        self.store.lookup(kid)
+    }
+
+    fn signers(&self) -> Vec<SignerCert> {
+        self.store.signers()
+    }
+}
+
+/// Find all certificate files below `dir`, in a stable order
+pub(crate) fn cert_files(dir: &Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let mut files = Vec::new();
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            files.extend(cert_files(&path)?);
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+            .unwrap_or(false)
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
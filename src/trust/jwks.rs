@@ -0,0 +1,156 @@
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use super::{subject_country, MemoryTrust, SignerCert, SignerKey};
+use crate::json::Loadable;
+
+/// `SubjectPublicKeyInfo` prefix of an uncompressed P-256 public key
+pub(crate) const P256_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00, 0x04,
+];
+/// DER encoded `AlgorithmIdentifier` for `rsaEncryption` (with NULL parameters)
+const RSA_ALGORITHM: &[u8] = &[
+    0x30, 0x0d, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01, 0x05, 0x00,
+];
+
+/// A JSON Web Key Set (RFC 7517)
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl Loadable for JwkSet {}
+
+/// A single JSON Web Key, with the members that are relevant for signature verification
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub kid: Option<String>,
+    pub crv: Option<String>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+    pub n: Option<String>,
+    pub e: Option<String>,
+    /// The certificate chain, as base64 encoded DER
+    pub x5c: Option<Vec<String>>,
+}
+
+impl Jwk {
+    /// Convert this key into a trust store entry
+    pub fn to_signer(&self) -> Result<SignerCert, String> {
+        let kid = self.kid.as_deref().ok_or("missing 'kid'")?;
+        let kid = base64::decode(kid)
+            .or_else(|_| base64::decode_config(kid, base64::URL_SAFE_NO_PAD))
+            .map_err(|e| format!("invalid 'kid': {}", e))?;
+
+        // Prefer the certificate, so that the usual checks on the DSC apply
+        if let Some(cert) = self.x5c.as_ref().and_then(|chain| chain.first()) {
+            let der = base64::decode(cert).map_err(|e| format!("invalid 'x5c': {}", e))?;
+            return Ok(SignerCert {
+                kid,
+                country: subject_country(&der),
+                key: SignerKey::Certificate(der),
+                sources: Vec::new(),
+            });
+        }
+
+        let spki = match self.kty.as_str() {
+            "EC" => {
+                if self.crv.as_deref() != Some("P-256") {
+                    return Err(format!("unsupported curve {:?}", self.crv));
+                }
+                let x = self.coordinate(&self.x, "x")?;
+                let y = self.coordinate(&self.y, "y")?;
+                let mut spki = P256_SPKI_PREFIX.to_vec();
+                spki.extend(&x);
+                spki.extend(&y);
+                spki
+            }
+            "RSA" => {
+                let n = self.param(&self.n, "n")?;
+                let e = self.param(&self.e, "e")?;
+                let mut rsa_key = der_unsigned_integer(&n);
+                rsa_key.extend(der_unsigned_integer(&e));
+                let mut bit_string = vec![0x00];
+                bit_string.extend(der_tlv(0x30, &rsa_key));
+                let mut spki = RSA_ALGORITHM.to_vec();
+                spki.extend(der_tlv(0x03, &bit_string));
+                der_tlv(0x30, &spki)
+            }
+            kty => return Err(format!("unsupported key type '{}'", kty)),
+        };
+        Ok(SignerCert {
+            kid,
+            country: None,
+            key: SignerKey::PublicKey(spki),
+            sources: Vec::new(),
+        })
+    }
+
+    fn param(&self, value: &Option<String>, name: &str) -> Result<Vec<u8>, String> {
+        let value = value
+            .as_deref()
+            .ok_or_else(|| format!("missing '{}'", name))?;
+        base64::decode_config(value, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| format!("invalid '{}': {}", name, e))
+    }
+
+    /// Decode a P-256 coordinate, left-padded to 32 bytes
+    fn coordinate(&self, value: &Option<String>, name: &str) -> Result<[u8; 32], String> {
+        let bytes = self.param(value, name)?;
+        if bytes.len() > 32 {
+            return Err(format!("'{}' is too long for P-256", name));
+        }
+        let mut coordinate = [0; 32];
+        coordinate[32 - bytes.len()..].copy_from_slice(&bytes);
+        Ok(coordinate)
+    }
+}
+
+impl JwkSet {
+    /// Build a trust store from all supported keys in this set
+    pub fn to_trust(&self) -> MemoryTrust {
+        let store: MemoryTrust = self
+            .keys
+            .iter()
+            .filter_map(|jwk| match jwk.to_signer() {
+                Ok(signer) => Some(signer),
+                Err(e) => {
+                    warn!("Skipping JWK {:?}: {}", jwk.kid, e);
+                    None
+                }
+            })
+            .collect();
+        debug!("Loaded {} keys from the JWK set", store.len());
+        store
+    }
+}
+
+/// Encode a DER tag-length-value
+pub(crate) fn der_tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend(&bytes[skip..]);
+    }
+    out.extend(value);
+    out
+}
+
+/// Encode a big-endian unsigned integer as a DER INTEGER
+pub(crate) fn der_unsigned_integer(value: &[u8]) -> Vec<u8> {
+    let skip = value.iter().take_while(|b| **b == 0).count();
+    let value = &value[skip.min(value.len().saturating_sub(1))..];
+    let mut content = Vec::with_capacity(value.len() + 1);
+    if !matches!(value.first(), Some(b) if b & 0x80 == 0) {
+        content.push(0x00);
+    }
+    content.extend(value);
+    der_tlv(0x02, &content)
+}
@@ -0,0 +1,66 @@
+use std::iter::FromIterator;
+
+use super::{retain_chained, SignerCert, TrustSource};
+use crate::cert::CscaStore;
+
+/// An in-memory store of signer certificates
+///
+/// This can be used to supply keys from other sources, e.g. a database.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTrust {
+    certificates: Vec<SignerCert>,
+}
+
+impl MemoryTrust {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, cert: SignerCert) {
+        self.certificates.push(cert);
+    }
+
+    /// Insert a DER encoded certificate, computing the kid from it
+    pub fn insert_der(&mut self, der: Vec<u8>) {
+        self.insert(SignerCert::from_der(der));
+    }
+
+    pub fn len(&self) -> usize {
+        self.certificates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SignerCert> {
+        self.certificates.iter()
+    }
+
+    /// Remove all DSCs that do not chain to a CSCA in `store`
+    pub fn retain_chained(&mut self, store: &CscaStore) {
+        retain_chained(&mut self.certificates, store);
+    }
+}
+
+impl FromIterator<SignerCert> for MemoryTrust {
+    fn from_iter<I: IntoIterator<Item = SignerCert>>(iter: I) -> Self {
+        Self {
+            certificates: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl TrustSource for MemoryTrust {
+    fn lookup(&self, kid: &[u8]) -> Vec<SignerCert> {
+        self.certificates
+            .iter()
+            .filter(|c| c.kid == kid)
+            .cloned()
+            .collect()
+    }
+
+    fn signers(&self) -> Vec<SignerCert> {
+        self.certificates.clone()
+    }
+}
@@ -0,0 +1,78 @@
+use tracing::debug;
+
+use super::{retain_chained, SignerCert, SignerKey, TrustSource};
+use crate::cert::CscaStore;
+
+/// Signer certificates merged from several trust sources
+///
+/// An entry that is in more than one source (the same kid and certificate or key) is
+/// kept once, with the names of all sources that contain it in [`SignerCert::sources`].
+#[derive(Debug, Clone, Default)]
+pub struct MergedTrust {
+    certificates: Vec<SignerCert>,
+}
+
+impl MergedTrust {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add all signers of `source`, recording `name` as their source
+    pub fn add(&mut self, name: &str, source: &dyn TrustSource) {
+        let (mut added, mut merged) = (0, 0);
+        for mut cert in source.signers() {
+            match self.certificates.iter_mut().find(|c| same_entry(c, &cert)) {
+                Some(existing) => {
+                    if !existing.sources.iter().any(|s| s == name) {
+                        existing.sources.push(name.to_string());
+                    }
+                    merged += 1;
+                }
+                None => {
+                    cert.sources = vec![name.to_string()];
+                    self.certificates.push(cert);
+                    added += 1;
+                }
+            }
+        }
+        debug!(
+            "Added {} signers from '{}' ({} already known)",
+            added, name, merged
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.certificates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+
+    /// Remove all DSCs that do not chain to a CSCA in `store`
+    pub fn retain_chained(&mut self, store: &CscaStore) {
+        retain_chained(&mut self.certificates, store);
+    }
+}
+
+/// Whether two entries have the same kid and thumbprint (of the certificate or key)
+fn same_entry(a: &SignerCert, b: &SignerCert) -> bool {
+    let der = |cert: &SignerCert| match &cert.key {
+        SignerKey::Certificate(der) | SignerKey::PublicKey(der) => crate::crypto::sha256(der),
+    };
+    a.kid == b.kid && der(a) == der(b)
+}
+
+impl TrustSource for MergedTrust {
+    fn lookup(&self, kid: &[u8]) -> Vec<SignerCert> {
+        self.certificates
+            .iter()
+            .filter(|c| c.kid == kid)
+            .cloned()
+            .collect()
+    }
+
+    fn signers(&self) -> Vec<SignerCert> {
+        self.certificates.clone()
+    }
+}
@@ -0,0 +1,295 @@
+use std::{fmt, str::FromStr};
+#[cfg(feature = "async")]
+use std::{future::Future, pin::Pin};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use x509_parser::{parse_x509_certificate, x509::SubjectPublicKeyInfo};
+
+use crate::cert::{compute_kid, get_pk_sig_algorithm, Certificate, CscaStore, TrustList};
+
+pub mod austria;
+mod blocklist;
+mod directory;
+pub mod jwks;
+mod memory;
+mod merged;
+
+pub use blocklist::Blocklist;
+pub(crate) use directory::cert_files;
+pub use directory::CertDirectory;
+pub use memory::MemoryTrust;
+pub use merged::MergedTrust;
+
+/// The key material of a document signer
+#[derive(Debug, Clone)]
+pub enum SignerKey {
+    /// A DER encoded X.509 certificate
+    Certificate(Vec<u8>),
+    /// A DER encoded `SubjectPublicKeyInfo`, without a certificate
+    PublicKey(Vec<u8>),
+}
+
+/// A certificate (or key) of a document signer (DSC) from a trust source
+#[derive(Debug, Clone)]
+pub struct SignerCert {
+    pub kid: Vec<u8>,
+    pub country: Option<String>,
+    pub key: SignerKey,
+    /// The names of the trust sources that contain this entry, if they were merged
+    pub sources: Vec<String>,
+}
+
+impl SignerCert {
+    /// Create an entry for a DER encoded certificate, computing the kid and country from it
+    pub fn from_der(der: Vec<u8>) -> Self {
+        Self {
+            kid: compute_kid(&der),
+            country: subject_country(&der),
+            key: SignerKey::Certificate(der),
+            sources: Vec::new(),
+        }
+    }
+
+    /// The DER encoded X.509 certificate, if there is one
+    pub fn certificate(&self) -> Option<&[u8]> {
+        match &self.key {
+            SignerKey::Certificate(der) => Some(der),
+            SignerKey::PublicKey(_) => None,
+        }
+    }
+
+    /// Summarize this entry, e.g. for listing the contents of a trust source
+    pub fn info(&self) -> SignerInfo {
+        let mut info = SignerInfo {
+            country: self.country.clone(),
+            sources: self.sources.clone(),
+            ..SignerInfo::unknown(&self.kid)
+        };
+        let time = |t: i64| Utc.timestamp_opt(t, 0).single();
+        match &self.key {
+            SignerKey::Certificate(der) => {
+                if let Ok((_, cert)) = parse_x509_certificate(der) {
+                    info.subject = cert
+                        .subject()
+                        .iter_common_name()
+                        .next()
+                        .and_then(|cn| cn.attr_value.as_str().ok())
+                        .map(str::to_string);
+                    info.algorithm = get_pk_sig_algorithm(&cert.tbs_certificate.subject_pki)
+                        .ok()
+                        .map(|alg| alg.to_string());
+                    info.not_before = time(cert.validity().not_before.timestamp());
+                    info.not_after = time(cert.validity().not_after.timestamp());
+                }
+            }
+            SignerKey::PublicKey(spki) => {
+                if let Ok((_, spki)) = SubjectPublicKeyInfo::from_der(spki) {
+                    info.algorithm = get_pk_sig_algorithm(&spki).ok().map(|alg| alg.to_string());
+                }
+            }
+        }
+        info
+    }
+}
+
+/// A summary of a [`SignerCert`]
+#[derive(Debug, Clone, Serialize)]
+pub struct SignerInfo {
+    /// The base64 encoded kid
+    pub kid: String,
+    pub country: Option<String>,
+    /// The common name of the subject
+    pub subject: Option<String>,
+    pub algorithm: Option<String>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+    /// The trust sources that vouch for the signer
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+impl SignerInfo {
+    /// A summary with only the kid, for a signer that is not in a trust source
+    pub fn unknown(kid: &[u8]) -> Self {
+        Self {
+            kid: base64::encode(kid),
+            country: None,
+            subject: None,
+            algorithm: None,
+            not_before: None,
+            not_after: None,
+            sources: Vec::new(),
+        }
+    }
+}
+
+/// The signers of `trust` whose certificate expires before `deadline`, including expired ones
+///
+/// Sorted by country and then by the time of expiry. Keys without a certificate are skipped,
+/// as they have no validity.
+pub fn expiring(trust: &dyn TrustSource, deadline: DateTime<Utc>) -> Vec<SignerInfo> {
+    let mut signers: Vec<SignerInfo> = trust
+        .signers()
+        .iter()
+        .map(SignerCert::info)
+        .filter(|info| info.not_after.is_some_and(|t| t <= deadline))
+        .collect();
+    signers.sort_by(|a, b| (&a.country, a.not_after).cmp(&(&b.country, b.not_after)));
+    signers
+}
+
+/// A source of trusted document signer certificates
+pub trait TrustSource {
+    /// Find all signer certificates with the given kid
+    fn lookup(&self, kid: &[u8]) -> Vec<SignerCert>;
+
+    /// All signer certificates of this source
+    fn signers(&self) -> Vec<SignerCert>;
+
+    /// Find all signer certificates of the given country, e.g. for a certificate without a
+    /// kid
+    fn lookup_country(&self, country: &str) -> Vec<SignerCert> {
+        let mut signers = self.signers();
+        signers.retain(|signer| signer.country.as_deref() == Some(country));
+        signers
+    }
+
+    /// Find all signer certificates with the given kid, without blocking the runtime
+    ///
+    /// This defaults to [`TrustSource::lookup`], which is fine for sources that are
+    /// already in memory; sources that ask e.g. a gateway should override it.
+    #[cfg(feature = "async")]
+    fn lookup_async<'a>(
+        &'a self,
+        kid: &'a [u8],
+    ) -> Pin<Box<dyn Future<Output = Vec<SignerCert>> + Send + 'a>> {
+        Box::pin(std::future::ready(self.lookup(kid)))
+    }
+}
+
+/// Strip the signature line before the JSON document, as in the german trust list
+pub fn strip_signature_line(body: Vec<u8>) -> Vec<u8> {
+    match body.iter().position(|b| *b == b'\n') {
+        Some(end) if body.first() != Some(&b'{') => body[end + 1..].to_vec(),
+        _ => body,
+    }
+}
+
+impl TrustList {
+    fn signers_matching<'a>(
+        &'a self,
+        filter: impl Fn(&Certificate) -> bool + 'a,
+    ) -> impl Iterator<Item = SignerCert> + 'a {
+        self.certificates
+            .iter()
+            .filter(move |c| filter(c))
+            .filter_map(|c| {
+                let res = base64::decode(&c.kid).and_then(|kid| {
+                    let der = base64::decode(&c.raw_data)?;
+                    Ok((kid, der))
+                });
+                match res {
+                    Ok((kid, der)) => Some(SignerCert {
+                        kid,
+                        country: Some(c.country.clone()),
+                        key: SignerKey::Certificate(der),
+                        sources: Vec::new(),
+                    }),
+                    Err(e) => {
+                        warn!("Invalid certificate data for kid '{}': {}", c.kid, e);
+                        None
+                    }
+                }
+            })
+    }
+}
+
+impl TrustSource for TrustList {
+    fn lookup(&self, kid: &[u8]) -> Vec<SignerCert> {
+        let b64_kid = base64::encode(kid);
+        self.signers_matching(|c| c.kid == b64_kid).collect()
+    }
+
+    fn signers(&self) -> Vec<SignerCert> {
+        self.signers_matching(|_| true).collect()
+    }
+}
+
+/// Remove all DSCs that do not chain to a CSCA in `store`
+pub(crate) fn retain_chained(certificates: &mut Vec<SignerCert>, store: &CscaStore) {
+    certificates.retain(|cert| match cert.certificate() {
+        Some(der) => match store.verify_der(der) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Dropping DSC '{}': {}", base64::encode(&cert.kid), e);
+                false
+            }
+        },
+        None => {
+            warn!(
+                "Dropping key '{}': no certificate to check against the CSCAs",
+                base64::encode(&cert.kid)
+            );
+            false
+        }
+    });
+}
+
+/// Get the country (`C`) from the subject of a DER encoded certificate
+pub fn subject_country(der: &[u8]) -> Option<String> {
+    let (_, cert) = parse_x509_certificate(der).ok()?;
+    let country = cert.subject().iter_country().next()?;
+    country.attr_value.as_str().ok().map(str::to_string)
+}
+
+/// The format of a trust list file
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustListFormat {
+    /// The JSON format of the DSC list of the german verifier backend
+    #[default]
+    Json,
+    /// The signed CBOR format published by Austria
+    At,
+    /// A JSON Web Key Set
+    Jwks,
+}
+
+impl fmt::Display for TrustListFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::At => write!(f, "at"),
+            Self::Jwks => write!(f, "jwks"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownFormat(String);
+
+impl std::error::Error for UnknownFormat {}
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown trust list format '{}' (expected 'json', 'at' or 'jwks')",
+            self.0
+        )
+    }
+}
+
+impl FromStr for TrustListFormat {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "at" => Ok(Self::At),
+            "jwks" => Ok(Self::Jwks),
+            _ => Err(UnknownFormat(s.to_string())),
+        }
+    }
+}
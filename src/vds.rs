@@ -0,0 +1,193 @@
+//! ICAO Visible Digital Seals for non-constrained environments (VDS-NC)
+//!
+//! A VDS-NC is a JSON document with the signed `data` (header and message) and a `sig`
+//! object, which contains the barcode signer certificate and the signature over the
+//! canonical JSON of `data`.
+use std::{error::Error, fmt};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::shc::{HealthCard, Immunization};
+
+/// The type of a proof of vaccination
+pub const TYPE_VACCINATION: &str = "icao.vacc";
+
+#[derive(Debug)]
+pub enum VdsError {
+    Json(serde_json::Error),
+    Base64(base64::DecodeError),
+    /// A seal of another type than [`TYPE_VACCINATION`]
+    UnsupportedType(String),
+}
+
+impl Error for VdsError {}
+impl fmt::Display for VdsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "Invalid VDS-NC: {}", e),
+            Self::Base64(e) => write!(f, "Base64 decoding failed: {}", e),
+            Self::UnsupportedType(t) => write!(f, "Unsupported VDS-NC type '{}'", t),
+        }
+    }
+}
+
+/// Check whether the text looks like a VDS-NC (a JSON object)
+pub fn is_vds(text: &str) -> bool {
+    text.trim_start().starts_with('{')
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Seal {
+    /// The signed part, kept as JSON for the canonicalization
+    pub data: Value,
+    pub sig: SealSignature,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SealSignature {
+    pub alg: String,
+    /// The base64url encoded DER barcode signer certificate
+    pub cer: String,
+    /// The base64url encoded signature value
+    pub sigvl: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Header {
+    /// The type of the seal, e.g. `icao.vacc`
+    pub t: String,
+    /// The version of the type
+    pub v: u32,
+    /// The issuing country (ICAO 3-letter code)
+    #[serde(rename = "is")]
+    pub issuer: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Data {
+    hdr: Header,
+    msg: Value,
+}
+
+fn base64url(text: &str) -> Result<Vec<u8>, VdsError> {
+    base64::decode_config(text.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+        .map_err(VdsError::Base64)
+}
+
+impl Seal {
+    pub fn parse(text: &str) -> Result<Self, VdsError> {
+        serde_json::from_str(text).map_err(VdsError::Json)
+    }
+
+    pub fn header(&self) -> Result<Header, VdsError> {
+        Header::deserialize(&self.data["hdr"]).map_err(VdsError::Json)
+    }
+
+    /// The signed bytes: the `data` object with sorted keys and without whitespace
+    pub fn signing_input(&self) -> Vec<u8> {
+        // `serde_json::Value` keeps object keys sorted
+        self.data.to_string().into_bytes()
+    }
+
+    /// The DER encoded barcode signer certificate
+    pub fn certificate(&self) -> Result<Vec<u8>, VdsError> {
+        base64url(&self.sig.cer)
+    }
+
+    pub fn signature(&self) -> Result<Vec<u8>, VdsError> {
+        base64url(&self.sig.sigvl)
+    }
+
+    /// Collect the holder and vaccination events of a proof of vaccination
+    pub fn to_card(&self) -> Result<HealthCard, VdsError> {
+        let data = Data::deserialize(&self.data).map_err(VdsError::Json)?;
+        if data.hdr.t != TYPE_VACCINATION {
+            return Err(VdsError::UnsupportedType(data.hdr.t));
+        }
+        let text = |value: &Value| value.as_str().map(str::to_string);
+        let msg = &data.msg;
+        let mut card = HealthCard {
+            issuer: data.hdr.issuer,
+            name: text(&msg["pid"]["n"]),
+            birth_date: text(&msg["pid"]["dob"]),
+            ..HealthCard::default()
+        };
+        for event in msg["ve"].as_array().into_iter().flatten() {
+            for dose in event["vd"].as_array().into_iter().flatten() {
+                card.immunizations.push(Immunization {
+                    vaccine_code: text(&event["des"]),
+                    vaccine_name: text(&event["nam"]),
+                    date: text(&dose["dvc"]),
+                    dose: dose["seq"].as_u64().map(|seq| seq as u32),
+                    lot_number: text(&dose["lot"]),
+                    performer: text(&dose["adm"]),
+                    country: text(&dose["ctr"]),
+                    ..Immunization::default()
+                });
+            }
+        }
+        Ok(card)
+    }
+}
+
+/// Verify the signature of a seal and check its signer certificate against `store`
+///
+/// Without a store, the signer can't be trusted and [`Verification::KeyNotFound`] is
+/// returned after checking the signature.
+///
+/// [`Verification::KeyNotFound`]: crate::verify::Verification::KeyNotFound
+#[cfg(feature = "verify")]
+#[tracing::instrument(name = "verify", level = "debug", skip_all)]
+pub fn verify(
+    seal: &Seal,
+    store: Option<&crate::cert::CscaStore>,
+) -> color_eyre::Result<crate::verify::Verification> {
+    use crate::{
+        crypto::{self, SignatureAlgorithm},
+        spec::{Finding, Rule},
+        verify::Verification,
+    };
+    use tracing::{info, warn};
+    use x509_parser::parse_x509_certificate;
+
+    if seal.sig.alg != "ES256" {
+        return Ok(Verification::UnsupportedAlgorithm(seal.sig.alg.clone()));
+    }
+    let der = seal.certificate()?;
+    let (_, cert) = parse_x509_certificate(&der)?;
+    if let Some(name) = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|name| name.attr_value.as_str().ok())
+    {
+        info!("barcode signer: {:?}", name);
+    }
+
+    // The signature is usually the raw `r || s`, but also accept DER
+    let signature = seal.signature()?;
+    let alg = if signature.len() == 64 {
+        SignatureAlgorithm::EcdsaP256Sha256Fixed
+    } else {
+        SignatureAlgorithm::EcdsaP256Sha256Asn1
+    };
+    let point = cert.tbs_certificate.subject_pki.subject_public_key.data;
+    if crypto::verify(alg, point, &seal.signing_input(), &signature).is_err() {
+        return Ok(Verification::Invalid);
+    }
+
+    match store {
+        Some(store) => match store.verify_dsc(&cert) {
+            Ok(()) => Ok(Verification::Verified),
+            Err(e) => Ok(Verification::Rejected(Finding::new(
+                Rule::CertificatePath,
+                format!("Barcode signer is not trusted: {}", e),
+            ))),
+        },
+        None => {
+            warn!("No CSCAs to check the barcode signer against (--vds-csca)");
+            Ok(Verification::KeyNotFound)
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_cose::{sig::Sig, HeaderParameter, Sign1};
+use tracing::{debug, info, instrument, warn};
+use x509_parser::{prelude::*, time::ASN1Time};
+
+use crate::{
+    cert::{self, Algorithm, CertSubject, Prime},
+    cose::{alg_name, ALG_ES256, ALG_ES384, ALG_ES512, ALG_PS256, ALG_PS384, ALG_PS512},
+    crypto::{self, SignatureAlgorithm},
+    dcc::{sign1_kid, CertPayload},
+    sig,
+    spec::{Finding, Rule},
+    trust::{SignerCert, SignerKey, TrustSource},
+};
+
+/// Options for the verification step
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifyOptions {
+    /// Only warn if the DSC was not valid at the issued-at time
+    pub warn_dsc_validity: bool,
+}
+
+/// The outcome of verifying the signature of a DCC
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status", content = "reason")]
+pub enum Verification {
+    /// The signature is valid and the DSC may sign this certificate
+    Verified,
+    /// No DSC with a matching kid was found
+    KeyNotFound,
+    /// The DSC uses an algorithm that is not supported
+    UnsupportedAlgorithm(String),
+    /// The `alg` header does not match the type of the DSC key
+    AlgorithmMismatch(String),
+    /// The DSC was not allowed to sign this certificate
+    Rejected(Finding),
+    /// The signature is invalid
+    Invalid,
+    /// The kid or the certificate identifier is on the blocklist
+    Blocked(String),
+    /// The signature is valid, but the DSC was revoked by its CSCA
+    Revoked(String),
+}
+
+impl Verification {
+    pub fn is_verified(&self) -> bool {
+        matches!(self, Self::Verified)
+    }
+
+    /// The rank of the outcome when several DSCs are tried, higher is more relevant
+    ///
+    /// A DSC that verified the signature wins, then one that rejected the certificate,
+    /// then one with an invalid signature.
+    fn relevance(&self) -> u8 {
+        match self {
+            Self::Verified | Self::Blocked(_) | Self::Revoked(_) => 5,
+            Self::Rejected(_) => 4,
+            Self::Invalid => 3,
+            Self::AlgorithmMismatch(_) => 2,
+            Self::UnsupportedAlgorithm(_) => 1,
+            Self::KeyNotFound => 0,
+        }
+    }
+
+    /// The name of the outcome, as in the `status` field of the JSON representation
+    pub fn status(&self) -> &'static str {
+        match self {
+            Self::Verified => "verified",
+            Self::KeyNotFound => "key_not_found",
+            Self::UnsupportedAlgorithm(_) => "unsupported_algorithm",
+            Self::AlgorithmMismatch(_) => "algorithm_mismatch",
+            Self::Rejected(_) => "rejected",
+            Self::Invalid => "invalid",
+            Self::Blocked(_) => "blocked",
+            Self::Revoked(_) => "revoked",
+        }
+    }
+
+    /// The violated rule, if the certificate was not verified
+    pub fn finding(&self) -> Option<Finding> {
+        match self {
+            Self::Verified => None,
+            Self::KeyNotFound => Some(Finding::new(Rule::KeyIdentifier, self.to_string())),
+            Self::UnsupportedAlgorithm(_) | Self::AlgorithmMismatch(_) => {
+                Some(Finding::new(Rule::SignatureAlgorithm, self.to_string()))
+            }
+            Self::Rejected(finding) => Some(finding.clone()),
+            Self::Invalid => Some(Finding::new(Rule::Signature, self.to_string())),
+            Self::Revoked(_) => Some(Finding::new(Rule::DscRevocation, self.to_string())),
+            // Not a violation of the specification, the reason is in the message
+            Self::Blocked(_) => None,
+        }
+    }
+}
+
+impl std::error::Error for Verification {}
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Verified => write!(f, "Verified OK"),
+            Self::KeyNotFound => write!(f, "Did not find certificate with matching kid"),
+            Self::UnsupportedAlgorithm(alg) => {
+                write!(f, "Unsupported signature algorithm: {}", alg)
+            }
+            Self::AlgorithmMismatch(reason) => write!(f, "Algorithm mismatch: {}", reason),
+            Self::Rejected(finding) => write!(f, "Rejected: {}", finding),
+            Self::Invalid => write!(f, "Verification failed"),
+            Self::Blocked(reason) => write!(f, "Blocked: {}", reason),
+            Self::Revoked(reason) => write!(f, "Signer revoked: {}", reason),
+        }
+    }
+}
+
+/// Verify the signature of `sign1` against the DSCs from `trust`
+pub fn verify_sign1(
+    sign1: &Sign1,
+    payload: &CertPayload,
+    trust: &dyn TrustSource,
+    options: &VerifyOptions,
+) -> color_eyre::Result<Verification> {
+    let (verification, _) = find_signer(sign1, payload, trust, options)?;
+    Ok(verification)
+}
+
+/// Verify the signature of `sign1` like [`verify_sign1`], returning the DSC that was
+/// checked (the one that verified it, if any)
+///
+/// If the message has no kid, all DSCs of the issuing country (`iss`) are tried. DSCs that
+/// fail to load are skipped, and the most relevant outcome is returned with its DSC.
+#[instrument(name = "verify", level = "debug", skip_all)]
+pub fn find_signer(
+    sign1: &Sign1,
+    payload: &CertPayload,
+    trust: &dyn TrustSource,
+    options: &VerifyOptions,
+) -> color_eyre::Result<(Verification, Option<SignerCert>)> {
+    let kid = sign1_kid(sign1);
+    let candidates = match kid {
+        Some(kid) => trust.lookup(kid),
+        None => {
+            let candidates = trust.lookup_country(&payload.issuer);
+            warn!(
+                "No kid in the COSE message, trying the {} DSCs of '{}'",
+                candidates.len(),
+                payload.issuer
+            );
+            candidates
+        }
+    };
+    let mut verification = Verification::KeyNotFound;
+    let mut checked = None;
+    for signer in candidates {
+        let result = match kid {
+            Some(_) => {
+                info!("Found certificate with matching kid in trustlist");
+                verify_with_signer(sign1, payload, &signer, options)?
+            }
+            None => {
+                let signer_kid = base64::encode(&signer.kid);
+                info!("Trying the DSC '{}'", signer_kid);
+                match verify_with_signer(sign1, payload, &signer, options) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        warn!("Skipping the DSC '{}': {}", signer_kid, e);
+                        continue;
+                    }
+                }
+            }
+        };
+        // Keep the most relevant outcome of all candidates
+        if checked.is_none() || result.relevance() > verification.relevance() {
+            verification = result;
+            checked = Some(signer);
+        }
+        if verification.is_verified() {
+            break;
+        }
+    }
+    Ok((verification, checked))
+}
+
+/// Verify the signature of `sign1` against a single DSC
+pub fn verify_with_signer(
+    sign1: &Sign1,
+    payload: &CertPayload,
+    signer: &SignerCert,
+    options: &VerifyOptions,
+) -> color_eyre::Result<Verification> {
+    let der = match &signer.key {
+        SignerKey::Certificate(der) => der,
+        SignerKey::PublicKey(spki) => {
+            debug!("Signer has no certificate, only checking the signature");
+            let (_, spki) = SubjectPublicKeyInfo::from_der(spki)?;
+            return verify_with_key(sign1, &spki);
+        }
+    };
+
+    // Read the X.509 certificate
+    let (_, sigcert) = parse_x509_certificate(der)?;
+    debug!("Loaded issuer X.509 certificate");
+
+    let subject = &sigcert.tbs_certificate.subject;
+    if let Some(name) = subject
+        .iter_common_name()
+        .next()
+        .and_then(|name| name.attr_value.as_str().ok())
+    {
+        info!("subject common name: {:?}", name);
+    }
+    debug!("subject: {:?}", CertSubject(subject));
+
+    // Check the extended key usage
+    if let Some(allowed) = cert::get_allowed_entry_kinds(&sigcert) {
+        debug!("DSC is restricted to {:?}", allowed);
+        for kind in payload.health_claim.cert.entry_kinds() {
+            if !allowed.contains(&kind) {
+                let reason = format!("DSC is not allowed to sign {} certificates", kind);
+                return Ok(Verification::Rejected(Finding::new(
+                    Rule::DscKeyUsage,
+                    reason,
+                )));
+            }
+        }
+    }
+
+    // Check the DSC validity window against the issued-at time
+    let validity = sigcert.validity();
+    if !validity.is_valid_at(ASN1Time::from_timestamp(payload.issued_at.timestamp())) {
+        let msg = format!(
+            "DSC was not valid at issued-at time {} (valid from {} until {})",
+            payload.issued_at,
+            validity.not_before.to_rfc2822(),
+            validity.not_after.to_rfc2822()
+        );
+        let finding = Finding::new(Rule::DscValidity, msg);
+        if options.warn_dsc_validity {
+            warn!("{}", finding);
+        } else {
+            return Ok(Verification::Rejected(finding));
+        }
+    }
+
+    verify_with_key(sign1, &sigcert.tbs_certificate.subject_pki)
+}
+
+/// Verify the signature of `sign1` against a single public key, without any checks on a DSC
+pub fn verify_with_key(
+    sign1: &Sign1,
+    sigpki: &SubjectPublicKeyInfo,
+) -> color_eyre::Result<Verification> {
+    // Transform COSE_Sign1 into Signature1
+    let sig = Sig::from(sign1.clone());
+    let message = serde_cbor::to_vec(&sig)?;
+    debug!("Signature1 encoding successful");
+
+    // Check the type of the key
+    let key = match cert::get_pk_sig_algorithm(sigpki) {
+        Ok(key) => key,
+        Err(e) => return Ok(Verification::UnsupportedAlgorithm(e.to_string())),
+    };
+    debug!("found key algorithm: {:?}", key);
+    if !key.is_supported() {
+        let reason = format!(
+            "{} is a known curve, but not supported for verification",
+            key
+        );
+        return Ok(Verification::UnsupportedAlgorithm(reason));
+    }
+
+    // Choose the signature algorithm from the key and the algorithm in the protected header
+    let cose_alg = match sign1.protected.0.get(&HeaderParameter::Algorithm) {
+        Some(&cose_alg) => Some(i128::from(cose_alg)),
+        None => {
+            warn!("No algorithm in the protected header, using the key type");
+            None
+        }
+    };
+    let alg = match signature_algorithm(key, cose_alg) {
+        Ok(alg) => alg,
+        Err(verification) => return Ok(verification),
+    };
+    debug!("using signature algorithm {:?}", alg);
+
+    // COSE signatures are `r || s`, but some issuers use DER encoded signatures, which
+    // are accepted but reported by `signature_format`
+    let der;
+    let signature = match key {
+        Algorithm::IdEcPublicKey(prime) => {
+            if sig::der_signature_to_fixed(&sign1.signature, prime).is_some() {
+                debug!("The signature is DER encoded");
+                &sign1.signature
+            } else {
+                der = match sig::fixed_to_der(&sign1.signature) {
+                    Ok(der) => der,
+                    Err(_) => return Ok(Verification::Invalid),
+                };
+                &der
+            }
+        }
+        Algorithm::RsaEncryption => &sign1.signature,
+    };
+
+    let key = sigpki.subject_public_key.data;
+    match crypto::verify(alg, key, &message, signature) {
+        Ok(()) => Ok(Verification::Verified),
+        Err(_) => Ok(Verification::Invalid),
+    }
+}
+
+/// The algorithm to verify a COSE signature with a `key` of this type and the `alg` header
+///
+/// The curve is the one of the key, the hash the one of the `alg` header. Without an `alg`
+/// header, ECDSA uses the hash for the size of the curve.
+fn signature_algorithm(
+    key: Algorithm,
+    cose_alg: Option<i128>,
+) -> Result<SignatureAlgorithm, Verification> {
+    use SignatureAlgorithm::*;
+
+    const P256: Algorithm = Algorithm::IdEcPublicKey(Prime::Prime256v1);
+    const P384: Algorithm = Algorithm::IdEcPublicKey(Prime::Secp384r1);
+    let name = |alg: i128| {
+        alg_name(alg)
+            .map(str::to_string)
+            .unwrap_or_else(|| alg.to_string())
+    };
+    match (key, cose_alg) {
+        (P256, None | Some(ALG_ES256)) => Ok(EcdsaP256Sha256Asn1),
+        (P256, Some(ALG_ES384)) => Ok(EcdsaP256Sha384Asn1),
+        (P384, Some(ALG_ES256)) => Ok(EcdsaP384Sha256Asn1),
+        (P384, None | Some(ALG_ES384)) => Ok(EcdsaP384Sha384Asn1),
+        (Algorithm::RsaEncryption, None | Some(ALG_PS256)) => Ok(RsaPssSha256),
+        (Algorithm::IdEcPublicKey(_), Some(cose_alg @ (ALG_ES256 | ALG_ES384 | ALG_ES512)))
+        | (Algorithm::RsaEncryption, Some(cose_alg @ (ALG_PS384 | ALG_PS512))) => {
+            let reason = format!("{} is not supported with {} keys", name(cose_alg), key);
+            Err(Verification::UnsupportedAlgorithm(reason))
+        }
+        (_, Some(cose_alg)) => {
+            let reason = format!(
+                "certificate uses {}, but the key is {}",
+                name(cose_alg),
+                key
+            );
+            Err(Verification::AlgorithmMismatch(reason))
+        }
+        (_, None) => Err(Verification::UnsupportedAlgorithm(key.to_string())),
+    }
+}
+
+/// Report a DER encoded ECDSA signature, which is accepted but not conformant
+///
+/// The curve is taken from the `alg` header, so this also works without the DSC.
+pub fn signature_format(sign1: &Sign1) -> Option<Finding> {
+    let prime = match sign1.protected.0.get(&HeaderParameter::Algorithm) {
+        Some(&alg) => match i128::from(alg) {
+            ALG_ES256 => Prime::Prime256v1,
+            ALG_ES384 => Prime::Secp384r1,
+            ALG_ES512 => Prime::Secp521r1,
+            _ => return None,
+        },
+        None => return None,
+    };
+    sig::der_signature_to_fixed(&sign1.signature, prime)?;
+    let message = format!(
+        "The signature is DER encoded instead of r || s ({} bytes)",
+        sign1.signature.len()
+    );
+    Some(Finding::new(Rule::SignatureFormat, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P256: Algorithm = Algorithm::IdEcPublicKey(Prime::Prime256v1);
+    const P384: Algorithm = Algorithm::IdEcPublicKey(Prime::Secp384r1);
+
+    #[test]
+    fn curve_and_hash() {
+        use SignatureAlgorithm::*;
+
+        let supported = [
+            (P256, None, EcdsaP256Sha256Asn1),
+            (P256, Some(ALG_ES256), EcdsaP256Sha256Asn1),
+            (P256, Some(ALG_ES384), EcdsaP256Sha384Asn1),
+            (P384, None, EcdsaP384Sha384Asn1),
+            (P384, Some(ALG_ES256), EcdsaP384Sha256Asn1),
+            (P384, Some(ALG_ES384), EcdsaP384Sha384Asn1),
+            (Algorithm::RsaEncryption, None, RsaPssSha256),
+            (Algorithm::RsaEncryption, Some(ALG_PS256), RsaPssSha256),
+        ];
+        for (key, cose_alg, expected) in supported {
+            assert_eq!(signature_algorithm(key, cose_alg), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn unsupported_pairs() {
+        let unsupported = [
+            (P256, ALG_ES512),
+            (P384, ALG_ES512),
+            (Algorithm::RsaEncryption, ALG_PS384),
+            (Algorithm::RsaEncryption, ALG_PS512),
+        ];
+        for (key, cose_alg) in unsupported {
+            let verification = signature_algorithm(key, Some(cose_alg)).unwrap_err();
+            assert_eq!(verification.status(), "unsupported_algorithm");
+        }
+        let mismatch = [
+            (P256, ALG_PS256),
+            (Algorithm::RsaEncryption, ALG_ES256),
+            (P384, -8),
+        ];
+        for (key, cose_alg) in mismatch {
+            let verification = signature_algorithm(key, Some(cose_alg)).unwrap_err();
+            assert_eq!(verification.status(), "algorithm_mismatch");
+        }
+    }
+}